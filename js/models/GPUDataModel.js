@@ -20,14 +20,14 @@ const isTauriAvailable = () => {
  * 
  * @param {string} cmd - Command name
  * @param {Object} args - Command arguments
- * @returns {Promise<string>} Mock response data
+ * @returns {Promise<Object|string>} Mock response data
  */
 const mockInvoke = async (cmd, args) => {
     console.log(`Mock Tauri command: ${cmd}`, args);
     
     switch (cmd) {
         case 'get_gpu_telemetry':
-            return JSON.stringify({
+            return {
                 status: 'connected',
                 gpus: [{
                     index: 0,
@@ -43,21 +43,22 @@ const mockInvoke = async (cmd, args) => {
                 telemetry: {
                     timestamp: Date.now(),
                     device_index: 0,
-                    name: 'Mock GPU (RTX 4090)',
                     util_gpu: Math.floor(Math.random() * 60) + 20, // 20-80%
                     util_memory: Math.floor(Math.random() * 40) + 20, // 20-60%
                     memory_used_mb: 8192 + Math.floor(Math.random() * 4096),
-                    memory_total_mb: 24576,
                     sm_clock_mhz: 1800 + Math.floor(Math.random() * 400),
                     memory_clock_mhz: 7000 + Math.floor(Math.random() * 1000),
                     temperature_c: 55 + Math.floor(Math.random() * 20),
                     power_w: 200 + Math.floor(Math.random() * 150),
                     fan_speed_percent: 40 + Math.floor(Math.random() * 40),
+                    fan_speeds_percent: Array.from({length: 3}, () => 40 + Math.floor(Math.random() * 40)),
+                    target_fan_speeds_percent: [60, 60, 60],
+                    engine_utilization: { graphics: 50, encoder: Math.floor(Math.random() * 20), decoder: 0, jpeg: 0, ofa: 0 },
                     sm_utilizations: Array.from({length: 128}, () => Math.random()),
                     memory_bandwidth_gbps: 500 + Math.floor(Math.random() * 300),
                     pcie_utilization: Math.floor(Math.random() * 30) + 10
                 }
-            });
+            };
         case 'start_nvml_stream':
             // Start mock telemetry updates
             if (!window.mockTelemetryInterval) {
@@ -67,16 +68,17 @@ const mockInvoke = async (cmd, args) => {
                             detail: {
                                 timestamp: Date.now(),
                                 device_index: 0,
-                                name: 'Mock GPU (RTX 4090)',
                                 util_gpu: Math.floor(Math.random() * 60) + 20,
                                 util_memory: Math.floor(Math.random() * 40) + 20,
                                 memory_used_mb: 8192 + Math.floor(Math.random() * 4096),
-                                memory_total_mb: 24576,
                                 sm_clock_mhz: 1800 + Math.floor(Math.random() * 400),
                                 memory_clock_mhz: 7000 + Math.floor(Math.random() * 1000),
                                 temperature_c: 55 + Math.floor(Math.random() * 20),
                                 power_w: 200 + Math.floor(Math.random() * 150),
                                 fan_speed_percent: 40 + Math.floor(Math.random() * 40),
+                                fan_speeds_percent: Array.from({length: 3}, () => 40 + Math.floor(Math.random() * 40)),
+                                target_fan_speeds_percent: [60, 60, 60],
+                                engine_utilization: { graphics: 50, encoder: Math.floor(Math.random() * 20), decoder: 0, jpeg: 0, ofa: 0 },
                                 sm_utilizations: Array.from({length: 128}, () => Math.random()),
                                 memory_bandwidth_gbps: 500 + Math.floor(Math.random() * 300),
                                 pcie_utilization: Math.floor(Math.random() * 30) + 10
@@ -93,11 +95,11 @@ const mockInvoke = async (cmd, args) => {
             }
             return 'Mock stream stopped';
         case 'get_stream_status':
-            return JSON.stringify({
+            return {
                 streaming: !!window.mockTelemetryInterval
-            });
+            };
         case 'get_gpu_architecture':
-            return JSON.stringify({
+            return {
                 name: 'Mock RTX 4090',
                 compute_capability: '8.9',
                 sm_count: 128,
@@ -117,7 +119,7 @@ const mockInvoke = async (cmd, args) => {
                 memory_clock_mhz: 10501,
                 max_power_w: 450,
                 thermal_design_power_w: 450
-            });
+            };
         default:
             return 'Mock response';
     }
@@ -277,8 +279,7 @@ export class GPUDataModel {
 
         try {
             const safeInvoke = await getSafeInvoke();
-            const result = await safeInvoke('get_gpu_telemetry');
-            const data = JSON.parse(result);
+            const data = await safeInvoke('get_gpu_telemetry');
             
             this.state.devices = data.gpus || [];
             this.state.connected = data.status === 'connected';
@@ -34,7 +34,7 @@ const mockInvoke = async (command, payload = {}) => {
         case 'stop_gpu_recording':
             return './recordings/gpu_data_' + Date.now() + '.json';
         case 'get_recording_status':
-            return JSON.stringify({
+            return {
                 is_recording: false,
                 session_id: null,
                 duration_seconds: null,
@@ -43,20 +43,29 @@ const mockInvoke = async (command, payload = {}) => {
                 metrics: [],
                 samples_collected: 0,
                 output_file: null
-            });
+            };
+        case 'recover_recordings':
+            return [];
         case 'process_nsight_report':
-            return JSON.stringify({
+            return {
                 report_type: 'NSight Compute',
                 gpu_name: 'RTX 4090',
                 kernels: [],
-                bottlenecks: ['Memory Bandwidth', 'SM Utilization'],
-                recommendations: ['Increase memory coalescing', 'Optimize thread block size'],
+                bottlenecks: ['DRAM bandwidth', 'Low occupancy'],
+                recommendations: [{
+                    rule: 'dram_bound',
+                    kernel: 'example_kernel',
+                    severity: 'high',
+                    bottleneck: 'DRAM bandwidth',
+                    message: 'example_kernel: DRAM throughput is 91.0% of peak; reduce traffic by reusing data in shared memory, using smaller data types or fusing kernels',
+                    evidence: [{ metric: 'dram_throughput_percent', value: 91.0, threshold: 80.0 }]
+                }],
                 performance_summary: {
                     average_sm_utilization: 75.5,
                     memory_throughput_gbps: 850.2,
                     bottleneck_analysis: 'Memory Bandwidth Limited'
                 }
-            });
+            };
         default:
             throw new Error(`Unknown command: ${command}`);
     }
@@ -75,6 +84,19 @@ const getSafeInvoke = async () => {
     return mockInvoke;
 };
 
+// Tauri event listener, or null when running without Tauri
+const getSafeListen = async () => {
+    if (isTauriAvailable()) {
+        try {
+            const { listen } = await import('@tauri-apps/api/event');
+            return listen;
+        } catch (error) {
+            console.log('Failed to import Tauri event API');
+        }
+    }
+    return null;
+};
+
 class NSightfulApp {
     constructor() {
         this.dataModel = null;
@@ -108,12 +130,27 @@ class NSightfulApp {
             this.isInitialized = true;
             console.log('✅ NSightful initialized successfully');
             
+            // Finalize recordings interrupted by a crash
+            await this.recoverRecordings();
+            
         } catch (error) {
             console.error('❌ Failed to initialize NSightful:', error);
             this.showError('Initialization failed: ' + error.message);
         }
     }
 
+    async recoverRecordings() {
+        try {
+            const safeInvoke = await getSafeInvoke();
+            const recovered = await safeInvoke('recover_recordings');
+            recovered.forEach(recording => {
+                this.showInfo(`Recovered interrupted recording: ${recording.sample_count} samples saved to ${recording.output_file}`);
+            });
+        } catch (error) {
+            console.error('Failed to recover recordings:', error);
+        }
+    }
+
     initializeVisualizations() {
         // Initialize enhanced 3D GPU visualization
         const gpu3dCanvas = document.getElementById('gpu3d');
@@ -383,8 +420,7 @@ class NSightfulApp {
             // For now, just use the file name as a placeholder path
             const filePath = file.name;
             
-            const result = await safeInvoke('process_nsight_report', { filePath });
-            const analysis = JSON.parse(result);
+            const analysis = await safeInvoke('process_nsight_report', { filePath });
             
             console.log('✅ NSight report processed successfully:', analysis);
             
@@ -409,7 +445,7 @@ NSight Analysis Results:
 • Main Bottleneck: ${analysis.performance_summary.bottleneck_analysis}
 
 Top Recommendations:
-${analysis.recommendations.slice(0, 3).map(rec => `• ${rec}`).join('\n')}
+${analysis.recommendations.slice(0, 3).map(rec => `• [${rec.severity}] ${rec.message}`).join('\n')}
         `;
         
         console.log(summary);
@@ -466,15 +502,13 @@ ${analysis.recommendations.slice(0, 3).map(rec => `• ${rec}`).join('\n')}
             const safeInvoke = await getSafeInvoke();
             const outputFile = await safeInvoke('stop_gpu_recording');
             
+            // With events, recording-complete reports the saved file
+            if (this.recordingUnlisteners) return;
+            
             this.isRecording = false;
             this.recordingSessionId = null;
             this.updateRecordingUI(false);
-            
-            // Stop monitoring
-            if (this.recordingMonitorInterval) {
-                clearInterval(this.recordingMonitorInterval);
-                this.recordingMonitorInterval = null;
-            }
+            this.stopRecordingMonitor();
             
             this.showSuccess(`GPU recording completed! Data saved to: ${outputFile}`);
             
@@ -484,20 +518,32 @@ ${analysis.recommendations.slice(0, 3).map(rec => `• ${rec}`).join('\n')}
         }
     }
     
-    startRecordingMonitor() {
-        // Monitor recording status every second
+    async startRecordingMonitor() {
+        const listen = await getSafeListen();
+        if (listen) {
+            // The backend pushes progress and completion
+            this.recordingUnlisteners = await Promise.all([
+                listen('recording-progress', (event) => this.updateRecordingProgress(event.payload)),
+                listen('recording-complete', (event) => this.handleRecordingComplete(event.payload))
+            ]);
+            return;
+        }
+        
+        // Without Tauri events, poll the recording status every second
         this.recordingMonitorInterval = setInterval(async () => {
             try {
                 const safeInvoke = await getSafeInvoke();
-                const statusJson = await safeInvoke('get_recording_status');
-                const status = JSON.parse(statusJson);
+                const status = await safeInvoke('get_recording_status');
                 
                 if (!status.is_recording && this.isRecording) {
                     // Recording finished automatically
                     this.stopRecording();
                 } else if (status.is_recording) {
-                    // Update progress
-                    this.updateRecordingProgress(status);
+                    this.updateRecordingProgress({
+                        samples_collected: status.samples_collected,
+                        elapsed_seconds: status.elapsed_seconds,
+                        remaining_seconds: status.duration_seconds - status.elapsed_seconds
+                    });
                 }
                 
             } catch (error) {
@@ -506,13 +552,37 @@ ${analysis.recommendations.slice(0, 3).map(rec => `• ${rec}`).join('\n')}
         }, 1000);
     }
     
-    updateRecordingProgress(status) {
+    stopRecordingMonitor() {
+        if (this.recordingUnlisteners) {
+            this.recordingUnlisteners.forEach(unlisten => unlisten());
+            this.recordingUnlisteners = null;
+        }
+        if (this.recordingMonitorInterval) {
+            clearInterval(this.recordingMonitorInterval);
+            this.recordingMonitorInterval = null;
+        }
+    }
+    
+    handleRecordingComplete(complete) {
+        if (complete.session_id !== this.recordingSessionId) return;
+        
+        this.isRecording = false;
+        this.recordingSessionId = null;
+        this.updateRecordingUI(false);
+        this.stopRecordingMonitor();
+        
+        this.showSuccess(`GPU recording completed! ${complete.sample_count} samples saved to: ${complete.output_file}`);
+    }
+    
+    updateRecordingProgress(progress) {
         const recordingStatus = document.getElementById('recordingStatus');
         const statusText = recordingStatus?.querySelector('.status-text');
         
-        if (statusText && status.elapsed_seconds !== undefined && status.duration_seconds) {
-            const progress = Math.round((status.elapsed_seconds / status.duration_seconds) * 100);
-            statusText.textContent = `Recording... ${status.elapsed_seconds}/${status.duration_seconds}s (${progress}%)`;
+        if (statusText && progress.elapsed_seconds !== undefined) {
+            const elapsed = Math.round(progress.elapsed_seconds);
+            const total = Math.round(progress.elapsed_seconds + progress.remaining_seconds);
+            const percent = total > 0 ? Math.round((elapsed / total) * 100) : 0;
+            statusText.textContent = `Recording... ${elapsed}/${total}s (${percent}%), ${progress.samples_collected} samples`;
         }
     }
     
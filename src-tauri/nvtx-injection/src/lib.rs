@@ -0,0 +1,454 @@
+//! NVTX injection library forwarding ranges to NSightful
+//!
+//! The NVTX headers compiled into an application look for an injection
+//! library in `NVTX_INJECTION64_PATH` on the first NVTX call. Pointing it at
+//! this library, e.g.
+//!
+//! ```text
+//! cargo build -p nsightful-nvtx --release
+//! NVTX_INJECTION64_PATH=target/release/libnsightful_nvtx.so python train.py
+//! ```
+//!
+//! makes every `nvtxRangePush`/`nvtxRangePop` and `nvtxRangeStart`/
+//! `nvtxRangeEnd` call, with or without a domain, send one JSON datagram to
+//! the NSightful marker listener on 127.0.0.1, port `NSIGHTFUL_MARKER_PORT`
+//! (47021 by default). Datagrams use the event format documented in the
+//! app's `markers` module and carry Unix epoch timestamps, so ranges line up
+//! with telemetry. Nothing is sent while no listener is running, and the
+//! application is never blocked or failed by a missing listener.
+
+use std::cell::Cell;
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Port of the marker listener when `NSIGHTFUL_MARKER_PORT` is not set
+pub const DEFAULT_PORT: u16 = 47_021;
+
+/// `NVTX_ETID_CALLBACKS`
+const EXPORT_TABLE_CALLBACKS: u32 = 1;
+/// `NVTX_CB_MODULE_CORE`
+const MODULE_CORE: c_int = 1;
+/// `NVTX_CB_MODULE_CORE2`
+const MODULE_CORE2: c_int = 5;
+
+// `NvtxCallbackIdCore`
+const CORE_RANGE_START_EX: usize = 4;
+const CORE_RANGE_START_A: usize = 5;
+const CORE_RANGE_START_W: usize = 6;
+const CORE_RANGE_END: usize = 7;
+const CORE_RANGE_PUSH_EX: usize = 8;
+const CORE_RANGE_PUSH_A: usize = 9;
+const CORE_RANGE_PUSH_W: usize = 10;
+const CORE_RANGE_POP: usize = 11;
+
+// `NvtxCallbackIdCore2`
+const CORE2_DOMAIN_RANGE_START_EX: usize = 2;
+const CORE2_DOMAIN_RANGE_END: usize = 3;
+const CORE2_DOMAIN_RANGE_PUSH_EX: usize = 4;
+const CORE2_DOMAIN_RANGE_POP: usize = 5;
+const CORE2_DOMAIN_REGISTER_STRING_A: usize = 10;
+const CORE2_DOMAIN_REGISTER_STRING_W: usize = 11;
+const CORE2_DOMAIN_CREATE_A: usize = 12;
+const CORE2_DOMAIN_CREATE_W: usize = 13;
+
+// `nvtxMessageType_t`
+const MESSAGE_ASCII: i32 = 1;
+const MESSAGE_UNICODE: i32 = 2;
+const MESSAGE_REGISTERED: i32 = 3;
+
+type FunctionPointer = unsafe extern "C" fn();
+/// Slots of one module's function table, indexed by callback ID
+type FunctionTable = *mut *mut Option<FunctionPointer>;
+type GetModuleFunctionTable = unsafe extern "C" fn(c_int, *mut FunctionTable, *mut c_uint) -> c_int;
+type GetExportTable = unsafe extern "C" fn(u32) -> *const c_void;
+
+/// `NvtxExportTableCallbacks`
+#[repr(C)]
+struct ExportTableCallbacks {
+    struct_size: usize,
+    get_module_function_table: Option<GetModuleFunctionTable>,
+}
+
+/// `nvtxEventAttributes_t` (version 2), up to the message
+#[repr(C)]
+pub struct EventAttributes {
+    pub version: u16,
+    pub size: u16,
+    pub category: u32,
+    pub color_type: i32,
+    pub color: u32,
+    pub payload_type: i32,
+    pub reserved0: i32,
+    pub payload: u64,
+    pub message_type: i32,
+    pub message: *const c_void,
+}
+
+#[cfg(windows)]
+type WideChar = u16;
+#[cfg(not(windows))]
+type WideChar = u32;
+
+// Listener address and the socket sending to it; `None` if the socket could not be opened
+static SINK: OnceLock<Option<(UdpSocket, SocketAddr)>> = OnceLock::new();
+// IDs handed out by `nvtxRangeStart`
+static NEXT_RANGE_ID: AtomicU64 = AtomicU64::new(1);
+// Small per-process thread numbers, stable for a thread's lifetime
+static NEXT_THREAD_ID: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    static THREAD_ID: u32 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+    static DEPTH: Cell<c_int> = const { Cell::new(0) };
+}
+
+/// Entry point called by the NVTX headers when this library is injected
+///
+/// Installs the range callbacks. Returns 1 on success and 0 if the NVTX
+/// version in the application provides none of the callback tables.
+///
+/// # Safety
+/// `get_export_table` must be the function passed by the NVTX headers.
+#[no_mangle]
+pub unsafe extern "C" fn InitializeInjectionNvtx2(get_export_table: Option<GetExportTable>) -> c_int {
+    let Some(get_export_table) = get_export_table else { return 0 };
+    let callbacks = get_export_table(EXPORT_TABLE_CALLBACKS) as *const ExportTableCallbacks;
+    if callbacks.is_null() || (*callbacks).struct_size < std::mem::size_of::<ExportTableCallbacks>() {
+        return 0;
+    }
+    let Some(get_module_function_table) = (*callbacks).get_module_function_table else { return 0 };
+
+    let core: [(usize, FunctionPointer); 8] = [
+        (CORE_RANGE_START_EX, std::mem::transmute::<unsafe extern "C" fn(*const EventAttributes) -> u64, FunctionPointer>(range_start_ex)),
+        (CORE_RANGE_START_A, std::mem::transmute::<unsafe extern "C" fn(*const c_char) -> u64, FunctionPointer>(range_start_a)),
+        (CORE_RANGE_START_W, std::mem::transmute::<unsafe extern "C" fn(*const WideChar) -> u64, FunctionPointer>(range_start_w)),
+        (CORE_RANGE_END, std::mem::transmute::<extern "C" fn(u64), FunctionPointer>(range_end)),
+        (CORE_RANGE_PUSH_EX, std::mem::transmute::<unsafe extern "C" fn(*const EventAttributes) -> c_int, FunctionPointer>(range_push_ex)),
+        (CORE_RANGE_PUSH_A, std::mem::transmute::<unsafe extern "C" fn(*const c_char) -> c_int, FunctionPointer>(range_push_a)),
+        (CORE_RANGE_PUSH_W, std::mem::transmute::<unsafe extern "C" fn(*const WideChar) -> c_int, FunctionPointer>(range_push_w)),
+        (CORE_RANGE_POP, std::mem::transmute::<extern "C" fn() -> c_int, FunctionPointer>(range_pop)),
+    ];
+    let core2: [(usize, FunctionPointer); 8] = [
+        (CORE2_DOMAIN_RANGE_START_EX, std::mem::transmute::<unsafe extern "C" fn(*const c_void, *const EventAttributes) -> u64, FunctionPointer>(domain_range_start_ex)),
+        (CORE2_DOMAIN_RANGE_END, std::mem::transmute::<extern "C" fn(*const c_void, u64), FunctionPointer>(domain_range_end)),
+        (CORE2_DOMAIN_RANGE_PUSH_EX, std::mem::transmute::<unsafe extern "C" fn(*const c_void, *const EventAttributes) -> c_int, FunctionPointer>(domain_range_push_ex)),
+        (CORE2_DOMAIN_RANGE_POP, std::mem::transmute::<extern "C" fn(*const c_void) -> c_int, FunctionPointer>(domain_range_pop)),
+        (CORE2_DOMAIN_REGISTER_STRING_A, std::mem::transmute::<unsafe extern "C" fn(*const c_void, *const c_char) -> *const c_char, FunctionPointer>(domain_register_string_a)),
+        (CORE2_DOMAIN_REGISTER_STRING_W, std::mem::transmute::<unsafe extern "C" fn(*const c_void, *const WideChar) -> *const c_char, FunctionPointer>(domain_register_string_w)),
+        (CORE2_DOMAIN_CREATE_A, std::mem::transmute::<unsafe extern "C" fn(*const c_char) -> *const c_char, FunctionPointer>(domain_create_a)),
+        (CORE2_DOMAIN_CREATE_W, std::mem::transmute::<unsafe extern "C" fn(*const WideChar) -> *const c_char, FunctionPointer>(domain_create_w)),
+    ];
+    let installed_core = install(get_module_function_table, MODULE_CORE, &core);
+    let installed_core2 = install(get_module_function_table, MODULE_CORE2, &core2);
+    c_int::from(installed_core || installed_core2)
+}
+
+// Point the given callback slots of one module at our functions
+unsafe fn install(get_module_function_table: GetModuleFunctionTable, module: c_int, callbacks: &[(usize, FunctionPointer)]) -> bool {
+    let mut table: FunctionTable = std::ptr::null_mut();
+    let mut size: c_uint = 0;
+    if get_module_function_table(module, &mut table, &mut size) == 0 || table.is_null() {
+        return false;
+    }
+    // Older NVTX versions have shorter tables; their missing calls are not hooked
+    for &(id, callback) in callbacks.iter().filter(|(id, _)| *id < size as usize) {
+        let slot = *table.add(id);
+        if !slot.is_null() {
+            *slot = Some(callback);
+        }
+    }
+    true
+}
+
+/// One range event, serialized as a marker listener datagram
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Push { name: String, domain: Option<String> },
+    Pop,
+    Start { id: u64, name: String, domain: Option<String> },
+    End { id: u64 },
+}
+
+impl Event {
+    /// JSON datagram for this event, sent from process `pid`, thread `tid`
+    pub fn to_json(&self, pid: u32, tid: u32, timestamp_ns: u64) -> String {
+        let domain = |domain: &Option<String>| domain.as_deref()
+            .map_or_else(String::new, |domain| format!(",\"domain\":{}", json_string(domain)));
+        match self {
+            Event::Push { name, domain: range_domain } => format!(
+                "{{\"type\":\"push\",\"name\":{},\"pid\":{},\"tid\":{}{},\"timestamp_ns\":{}}}",
+                json_string(name), pid, tid, domain(range_domain), timestamp_ns
+            ),
+            Event::Pop => format!(
+                "{{\"type\":\"pop\",\"pid\":{},\"tid\":{},\"timestamp_ns\":{}}}",
+                pid, tid, timestamp_ns
+            ),
+            Event::Start { id, name, domain: range_domain } => format!(
+                "{{\"type\":\"start\",\"id\":{},\"name\":{},\"pid\":{}{},\"timestamp_ns\":{}}}",
+                id, json_string(name), pid, domain(range_domain), timestamp_ns
+            ),
+            Event::End { id } => format!(
+                "{{\"type\":\"end\",\"id\":{},\"pid\":{},\"timestamp_ns\":{}}}",
+                id, pid, timestamp_ns
+            ),
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if u32::from(c) < 0x20 => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Send an event to the listener; a missing listener is not an error
+fn send(event: Event) {
+    let sink = SINK.get_or_init(|| {
+        let port = std::env::var("NSIGHTFUL_MARKER_PORT").ok()
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(DEFAULT_PORT);
+        let socket = UdpSocket::bind(("127.0.0.1", 0)).ok()?;
+        Some((socket, SocketAddr::from(([127, 0, 0, 1], port))))
+    });
+    let Some((socket, listener)) = sink else { return };
+    let timestamp_ns = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    let json = event.to_json(std::process::id(), THREAD_ID.with(|tid| *tid), timestamp_ns);
+    let _ = socket.send_to(json.as_bytes(), listener);
+}
+
+unsafe fn c_string(value: *const c_char) -> Option<String> {
+    (!value.is_null()).then(|| CStr::from_ptr(value).to_string_lossy().into_owned())
+}
+
+unsafe fn wide_string(value: *const WideChar) -> Option<String> {
+    if value.is_null() {
+        return None;
+    }
+    let length = (0..).take_while(|&i| *value.add(i) != 0).count();
+    let units = std::slice::from_raw_parts(value, length);
+    #[cfg(windows)]
+    return Some(String::from_utf16_lossy(units));
+    #[cfg(not(windows))]
+    return Some(units.iter().map(|&unit| char::from_u32(unit).unwrap_or(char::REPLACEMENT_CHARACTER)).collect());
+}
+
+// Message of an event attribute structure; registered strings are C strings
+unsafe fn attributes_message(attributes: *const EventAttributes) -> String {
+    if attributes.is_null() {
+        return String::new();
+    }
+    let attributes = &*attributes;
+    match attributes.message_type {
+        MESSAGE_ASCII | MESSAGE_REGISTERED => c_string(attributes.message as *const c_char),
+        MESSAGE_UNICODE => wide_string(attributes.message as *const WideChar),
+        _ => None,
+    }.unwrap_or_default()
+}
+
+// Domain handles and registered strings are leaked C strings of their name
+fn leak(value: Option<String>) -> *const c_char {
+    CString::new(value.unwrap_or_default().replace('\0', ""))
+        .map_or(std::ptr::null(), |value| value.into_raw() as *const c_char)
+}
+
+unsafe fn domain_name(domain: *const c_void) -> Option<String> {
+    c_string(domain as *const c_char)
+}
+
+fn push(name: String, domain: Option<String>) -> c_int {
+    send(Event::Push { name, domain });
+    DEPTH.with(|depth| {
+        let level = depth.get();
+        depth.set(level + 1);
+        level
+    })
+}
+
+fn pop() -> c_int {
+    let level = DEPTH.with(|depth| {
+        let level = depth.get() - 1;
+        depth.set(level.max(0));
+        level
+    });
+    if level >= 0 {
+        send(Event::Pop);
+    }
+    level
+}
+
+fn start(name: String, domain: Option<String>) -> u64 {
+    let id = NEXT_RANGE_ID.fetch_add(1, Ordering::Relaxed);
+    send(Event::Start { id, name, domain });
+    id
+}
+
+unsafe extern "C" fn range_start_ex(attributes: *const EventAttributes) -> u64 {
+    start(attributes_message(attributes), None)
+}
+
+unsafe extern "C" fn range_start_a(message: *const c_char) -> u64 {
+    start(c_string(message).unwrap_or_default(), None)
+}
+
+unsafe extern "C" fn range_start_w(message: *const WideChar) -> u64 {
+    start(wide_string(message).unwrap_or_default(), None)
+}
+
+extern "C" fn range_end(id: u64) {
+    send(Event::End { id });
+}
+
+unsafe extern "C" fn range_push_ex(attributes: *const EventAttributes) -> c_int {
+    push(attributes_message(attributes), None)
+}
+
+unsafe extern "C" fn range_push_a(message: *const c_char) -> c_int {
+    push(c_string(message).unwrap_or_default(), None)
+}
+
+unsafe extern "C" fn range_push_w(message: *const WideChar) -> c_int {
+    push(wide_string(message).unwrap_or_default(), None)
+}
+
+extern "C" fn range_pop() -> c_int {
+    pop()
+}
+
+unsafe extern "C" fn domain_range_start_ex(domain: *const c_void, attributes: *const EventAttributes) -> u64 {
+    start(attributes_message(attributes), domain_name(domain))
+}
+
+extern "C" fn domain_range_end(_domain: *const c_void, id: u64) {
+    send(Event::End { id });
+}
+
+unsafe extern "C" fn domain_range_push_ex(domain: *const c_void, attributes: *const EventAttributes) -> c_int {
+    push(attributes_message(attributes), domain_name(domain))
+}
+
+extern "C" fn domain_range_pop(_domain: *const c_void) -> c_int {
+    pop()
+}
+
+unsafe extern "C" fn domain_register_string_a(_domain: *const c_void, value: *const c_char) -> *const c_char {
+    leak(c_string(value))
+}
+
+unsafe extern "C" fn domain_register_string_w(_domain: *const c_void, value: *const WideChar) -> *const c_char {
+    leak(wide_string(value))
+}
+
+unsafe extern "C" fn domain_create_a(name: *const c_char) -> *const c_char {
+    leak(c_string(name))
+}
+
+unsafe extern "C" fn domain_create_w(name: *const WideChar) -> *const c_char {
+    leak(wide_string(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    const TABLE_SIZE: usize = 16;
+
+    // Function slots of a fake NVTX runtime, as the headers lay them out
+    static SLOTS: Mutex<[[Option<FunctionPointer>; TABLE_SIZE]; 2]> = Mutex::new([[None; TABLE_SIZE]; 2]);
+    static mut TABLES: [[*mut Option<FunctionPointer>; TABLE_SIZE]; 2] = [[std::ptr::null_mut(); TABLE_SIZE]; 2];
+
+    unsafe extern "C" fn fake_get_module_function_table(module: c_int, table: *mut FunctionTable, size: *mut c_uint) -> c_int {
+        let index = match module {
+            MODULE_CORE => 0,
+            MODULE_CORE2 => 1,
+            _ => return 0,
+        };
+        *table = std::ptr::addr_of_mut!(TABLES[index]) as FunctionTable;
+        *size = (TABLE_SIZE - 1) as c_uint;
+        1
+    }
+
+    static CALLBACKS: ExportTableCallbacks = ExportTableCallbacks {
+        struct_size: std::mem::size_of::<ExportTableCallbacks>(),
+        get_module_function_table: Some(fake_get_module_function_table),
+    };
+
+    unsafe extern "C" fn fake_get_export_table(id: u32) -> *const c_void {
+        if id == EXPORT_TABLE_CALLBACKS {
+            &CALLBACKS as *const ExportTableCallbacks as *const c_void
+        } else {
+            std::ptr::null()
+        }
+    }
+
+    #[test]
+    fn test_event_json_matches_listener_format() {
+        let push = Event::Push { name: "step \"1\"".to_string(), domain: Some("train".to_string()) };
+        assert_eq!(
+            push.to_json(42, 3, 1_000),
+            r#"{"type":"push","name":"step \"1\"","pid":42,"tid":3,"domain":"train","timestamp_ns":1000}"#
+        );
+        assert_eq!(Event::End { id: 7 }.to_json(42, 3, 5), r#"{"type":"end","id":7,"pid":42,"timestamp_ns":5}"#);
+    }
+
+    #[test]
+    fn test_injected_callbacks_send_ranges() {
+        let listener = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        listener.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        std::env::set_var("NSIGHTFUL_MARKER_PORT", listener.local_addr().unwrap().port().to_string());
+
+        let mut slots = SLOTS.lock().unwrap();
+        unsafe {
+            for (module, tables) in slots.iter_mut().enumerate() {
+                for (id, slot) in tables.iter_mut().enumerate() {
+                    TABLES[module][id] = slot as *mut Option<FunctionPointer>;
+                }
+            }
+            assert_eq!(InitializeInjectionNvtx2(Some(fake_get_export_table)), 1);
+        }
+
+        // Call through the installed slots as the NVTX headers would
+        let push_a: unsafe extern "C" fn(*const c_char) -> c_int = unsafe { std::mem::transmute(slots[0][CORE_RANGE_PUSH_A].unwrap()) };
+        let pop: extern "C" fn() -> c_int = unsafe { std::mem::transmute(slots[0][CORE_RANGE_POP].unwrap()) };
+        let create_domain: unsafe extern "C" fn(*const c_char) -> *const c_char = unsafe { std::mem::transmute(slots[1][CORE2_DOMAIN_CREATE_A].unwrap()) };
+        let domain_start: unsafe extern "C" fn(*const c_void, *const EventAttributes) -> u64 = unsafe { std::mem::transmute(slots[1][CORE2_DOMAIN_RANGE_START_EX].unwrap()) };
+        drop(slots);
+
+        let name = CString::new("forward pass").unwrap();
+        assert_eq!(unsafe { push_a(name.as_ptr()) }, 0);
+        assert_eq!(pop(), 0);
+        assert_eq!(pop(), -1);
+        let domain = unsafe { create_domain(c"train".as_ptr()) };
+        let attributes = EventAttributes {
+            version: 2, size: std::mem::size_of::<EventAttributes>() as u16, category: 0, color_type: 0, color: 0,
+            payload_type: 0, reserved0: 0, payload: 0, message_type: MESSAGE_ASCII, message: c"epoch".as_ptr() as *const c_void,
+        };
+        let id = unsafe { domain_start(domain as *const c_void, &attributes) };
+
+        let mut received = Vec::new();
+        let mut buffer = [0u8; 1024];
+        for _ in 0..3 {
+            let length = listener.recv(&mut buffer).unwrap();
+            received.push(String::from_utf8(buffer[..length].to_vec()).unwrap());
+        }
+        assert!(received[0].starts_with(r#"{"type":"push","name":"forward pass","pid":"#), "{}", received[0]);
+        assert!(received[1].starts_with(r#"{"type":"pop","pid":"#), "{}", received[1]);
+        assert!(received[2].starts_with(&format!(r#"{{"type":"start","id":{},"name":"epoch","pid":{},"domain":"train""#, id, std::process::id())), "{}", received[2]);
+    }
+}
@@ -0,0 +1,592 @@
+//! Health alerts pushed to webhooks
+//!
+//! The alert monitor re-runs the health check on the watched devices at a
+//! fixed interval and raises an alert whenever a check reaches the
+//! configured severity (e.g. the temperature nearing its slowdown threshold
+//! or ECC pages pending retirement), changes severity, or recovers. Alerts
+//! are emitted to the frontend as `gpu-alert` and POSTed to each configured
+//! webhook, formatted for Slack, Discord or as plain JSON.
+//!
+//! Requests are made with the system `curl`, which handles HTTPS and
+//! proxies without the app carrying its own TLS stack. Each webhook has its
+//! own delivery queue and task, so neither the health checks nor the other
+//! webhooks wait on a slow one. Failed deliveries are retried with
+//! exponential backoff.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::Nvml;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tauri::Window;
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+
+use crate::error::AppError;
+use crate::health::{self, CheckStatus, HealthReport};
+use crate::nvml;
+use crate::sessions;
+
+/// Check interval used when none is requested
+pub const DEFAULT_INTERVAL_SECONDS: u64 = 60;
+/// Shortest check interval; each check samples throttling for about a second
+pub const MIN_INTERVAL_SECONDS: u64 = 10;
+/// Delivery attempts per webhook before an alert is given up on
+const MAX_ATTEMPTS: u32 = 4;
+/// Wait before the first retry; doubled for every further retry
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+/// Time limit of one webhook request
+const REQUEST_TIMEOUT_SECONDS: u64 = 10;
+/// Deliveries kept in the monitor status
+const RECENT_DELIVERIES: usize = 20;
+/// Alerts waiting for delivery per webhook; further alerts are dropped as failed deliveries
+const DELIVERY_QUEUE: usize = 32;
+
+/// Payload format expected by a webhook
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookKind {
+    /// Slack incoming webhook (`{"text": ...}`)
+    Slack,
+    /// Discord webhook (`{"content": ...}`)
+    Discord,
+    /// The alert itself as JSON
+    Generic,
+}
+
+/// A webhook alerts are sent to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Webhook {
+    pub url: String,
+    pub kind: WebhookKind,
+}
+
+/// Settings of the alert monitor
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AlertConfig {
+    pub webhooks: Vec<Webhook>,
+    /// Devices to watch; every device if empty
+    #[serde(default)]
+    pub device_indices: Vec<u32>,
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+    /// Lowest check severity that raises an alert
+    #[serde(default = "default_min_status")]
+    pub min_status: CheckStatus,
+}
+
+fn default_interval_seconds() -> u64 {
+    DEFAULT_INTERVAL_SECONDS
+}
+
+fn default_min_status() -> CheckStatus {
+    CheckStatus::Warn
+}
+
+/// A change in a device's health
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Alert {
+    /// Machine the alert comes from
+    pub host: String,
+    pub device_index: u32,
+    pub device_name: String,
+    /// Health check that changed, e.g. "temperature" or "ecc_retirements"
+    pub check: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// The check dropped back below the alert severity
+    pub resolved: bool,
+    pub timestamp_ms: u64,
+}
+
+/// Outcome of sending one alert to one webhook
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Delivery {
+    pub url: String,
+    pub check: String,
+    pub device_index: u32,
+    pub attempts: u32,
+    /// Last error; `None` once delivered
+    pub error: Option<String>,
+}
+
+/// State of the alert monitor
+#[derive(Serialize, Clone, Debug)]
+pub struct AlertStatus {
+    pub running: bool,
+    pub config: AlertConfig,
+    /// Time of the last completed check round (Unix milliseconds)
+    pub last_check_ms: Option<u64>,
+    pub alerts_raised: u64,
+    pub deliveries_failed: u64,
+    /// Most recent deliveries, newest last
+    pub recent_deliveries: Vec<Delivery>,
+}
+
+// Current or most recent monitor; kept after it stops so its counters can be read
+static ALERT_STATE: std::sync::RwLock<Option<AlertStatus>> = std::sync::RwLock::new(None);
+
+// Cancelled to stop the monitor, even mid-wait
+static ALERT_CANCEL: std::sync::Mutex<Option<CancellationToken>> = std::sync::Mutex::new(None);
+
+// Handle to the background task, used to wait for pending deliveries
+static ALERT_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+// Raised alerts, for in-process consumers such as automation hooks
+static ALERT_EVENTS: OnceLock<broadcast::Sender<Alert>> = OnceLock::new();
+
+fn alert_events() -> &'static broadcast::Sender<Alert> {
+    ALERT_EVENTS.get_or_init(|| broadcast::channel(64).0)
+}
+
+/// Receive alerts as the monitor raises them
+pub fn subscribe_alerts() -> broadcast::Receiver<Alert> {
+    alert_events().subscribe()
+}
+
+/// Start watching device health and pushing alerts to webhooks
+///
+/// # Arguments
+/// * `config` - Webhooks, devices, interval and alert severity
+/// * `window` - Tauri window handle for `gpu-alert` events
+///
+/// # Returns
+/// * `Result<AlertStatus>` - Status of the started monitor or error for an invalid config
+pub async fn start_alert_monitor(config: AlertConfig, window: Window) -> Result<AlertStatus> {
+    validate(&config)?;
+    let status = AlertStatus {
+        running: true,
+        config: config.clone(),
+        last_check_ms: None,
+        alerts_raised: 0,
+        deliveries_failed: 0,
+        recent_deliveries: Vec::new(),
+    };
+    let claim = sessions::claim(&ALERT_STATE, |status| status.running, status.clone(), "The alert monitor is already running")?;
+    let device_indices = if config.device_indices.is_empty() {
+        let count = nvml::blocking(|| {
+            let nvml = Nvml::init().context("Failed to initialize NVML")?;
+            nvml.device_count().context("Failed to get device count")
+        }).await?;
+        (0..count).collect()
+    } else {
+        config.device_indices.clone()
+    };
+
+    let status = claim.commit(status);
+    let cancel = CancellationToken::new();
+    *ALERT_CANCEL.lock().unwrap() = Some(cancel.clone());
+
+    let task = tokio::spawn(async move {
+        let (queues, workers): (Vec<_>, Vec<_>) = config.webhooks.iter()
+            .map(|webhook| spawn_delivery_worker(webhook.clone(), cancel.clone()))
+            .unzip();
+        let mut tracker = AlertTracker::new(config.min_status);
+        let host = host_name();
+        let mut interval = tokio::time::interval(Duration::from_secs(config.interval_seconds));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+
+            let mut alerts = Vec::new();
+            for &device_index in &device_indices {
+                match health::run_health_check(Some(device_index)).await {
+                    Ok(report) => alerts.extend(tracker.observe(&report, &host)),
+                    Err(e) => eprintln!("Alert monitor could not check GPU {}: {:#}", device_index, e),
+                }
+            }
+            if let Some(status) = ALERT_STATE.write().unwrap().as_mut() {
+                status.last_check_ms = Some(nvml::now_ms() as u64);
+                status.alerts_raised += alerts.len() as u64;
+            }
+
+            for alert in alerts {
+                if let Err(e) = window.emit("gpu-alert", &alert) {
+                    eprintln!("Failed to emit alert event: {}", e);
+                }
+                // No receivers just means nothing is listening in-process
+                let _ = alert_events().send(alert.clone());
+                for (webhook, queue) in config.webhooks.iter().zip(&queues) {
+                    if queue.try_send(alert.clone()).is_err() {
+                        eprintln!("Delivery queue of {} is full; dropping alert", webhook.url);
+                        record_delivery(Delivery {
+                            url: webhook.url.clone(),
+                            check: alert.check.clone(),
+                            device_index: alert.device_index,
+                            attempts: 0,
+                            error: Some("Delivery queue full; alert dropped".to_string()),
+                        });
+                    }
+                }
+            }
+        }
+
+        drop(queues);
+        for worker in workers {
+            if let Err(e) = worker.await {
+                eprintln!("Alert delivery task ended abnormally: {}", e);
+            }
+        }
+        if let Some(status) = ALERT_STATE.write().unwrap().as_mut() {
+            status.running = false;
+        }
+    });
+    *ALERT_TASK.lock().unwrap() = Some(task);
+
+    Ok(status)
+}
+
+/// Stop the alert monitor
+///
+/// # Returns
+/// * `Result<AlertStatus>` - Final status or error if the monitor is not running
+pub async fn stop_alert_monitor() -> Result<AlertStatus> {
+    if !ALERT_STATE.read().unwrap().as_ref().is_some_and(|status| status.running) {
+        return Err(AppError::InvalidArgument("The alert monitor is not running".to_string()).into());
+    }
+    finish_active_alert_monitor().await?;
+    get_alert_status().context("Alert monitor state missing after stop")
+}
+
+/// Stop any running alert monitor and wait for it to exit
+pub async fn finish_active_alert_monitor() -> Result<()> {
+    if let Some(cancel) = ALERT_CANCEL.lock().unwrap().take() {
+        cancel.cancel();
+    }
+    let task = ALERT_TASK.lock().unwrap().take();
+    if let Some(task) = task {
+        task.await.context("Alert monitor task ended abnormally")?;
+    }
+    Ok(())
+}
+
+/// Get the status of the current or most recent alert monitor
+///
+/// # Returns
+/// * `Option<AlertStatus>` - Status, or `None` if the monitor has not been started
+pub fn get_alert_status() -> Option<AlertStatus> {
+    ALERT_STATE.read().unwrap().clone()
+}
+
+/// Send a test alert to a webhook
+///
+/// # Arguments
+/// * `webhook` - Webhook to try
+///
+/// # Returns
+/// * `Result<Delivery>` - Outcome of the delivery or error for an invalid URL
+pub async fn send_test_alert(webhook: Webhook) -> Result<Delivery> {
+    validate_url(&webhook.url)?;
+    let alert = Alert {
+        host: host_name(),
+        device_index: 0,
+        device_name: "Test".to_string(),
+        check: "test".to_string(),
+        status: CheckStatus::Pass,
+        detail: "Test alert from NSightful".to_string(),
+        resolved: false,
+        timestamp_ms: nvml::now_ms() as u64,
+    };
+    Ok(deliver(&webhook, &alert, &CancellationToken::new()).await)
+}
+
+fn validate(config: &AlertConfig) -> Result<()> {
+    if config.webhooks.is_empty() {
+        return Err(AppError::InvalidArgument("At least one webhook is required".to_string()).into());
+    }
+    if config.interval_seconds < MIN_INTERVAL_SECONDS {
+        return Err(AppError::InvalidArgument(format!(
+            "Alert check interval must be at least {} seconds", MIN_INTERVAL_SECONDS
+        )).into());
+    }
+    if config.min_status <= CheckStatus::Pass {
+        return Err(AppError::InvalidArgument("Alert severity must be warn or fail".to_string()).into());
+    }
+    config.webhooks.iter().try_for_each(|webhook| validate_url(&webhook.url))
+}
+
+/// Reject anything but plain web URLs, which curl could read as an option or a local file
+pub fn validate_url(url: &str) -> Result<()> {
+    if url.starts_with("https://") || url.starts_with("http://") {
+        Ok(())
+    } else {
+        Err(AppError::InvalidArgument(format!("URL must start with https:// or http://, got {:?}", url)).into())
+    }
+}
+
+/// Turns health reports into alerts on severity changes
+struct AlertTracker {
+    min_status: CheckStatus,
+    last: HashMap<(u32, String), CheckStatus>,
+}
+
+impl AlertTracker {
+    fn new(min_status: CheckStatus) -> Self {
+        AlertTracker { min_status, last: HashMap::new() }
+    }
+
+    // Alerts for checks that reached, changed within, or left the alert severity
+    fn observe(&mut self, report: &HealthReport, host: &str) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for check in report.checks.iter().filter(|check| check.status != CheckStatus::Skipped) {
+            let previous = self.last.insert((report.device_index, check.name.clone()), check.status);
+            let alerting = check.status >= self.min_status;
+            let was_alerting = previous.is_some_and(|status| status >= self.min_status);
+            if (alerting && previous != Some(check.status)) || (was_alerting && !alerting) {
+                alerts.push(Alert {
+                    host: host.to_string(),
+                    device_index: report.device_index,
+                    device_name: report.name.clone(),
+                    check: check.name.clone(),
+                    status: check.status,
+                    detail: check.detail.clone(),
+                    resolved: !alerting,
+                    timestamp_ms: nvml::now_ms() as u64,
+                });
+            }
+        }
+        alerts
+    }
+}
+
+// Body of the webhook request for an alert
+fn payload(kind: WebhookKind, alert: &Alert) -> serde_json::Value {
+    let text = if alert.resolved {
+        format!(
+            "Resolved: GPU {} ({}) on {}: {} is back to normal ({})",
+            alert.device_index, alert.device_name, alert.host, alert.check, alert.detail
+        )
+    } else {
+        format!(
+            "{:?}: GPU {} ({}) on {}: {}: {}",
+            alert.status, alert.device_index, alert.device_name, alert.host, alert.check, alert.detail
+        )
+    };
+    match kind {
+        WebhookKind::Slack => json!({ "text": text }),
+        WebhookKind::Discord => json!({ "content": text }),
+        WebhookKind::Generic => json!({ "summary": text, "alert": alert }),
+    }
+}
+
+// Wait before retry `attempt` (1 for the first retry)
+fn retry_delay(attempt: u32) -> Duration {
+    RETRY_BASE_DELAY * 2u32.pow(attempt.saturating_sub(1))
+}
+
+// Deliver the alerts queued for one webhook, in order, until the queue
+// closes or the monitor is cancelled; alerts still queued then are dropped
+fn spawn_delivery_worker(webhook: Webhook, cancel: CancellationToken) -> (mpsc::Sender<Alert>, tokio::task::JoinHandle<()>) {
+    let (queue, mut pending) = mpsc::channel::<Alert>(DELIVERY_QUEUE);
+    let worker = tokio::spawn(async move {
+        loop {
+            let alert = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => break,
+                alert = pending.recv() => match alert {
+                    Some(alert) => alert,
+                    None => break,
+                },
+            };
+            let delivery = deliver(&webhook, &alert, &cancel).await;
+            if let Some(error) = &delivery.error {
+                eprintln!("Failed to deliver alert to {}: {}", webhook.url, error);
+            }
+            record_delivery(delivery);
+        }
+    });
+    (queue, worker)
+}
+
+// POST an alert, retrying with backoff until delivered, out of attempts, or cancelled
+async fn deliver(webhook: &Webhook, alert: &Alert, cancel: &CancellationToken) -> Delivery {
+    let body = payload(webhook.kind, alert).to_string();
+    let mut delivery = Delivery {
+        url: webhook.url.clone(),
+        check: alert.check.clone(),
+        device_index: alert.device_index,
+        attempts: 0,
+        error: None,
+    };
+    while delivery.attempts < MAX_ATTEMPTS {
+        if delivery.attempts > 0 {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = tokio::time::sleep(retry_delay(delivery.attempts)) => {}
+            }
+        }
+        delivery.attempts += 1;
+        let (url, body) = (webhook.url.clone(), body.clone());
+        let result = tokio::task::spawn_blocking(move || post_json(&url, &[], &body)).await
+            .context("Webhook request panicked")
+            .and_then(|result| result);
+        match result {
+            Ok(()) => {
+                delivery.error = None;
+                break;
+            }
+            Err(e) => delivery.error = Some(format!("{:#}", e)),
+        }
+    }
+    delivery
+}
+
+/// POST a JSON body with the system `curl`
+///
+/// The URL, headers and body go to curl as a config file on stdin rather
+/// than as arguments, so webhook secrets and authorization headers are not
+/// visible to other users in the process list.
+///
+/// # Arguments
+/// * `url` - Destination, checked with `validate_url`
+/// * `headers` - Extra headers as `Name: value`
+/// * `body` - JSON to send
+///
+/// # Returns
+/// * `Result<()>` - Success or error with curl's message if the request failed
+pub fn post_json(url: &str, headers: &[String], body: &str) -> Result<()> {
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time", &REQUEST_TIMEOUT_SECONDS.to_string()])
+        .args(["--request", "POST", "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => AppError::NotSupported("curl is required to send alerts and metrics".to_string()).into(),
+            _ => anyhow::Error::new(e).context("Failed to run curl"),
+        })?;
+    child.stdin.take().context("curl stdin unavailable")?
+        .write_all(curl_config(url, headers, body).as_bytes())
+        .context("Failed to send request to curl")?;
+    let output = child.wait_with_output().context("Failed to wait for curl")?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+// curl config for a JSON POST; `data-raw` sends the body as is, even if
+// it starts with `@`
+fn curl_config(url: &str, headers: &[String], body: &str) -> String {
+    let mut config = String::new();
+    let json_header = "Content-Type: application/json".to_string();
+    for header in std::iter::once(&json_header).chain(headers) {
+        config.push_str(&config_line("header", header));
+    }
+    config.push_str(&config_line("data-raw", body));
+    config.push_str(&config_line("url", url));
+    config
+}
+
+// One `option = "value"` line, with the escapes curl understands inside quotes
+fn config_line(option: &str, value: &str) -> String {
+    let mut line = format!("{} = \"", option);
+    for c in value.chars() {
+        match c {
+            '\\' => line.push_str("\\\\"),
+            '"' => line.push_str("\\\""),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            '\x0b' => line.push_str("\\v"),
+            c => line.push(c),
+        }
+    }
+    line.push_str("\"\n");
+    line
+}
+
+fn record_delivery(delivery: Delivery) {
+    if let Some(status) = ALERT_STATE.write().unwrap().as_mut() {
+        if delivery.error.is_some() {
+            status.deliveries_failed += 1;
+        }
+        status.recent_deliveries.push(delivery);
+        let excess = status.recent_deliveries.len().saturating_sub(RECENT_DELIVERIES);
+        status.recent_deliveries.drain(..excess);
+    }
+}
+
+/// Name of this machine, as reported in alerts and exported metrics
+pub fn host_name() -> String {
+    std::env::var("HOSTNAME").or_else(|_| std::env::var("COMPUTERNAME")).ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown host".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::HealthCheck;
+
+    fn report(temperature: CheckStatus, ecc: CheckStatus) -> HealthReport {
+        let check = |name: &str, status| HealthCheck { name: name.to_string(), status, detail: format!("{} detail", name) };
+        HealthReport {
+            device_index: 1,
+            name: "Test GPU".to_string(),
+            overall: temperature.max(ecc),
+            checks: vec![check("temperature", temperature), check("ecc_retirements", ecc)],
+        }
+    }
+
+    #[test]
+    fn test_alerts_on_severity_changes_and_recovery() {
+        let mut tracker = AlertTracker::new(CheckStatus::Warn);
+        assert!(tracker.observe(&report(CheckStatus::Pass, CheckStatus::Skipped), "host").is_empty());
+
+        let raised = tracker.observe(&report(CheckStatus::Warn, CheckStatus::Skipped), "host");
+        assert_eq!(raised.len(), 1);
+        assert_eq!((raised[0].check.as_str(), raised[0].status, raised[0].resolved), ("temperature", CheckStatus::Warn, false));
+        assert!(tracker.observe(&report(CheckStatus::Warn, CheckStatus::Skipped), "host").is_empty());
+
+        let escalated = tracker.observe(&report(CheckStatus::Fail, CheckStatus::Fail), "host");
+        assert_eq!(escalated.len(), 2);
+        let resolved = tracker.observe(&report(CheckStatus::Pass, CheckStatus::Fail), "host");
+        assert_eq!((resolved.len(), resolved[0].resolved), (1, true));
+    }
+
+    #[test]
+    fn test_payload_per_webhook_kind() {
+        let alert = AlertTracker::new(CheckStatus::Warn).observe(&report(CheckStatus::Fail, CheckStatus::Pass), "lab-01").remove(0);
+        let slack = payload(WebhookKind::Slack, &alert);
+        assert_eq!(slack["text"], "Fail: GPU 1 (Test GPU) on lab-01: temperature: temperature detail");
+        assert_eq!(payload(WebhookKind::Discord, &alert)["content"], slack["text"]);
+        let generic = payload(WebhookKind::Generic, &alert);
+        assert_eq!((generic["alert"]["check"].as_str(), generic["alert"]["status"].as_str()), (Some("temperature"), Some("fail")));
+    }
+
+    #[test]
+    fn test_curl_config_keeps_secrets_off_the_command_line() {
+        let headers = vec!["Authorization: Bearer \"quoted\" token".to_string()];
+        let config = curl_config("https://hooks.example.com/secret", &headers, r#"{"text":"C:\\gpu\n"}"#);
+        assert_eq!(config, concat!(
+            "header = \"Content-Type: application/json\"\n",
+            "header = \"Authorization: Bearer \\\"quoted\\\" token\"\n",
+            "data-raw = \"{\\\"text\\\":\\\"C:\\\\\\\\gpu\\\\n\\\"}\"\n",
+            "url = \"https://hooks.example.com/secret\"\n",
+        ));
+    }
+
+    #[test]
+    fn test_config_validation_and_backoff() {
+        let config: AlertConfig = serde_json::from_str(r#"{"webhooks": [{"url": "https://hooks.example.com/x", "kind": "slack"}]}"#).unwrap();
+        assert_eq!((config.interval_seconds, config.min_status), (DEFAULT_INTERVAL_SECONDS, CheckStatus::Warn));
+        assert!(validate(&config).is_ok());
+
+        let local_file = AlertConfig { webhooks: vec![Webhook { url: "file:///etc/passwd".to_string(), kind: WebhookKind::Generic }], ..config.clone() };
+        for invalid in [local_file, AlertConfig { interval_seconds: 1, ..config.clone() }, AlertConfig { webhooks: Vec::new(), ..config }] {
+            assert_eq!(AppError::from(validate(&invalid).unwrap_err()).code(), "INVALID_ARGUMENT");
+        }
+        assert_eq!((1..MAX_ATTEMPTS).map(retry_delay).collect::<Vec<_>>(), vec![Duration::from_secs(1), Duration::from_secs(2), Duration::from_secs(4)]);
+    }
+}
@@ -0,0 +1,418 @@
+//! Device memory allocation timelines
+//!
+//! Ties VRAM spikes to allocations. NSight Systems reports collected with
+//! `--cuda-memory-usage=true` contain every allocation and free made
+//! through the CUDA driver, with size and address. A running process cannot
+//! be instrumented from outside, so live tracking polls the per-process
+//! memory NVML reports and turns each change into an inferred allocation or
+//! free; several allocations between two polls show up as one event.
+//! Timestamps are Unix milliseconds in both cases, on the same clock as
+//! recordings.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::enums::device::UsedGpuMemory;
+use nvml_wrapper::Nvml;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::capabilities::{self, Capability};
+use crate::error::AppError;
+use crate::nsys;
+use crate::nvml;
+
+/// Polling rate used when live tracking does not specify one
+pub const DEFAULT_SAMPLE_RATE_HZ: u64 = 10;
+/// Highest live polling rate
+pub const MAX_SAMPLE_RATE_HZ: u64 = 100;
+/// Most events kept in a timeline; later events only update usage and peaks
+pub const MAX_EVENTS: usize = 100_000;
+
+const MEMORY_QUERY: &str = "SELECT start, deviceId AS device_id, bytes, memoryOperationType AS operation, \
+    address, globalPid AS global_pid FROM CUDA_GPU_MEMORY_USAGE_EVENTS ORDER BY start";
+const SESSION_START_QUERY: &str = "SELECT utcEpochNs AS utc_epoch_ns FROM TARGET_INFO_SESSION_START_TIME";
+
+/// Where the events of a timeline come from
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AllocationSource {
+    /// Exact driver events from an NSight Systems report
+    Report,
+    /// Changes in NVML per-process memory usage
+    Inferred,
+}
+
+/// Direction of a memory event
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AllocationKind {
+    Alloc,
+    Free,
+}
+
+/// One allocation or free
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AllocationEvent {
+    pub timestamp_ms: f64,
+    pub device_index: u32,
+    pub pid: u32,
+    pub kind: AllocationKind,
+    pub bytes: u64,
+    /// Device address; only known for report events
+    pub address: Option<u64>,
+    /// Memory in use on the device by the tracked processes after the event
+    pub in_use_bytes: u64,
+}
+
+/// Highest memory use of one device
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct DevicePeak {
+    pub device_index: u32,
+    pub peak_bytes: u64,
+    pub peak_at_ms: f64,
+    /// Memory in use at the end of the timeline
+    pub in_use_bytes: u64,
+}
+
+/// Allocations and frees in time order, with per-device peaks
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AllocationTimeline {
+    pub source: AllocationSource,
+    /// Process the timeline is limited to; every process if unset
+    pub pid: Option<u32>,
+    pub events: Vec<AllocationEvent>,
+    pub peaks: Vec<DevicePeak>,
+    /// Events beyond `MAX_EVENTS` were dropped
+    pub truncated: bool,
+}
+
+impl AllocationTimeline {
+    fn new(source: AllocationSource, pid: Option<u32>) -> Self {
+        AllocationTimeline { source, pid, events: Vec::new(), peaks: Vec::new(), truncated: false }
+    }
+
+    /// Add an event, updating the device's memory in use and peak
+    pub fn push(&mut self, timestamp_ms: f64, device_index: u32, pid: u32, kind: AllocationKind, bytes: u64, address: Option<u64>) {
+        let position = match self.peaks.iter().position(|peak| peak.device_index == device_index) {
+            Some(position) => position,
+            None => {
+                self.peaks.push(DevicePeak { device_index, peak_bytes: 0, peak_at_ms: timestamp_ms, in_use_bytes: 0 });
+                self.peaks.len() - 1
+            }
+        };
+        let peak = &mut self.peaks[position];
+        peak.in_use_bytes = match kind {
+            AllocationKind::Alloc => peak.in_use_bytes.saturating_add(bytes),
+            AllocationKind::Free => peak.in_use_bytes.saturating_sub(bytes),
+        };
+        if peak.in_use_bytes > peak.peak_bytes {
+            peak.peak_bytes = peak.in_use_bytes;
+            peak.peak_at_ms = timestamp_ms;
+        }
+        let in_use_bytes = peak.in_use_bytes;
+
+        if self.events.len() >= MAX_EVENTS {
+            self.truncated = true;
+            return;
+        }
+        self.events.push(AllocationEvent { timestamp_ms, device_index, pid, kind, bytes, address, in_use_bytes });
+    }
+
+    /// Copy of the timeline with only the events in `[start_ms, end_ms]`; peaks cover the whole timeline
+    pub fn between(&self, start_ms: f64, end_ms: f64) -> AllocationTimeline {
+        AllocationTimeline {
+            events: self.events.iter()
+                .filter(|event| (start_ms..=end_ms).contains(&event.timestamp_ms))
+                .cloned()
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Turns successive per-process memory readings into allocation events
+#[derive(Default)]
+pub struct UsageTracker {
+    last: HashMap<(u32, u32), u64>,
+}
+
+impl UsageTracker {
+    /// Compare a reading with the previous one
+    ///
+    /// A process seen for the first time allocates everything it holds; a
+    /// process that is gone frees everything it held.
+    ///
+    /// # Arguments
+    /// * `timestamp_ms` - Time of the reading
+    /// * `usage` - `(device_index, pid, bytes)` of every tracked process
+    /// * `timeline` - Timeline to add the events to
+    pub fn observe(&mut self, timestamp_ms: f64, usage: &[(u32, u32, u64)], timeline: &mut AllocationTimeline) {
+        let current: HashMap<(u32, u32), u64> = usage.iter()
+            .map(|&(device_index, pid, bytes)| ((device_index, pid), bytes))
+            .collect();
+        let mut keys: Vec<(u32, u32)> = current.keys().chain(self.last.keys()).copied().collect();
+        keys.sort_unstable();
+        keys.dedup();
+        for (device_index, pid) in keys {
+            let before = self.last.get(&(device_index, pid)).copied().unwrap_or(0);
+            let after = current.get(&(device_index, pid)).copied().unwrap_or(0);
+            if after > before {
+                timeline.push(timestamp_ms, device_index, pid, AllocationKind::Alloc, after - before, None);
+            } else if after < before {
+                timeline.push(timestamp_ms, device_index, pid, AllocationKind::Free, before - after, None);
+            }
+        }
+        self.last = current;
+    }
+}
+
+/// Live tracking state
+#[derive(Serialize, Clone, Debug)]
+pub struct AllocationTracking {
+    pub running: bool,
+    pub sample_rate_hz: u64,
+    pub timeline: AllocationTimeline,
+}
+
+// Current or most recent live tracking; kept after it stops so its timeline can be read
+static TRACKING_STATE: std::sync::RwLock<Option<AllocationTracking>> = std::sync::RwLock::new(None);
+
+// Cancelled to stop live tracking
+static TRACKING_CANCEL: std::sync::Mutex<Option<CancellationToken>> = std::sync::Mutex::new(None);
+
+// Handle to the polling task, used to wait for it to exit
+static TRACKING_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+/// Start tracking allocations through NVML process memory
+///
+/// # Arguments
+/// * `pid` - Process to track; every GPU process if unset
+/// * `sample_rate_hz` - Polling frequency
+///
+/// # Returns
+/// * `Result<AllocationTracking>` - Initial state or error if tracking is running or the rate is out of range
+pub async fn start_tracking(pid: Option<u32>, sample_rate_hz: u64) -> Result<AllocationTracking> {
+    if !(1..=MAX_SAMPLE_RATE_HZ).contains(&sample_rate_hz) {
+        return Err(AppError::InvalidArgument(format!(
+            "Sample rate must be between 1 and {} Hz, got {}",
+            MAX_SAMPLE_RATE_HZ, sample_rate_hz
+        )).into());
+    }
+    if TRACKING_STATE.read().unwrap().as_ref().is_some_and(|tracking| tracking.running) {
+        return Err(AppError::InvalidArgument("Allocation tracking is already running".to_string()).into());
+    }
+    // Fail now rather than in the background if NVML is unavailable
+    nvml::blocking(|| Nvml::init().map(drop).context("Failed to initialize NVML")).await?;
+
+    let tracking = AllocationTracking {
+        running: true,
+        sample_rate_hz,
+        timeline: AllocationTimeline::new(AllocationSource::Inferred, pid),
+    };
+    *TRACKING_STATE.write().unwrap() = Some(tracking.clone());
+    let cancel = CancellationToken::new();
+    *TRACKING_CANCEL.lock().unwrap() = Some(cancel.clone());
+
+    let task = tokio::spawn(async move {
+        let mut tracker = UsageTracker::default();
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / sample_rate_hz as f64));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            let usage = match nvml::blocking(move || read_process_memory(pid)).await {
+                Ok(usage) => usage,
+                Err(e) => {
+                    eprintln!("Allocation tracking could not read process memory: {:#}", e);
+                    continue;
+                }
+            };
+            if let Some(tracking) = TRACKING_STATE.write().unwrap().as_mut() {
+                tracker.observe(nvml::now_ms() as f64, &usage, &mut tracking.timeline);
+            }
+        }
+        if let Some(tracking) = TRACKING_STATE.write().unwrap().as_mut() {
+            tracking.running = false;
+        }
+    });
+    *TRACKING_TASK.lock().unwrap() = Some(task);
+
+    Ok(tracking)
+}
+
+/// Stop live tracking
+///
+/// # Returns
+/// * `Result<AllocationTracking>` - Final state or error if tracking is not running
+pub async fn stop_tracking() -> Result<AllocationTracking> {
+    if !TRACKING_STATE.read().unwrap().as_ref().is_some_and(|tracking| tracking.running) {
+        return Err(AppError::InvalidArgument("Allocation tracking is not running".to_string()).into());
+    }
+    finish_active_tracking().await?;
+    TRACKING_STATE.read().unwrap().clone().context("Allocation tracking state missing after stop")
+}
+
+/// Stop any live tracking and wait for it to exit
+pub async fn finish_active_tracking() -> Result<()> {
+    if let Some(cancel) = TRACKING_CANCEL.lock().unwrap().take() {
+        cancel.cancel();
+    }
+    let task = TRACKING_TASK.lock().unwrap().take();
+    if let Some(task) = task {
+        task.await.context("Allocation tracking task ended abnormally")?;
+    }
+    Ok(())
+}
+
+/// Get the live timeline, optionally limited to a time window
+///
+/// # Arguments
+/// * `start_ms` - Earliest event to include (Unix milliseconds)
+/// * `end_ms` - Latest event to include (Unix milliseconds)
+///
+/// # Returns
+/// * `Option<AllocationTracking>` - State, or `None` if tracking has not been started
+pub fn get_tracking(start_ms: Option<f64>, end_ms: Option<f64>) -> Option<AllocationTracking> {
+    let mut tracking = TRACKING_STATE.read().unwrap().clone()?;
+    if start_ms.is_some() || end_ms.is_some() {
+        tracking.timeline = tracking.timeline.between(start_ms.unwrap_or(f64::MIN), end_ms.unwrap_or(f64::MAX));
+    }
+    Some(tracking)
+}
+
+// `(device_index, pid, bytes)` of the tracked processes on every device
+fn read_process_memory(pid: Option<u32>) -> Result<Vec<(u32, u32, u64)>> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let mut usage = Vec::new();
+    for (index, device) in nvml::list_devices(&nvml)?.into_iter().enumerate() {
+        if capabilities::known_unavailable(index as u32, Capability::ProcessList) {
+            continue;
+        }
+        let listed = device.running_compute_processes().into_iter()
+            .chain(device.running_graphics_processes())
+            .flatten();
+        for info in listed.filter(|info| pid.is_none_or(|pid| info.pid == pid)) {
+            if let UsedGpuMemory::Used(bytes) = info.used_gpu_memory {
+                usage.push((index as u32, info.pid, bytes));
+            }
+        }
+    }
+    // A process with compute and graphics contexts is listed twice
+    usage.sort_unstable();
+    usage.dedup_by_key(|&mut (device_index, pid, _)| (device_index, pid));
+    Ok(usage)
+}
+
+/// Memory event row of the NSight Systems SQLite export
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct MemoryRow {
+    pub start: u64,
+    pub device_id: u32,
+    pub bytes: u64,
+    /// 0 for an allocation, 1 for a free
+    pub operation: u8,
+    pub address: u64,
+    pub global_pid: u64,
+}
+
+#[derive(Deserialize)]
+struct SessionStartRow {
+    utc_epoch_ns: u64,
+}
+
+/// Read the allocation timeline of an NSight Systems report
+///
+/// # Arguments
+/// * `report` - `.nsys-rep` file collected with `--cuda-memory-usage=true`
+/// * `pid` - Process to include; every process if unset
+///
+/// # Returns
+/// * `Result<AllocationTimeline>` - Timeline or error if nsys is unavailable or the report has no memory events
+pub fn report_timeline(report: &Path, pid: Option<u32>) -> Result<AllocationTimeline> {
+    if !report.exists() {
+        return Err(AppError::Io(format!("NSight report file not found: {}", report.display())).into());
+    }
+    let database = nsys::export_sqlite(report)
+        .ok_or_else(|| AppError::NotSupported("Reading NSight Systems reports requires the nsys CLI".to_string()))?;
+    let rows: Vec<MemoryRow> = nsys::query(&database, MEMORY_QUERY)
+        .map(|output| nsys::parse_rows(&output))
+        .ok_or_else(|| AppError::NotSupported(
+            "The report has no CUDA memory events; collect it with --cuda-memory-usage=true".to_string(),
+        ))?;
+    let session_start_ns = nsys::query(&database, SESSION_START_QUERY)
+        .and_then(|output| nsys::parse_rows::<SessionStartRow>(&output).first().map(|row| row.utc_epoch_ns))
+        .unwrap_or(0);
+    Ok(timeline_from_rows(&rows, session_start_ns, pid))
+}
+
+/// Build a timeline from report rows
+///
+/// # Arguments
+/// * `rows` - Memory events in time order, timed from the session start
+/// * `session_start_ns` - Session start as Unix nanoseconds
+/// * `pid` - Process to include; every process if unset
+pub fn timeline_from_rows(rows: &[MemoryRow], session_start_ns: u64, pid: Option<u32>) -> AllocationTimeline {
+    let mut timeline = AllocationTimeline::new(AllocationSource::Report, pid);
+    for row in rows {
+        let row_pid = ((row.global_pid >> 24) & 0xFF_FFFF) as u32;
+        if pid.is_some_and(|pid| pid != row_pid) {
+            continue;
+        }
+        let kind = if row.operation == 0 { AllocationKind::Alloc } else { AllocationKind::Free };
+        let timestamp_ms = (session_start_ns + row.start) as f64 / 1e6;
+        timeline.push(timestamp_ms, row.device_id, row_pid, kind, row.bytes, Some(row.address));
+    }
+    timeline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_infers_events_from_usage_changes() {
+        let mut timeline = AllocationTimeline::new(AllocationSource::Inferred, None);
+        let mut tracker = UsageTracker::default();
+        tracker.observe(0.0, &[(0, 10, 100 * MIB)], &mut timeline);
+        tracker.observe(100.0, &[(0, 10, 900 * MIB), (0, 11, 50 * MIB)], &mut timeline);
+        tracker.observe(200.0, &[(0, 10, 900 * MIB), (0, 11, 50 * MIB)], &mut timeline);
+        tracker.observe(300.0, &[(0, 10, 300 * MIB)], &mut timeline);
+
+        let events: Vec<(f64, u32, AllocationKind, u64, u64)> = timeline.events.iter()
+            .map(|event| (event.timestamp_ms, event.pid, event.kind, event.bytes / MIB, event.in_use_bytes / MIB))
+            .collect();
+        assert_eq!(events, vec![
+            (0.0, 10, AllocationKind::Alloc, 100, 100),
+            (100.0, 10, AllocationKind::Alloc, 800, 900),
+            (100.0, 11, AllocationKind::Alloc, 50, 950),
+            (300.0, 10, AllocationKind::Free, 600, 350),
+            (300.0, 11, AllocationKind::Free, 50, 300),
+        ]);
+        assert_eq!(timeline.peaks, vec![DevicePeak { device_index: 0, peak_bytes: 950 * MIB, peak_at_ms: 100.0, in_use_bytes: 300 * MIB }]);
+        assert_eq!(timeline.between(50.0, 150.0).events.len(), 2);
+    }
+
+    #[test]
+    fn test_report_rows_become_timeline() {
+        let global_pid = |pid: u64| pid << 24;
+        let row = |start, bytes, operation, pid| MemoryRow { start, device_id: 1, bytes, operation, address: 0x7f00 + start, global_pid: global_pid(pid) };
+        let rows = [row(1_000_000, 4 * MIB, 0, 42), row(2_000_000, 8 * MIB, 0, 7), row(3_000_000, 4 * MIB, 1, 42)];
+
+        let timeline = timeline_from_rows(&rows, 1_700_000_000_000_000_000, Some(42));
+        assert_eq!(timeline.source, AllocationSource::Report);
+        assert_eq!(timeline.events.len(), 2);
+        assert_eq!(timeline.events[0].timestamp_ms, 1_700_000_000_001.0);
+        assert_eq!((timeline.events[1].kind, timeline.events[1].address), (AllocationKind::Free, Some(0x7f00 + 3_000_000)));
+        assert_eq!((timeline.peaks[0].peak_bytes, timeline.peaks[0].in_use_bytes), (4 * MIB, 0));
+
+        let output = "start,device_id,bytes,operation,address,global_pid\n1000,0,4096,0,140000,704643072\n";
+        let parsed: Vec<MemoryRow> = nsys::parse_rows(output);
+        assert_eq!(timeline_from_rows(&parsed, 0, None).events[0].pid, 42);
+    }
+}
@@ -0,0 +1,419 @@
+//! Post-recording analysis
+//!
+//! Summarizes a recording into utilization distribution, thermal behavior,
+//! time spent throttled, sustained clocks and idle gaps, and writes the
+//! summary as JSON and HTML next to the raw recording.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+use crate::nvml::{RecordingFile, StaticDeviceInfo, TelemetryFrame};
+use crate::residency::{self, Residency};
+use crate::schema;
+
+/// GPU utilization at or below which a sample counts as idle
+const IDLE_UTIL_PERCENT: u32 = 5;
+/// Shortest idle stretch reported as a gap
+const MIN_IDLE_GAP_MS: u64 = 1000;
+/// GPU utilization at or above which a sample counts as under load
+const LOAD_UTIL_PERCENT: u32 = 50;
+/// Throttle reasons that actually limit performance (idle, application
+/// clocks, sync boost and display clocks are benign)
+const LIMITING_THROTTLE_REASONS: [&str; 5] = [
+    "sw_power_cap",
+    "hw_slowdown",
+    "sw_thermal_slowdown",
+    "hw_thermal_slowdown",
+    "hw_power_brake",
+];
+
+/// Summary of a complete recording
+#[derive(Serialize, Clone, Debug)]
+pub struct RecordingAnalysis {
+    pub source: String,
+    pub device: StaticDeviceInfo,
+    pub sample_count: usize,
+    pub duration_seconds: f64,
+    pub utilization: UtilizationSummary,
+    pub thermal: ThermalSummary,
+    pub throttling: ThrottleSummary,
+    pub clocks: ClockSummary,
+    pub residency: Residency,
+    pub idle_gaps: Vec<IdleGap>,
+}
+
+/// Distribution of GPU utilization across samples
+#[derive(Serialize, Clone, Debug)]
+pub struct UtilizationSummary {
+    pub mean: f64,
+    pub p50: u32,
+    pub p95: u32,
+    pub max: u32,
+    /// Share of recording time in each 10% bucket (0-9%, ..., 90-100%)
+    pub histogram: Vec<f64>,
+}
+
+/// Temperature and power behavior over the recording
+#[derive(Serialize, Clone, Debug)]
+pub struct ThermalSummary {
+    pub min_c: u32,
+    pub mean_c: f64,
+    pub max_c: u32,
+    /// Temperature change from the first to the last sample
+    pub rise_c: i64,
+    pub max_power_w: f32,
+}
+
+/// Time spent with performance-limiting throttle reasons active
+#[derive(Serialize, Clone, Debug)]
+pub struct ThrottleSummary {
+    pub throttled_seconds: f64,
+    pub throttled_percent: f64,
+    /// Seconds each limiting reason was active
+    pub by_reason: BTreeMap<String, f64>,
+}
+
+/// Clock behavior over the recording
+#[derive(Serialize, Clone, Debug)]
+pub struct ClockSummary {
+    pub peak_sm_clock_mhz: u32,
+    /// SM clock held for 90% of the time under load; `None` if never loaded
+    pub sustained_sm_clock_mhz: Option<u32>,
+    pub mean_memory_clock_mhz: f64,
+}
+
+/// Stretch of consecutive idle samples
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct IdleGap {
+    /// Offset from the first sample
+    pub start_offset_ms: u64,
+    pub duration_ms: u64,
+}
+
+/// Analyze a recording file and write the summary next to it
+///
+/// For `gpu_recording_x.json` the summary is written to
+/// `gpu_recording_x.analysis.json` and `gpu_recording_x.analysis.html`.
+///
+/// # Arguments
+/// * `path` - Path to the recording JSON file
+///
+/// # Returns
+/// * `Result<RecordingAnalysis>` - Summary or error if the recording cannot be read
+pub fn analyze_recording(path: &Path) -> Result<RecordingAnalysis> {
+    let recording = schema::load_recording(path)?;
+    let analysis = analyze(&recording, &path.display().to_string())?;
+
+    let json = serde_json::to_string_pretty(&analysis)
+        .context("Failed to serialize recording analysis")?;
+    std::fs::write(report_path(path, "json"), json)
+        .context("Failed to write analysis JSON")?;
+    std::fs::write(report_path(path, "html"), render_html(&analysis))
+        .context("Failed to write analysis HTML")?;
+
+    Ok(analysis)
+}
+
+/// Compute the summary for an in-memory recording
+///
+/// Multi-device recordings are summarized for their primary device.
+///
+/// # Arguments
+/// * `recording` - Recording to summarize
+/// * `source` - Where the recording came from, for display
+///
+/// # Returns
+/// * `Result<RecordingAnalysis>` - Summary or error if the recording has no samples
+pub fn analyze(recording: &RecordingFile, source: &str) -> Result<RecordingAnalysis> {
+    let primary;
+    let samples = if recording.devices.len() > 1 {
+        primary = recording.samples_of(recording.device.index);
+        &primary
+    } else {
+        &recording.samples
+    };
+    if samples.is_empty() {
+        return Err(AppError::InvalidArgument("Recording contains no samples".to_string()).into());
+    }
+
+    let weights = sample_durations_ms(samples);
+    let total_ms: u64 = weights.iter().sum();
+
+    Ok(RecordingAnalysis {
+        source: source.to_string(),
+        device: recording.device.clone(),
+        sample_count: samples.len(),
+        duration_seconds: total_ms as f64 / 1000.0,
+        utilization: summarize_utilization(samples, &weights, total_ms),
+        thermal: summarize_thermal(samples),
+        throttling: summarize_throttling(samples, &weights, total_ms),
+        clocks: summarize_clocks(samples),
+        residency: residency::from_frames(recording.device.index, samples),
+        idle_gaps: find_idle_gaps(samples, &weights),
+    })
+}
+
+/// Path of the summary written next to a recording, with extension `json` or `html`
+pub fn report_path(path: &Path, extension: &str) -> PathBuf {
+    path.with_extension(format!("analysis.{}", extension))
+}
+
+// Time each sample stands for: the gap to the next sample, with the last
+// sample given the median gap
+fn sample_durations_ms(samples: &[TelemetryFrame]) -> Vec<u64> {
+    let mut gaps: Vec<u64> = samples.windows(2)
+        .map(|pair| pair[1].ms_since(&pair[0]).round().max(0.0) as u64)
+        .collect();
+    let mut sorted = gaps.clone();
+    sorted.sort_unstable();
+    gaps.push(sorted.get(sorted.len() / 2).copied().unwrap_or(0));
+    gaps
+}
+
+// Nearest-rank percentile of an already sorted slice
+fn percentile(sorted: &[u32], pct: usize) -> u32 {
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn share_percent(part_ms: u64, total_ms: u64) -> f64 {
+    if total_ms == 0 {
+        0.0
+    } else {
+        part_ms as f64 * 100.0 / total_ms as f64
+    }
+}
+
+fn summarize_utilization(samples: &[TelemetryFrame], weights: &[u64], total_ms: u64) -> UtilizationSummary {
+    let mut sorted: Vec<u32> = samples.iter().map(|frame| frame.util_gpu).collect();
+    sorted.sort_unstable();
+
+    let mut bucket_ms = [0u64; 10];
+    for (frame, weight) in samples.iter().zip(weights) {
+        bucket_ms[(frame.util_gpu as usize / 10).min(9)] += weight;
+    }
+
+    UtilizationSummary {
+        mean: sorted.iter().map(|&util| util as f64).sum::<f64>() / sorted.len() as f64,
+        p50: percentile(&sorted, 50),
+        p95: percentile(&sorted, 95),
+        max: *sorted.last().unwrap(),
+        histogram: bucket_ms.iter().map(|&ms| share_percent(ms, total_ms)).collect(),
+    }
+}
+
+fn summarize_thermal(samples: &[TelemetryFrame]) -> ThermalSummary {
+    let temperatures = samples.iter().map(|frame| frame.temperature_c);
+    let first = samples.first().unwrap().temperature_c as i64;
+    let last = samples.last().unwrap().temperature_c as i64;
+
+    ThermalSummary {
+        min_c: temperatures.clone().min().unwrap(),
+        mean_c: temperatures.clone().map(f64::from).sum::<f64>() / samples.len() as f64,
+        max_c: temperatures.max().unwrap(),
+        rise_c: last - first,
+        max_power_w: samples.iter().map(|frame| frame.power_w).fold(0.0, f32::max),
+    }
+}
+
+fn summarize_throttling(samples: &[TelemetryFrame], weights: &[u64], total_ms: u64) -> ThrottleSummary {
+    let mut throttled_ms = 0;
+    let mut by_reason_ms: BTreeMap<String, u64> = BTreeMap::new();
+
+    for (frame, &weight) in samples.iter().zip(weights) {
+        let limiting: Vec<&String> = frame.throttle_reasons.iter()
+            .filter(|reason| LIMITING_THROTTLE_REASONS.contains(&reason.as_str()))
+            .collect();
+        if !limiting.is_empty() {
+            throttled_ms += weight;
+        }
+        for reason in limiting {
+            *by_reason_ms.entry(reason.clone()).or_default() += weight;
+        }
+    }
+
+    ThrottleSummary {
+        throttled_seconds: throttled_ms as f64 / 1000.0,
+        throttled_percent: share_percent(throttled_ms, total_ms),
+        by_reason: by_reason_ms.into_iter()
+            .map(|(reason, ms)| (reason, ms as f64 / 1000.0))
+            .collect(),
+    }
+}
+
+fn summarize_clocks(samples: &[TelemetryFrame]) -> ClockSummary {
+    let mut loaded: Vec<u32> = samples.iter()
+        .filter(|frame| frame.util_gpu >= LOAD_UTIL_PERCENT)
+        .map(|frame| frame.sm_clock_mhz)
+        .collect();
+    loaded.sort_unstable();
+
+    ClockSummary {
+        peak_sm_clock_mhz: samples.iter().map(|frame| frame.sm_clock_mhz).max().unwrap(),
+        sustained_sm_clock_mhz: (!loaded.is_empty()).then(|| percentile(&loaded, 10)),
+        mean_memory_clock_mhz: samples.iter().map(|frame| frame.memory_clock_mhz as f64).sum::<f64>()
+            / samples.len() as f64,
+    }
+}
+
+fn find_idle_gaps(samples: &[TelemetryFrame], weights: &[u64]) -> Vec<IdleGap> {
+    let origin = samples[0].timestamp;
+    let mut gaps = Vec::new();
+    let mut current: Option<IdleGap> = None;
+
+    for (frame, &weight) in samples.iter().zip(weights) {
+        if frame.util_gpu <= IDLE_UTIL_PERCENT {
+            let gap = current.get_or_insert(IdleGap {
+                start_offset_ms: frame.timestamp.saturating_sub(origin) as u64,
+                duration_ms: 0,
+            });
+            gap.duration_ms += weight;
+        } else if let Some(gap) = current.take() {
+            gaps.push(gap);
+        }
+    }
+    gaps.extend(current);
+    gaps.retain(|gap| gap.duration_ms >= MIN_IDLE_GAP_MS);
+    gaps
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_html(analysis: &RecordingAnalysis) -> String {
+    let row = |label: &str, value: String| format!("<tr><th>{}</th><td>{}</td></tr>\n", label, escape_html(&value));
+    let sustained = analysis.clocks.sustained_sm_clock_mhz
+        .map_or_else(|| "n/a (never under load)".to_string(), |mhz| format!("{} MHz", mhz));
+    let reasons = if analysis.throttling.by_reason.is_empty() {
+        "none".to_string()
+    } else {
+        analysis.throttling.by_reason.iter()
+            .map(|(reason, seconds)| format!("{} ({:.1} s)", reason, seconds))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let pstates = analysis.residency.pstates.iter()
+        .map(|bucket| format!("{} {:.1}%", bucket.label, bucket.percent))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut rows = String::new();
+    rows += &row("Device", format!("{} (GPU {})", analysis.device.name, analysis.device.index));
+    rows += &row("Samples", format!("{} over {:.1} s", analysis.sample_count, analysis.duration_seconds));
+    rows += &row("GPU utilization", format!(
+        "mean {:.1}%, p50 {}%, p95 {}%, max {}%",
+        analysis.utilization.mean, analysis.utilization.p50, analysis.utilization.p95, analysis.utilization.max
+    ));
+    rows += &row("Temperature", format!(
+        "{}-{} °C (mean {:.1} °C, rise {:+} °C)",
+        analysis.thermal.min_c, analysis.thermal.max_c, analysis.thermal.mean_c, analysis.thermal.rise_c
+    ));
+    rows += &row("Peak power", format!("{:.1} W", analysis.thermal.max_power_w));
+    rows += &row("Throttled", format!(
+        "{:.1} s ({:.1}%): {}",
+        analysis.throttling.throttled_seconds, analysis.throttling.throttled_percent, reasons
+    ));
+    rows += &row("SM clock", format!("peak {} MHz, sustained {}", analysis.clocks.peak_sm_clock_mhz, sustained));
+    rows += &row("P-state residency", if pstates.is_empty() { "n/a".to_string() } else { pstates });
+    rows += &row("Idle gaps", format!(
+        "{} ({:.1} s total)",
+        analysis.idle_gaps.len(),
+        analysis.idle_gaps.iter().map(|gap| gap.duration_ms).sum::<u64>() as f64 / 1000.0
+    ));
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Recording analysis</title></head>\n\
+         <body>\n<h1>Recording analysis</h1>\n<p>{}</p>\n<table>\n{}</table>\n</body>\n</html>\n",
+        escape_html(&analysis.source),
+        rows
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp: u128, util_gpu: u32, temperature_c: u32, throttle: &[&str]) -> TelemetryFrame {
+        TelemetryFrame {
+            timestamp,
+            util_gpu,
+            temperature_c,
+            sm_clock_mhz: 1000 + util_gpu * 10,
+            throttle_reasons: throttle.iter().map(|reason| reason.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn recording(samples: Vec<TelemetryFrame>) -> RecordingFile {
+        RecordingFile {
+            schema_version: schema::CURRENT_SCHEMA_VERSION,
+            device: StaticDeviceInfo::default(),
+            devices: vec![StaticDeviceInfo::default()],
+            samples,
+            markers: Vec::new(),
+            timing: None,
+            process: None,
+            violations: Vec::new(),
+            clock: None,
+        }
+    }
+
+    #[test]
+    fn test_analyze_summarizes_recording() {
+        // 1 s busy, 2 s idle, 1 s busy and power capped, sampled every 500 ms
+        let utils = [90, 90, 0, 0, 0, 0, 100, 100];
+        let samples = utils.iter().enumerate()
+            .map(|(i, &util)| {
+                let throttle: &[&str] = if i >= 6 { &["sw_power_cap", "gpu_idle"] } else { &["gpu_idle"] };
+                frame(i as u128 * 500, util, 50 + i as u32, throttle)
+            })
+            .collect();
+
+        let analysis = analyze(&recording(samples), "test").unwrap();
+        assert_eq!(analysis.duration_seconds, 4.0);
+        assert_eq!(analysis.utilization.max, 100);
+        assert_eq!(analysis.utilization.histogram[0], 50.0);
+        assert_eq!(analysis.thermal.rise_c, 7);
+        assert_eq!(analysis.throttling.throttled_seconds, 1.0);
+        assert_eq!(analysis.throttling.by_reason.keys().collect::<Vec<_>>(), vec!["sw_power_cap"]);
+        assert_eq!(analysis.clocks.sustained_sm_clock_mhz, Some(1900));
+        assert_eq!(analysis.idle_gaps, vec![IdleGap { start_offset_ms: 1000, duration_ms: 2000 }]);
+    }
+
+    #[test]
+    fn test_short_idle_stretches_are_not_gaps() {
+        let samples = vec![frame(0, 80, 60, &[]), frame(500, 0, 60, &[]), frame(1000, 80, 60, &[])];
+        let analysis = analyze(&recording(samples), "test").unwrap();
+        assert!(analysis.idle_gaps.is_empty());
+    }
+
+    #[test]
+    fn test_multi_device_recording_summarizes_primary_device() {
+        let second = |timestamp| TelemetryFrame { device_index: 1, ..frame(timestamp, 100, 90, &[]) };
+        let mut multi = recording(vec![frame(0, 20, 50, &[]), second(0), frame(500, 20, 50, &[]), second(500)]);
+        multi.devices.push(StaticDeviceInfo { index: 1, ..Default::default() });
+
+        let analysis = analyze(&multi, "test").unwrap();
+        assert_eq!(analysis.sample_count, 2);
+        assert_eq!(analysis.thermal.max_c, 50);
+    }
+
+    #[test]
+    fn test_empty_recording_is_rejected() {
+        let err = analyze(&recording(Vec::new()), "test").unwrap_err();
+        assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+    }
+
+    #[test]
+    fn test_report_paths_sit_next_to_recording() {
+        let path = Path::new("recordings/gpu_recording_rec_1.json");
+        assert_eq!(report_path(path, "html"), Path::new("recordings/gpu_recording_rec_1.analysis.html"));
+    }
+}
@@ -0,0 +1,513 @@
+//! Scriptable automation hooks
+//!
+//! A Rhai script reacts to telemetry frames and health alerts. It defines
+//! `on_frame(frame)` and/or `on_alert(alert)`, which receive the event as
+//! an object map with the same fields as the `telemetry-update` and
+//! `gpu-alert` events, and share `this`, a map kept between calls for the
+//! script's own state. For example:
+//!
+//! ```rhai
+//! fn on_frame(frame) {
+//!     if frame.temperature_c > 85 && this.capped != true {
+//!         set_power_limit(frame.device_index, 250);
+//!         add_marker("power capped");
+//!         this.capped = true;
+//!     }
+//! }
+//! ```
+//!
+//! Scripts can only call the actions registered here: `start_recording`,
+//! `add_marker`, `set_power_limit` and `notify`. They have no file,
+//! network or process access, and each call is bounded in operations and
+//! actions, so a runaway script cannot stall the app. Actions requested
+//! during a call are carried out after it returns. Frames come from the
+//! stream running when the hooks start.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::Nvml;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, ImmutableString, Scope, AST};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tauri::Window;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+use crate::alerts::{self, Alert};
+use crate::error::AppError;
+use crate::markers;
+use crate::nvml::{self, TelemetryFrame};
+use crate::sessions;
+use crate::stream_stats::StreamStats;
+
+/// Operations one hook call may run before it is aborted
+const MAX_OPERATIONS: u64 = 100_000;
+/// Actions one hook call may request
+const MAX_ACTIONS_PER_CALL: usize = 4;
+/// Longest recording a script may start
+const MAX_RECORDING_SECONDS: u64 = 60 * 60;
+/// Sample rate of recordings started by a script
+const RECORDING_RATE_HZ: u64 = 10;
+/// Domain of markers added by a script
+const MARKER_DOMAIN: &str = "automation";
+/// Actions kept in the status
+const RECENT_ACTIONS: usize = 20;
+
+/// Script to run as automation hooks
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AutomationConfig {
+    /// Rhai source defining `on_frame(frame)` and/or `on_alert(alert)`
+    pub script: String,
+}
+
+/// An action requested by a script
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AutomationAction {
+    StartRecording { duration_seconds: u64, device_indices: Vec<u32> },
+    AddMarker { name: String },
+    SetPowerLimit { device_index: u32, watts: u32 },
+    Notify { title: String, message: String },
+}
+
+/// Outcome of one requested action
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ActionOutcome {
+    #[serde(flatten)]
+    pub action: AutomationAction,
+    pub timestamp_ms: u64,
+    /// Why the action failed; `None` once carried out
+    pub error: Option<String>,
+}
+
+/// Notification sent by a script, emitted as `automation-notification`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AutomationNotification {
+    pub title: String,
+    pub message: String,
+    pub timestamp_ms: u64,
+}
+
+/// State of the automation hooks
+#[derive(Serialize, Clone, Debug)]
+pub struct AutomationStatus {
+    pub running: bool,
+    pub has_frame_hook: bool,
+    pub has_alert_hook: bool,
+    pub frames_handled: u64,
+    pub alerts_handled: u64,
+    pub script_errors: u64,
+    pub last_error: Option<String>,
+    /// Most recent actions, newest last
+    pub recent_actions: Vec<ActionOutcome>,
+}
+
+/// A compiled script and the state it keeps between calls
+pub struct Hooks {
+    engine: Engine,
+    ast: AST,
+    this: Dynamic,
+    requested: Arc<Mutex<Vec<AutomationAction>>>,
+    has_frame_hook: bool,
+    has_alert_hook: bool,
+}
+
+impl Hooks {
+    /// Compile a script
+    ///
+    /// # Arguments
+    /// * `script` - Rhai source
+    ///
+    /// # Returns
+    /// * `Result<Hooks>` - Hooks or error if the script does not compile or defines no hook
+    pub fn compile(script: &str) -> Result<Hooks> {
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        let engine = build_engine(requested.clone());
+        let ast = engine.compile(script)
+            .map_err(|e| AppError::InvalidArgument(format!("Automation script does not compile: {}", e)))?;
+        let defines = |name: &str| ast.iter_functions().any(|function| function.name == name && function.params.len() == 1);
+        let (has_frame_hook, has_alert_hook) = (defines("on_frame"), defines("on_alert"));
+        if !has_frame_hook && !has_alert_hook {
+            return Err(AppError::InvalidArgument("Automation script defines neither on_frame(frame) nor on_alert(alert)".to_string()).into());
+        }
+        Ok(Hooks { engine, ast, this: Dynamic::from_map(Default::default()), requested, has_frame_hook, has_alert_hook })
+    }
+
+    /// Run `on_frame`, returning the actions it requested
+    pub fn on_frame(&mut self, frame: &TelemetryFrame) -> Result<Vec<AutomationAction>> {
+        if !self.has_frame_hook {
+            return Ok(Vec::new());
+        }
+        let event = rhai::serde::to_dynamic(frame).map_err(|e| anyhow::anyhow!("Failed to pass frame to script: {}", e))?;
+        self.call("on_frame", event)
+    }
+
+    /// Run `on_alert`, returning the actions it requested
+    pub fn on_alert(&mut self, alert: &Alert) -> Result<Vec<AutomationAction>> {
+        if !self.has_alert_hook {
+            return Ok(Vec::new());
+        }
+        let event = rhai::serde::to_dynamic(alert).map_err(|e| anyhow::anyhow!("Failed to pass alert to script: {}", e))?;
+        self.call("on_alert", event)
+    }
+
+    fn call(&mut self, hook: &str, event: Dynamic) -> Result<Vec<AutomationAction>> {
+        self.requested.lock().unwrap().clear();
+        let options = rhai::CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.this);
+        let result = self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, hook, (event,));
+        // Actions requested before an error are dropped with the call
+        let requested = std::mem::take(&mut *self.requested.lock().unwrap());
+        // The hook's return value is not used
+        result.map(drop).map_err(|e| anyhow::anyhow!("{} failed: {}", hook, e))?;
+        Ok(requested)
+    }
+}
+
+// Current or most recent hooks; kept after they stop so their counters can be read
+static AUTOMATION_STATE: std::sync::RwLock<Option<AutomationStatus>> = std::sync::RwLock::new(None);
+
+// Cancellation token of the running hooks
+static AUTOMATION_CANCEL: std::sync::Mutex<Option<CancellationToken>> = std::sync::Mutex::new(None);
+
+// Task of the running hooks
+static AUTOMATION_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+/// Start running a script on telemetry frames and alerts
+///
+/// # Arguments
+/// * `config` - Script to run
+/// * `frames` - Receiver on the running telemetry stream, if any
+/// * `stats` - Counters of the stream, which count frames the hooks fall behind on
+/// * `window` - Tauri window handle for recordings, markers and notifications
+///
+/// # Returns
+/// * `Result<AutomationStatus>` - Status of the started hooks or error for an invalid script
+pub async fn start_automation(
+    config: AutomationConfig,
+    frames: Option<broadcast::Receiver<TelemetryFrame>>,
+    stats: Arc<StreamStats>,
+    window: Window,
+) -> Result<AutomationStatus> {
+    let hooks = Hooks::compile(&config.script)?;
+    if hooks.has_frame_hook && !hooks.has_alert_hook && frames.is_none() {
+        return Err(AppError::InvalidArgument("on_frame needs a running telemetry stream".to_string()).into());
+    }
+
+    let status = AutomationStatus {
+        running: true,
+        has_frame_hook: hooks.has_frame_hook,
+        has_alert_hook: hooks.has_alert_hook,
+        frames_handled: 0,
+        alerts_handled: 0,
+        script_errors: 0,
+        last_error: None,
+        recent_actions: Vec::new(),
+    };
+    let status = sessions::claim(&AUTOMATION_STATE, |status| status.running, status.clone(), "Automation hooks are already running")?
+        .commit(status);
+    let cancel = CancellationToken::new();
+    *AUTOMATION_CANCEL.lock().unwrap() = Some(cancel.clone());
+
+    let mut frames = frames.filter(|_| hooks.has_frame_hook);
+    let mut alerts = hooks.has_alert_hook.then(alerts::subscribe_alerts);
+    let task = tokio::spawn(async move {
+        let mut hooks = hooks;
+        loop {
+            let event = tokio::select! {
+                _ = cancel.cancelled() => break,
+                frame = next_event(&mut frames, |missed| stats.record_dropped(missed)) => Event::Frame(Box::new(frame)),
+                alert = next_event(&mut alerts, |_| {}) => Event::Alert(alert),
+            };
+            let is_frame = matches!(event, Event::Frame(_));
+            let (returned, result) = match nvml::blocking(move || Ok(run_hook(hooks, event))).await {
+                Ok(returned) => returned,
+                Err(e) => {
+                    eprintln!("Automation hook panicked: {:#}", e);
+                    break;
+                }
+            };
+            hooks = returned;
+
+            let mut outcomes = Vec::new();
+            match result {
+                Ok(actions) => {
+                    for action in actions {
+                        let error = execute(&action, &window).await.err().map(|e| format!("{:#}", e));
+                        outcomes.push(ActionOutcome { action, timestamp_ms: nvml::now_ms() as u64, error });
+                    }
+                }
+                Err(e) => record_error(format!("{:#}", e)),
+            }
+            if let Some(status) = AUTOMATION_STATE.write().unwrap().as_mut() {
+                if is_frame { status.frames_handled += 1 } else { status.alerts_handled += 1 }
+                for outcome in outcomes {
+                    if let Some(error) = &outcome.error {
+                        eprintln!("Automation action failed: {}", error);
+                    }
+                    status.recent_actions.push(outcome);
+                }
+                let excess = status.recent_actions.len().saturating_sub(RECENT_ACTIONS);
+                status.recent_actions.drain(..excess);
+            }
+        }
+
+        if let Some(status) = AUTOMATION_STATE.write().unwrap().as_mut() {
+            status.running = false;
+        }
+    });
+    *AUTOMATION_TASK.lock().unwrap() = Some(task);
+
+    Ok(status)
+}
+
+/// Stop the automation hooks
+///
+/// # Returns
+/// * `Result<AutomationStatus>` - Final status or error if the hooks are not running
+pub async fn stop_automation() -> Result<AutomationStatus> {
+    if !AUTOMATION_STATE.read().unwrap().as_ref().is_some_and(|status| status.running) {
+        return Err(AppError::InvalidArgument("Automation hooks are not running".to_string()).into());
+    }
+    finish_active_automation().await?;
+    get_automation_status().context("Automation state missing after stop")
+}
+
+/// Stop any running hooks and wait for them to exit
+pub async fn finish_active_automation() -> Result<()> {
+    if let Some(cancel) = AUTOMATION_CANCEL.lock().unwrap().take() {
+        cancel.cancel();
+    }
+    let task = AUTOMATION_TASK.lock().unwrap().take();
+    if let Some(task) = task {
+        task.await.context("Automation task ended abnormally")?;
+    }
+    Ok(())
+}
+
+/// Get the status of the current or most recent hooks
+///
+/// # Returns
+/// * `Option<AutomationStatus>` - Status, or `None` if hooks have not been started
+pub fn get_automation_status() -> Option<AutomationStatus> {
+    AUTOMATION_STATE.read().unwrap().clone()
+}
+
+enum Event {
+    Frame(Box<TelemetryFrame>),
+    Alert(Alert),
+}
+
+// Next event of a feed; waits forever once the feed is gone. A hook that
+// falls behind sees the latest events rather than every event, and the
+// events it missed are passed to `on_lagged`.
+async fn next_event<T: Clone>(feed: &mut Option<broadcast::Receiver<T>>, on_lagged: impl Fn(u64)) -> T {
+    loop {
+        let Some(receiver) = feed.as_mut() else {
+            return std::future::pending().await;
+        };
+        match receiver.recv().await {
+            Ok(event) => return event,
+            Err(RecvError::Lagged(missed)) => on_lagged(missed),
+            Err(RecvError::Closed) => *feed = None,
+        }
+    }
+}
+
+fn run_hook(mut hooks: Hooks, event: Event) -> (Hooks, Result<Vec<AutomationAction>>) {
+    let result = match &event {
+        Event::Frame(frame) => hooks.on_frame(frame),
+        Event::Alert(alert) => hooks.on_alert(alert),
+    };
+    (hooks, result)
+}
+
+fn record_error(error: String) {
+    eprintln!("Automation script error: {}", error);
+    if let Some(status) = AUTOMATION_STATE.write().unwrap().as_mut() {
+        status.script_errors += 1;
+        status.last_error = Some(error);
+    }
+}
+
+// Carry out an action requested by a script
+async fn execute(action: &AutomationAction, window: &Window) -> Result<()> {
+    match action {
+        AutomationAction::StartRecording { duration_seconds, device_indices } => {
+            nvml::start_interval_recording(*duration_seconds, RECORDING_RATE_HZ, Vec::new(), device_indices.clone(), false, window.clone())
+                .await
+                .map(drop)
+        }
+        AutomationAction::AddMarker { name } => {
+            let range = markers::add_marker(name, MARKER_DOMAIN);
+            window.emit("marker-range", &range).context("Failed to emit marker range")
+        }
+        AutomationAction::SetPowerLimit { device_index, watts } => set_power_limit(*device_index, *watts).await,
+        AutomationAction::Notify { title, message } => {
+            let notification = AutomationNotification {
+                title: title.clone(),
+                message: message.clone(),
+                timestamp_ms: nvml::now_ms() as u64,
+            };
+            window.emit("automation-notification", &notification).context("Failed to emit notification")
+        }
+    }
+}
+
+async fn set_power_limit(device_index: u32, watts: u32) -> Result<()> {
+    nvml::blocking(move || {
+        let nvml = Nvml::init().context("Failed to initialize NVML")?;
+        let mut device = nvml::device_at(&nvml, device_index)?;
+        let limits = device.power_management_limit_constraints()
+            .with_context(|| format!("Failed to read the power limit range of GPU {}", device_index))?;
+        let milliwatts = watts.saturating_mul(1000);
+        if !(limits.min_limit..=limits.max_limit).contains(&milliwatts) {
+            return Err(AppError::InvalidArgument(format!(
+                "Power limit {} W is outside the {}-{} W range of GPU {}",
+                watts, limits.min_limit / 1000, limits.max_limit / 1000, device_index
+            )).into());
+        }
+        device.set_power_management_limit(milliwatts)
+            .with_context(|| format!("Failed to set power limit on GPU {} (requires root)", device_index))
+    }).await
+}
+
+// Engine exposing only the automation actions, with resource limits
+fn build_engine(requested: Arc<Mutex<Vec<AutomationAction>>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(64 * 1024);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+    engine.disable_symbol("eval");
+    engine.on_print(|text| eprintln!("Automation script: {}", text));
+    engine.on_debug(|text, _, _| eprintln!("Automation script: {}", text));
+
+    let queue = move |action: AutomationAction| -> Result<(), Box<EvalAltResult>> {
+        let mut requested = requested.lock().unwrap();
+        if requested.len() >= MAX_ACTIONS_PER_CALL {
+            return Err(format!("A hook may request at most {} actions per call", MAX_ACTIONS_PER_CALL).into());
+        }
+        requested.push(action);
+        Ok(())
+    };
+
+    let start_recording = queue.clone();
+    engine.register_fn("start_recording", move |duration_seconds: i64| -> Result<(), Box<EvalAltResult>> {
+        start_recording(AutomationAction::StartRecording { duration_seconds: recording_seconds(duration_seconds)?, device_indices: Vec::new() })
+    });
+    let start_recording = queue.clone();
+    engine.register_fn("start_recording", move |duration_seconds: i64, devices: Array| -> Result<(), Box<EvalAltResult>> {
+        let device_indices = devices.into_iter()
+            .map(|device| device.as_int().map_err(|_| "Device indices must be integers".into()).and_then(device_index))
+            .collect::<Result<Vec<u32>, Box<EvalAltResult>>>()?;
+        start_recording(AutomationAction::StartRecording { duration_seconds: recording_seconds(duration_seconds)?, device_indices })
+    });
+    let add_marker = queue.clone();
+    engine.register_fn("add_marker", move |name: ImmutableString| -> Result<(), Box<EvalAltResult>> {
+        add_marker(AutomationAction::AddMarker { name: name.to_string() })
+    });
+    let set_power_limit = queue.clone();
+    engine.register_fn("set_power_limit", move |device: i64, watts: i64| -> Result<(), Box<EvalAltResult>> {
+        let watts = u32::try_from(watts).ok().filter(|&watts| watts > 0).ok_or_else(|| format!("Invalid power limit {} W", watts))?;
+        set_power_limit(AutomationAction::SetPowerLimit { device_index: device_index(device)?, watts })
+    });
+    let notify = queue.clone();
+    engine.register_fn("notify", move |title: ImmutableString, message: ImmutableString| -> Result<(), Box<EvalAltResult>> {
+        notify(AutomationAction::Notify { title: title.to_string(), message: message.to_string() })
+    });
+    engine.register_fn("notify", move |message: ImmutableString| -> Result<(), Box<EvalAltResult>> {
+        queue(AutomationAction::Notify { title: "Automation".to_string(), message: message.to_string() })
+    });
+    engine
+}
+
+fn recording_seconds(seconds: i64) -> Result<u64, Box<EvalAltResult>> {
+    u64::try_from(seconds).ok()
+        .filter(|seconds| (1..=MAX_RECORDING_SECONDS).contains(seconds))
+        .ok_or_else(|| format!("Recording duration must be between 1 and {} seconds, got {}", MAX_RECORDING_SECONDS, seconds).into())
+}
+
+fn device_index(device: i64) -> Result<u32, Box<EvalAltResult>> {
+    u32::try_from(device).map_err(|_| format!("Invalid device index {}", device).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_next_event_reports_missed_events() {
+        let (sender, receiver) = broadcast::channel(2);
+        for value in 0..5 {
+            sender.send(value).unwrap();
+        }
+        let missed = std::cell::Cell::new(0);
+        let mut feed = Some(receiver);
+        assert_eq!(next_event(&mut feed, |count| missed.set(missed.get() + count)).await, 3);
+        assert_eq!(missed.get(), 3);
+    }
+
+    #[test]
+    fn test_hooks_request_actions_and_keep_state() {
+        let mut hooks = Hooks::compile(r#"
+            fn on_frame(frame) {
+                if frame.temperature_c > 85 && this.capped != true {
+                    set_power_limit(frame.device_index, 250);
+                    add_marker("capped at " + frame.temperature_c);
+                    this.capped = true;
+                }
+            }
+        "#).unwrap();
+        assert!(hooks.has_frame_hook && !hooks.has_alert_hook);
+
+        let hot = TelemetryFrame { device_index: 1, temperature_c: 90, ..Default::default() };
+        assert!(hooks.on_frame(&TelemetryFrame { temperature_c: 70, ..Default::default() }).unwrap().is_empty());
+        assert_eq!(hooks.on_frame(&hot).unwrap(), vec![
+            AutomationAction::SetPowerLimit { device_index: 1, watts: 250 },
+            AutomationAction::AddMarker { name: "capped at 90".to_string() },
+        ]);
+        // `this` persists, so the cap is applied once
+        assert!(hooks.on_frame(&hot).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_alert_hook_and_whitelist() {
+        let mut hooks = Hooks::compile(r#"
+            fn on_alert(alert) {
+                if !alert.resolved {
+                    notify(alert.check, alert.detail);
+                    start_recording(60, [alert.device_index]);
+                }
+            }
+        "#).unwrap();
+        let alert = Alert {
+            host: "node".to_string(),
+            device_index: 2,
+            device_name: "GPU".to_string(),
+            check: "temperature".to_string(),
+            status: crate::health::CheckStatus::Warn,
+            detail: "84 C".to_string(),
+            resolved: false,
+            timestamp_ms: 0,
+        };
+        assert_eq!(hooks.on_alert(&alert).unwrap(), vec![
+            AutomationAction::Notify { title: "temperature".to_string(), message: "84 C".to_string() },
+            AutomationAction::StartRecording { duration_seconds: 60, device_indices: vec![2] },
+        ]);
+
+        let invalid = |script: &str| AppError::from(Hooks::compile(script).err().unwrap()).code();
+        assert_eq!(invalid("fn on_frame(frame) {"), "INVALID_ARGUMENT");
+        assert_eq!(invalid("fn helper(x) { x }"), "INVALID_ARGUMENT");
+        // Only registered actions exist, and each call is bounded
+        assert_eq!(invalid(r#"fn on_frame(frame) { eval("1") }"#), "INVALID_ARGUMENT");
+        let mut hooks = Hooks::compile(r#"fn on_frame(frame) { open_file("/etc/passwd") }"#).unwrap();
+        assert!(hooks.on_frame(&TelemetryFrame::default()).is_err());
+        let mut hooks = Hooks::compile("fn on_frame(frame) { loop {} }").unwrap();
+        assert!(hooks.on_frame(&TelemetryFrame::default()).is_err());
+        let mut hooks = Hooks::compile(r#"fn on_frame(frame) { for i in 0..10 { add_marker("m") } }"#).unwrap();
+        assert!(hooks.on_frame(&TelemetryFrame::default()).is_err());
+        let mut hooks = Hooks::compile("fn on_frame(frame) { start_recording(0) }").unwrap();
+        assert!(hooks.on_frame(&TelemetryFrame::default()).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! Built-in memory bandwidth benchmarks
+//!
+//! Measures host-to-device, device-to-host and device-to-device copy
+//! bandwidth through the CUDA driver API, so the name-based
+//! `memory_bandwidth_gbps` estimate can be checked against a real number.
+//! The driver library is loaded at runtime and only in builds with the
+//! `cuda` feature; other builds report benchmarks as not supported.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::Nvml;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::error::AppError;
+use crate::nvml;
+
+/// Transfer size used when none is requested
+pub const DEFAULT_TRANSFER_MB: u64 = 256;
+/// Largest accepted transfer size
+pub const MAX_TRANSFER_MB: u64 = 4096;
+/// Untimed copies made first so clocks and the driver settle
+const WARMUP_ITERATIONS: u32 = 2;
+/// Timed copies per benchmark
+const ITERATIONS: u32 = 10;
+/// Per-lane PCIe bandwidth in GB/s after encoding overhead, indexed by generation - 1
+const PCIE_LANE_GBPS: [f64; 5] = [0.25, 0.5, 0.985, 1.969, 3.938];
+
+/// Copy direction to measure
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BenchmarkKind {
+    /// Pinned host memory to device memory
+    HostToDevice,
+    /// Device memory to pinned host memory
+    DeviceToHost,
+    /// Between two device buffers
+    DeviceToDevice,
+}
+
+/// Measured bandwidth of one benchmark
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BenchmarkResult {
+    pub kind: BenchmarkKind,
+    pub device_index: u32,
+    pub transfer_size_mb: u64,
+    pub iterations: u32,
+    /// Mean bandwidth over the timed copies; device-to-device counts both the
+    /// read and the write
+    pub bandwidth_gbps: f64,
+    pub best_gbps: f64,
+    /// What the measurement can be compared against: the name-based memory
+    /// bandwidth estimate for device-to-device, the theoretical PCIe link
+    /// bandwidth for host transfers
+    pub reference_gbps: Option<f64>,
+}
+
+/// Run a bandwidth benchmark on a device
+///
+/// Allocates two buffers of `transfer_size_mb` and briefly loads the copy
+/// engines or memory; other workloads on the device skew the result.
+///
+/// # Arguments
+/// * `kind` - Copy direction to measure
+/// * `device_index` - Device to benchmark (defaults to 0)
+/// * `transfer_size_mb` - Size of each copy (defaults to 256 MB, at most 4096 MB)
+///
+/// # Returns
+/// * `Result<BenchmarkResult>` - Measured bandwidth, or error if the build or device cannot run it
+pub async fn run_benchmark(kind: BenchmarkKind, device_index: Option<u32>, transfer_size_mb: Option<u64>) -> Result<BenchmarkResult> {
+    let transfer_size_mb = transfer_size_mb.unwrap_or(DEFAULT_TRANSFER_MB);
+    if !(1..=MAX_TRANSFER_MB).contains(&transfer_size_mb) {
+        return Err(AppError::InvalidArgument(format!(
+            "Transfer size must be between 1 and {} MB, got {}",
+            MAX_TRANSFER_MB, transfer_size_mb
+        )).into());
+    }
+    let device_index = device_index.unwrap_or(0);
+    nvml::blocking(move || run_benchmark_blocking(kind, device_index, transfer_size_mb)).await
+}
+
+fn run_benchmark_blocking(kind: BenchmarkKind, device_index: u32, transfer_size_mb: u64) -> Result<BenchmarkResult> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let device = nvml::device_at(&nvml, device_index)?;
+    // CUDA orders devices differently from NVML; the PCI bus ID is shared
+    let bus_id = device.pci_info().context("Failed to read PCI bus ID")?.bus_id;
+    let reference_gbps = match kind {
+        BenchmarkKind::DeviceToDevice => device.name().ok().map(|name| nvml::estimate_memory_bandwidth(&name, 100) as f64),
+        _ => device.max_pcie_link_gen().ok()
+            .zip(device.max_pcie_link_width().ok())
+            .and_then(|(generation, width)| pcie_bandwidth_gbps(generation, width)),
+    };
+
+    let bytes = transfer_size_mb as usize * 1024 * 1024;
+    let timings = driver::measure_copies(kind, &bus_id, bytes, WARMUP_ITERATIONS, ITERATIONS)
+        .with_context(|| format!("Failed to benchmark GPU {}", device_index))?;
+    Ok(summarize(kind, device_index, transfer_size_mb, &timings, reference_gbps))
+}
+
+// Bandwidth from copy timings; a device-to-device copy moves every byte twice
+fn summarize(
+    kind: BenchmarkKind,
+    device_index: u32,
+    transfer_size_mb: u64,
+    timings: &[Duration],
+    reference_gbps: Option<f64>,
+) -> BenchmarkResult {
+    let passes = if kind == BenchmarkKind::DeviceToDevice { 2.0 } else { 1.0 };
+    let gigabytes = transfer_size_mb as f64 * 1024.0 * 1024.0 * passes / 1e9;
+    let total: Duration = timings.iter().sum();
+    let fastest = timings.iter().min().copied().unwrap_or_default();
+    let rate = |seconds: f64, count: usize| if seconds > 0.0 { gigabytes * count as f64 / seconds } else { 0.0 };
+
+    BenchmarkResult {
+        kind,
+        device_index,
+        transfer_size_mb,
+        iterations: timings.len() as u32,
+        bandwidth_gbps: rate(total.as_secs_f64(), timings.len()),
+        best_gbps: rate(fastest.as_secs_f64(), 1),
+        reference_gbps,
+    }
+}
+
+fn pcie_bandwidth_gbps(generation: u32, width: u32) -> Option<f64> {
+    let lane = PCIE_LANE_GBPS.get(generation.checked_sub(1)? as usize)?;
+    Some(lane * width as f64)
+}
+
+#[cfg(not(feature = "cuda"))]
+mod driver {
+    use super::*;
+
+    pub fn measure_copies(_kind: BenchmarkKind, _bus_id: &str, _bytes: usize, _warmup: u32, _iterations: u32) -> Result<Vec<Duration>> {
+        Err(AppError::NotSupported("This build does not include benchmarks; rebuild with `--features cuda`".to_string()).into())
+    }
+}
+
+#[cfg(feature = "cuda")]
+mod driver {
+    use super::*;
+    use crate::cuda::Cuda;
+    use std::time::Instant;
+
+    pub fn measure_copies(kind: BenchmarkKind, bus_id: &str, bytes: usize, warmup: u32, iterations: u32) -> Result<Vec<Duration>> {
+        let cuda = Cuda::load()?;
+        let context = cuda.create_context(bus_id)?;
+        let (dst, src) = match kind {
+            BenchmarkKind::HostToDevice => (context.alloc_device(bytes)?, context.alloc_host(bytes)?),
+            BenchmarkKind::DeviceToHost => (context.alloc_host(bytes)?, context.alloc_device(bytes)?),
+            BenchmarkKind::DeviceToDevice => (context.alloc_device(bytes)?, context.alloc_device(bytes)?),
+        };
+        for _ in 0..warmup {
+            context.copy(&dst, &src, bytes)?;
+        }
+        context.synchronize()?;
+
+        (0..iterations)
+            .map(|_| {
+                let start = Instant::now();
+                context.copy(&dst, &src, bytes)?;
+                context.synchronize()?;
+                Ok(start.elapsed())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize_counts_both_passes_for_device_copies() {
+        let timings = [Duration::from_millis(100), Duration::from_millis(50), Duration::from_millis(150)];
+        let host = summarize(BenchmarkKind::HostToDevice, 0, 1000, &timings, None);
+        let device = summarize(BenchmarkKind::DeviceToDevice, 0, 1000, &timings, None);
+
+        let gigabytes = 1000.0 * 1024.0 * 1024.0 / 1e9;
+        assert!((host.bandwidth_gbps - gigabytes / 0.1).abs() < 1e-9);
+        assert!((host.best_gbps - gigabytes / 0.05).abs() < 1e-9);
+        assert!((device.bandwidth_gbps - 2.0 * host.bandwidth_gbps).abs() < 1e-9);
+        assert_eq!(device.iterations, 3);
+    }
+
+    #[test]
+    fn test_pcie_bandwidth() {
+        assert_eq!(pcie_bandwidth_gbps(4, 16), Some(1.969 * 16.0));
+        assert_eq!(pcie_bandwidth_gbps(0, 16), None);
+        assert_eq!(pcie_bandwidth_gbps(9, 16), None);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_oversized_transfer() {
+        let err = run_benchmark(BenchmarkKind::HostToDevice, None, Some(MAX_TRANSFER_MB + 1)).await.unwrap_err();
+        assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+    }
+}
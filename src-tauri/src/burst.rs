@@ -0,0 +1,216 @@
+//! Burst capture
+//!
+//! Streams are held to `MIN_STREAM_PERIOD_MS`, which is too coarse to see
+//! spikes lasting a few tens of milliseconds. A burst lowers the period of
+//! a running stream to as little as `MIN_BURST_PERIOD_MS` for a bounded
+//! time, after which every device falls back to its normal period. While
+//! it runs, the achieved interval and the time spent in NVML calls are
+//! measured per device: when the calls alone take longer than the
+//! requested period, NVML rather than the stream is what limits the rate.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::error::AppError;
+
+/// Shortest period a burst may sample at
+pub const MIN_BURST_PERIOD_MS: u64 = 10;
+/// Longest a burst may run
+pub const MAX_BURST_DURATION_MS: u64 = 30_000;
+
+/// Measured rate of one device during a burst
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BurstDeviceRate {
+    pub device_index: u32,
+    pub frames: u64,
+    /// Mean time between frames
+    pub achieved_period_ms: f64,
+    pub achieved_rate_hz: f64,
+    /// Mean time spent in the NVML calls of one frame
+    pub mean_sample_ms: f64,
+    /// The NVML calls alone take longer than the requested period
+    pub nvml_limited: bool,
+}
+
+/// State of the current or last burst
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct BurstStatus {
+    pub active: bool,
+    pub requested_period_ms: u64,
+    pub duration_ms: u64,
+    /// Time left before the stream falls back to its normal period
+    pub remaining_ms: u64,
+    pub devices: Vec<BurstDeviceRate>,
+}
+
+// Measurements of one device
+#[derive(Debug, Default)]
+struct DeviceTiming {
+    frames: u64,
+    first_frame: Option<Instant>,
+    last_frame: Option<Instant>,
+    sampling: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Burst {
+    period_ms: u64,
+    duration_ms: u64,
+    started: Option<Instant>,
+    active: bool,
+    devices: BTreeMap<u32, DeviceTiming>,
+}
+
+/// Burst state shared by the stream's device tasks
+#[derive(Debug, Default)]
+pub struct BurstControl {
+    burst: Mutex<Burst>,
+}
+
+impl BurstControl {
+    /// Start a burst, replacing any burst still running
+    ///
+    /// # Arguments
+    /// * `period_ms` - Sampling period during the burst (at least `MIN_BURST_PERIOD_MS`)
+    /// * `duration_ms` - How long the burst lasts (at most `MAX_BURST_DURATION_MS`)
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success or error for a period or duration out of range
+    pub fn start(&self, period_ms: u64, duration_ms: u64) -> Result<()> {
+        if period_ms < MIN_BURST_PERIOD_MS {
+            return Err(AppError::InvalidArgument(format!("Burst period must be at least {} ms, got {}", MIN_BURST_PERIOD_MS, period_ms)).into());
+        }
+        if !(1..=MAX_BURST_DURATION_MS).contains(&duration_ms) {
+            return Err(AppError::InvalidArgument(format!("Burst duration must be between 1 and {} ms, got {}", MAX_BURST_DURATION_MS, duration_ms)).into());
+        }
+        *self.burst.lock().unwrap() = Burst {
+            period_ms,
+            duration_ms,
+            started: Some(Instant::now()),
+            active: true,
+            devices: BTreeMap::new(),
+        };
+        Ok(())
+    }
+
+    /// Period to sample at now, if a burst is running
+    pub fn period_ms(&self) -> Option<u64> {
+        let burst = self.burst.lock().unwrap();
+        (burst.active && !burst.expired()).then_some(burst.period_ms)
+    }
+
+    /// Record a frame sampled during a burst
+    ///
+    /// # Arguments
+    /// * `device_index` - Device the frame is from
+    /// * `sampling` - Time the NVML calls for the frame took
+    pub fn record(&self, device_index: u32, sampling: Duration) {
+        let mut burst = self.burst.lock().unwrap();
+        if !burst.active || burst.expired() {
+            return;
+        }
+        let now = Instant::now();
+        let timing = burst.devices.entry(device_index).or_default();
+        timing.frames += 1;
+        timing.first_frame.get_or_insert(now);
+        timing.last_frame = Some(now);
+        timing.sampling += sampling;
+    }
+
+    /// End a burst whose time is up
+    ///
+    /// # Returns
+    /// * `Option<BurstStatus>` - Final measurements, returned only to the first caller after expiry
+    pub fn finish_if_expired(&self) -> Option<BurstStatus> {
+        let mut burst = self.burst.lock().unwrap();
+        if !burst.active || !burst.expired() {
+            return None;
+        }
+        burst.active = false;
+        Some(burst.status())
+    }
+
+    pub fn status(&self) -> BurstStatus {
+        self.burst.lock().unwrap().status()
+    }
+}
+
+impl Burst {
+    fn elapsed_ms(&self) -> u64 {
+        self.started.map_or(0, |started| started.elapsed().as_millis() as u64)
+    }
+
+    fn expired(&self) -> bool {
+        self.elapsed_ms() >= self.duration_ms
+    }
+
+    fn status(&self) -> BurstStatus {
+        BurstStatus {
+            active: self.active && !self.expired(),
+            requested_period_ms: self.period_ms,
+            duration_ms: self.duration_ms,
+            remaining_ms: if self.active { self.duration_ms.saturating_sub(self.elapsed_ms()) } else { 0 },
+            devices: self.devices.iter()
+                .map(|(&device_index, timing)| rate(device_index, timing, self.period_ms))
+                .collect(),
+        }
+    }
+}
+
+fn rate(device_index: u32, timing: &DeviceTiming, requested_period_ms: u64) -> BurstDeviceRate {
+    let span_ms = match (timing.first_frame, timing.last_frame) {
+        (Some(first), Some(last)) => last.duration_since(first).as_secs_f64() * 1000.0,
+        _ => 0.0,
+    };
+    let intervals = timing.frames.saturating_sub(1);
+    let achieved_period_ms = if intervals > 0 { span_ms / intervals as f64 } else { 0.0 };
+    let mean_sample_ms = if timing.frames > 0 { timing.sampling.as_secs_f64() * 1000.0 / timing.frames as f64 } else { 0.0 };
+    BurstDeviceRate {
+        device_index,
+        frames: timing.frames,
+        achieved_period_ms,
+        achieved_rate_hz: if achieved_period_ms > 0.0 { 1000.0 / achieved_period_ms } else { 0.0 },
+        mean_sample_ms,
+        nvml_limited: mean_sample_ms > requested_period_ms as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_validates_and_expires() {
+        let control = BurstControl::default();
+        assert!(control.start(5, 1_000).is_err());
+        assert!(control.start(10, 0).is_err());
+        assert!(control.start(10, MAX_BURST_DURATION_MS + 1).is_err());
+        assert_eq!(control.period_ms(), None);
+
+        control.start(10, 1).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(control.period_ms(), None);
+        let finished = control.finish_if_expired().unwrap();
+        assert_eq!((finished.active, finished.requested_period_ms, finished.remaining_ms), (false, 10, 0));
+        assert_eq!(control.finish_if_expired(), None);
+    }
+
+    #[test]
+    fn test_rate_flags_nvml_as_limiter() {
+        let now = Instant::now();
+        let timing = DeviceTiming {
+            frames: 11,
+            first_frame: Some(now),
+            last_frame: Some(now + Duration::from_millis(150)),
+            sampling: Duration::from_millis(154),
+        };
+        let rate = rate(0, &timing, 10);
+        assert_eq!((rate.frames, rate.achieved_period_ms, rate.achieved_rate_hz), (11, 15.0, 1000.0 / 15.0));
+        assert_eq!(rate.mean_sample_ms, 14.0);
+        assert!(rate.nvml_limited);
+    }
+}
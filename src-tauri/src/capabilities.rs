@@ -0,0 +1,240 @@
+//! Per-device capability probing
+//!
+//! Some NVML calls only work on certain GPU classes (ECC and accounting on
+//! datacenter boards, NVLink on multi-GPU systems) or with administrator
+//! rights, and fail the same way every time they are made. Each device is
+//! probed once at startup with read-only calls and the outcome is cached,
+//! so periodic work can skip calls known to fail instead of erroring every
+//! tick. Control capabilities are judged without changing any setting.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::enum_wrappers::device::{
+    Api, EccCounter, MemoryError, PerformancePolicy, RetirementCause, Sampling,
+};
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::{device::Device, Nvml};
+use serde::Serialize;
+use std::sync::RwLock;
+
+use crate::nvml;
+use crate::tuning;
+
+/// NVML feature that may be unavailable on a device
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Per-process accounting statistics
+    Accounting,
+    Ecc,
+    EccErrorCounts,
+    RetiredPages,
+    ViolationCounters,
+    SampleBuffers,
+    ProcessList,
+    ProcessUtilization,
+    Nvlink,
+    FanSpeed,
+    FanControl,
+    PowerLimitControl,
+    ApplicationClocks,
+    ClockOffsets,
+}
+
+/// Outcome of probing a capability
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CapabilityStatus {
+    Available,
+    /// Supported but switched off, e.g. accounting mode
+    Disabled,
+    /// Needs administrator rights
+    NoPermission,
+    NotSupported,
+    /// The call failed for another reason
+    Failed,
+}
+
+/// Probe result of one capability
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CapabilityProbe {
+    pub capability: Capability,
+    pub status: CapabilityStatus,
+    pub detail: Option<String>,
+}
+
+/// Probe results of one device
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct DeviceCapabilities {
+    pub device_index: u32,
+    pub name: String,
+    pub probes: Vec<CapabilityProbe>,
+}
+
+impl DeviceCapabilities {
+    /// Status of a capability, if it was probed
+    pub fn status(&self, capability: Capability) -> Option<CapabilityStatus> {
+        self.probes.iter().find(|probe| probe.capability == capability).map(|probe| probe.status)
+    }
+}
+
+// Results of the last probe, `None` until the first one completes
+static CAPABILITIES: RwLock<Option<Vec<DeviceCapabilities>>> = RwLock::new(None);
+
+/// Probe every device and cache the results
+///
+/// # Returns
+/// * `Result<Vec<DeviceCapabilities>>` - Results per device or error if NVML is unavailable
+pub async fn probe() -> Result<Vec<DeviceCapabilities>> {
+    let capabilities = nvml::blocking(|| {
+        let nvml = Nvml::init().context("Failed to initialize NVML")?;
+        Ok(nvml::list_devices(&nvml)?.iter()
+            .enumerate()
+            .map(|(index, device)| probe_device(device, index as u32))
+            .collect::<Vec<_>>())
+    }).await?;
+    *CAPABILITIES.write().unwrap() = Some(capabilities.clone());
+    Ok(capabilities)
+}
+
+/// Probe at startup, logging rather than failing when NVML is unavailable
+pub async fn probe_on_launch() {
+    if let Err(e) = probe().await {
+        eprintln!("Device capability probe failed: {:#}", e);
+    }
+}
+
+/// Get the cached results, probing first if there are none or `refresh` is set
+///
+/// # Arguments
+/// * `refresh` - Probe again, e.g. after a driver setting changed
+pub async fn get_device_capabilities(refresh: bool) -> Result<Vec<DeviceCapabilities>> {
+    let cached = CAPABILITIES.read().unwrap().clone();
+    match cached {
+        Some(capabilities) if !refresh => Ok(capabilities),
+        _ => probe().await,
+    }
+}
+
+/// Whether a probe found a capability unusable on a device
+///
+/// Unprobed devices and capabilities count as usable, so callers fall back
+/// to making the call.
+pub fn known_unavailable(device_index: u32, capability: Capability) -> bool {
+    CAPABILITIES.read().unwrap().as_ref()
+        .and_then(|devices| devices.iter().find(|device| device.device_index == device_index))
+        .and_then(|device| device.status(capability))
+        .is_some_and(|status| !matches!(status, CapabilityStatus::Available | CapabilityStatus::Disabled))
+}
+
+fn probe_device(device: &Device, device_index: u32) -> DeviceCapabilities {
+    use Capability::*;
+    let probes = vec![
+        match device.is_accounting_enabled() {
+            Ok(true) => available(Accounting),
+            Ok(false) => CapabilityProbe { capability: Accounting, status: CapabilityStatus::Disabled, detail: Some("Accounting mode is off".to_string()) },
+            Err(e) => from_error(Accounting, e),
+        },
+        from_result(Ecc, device.is_ecc_enabled()),
+        from_result(EccErrorCounts, device.total_ecc_errors(MemoryError::Corrected, EccCounter::Volatile)),
+        from_result(RetiredPages, device.retired_pages(RetirementCause::MultipleSingleBitEccErrors)),
+        from_result(ViolationCounters, device.violation_status(PerformancePolicy::Power)),
+        // An empty buffer reports NotFound
+        match device.samples(Sampling::Power, None) {
+            Ok(_) | Err(NvmlError::NotFound) => available(SampleBuffers),
+            Err(e) => from_error(SampleBuffers, e),
+        },
+        from_result(ProcessList, device.running_compute_processes()),
+        match device.process_utilization_stats(None) {
+            Ok(_) | Err(NvmlError::NotFound) => available(ProcessUtilization),
+            Err(e) => from_error(ProcessUtilization, e),
+        },
+        from_result(Nvlink, device.link_wrapper_for(0).is_active()),
+        from_result(FanSpeed, device.fan_speed(0)),
+        if device.num_fans().unwrap_or(0) == 0 {
+            unavailable(FanControl, CapabilityStatus::NotSupported, "No controllable fans")
+        } else if !tuning::fan_control_supported() {
+            unavailable(FanControl, CapabilityStatus::NotSupported, "The driver does not support manual fan speeds")
+        } else {
+            with_admin_note(FanControl)
+        },
+        match device.power_management_limit_constraints() {
+            Ok(limits) if limits.max_limit > limits.min_limit => with_admin_note(PowerLimitControl),
+            Ok(_) => unavailable(PowerLimitControl, CapabilityStatus::NotSupported, "The power limit is fixed"),
+            Err(e) => from_error(PowerLimitControl, e),
+        },
+        match device.is_api_restricted(Api::ApplicationClocks) {
+            Ok(false) => available(ApplicationClocks),
+            Ok(true) => unavailable(ApplicationClocks, CapabilityStatus::NoPermission, "Restricted to administrators"),
+            Err(e) => from_error(ApplicationClocks, e),
+        },
+        if tuning::clock_offsets_supported() {
+            with_admin_note(ClockOffsets)
+        } else {
+            unavailable(ClockOffsets, CapabilityStatus::NotSupported, "The driver does not support clock offsets")
+        },
+    ];
+    DeviceCapabilities {
+        device_index,
+        name: device.name().unwrap_or_else(|_| format!("GPU {}", device_index)),
+        probes,
+    }
+}
+
+fn available(capability: Capability) -> CapabilityProbe {
+    CapabilityProbe { capability, status: CapabilityStatus::Available, detail: None }
+}
+
+// Setters are not called while probing; they may still be refused at use
+fn with_admin_note(capability: Capability) -> CapabilityProbe {
+    CapabilityProbe { detail: Some("Changing it usually requires administrator rights".to_string()), ..available(capability) }
+}
+
+fn unavailable(capability: Capability, status: CapabilityStatus, detail: &str) -> CapabilityProbe {
+    CapabilityProbe { capability, status, detail: Some(detail.to_string()) }
+}
+
+fn from_result<T>(capability: Capability, result: Result<T, NvmlError>) -> CapabilityProbe {
+    match result {
+        Ok(_) => available(capability),
+        Err(e) => from_error(capability, e),
+    }
+}
+
+fn from_error(capability: Capability, error: NvmlError) -> CapabilityProbe {
+    let status = match error {
+        NvmlError::NotSupported | NvmlError::FunctionNotFound => CapabilityStatus::NotSupported,
+        NvmlError::NoPermission => CapabilityStatus::NoPermission,
+        _ => CapabilityStatus::Failed,
+    };
+    CapabilityProbe { capability, status, detail: Some(error.to_string()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_map_to_statuses() {
+        assert_eq!(from_error(Capability::Ecc, NvmlError::NotSupported).status, CapabilityStatus::NotSupported);
+        assert_eq!(from_error(Capability::Accounting, NvmlError::NoPermission).status, CapabilityStatus::NoPermission);
+        assert_eq!(from_error(Capability::Nvlink, NvmlError::GpuLost).status, CapabilityStatus::Failed);
+        assert_eq!(from_result(Capability::FanSpeed, Ok::<u32, NvmlError>(40)), available(Capability::FanSpeed));
+    }
+
+    #[test]
+    fn test_known_unavailable_uses_cached_probe() {
+        *CAPABILITIES.write().unwrap() = Some(vec![DeviceCapabilities {
+            device_index: 0,
+            name: "GPU 0".to_string(),
+            probes: vec![
+                from_error(Capability::ViolationCounters, NvmlError::NotSupported),
+                CapabilityProbe { capability: Capability::Accounting, status: CapabilityStatus::Disabled, detail: None },
+            ],
+        }]);
+        assert!(known_unavailable(0, Capability::ViolationCounters));
+        assert!(!known_unavailable(0, Capability::Accounting));
+        assert!(!known_unavailable(0, Capability::Ecc));
+        assert!(!known_unavailable(1, Capability::ViolationCounters));
+        *CAPABILITIES.write().unwrap() = None;
+    }
+}
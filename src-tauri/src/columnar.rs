@@ -0,0 +1,482 @@
+//! Columnar binary recording format
+//!
+//! JSON recordings must be parsed in full before any sample can be read,
+//! which is slow for hour-long, high-rate sessions. The columnar format
+//! stores samples in fixed-size chunks, each laid out column by column,
+//! and ends with an index of chunk offsets and time ranges, so a time
+//! window of selected metrics can be read with a few seeks.
+//!
+//! Layout (all integers little-endian):
+//! * header: magic `GPUREC01`, header length `u32`, header JSON
+//!   (`ColumnarHeader`)
+//! * chunks: frame count `u32`, `u64` timestamps, then one `f64` block
+//!   per column in header order
+//! * index: one entry per chunk (offset `u64`, frames `u32`, first and
+//!   last timestamp `u64`)
+//! * trailer: entry count `u32`, index offset `u64`, magic `GPUIDX01`
+//!
+//! Only scalar metrics are stored; per-SM, per-fan and throttle-reason
+//! lists stay in the JSON recording.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+use crate::gaps::SampleGap;
+use crate::nvml::{StaticDeviceInfo, TelemetryFrame};
+use crate::schema;
+
+/// File extension of columnar recordings
+pub const COLUMNAR_EXTENSION: &str = "gpurec";
+/// Version of the columnar layout written by this build
+pub const COLUMNAR_FORMAT_VERSION: u32 = 1;
+/// Frames per chunk; one chunk is the smallest unit read from disk
+pub const CHUNK_FRAMES: usize = 1024;
+
+const HEADER_MAGIC: &[u8; 8] = b"GPUREC01";
+const TRAILER_MAGIC: &[u8; 8] = b"GPUIDX01";
+const INDEX_ENTRY_BYTES: u64 = 8 + 4 + 8 + 8;
+const TRAILER_BYTES: u64 = 4 + 8 + 8;
+
+/// Reads one column value from a frame
+pub type ColumnValue = fn(&TelemetryFrame) -> f64;
+
+/// Scalar metric columns stored in the columnar format, in file order
+pub const METRIC_COLUMNS: [(&str, ColumnValue); 10] = [
+    ("util_gpu", |frame| frame.util_gpu as f64),
+    ("util_memory", |frame| frame.util_memory as f64),
+    ("memory_used_mb", |frame| frame.memory_used_mb as f64),
+    ("sm_clock_mhz", |frame| frame.sm_clock_mhz as f64),
+    ("memory_clock_mhz", |frame| frame.memory_clock_mhz as f64),
+    ("temperature_c", |frame| frame.temperature_c as f64),
+    ("power_w", |frame| frame.power_w as f64),
+    ("fan_speed_percent", |frame| frame.fan_speed_percent as f64),
+    ("memory_bandwidth_gbps", |frame| frame.memory_bandwidth_gbps as f64),
+    ("pcie_utilization", |frame| frame.pcie_utilization as f64),
+];
+
+/// Self-describing header stored at the start of the file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ColumnarHeader {
+    pub format_version: u32,
+    pub device: StaticDeviceInfo,
+    pub columns: Vec<String>,
+}
+
+/// Header and extent of a columnar recording
+#[derive(Clone, Debug)]
+pub struct ColumnarSummary {
+    pub header: ColumnarHeader,
+    pub frames: u64,
+    /// Timestamps of the first and last frames; `None` for an empty recording
+    pub first_ms: Option<u64>,
+    pub last_ms: Option<u64>,
+}
+
+/// Location and time range of one chunk
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ChunkEntry {
+    offset: u64,
+    frames: u32,
+    first_ms: u64,
+    last_ms: u64,
+}
+
+/// Samples of selected metrics within a time window
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct RangeData {
+    pub device: StaticDeviceInfo,
+    pub timestamps: Vec<u64>,
+    /// Values per requested metric, aligned with `timestamps`
+    pub columns: BTreeMap<String, Vec<f64>>,
+    /// Sampling gaps, when marked by `gaps::mark`
+    pub gaps: Vec<SampleGap>,
+}
+
+/// Incremental writer for columnar recordings
+///
+/// Frames are buffered until a chunk is full; `finish` writes the last
+/// partial chunk and the index. Frames must be pushed in time order.
+pub struct ColumnarWriter<W: Write + Seek> {
+    writer: W,
+    pending: Vec<TelemetryFrame>,
+    index: Vec<ChunkEntry>,
+}
+
+impl<W: Write + Seek> ColumnarWriter<W> {
+    /// Start a recording by writing the header
+    pub fn new(mut writer: W, device: &StaticDeviceInfo) -> Result<Self> {
+        let header = ColumnarHeader {
+            format_version: COLUMNAR_FORMAT_VERSION,
+            device: device.clone(),
+            columns: METRIC_COLUMNS.iter().map(|(name, _)| name.to_string()).collect(),
+        };
+        let header = serde_json::to_vec(&header).context("Failed to serialize columnar header")?;
+        writer.write_all(HEADER_MAGIC)?;
+        writer.write_all(&(header.len() as u32).to_le_bytes())?;
+        writer.write_all(&header)?;
+
+        Ok(ColumnarWriter {
+            writer,
+            pending: Vec::with_capacity(CHUNK_FRAMES),
+            index: Vec::new(),
+        })
+    }
+
+    /// Append a frame, writing a chunk once enough frames are buffered
+    pub fn push(&mut self, frame: TelemetryFrame) -> Result<()> {
+        self.pending.push(frame);
+        if self.pending.len() >= CHUNK_FRAMES {
+            self.write_chunk()?;
+        }
+        Ok(())
+    }
+
+    /// Write remaining frames and the index, returning the inner writer
+    pub fn finish(mut self) -> Result<W> {
+        self.write_chunk()?;
+        let index_offset = self.writer.stream_position()?;
+        for entry in &self.index {
+            self.writer.write_all(&entry.offset.to_le_bytes())?;
+            self.writer.write_all(&entry.frames.to_le_bytes())?;
+            self.writer.write_all(&entry.first_ms.to_le_bytes())?;
+            self.writer.write_all(&entry.last_ms.to_le_bytes())?;
+        }
+        self.writer.write_all(&(self.index.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&index_offset.to_le_bytes())?;
+        self.writer.write_all(TRAILER_MAGIC)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_chunk(&mut self) -> Result<()> {
+        let (Some(first), Some(last)) = (self.pending.first(), self.pending.last()) else {
+            return Ok(());
+        };
+        let entry = ChunkEntry {
+            offset: self.writer.stream_position()?,
+            frames: self.pending.len() as u32,
+            first_ms: first.timestamp as u64,
+            last_ms: last.timestamp as u64,
+        };
+
+        let mut block = Vec::with_capacity(4 + self.pending.len() * 8 * (1 + METRIC_COLUMNS.len()));
+        block.extend_from_slice(&entry.frames.to_le_bytes());
+        for frame in &self.pending {
+            block.extend_from_slice(&(frame.timestamp as u64).to_le_bytes());
+        }
+        for (_, value) in METRIC_COLUMNS {
+            for frame in &self.pending {
+                block.extend_from_slice(&value(frame).to_le_bytes());
+            }
+        }
+        self.writer.write_all(&block)?;
+
+        self.index.push(entry);
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+/// Read selected metrics within a time window from a columnar recording
+///
+/// Only chunks overlapping the window are read, and within each chunk
+/// only the timestamp block and the requested columns.
+///
+/// # Arguments
+/// * `path` - Path to a `.gpurec` file
+/// * `start_ms` - Inclusive window start (Unix milliseconds)
+/// * `end_ms` - Inclusive window end (Unix milliseconds)
+/// * `metrics` - Column names to read; empty reads all columns
+///
+/// # Returns
+/// * `Result<RangeData>` - Samples in the window or error for unknown metrics or a malformed file
+pub fn read_range(path: &Path, start_ms: u64, end_ms: u64, metrics: &[String]) -> Result<RangeData> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open columnar recording {}", path.display()))?;
+    read_range_from(BufReader::new(file), start_ms, end_ms, metrics)
+}
+
+fn read_range_from<R: Read + Seek>(mut reader: R, start_ms: u64, end_ms: u64, metrics: &[String]) -> Result<RangeData> {
+    let header = read_header(&mut reader)?;
+    let selected: Vec<(usize, &String)> = if metrics.is_empty() {
+        header.columns.iter().enumerate().collect()
+    } else {
+        metrics.iter()
+            .map(|metric| {
+                header.columns.iter().position(|column| column == metric)
+                    .map(|position| (position, metric))
+                    .ok_or_else(|| AppError::InvalidArgument(format!("Unknown recording metric: {}", metric)))
+            })
+            .collect::<std::result::Result<_, _>>()?
+    };
+    let index = read_index(&mut reader, header.columns.len())?;
+
+    let mut data = RangeData {
+        device: header.device,
+        ..Default::default()
+    };
+    for (_, metric) in &selected {
+        data.columns.insert(metric.to_string(), Vec::new());
+    }
+
+    for chunk in index.iter().filter(|chunk| chunk.last_ms >= start_ms && chunk.first_ms <= end_ms) {
+        let frames = chunk.frames as usize;
+        let timestamps = read_u64_block(&mut reader, chunk.offset + 4, frames)?;
+        let rows: Vec<usize> = (0..frames)
+            .filter(|&row| (start_ms..=end_ms).contains(&timestamps[row]))
+            .collect();
+        data.timestamps.extend(rows.iter().map(|&row| timestamps[row]));
+
+        for (column, metric) in &selected {
+            let offset = chunk.offset + 4 + 8 * frames as u64 * (1 + *column as u64);
+            let values = read_f64_block(&mut reader, offset, frames)?;
+            data.columns.get_mut(metric.as_str()).unwrap()
+                .extend(rows.iter().map(|&row| values[row]));
+        }
+    }
+
+    Ok(data)
+}
+
+/// Read the header and frame span of a columnar recording without its chunks
+///
+/// # Arguments
+/// * `path` - Path to a `.gpurec` file
+///
+/// # Returns
+/// * `Result<ColumnarSummary>` - Header, frame count and time span or error for a malformed file
+pub fn read_summary(path: &Path) -> Result<ColumnarSummary> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open columnar recording {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let header = read_header(&mut reader)?;
+    let index = read_index(&mut reader, header.columns.len())?;
+    Ok(ColumnarSummary {
+        header,
+        frames: index.iter().map(|chunk| chunk.frames as u64).sum(),
+        first_ms: index.first().map(|chunk| chunk.first_ms),
+        last_ms: index.last().map(|chunk| chunk.last_ms),
+    })
+}
+
+/// Convert a JSON recording (any schema version) into a columnar recording
+///
+/// The output is written next to the input with the `.gpurec` extension.
+/// Only the primary device of a multi-device recording is converted, as the
+/// columnar header describes a single device.
+///
+/// # Arguments
+/// * `path` - Path to the JSON recording
+///
+/// # Returns
+/// * `Result<PathBuf>` - Path of the columnar recording or error
+pub fn convert_json_recording(path: &Path) -> Result<PathBuf> {
+    let recording = schema::load_recording(path)?;
+    let output = path.with_extension(COLUMNAR_EXTENSION);
+    let file = File::create(&output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+
+    let samples = if recording.devices.len() > 1 {
+        recording.samples_of(recording.device.index)
+    } else {
+        recording.samples
+    };
+    let mut writer = ColumnarWriter::new(BufWriter::new(file), &recording.device)?;
+    for frame in samples {
+        writer.push(frame)?;
+    }
+    writer.finish()?;
+    Ok(output)
+}
+
+fn malformed(what: &str) -> anyhow::Error {
+    AppError::InvalidArgument(format!("Not a valid columnar recording: {}", what)).into()
+}
+
+// Lengths and offsets read from the file are checked against its size
+// before anything is allocated, so a corrupt file cannot exhaust memory
+fn file_length<R: Seek>(reader: &mut R) -> Result<u64> {
+    Ok(reader.seek(SeekFrom::End(0))?)
+}
+
+fn read_header<R: Read + Seek>(reader: &mut R) -> Result<ColumnarHeader> {
+    let file_length = file_length(reader)?;
+    let mut magic = [0u8; 8];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut magic).map_err(|_| malformed("file too short"))?;
+    if &magic != HEADER_MAGIC {
+        return Err(malformed("bad header magic"));
+    }
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length).map_err(|_| malformed("file too short"))?;
+    let length = u64::from(u32::from_le_bytes(length));
+    if length > file_length.saturating_sub(12) {
+        return Err(malformed("header longer than the file"));
+    }
+    let mut header = vec![0u8; length as usize];
+    reader.read_exact(&mut header).map_err(|_| malformed("truncated header"))?;
+    let header: ColumnarHeader = serde_json::from_slice(&header).map_err(|_| malformed("unreadable header"))?;
+    if header.format_version > COLUMNAR_FORMAT_VERSION {
+        return Err(AppError::InvalidArgument(format!(
+            "Columnar format version {} is newer than supported version {}",
+            header.format_version, COLUMNAR_FORMAT_VERSION
+        )).into());
+    }
+    Ok(header)
+}
+
+// Read the chunk index, checking every chunk of `columns` columns lies
+// before the index
+fn read_index<R: Read + Seek>(reader: &mut R, columns: usize) -> Result<Vec<ChunkEntry>> {
+    let file_length = file_length(reader)?;
+    let mut trailer = [0u8; TRAILER_BYTES as usize];
+    if file_length < TRAILER_BYTES {
+        return Err(malformed("missing index"));
+    }
+    reader.seek(SeekFrom::Start(file_length - TRAILER_BYTES))?;
+    reader.read_exact(&mut trailer)?;
+    if &trailer[12..] != TRAILER_MAGIC {
+        return Err(malformed("missing index (recording not finished?)"));
+    }
+    let count = u64::from(u32::from_le_bytes(trailer[0..4].try_into().unwrap()));
+    let offset = u64::from_le_bytes(trailer[4..12].try_into().unwrap());
+    let index_bytes = count.checked_mul(INDEX_ENTRY_BYTES)
+        .filter(|&bytes| offset.checked_add(bytes) == Some(file_length - TRAILER_BYTES))
+        .ok_or_else(|| malformed("index does not fit the file"))?;
+
+    let mut entries = vec![0u8; index_bytes as usize];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut entries).map_err(|_| malformed("truncated index"))?;
+    let index: Vec<ChunkEntry> = entries.chunks_exact(INDEX_ENTRY_BYTES as usize)
+        .map(|entry| ChunkEntry {
+            offset: u64::from_le_bytes(entry[0..8].try_into().unwrap()),
+            frames: u32::from_le_bytes(entry[8..12].try_into().unwrap()),
+            first_ms: u64::from_le_bytes(entry[12..20].try_into().unwrap()),
+            last_ms: u64::from_le_bytes(entry[20..28].try_into().unwrap()),
+        })
+        .collect();
+
+    // A chunk holds its frame count, timestamps and one block per column
+    let blocks = columns as u64 + 1;
+    for chunk in &index {
+        let end = u64::from(chunk.frames).checked_mul(8 * blocks)
+            .and_then(|bytes| bytes.checked_add(4))
+            .and_then(|bytes| chunk.offset.checked_add(bytes));
+        if end.is_none_or(|end| end > offset) {
+            return Err(malformed("chunk extends past the index"));
+        }
+    }
+    Ok(index)
+}
+
+fn read_block<R: Read + Seek>(reader: &mut R, offset: u64, count: usize) -> Result<Vec<[u8; 8]>> {
+    let mut bytes = vec![0u8; count * 8];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut bytes).map_err(|_| malformed("truncated chunk"))?;
+    Ok(bytes.chunks_exact(8).map(|value| value.try_into().unwrap()).collect())
+}
+
+fn read_u64_block<R: Read + Seek>(reader: &mut R, offset: u64, count: usize) -> Result<Vec<u64>> {
+    Ok(read_block(reader, offset, count)?.into_iter().map(u64::from_le_bytes).collect())
+}
+
+fn read_f64_block<R: Read + Seek>(reader: &mut R, offset: u64, count: usize) -> Result<Vec<f64>> {
+    Ok(read_block(reader, offset, count)?.into_iter().map(f64::from_le_bytes).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn write_frames(count: u64) -> Cursor<Vec<u8>> {
+        let device = StaticDeviceInfo { name: "Test GPU".to_string(), ..Default::default() };
+        let mut writer = ColumnarWriter::new(Cursor::new(Vec::new()), &device).unwrap();
+        for i in 0..count {
+            writer.push(TelemetryFrame {
+                timestamp: 1_000 + i as u128 * 10,
+                util_gpu: (i % 100) as u32,
+                temperature_c: 40 + (i % 30) as u32,
+                ..Default::default()
+            }).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_read_range_across_chunks() {
+        let count = CHUNK_FRAMES as u64 * 2 + 10;
+        let mut file = write_frames(count);
+        assert_eq!(read_index(&mut file, METRIC_COLUMNS.len()).unwrap().len(), 3);
+
+        // Window straddling the first chunk boundary
+        let start = 1_000 + (CHUNK_FRAMES as u64 - 2) * 10;
+        let metrics = vec!["util_gpu".to_string()];
+        let data = read_range_from(file, start, start + 30, &metrics).unwrap();
+
+        assert_eq!(data.device.name, "Test GPU");
+        assert_eq!(data.timestamps, vec![start, start + 10, start + 20, start + 30]);
+        let expected: Vec<f64> = (CHUNK_FRAMES as u64 - 2..CHUNK_FRAMES as u64 + 2)
+            .map(|i| (i % 100) as f64)
+            .collect();
+        assert_eq!(data.columns["util_gpu"], expected);
+        assert_eq!(data.columns.len(), 1);
+    }
+
+    #[test]
+    fn test_read_range_all_columns_and_empty_window() {
+        let data = read_range_from(write_frames(5), 0, u64::MAX, &[]).unwrap();
+        assert_eq!(data.timestamps.len(), 5);
+        assert_eq!(data.columns.len(), METRIC_COLUMNS.len());
+        assert_eq!(data.columns["temperature_c"][4], 44.0);
+
+        let data = read_range_from(write_frames(5), 5_000, 6_000, &[]).unwrap();
+        assert!(data.timestamps.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_unknown_metric_and_unfinished_file() {
+        let err = read_range_from(write_frames(5), 0, u64::MAX, &["bogus".to_string()]).unwrap_err();
+        assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+
+        let device = StaticDeviceInfo::default();
+        let unfinished = ColumnarWriter::new(Cursor::new(Vec::new()), &device).unwrap().writer;
+        assert!(read_range_from(unfinished, 0, u64::MAX, &[]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_corrupt_lengths_without_allocating() {
+        let valid = write_frames(5).into_inner();
+        let length = valid.len();
+        let corrupt = |at: usize, bytes: &[u8]| {
+            let mut file = valid.clone();
+            file[at..at + bytes.len()].copy_from_slice(bytes);
+            Cursor::new(file)
+        };
+        let index_offset = u64::from_le_bytes(valid[length - 16..length - 8].try_into().unwrap()) as usize;
+
+        for file in [
+            // Header length of 4 GiB
+            corrupt(8, &u32::MAX.to_le_bytes()),
+            // Index entry count far beyond the file
+            corrupt(length - 20, &u32::MAX.to_le_bytes()),
+            // Index offset that overflows when the index is added
+            corrupt(length - 16, &u64::MAX.to_le_bytes()),
+            // Chunk claiming 4 billion frames
+            corrupt(index_offset + 8, &u32::MAX.to_le_bytes()),
+            // Chunk offset that overflows
+            corrupt(index_offset, &u64::MAX.to_le_bytes()),
+            // Truncated in the middle of the chunks
+            Cursor::new([&valid[..40], &valid[length - 20..]].concat()),
+            Cursor::new(valid[..10].to_vec()),
+        ] {
+            let err = read_range_from(file, 0, u64::MAX, &[]).unwrap_err();
+            assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+        }
+    }
+}
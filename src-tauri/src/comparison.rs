@@ -0,0 +1,314 @@
+//! Marker-aligned comparison of two recordings
+//!
+//! Two runs of the same workload rarely start at the same moment or run at
+//! the same pace, so comparing them by absolute time lines up unrelated
+//! phases. Recordings are aligned instead on the NVTX markers both contain
+//! (e.g. "epoch start"): the k-th marker of a label in one recording is
+//! paired with the k-th of that label in the other. The first recording
+//! keeps its own time base; the second is stretched piecewise between
+//! anchors onto it. Both are then resampled onto one time grid, so the
+//! frontend can plot them side by side or overlaid.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::columnar::{ColumnValue, METRIC_COLUMNS};
+use crate::error::AppError;
+use crate::gaps;
+use crate::markers::MarkerRange;
+use crate::nvml::{RecordingFile, TelemetryFrame};
+use crate::schema;
+
+/// Points in the time grid when the query does not set a step
+pub const DEFAULT_POINTS: u64 = 1_000;
+/// Most points a comparison may have
+pub const MAX_POINTS: u64 = 100_000;
+
+/// What to compare and how
+#[derive(Clone, Debug, Default)]
+pub struct ComparisonOptions {
+    /// Marker labels to align on; every label both recordings share if unset
+    pub labels: Option<Vec<String>>,
+    /// Recording column names; every metric if empty
+    pub metrics: Vec<String>,
+    /// Device of each multi-device recording (the primary device if unset)
+    pub device_a: Option<u32>,
+    pub device_b: Option<u32>,
+    /// Grid spacing; chosen for about `DEFAULT_POINTS` points if unset
+    pub step_ms: Option<u64>,
+}
+
+/// Pair of markers the recordings are aligned on
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AlignmentAnchor {
+    pub label: String,
+    /// Which occurrence of the label this is (0 = first)
+    pub occurrence: usize,
+    /// Marker start in each recording (Unix milliseconds)
+    pub a_ms: u128,
+    pub b_ms: u128,
+}
+
+/// One metric of both recordings on the shared grid
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PairedSeries {
+    pub metric: String,
+    /// `None` where a recording has no samples
+    pub a: Vec<Option<f64>>,
+    pub b: Vec<Option<f64>>,
+}
+
+/// Two recordings resampled onto a shared, marker-aligned time grid
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RecordingComparison {
+    pub anchors: Vec<AlignmentAnchor>,
+    pub step_ms: u64,
+    /// Grid times in milliseconds relative to the first anchor in recording A
+    pub offsets_ms: Vec<f64>,
+    pub series: Vec<PairedSeries>,
+}
+
+/// Align two saved recordings on their shared markers and resample them
+///
+/// # Arguments
+/// * `path_a` - Reference recording, whose time base is kept
+/// * `path_b` - Recording aligned onto the reference
+/// * `options` - Labels, metrics, devices and grid spacing
+///
+/// # Returns
+/// * `Result<RecordingComparison>` - Paired series or error if the recordings share no markers
+pub fn compare_recordings(path_a: &Path, path_b: &Path, options: &ComparisonOptions) -> Result<RecordingComparison> {
+    let a = schema::load_recording(path_a)?;
+    let b = schema::load_recording(path_b)?;
+    compare(&a, &b, options)
+}
+
+/// Align two loaded recordings; see `compare_recordings`
+pub fn compare(a: &RecordingFile, b: &RecordingFile, options: &ComparisonOptions) -> Result<RecordingComparison> {
+    let anchors = match_anchors(&a.markers, &b.markers, options.labels.as_deref());
+    let Some(origin) = anchors.first().map(|anchor| anchor.a_ms as f64) else {
+        return Err(AppError::InvalidArgument("The recordings share no markers to align on".to_string()).into());
+    };
+    let metrics = metric_columns(&options.metrics)?;
+
+    let side_a = Side::new(a, options.device_a, |ms| ms - origin)?;
+    let side_b = Side::new(b, options.device_b, |ms| warp(&anchors, ms) - origin)?;
+    let (Some((a_first, a_last)), Some((b_first, b_last))) = (side_a.span(), side_b.span()) else {
+        return Err(AppError::InvalidArgument("A recording has no samples of the compared device".to_string()).into());
+    };
+    let (first, last) = (a_first.min(b_first), a_last.max(b_last));
+
+    let step_ms = match options.step_ms {
+        Some(0) => return Err(AppError::InvalidArgument("Comparison step must be at least 1 ms".to_string()).into()),
+        Some(step) => step,
+        None => ((last - first) / DEFAULT_POINTS as f64).ceil().max(1.0) as u64,
+    };
+    let points = ((last - first) / step_ms as f64) as u64 + 1;
+    if points > MAX_POINTS {
+        return Err(AppError::InvalidArgument(format!("A {} ms step gives {} points, more than the {} allowed", step_ms, points, MAX_POINTS)).into());
+    }
+    let offsets_ms: Vec<f64> = (0..points).map(|point| first + (point * step_ms) as f64).collect();
+
+    Ok(RecordingComparison {
+        series: metrics.into_iter()
+            .map(|(metric, value)| PairedSeries {
+                metric: metric.to_string(),
+                a: side_a.resample(value, &offsets_ms),
+                b: side_b.resample(value, &offsets_ms),
+            })
+            .collect(),
+        anchors,
+        step_ms,
+        offsets_ms,
+    })
+}
+
+/// Pair the markers of two recordings by label and occurrence
+///
+/// Pairs that would run time backwards in recording B relative to earlier
+/// pairs are dropped, so the alignment stays monotonic.
+///
+/// # Arguments
+/// * `a` - Markers of the reference recording
+/// * `b` - Markers of the aligned recording
+/// * `labels` - Labels to pair; every shared label if unset
+///
+/// # Returns
+/// * `Vec<AlignmentAnchor>` - Anchors in time order
+pub fn match_anchors(a: &[MarkerRange], b: &[MarkerRange], labels: Option<&[String]>) -> Vec<AlignmentAnchor> {
+    let (starts_a, starts_b) = (starts_by_label(a), starts_by_label(b));
+    let wanted: Option<BTreeSet<&str>> = labels.map(|labels| labels.iter().map(String::as_str).collect());
+
+    let mut pairs: Vec<AlignmentAnchor> = starts_a.iter()
+        .filter(|(label, _)| wanted.as_ref().is_none_or(|wanted| wanted.contains(*label)))
+        .filter_map(|(label, a_starts)| Some((label, a_starts, starts_b.get(label)?)))
+        .flat_map(|(label, a_starts, b_starts)| {
+            a_starts.iter().zip(b_starts).enumerate().map(|(occurrence, (&a_ms, &b_ms))| AlignmentAnchor {
+                label: label.to_string(),
+                occurrence,
+                a_ms,
+                b_ms,
+            })
+        })
+        .collect();
+    pairs.sort_by_key(|anchor| (anchor.a_ms, anchor.b_ms));
+
+    let mut anchors: Vec<AlignmentAnchor> = Vec::with_capacity(pairs.len());
+    for anchor in pairs {
+        if anchors.last().is_none_or(|last| anchor.a_ms > last.a_ms && anchor.b_ms > last.b_ms) {
+            anchors.push(anchor);
+        }
+    }
+    anchors
+}
+
+// Marker start times per label, in time order
+fn starts_by_label(markers: &[MarkerRange]) -> BTreeMap<&str, Vec<u128>> {
+    let mut by_label: BTreeMap<&str, Vec<u128>> = BTreeMap::new();
+    for marker in markers {
+        by_label.entry(marker.name.as_str()).or_default().push(marker.start_ms);
+    }
+    by_label.values_mut().for_each(|starts| starts.sort_unstable());
+    by_label
+}
+
+// Map a time of recording B onto recording A's time base, stretching
+// linearly between anchors and shifting by the nearest anchor outside them
+fn warp(anchors: &[AlignmentAnchor], b_ms: f64) -> f64 {
+    let segment = anchors.windows(2).find(|pair| b_ms < pair[1].b_ms as f64);
+    match segment {
+        Some([start, end]) if b_ms >= start.b_ms as f64 => {
+            let scale = (end.a_ms - start.a_ms) as f64 / (end.b_ms - start.b_ms) as f64;
+            start.a_ms as f64 + (b_ms - start.b_ms as f64) * scale
+        }
+        _ => {
+            let nearest = if b_ms < anchors[0].b_ms as f64 { &anchors[0] } else { &anchors[anchors.len() - 1] };
+            b_ms - nearest.b_ms as f64 + nearest.a_ms as f64
+        }
+    }
+}
+
+fn metric_columns(metrics: &[String]) -> Result<Vec<(&'static str, ColumnValue)>> {
+    if metrics.is_empty() {
+        return Ok(METRIC_COLUMNS.to_vec());
+    }
+    metrics.iter()
+        .map(|metric| METRIC_COLUMNS.iter().find(|(name, _)| name == metric).copied()
+            .ok_or_else(|| AppError::InvalidArgument(format!("Unknown recording metric: {}", metric)).into()))
+        .collect()
+}
+
+// Frames of one device with their times on the shared time base
+struct Side<'a> {
+    frames: Vec<(f64, &'a TelemetryFrame)>,
+    // Indices of frames followed by a sampling gap
+    gap_after: BTreeSet<usize>,
+}
+
+impl<'a> Side<'a> {
+    fn new(recording: &'a RecordingFile, device_index: Option<u32>, to_offset: impl Fn(f64) -> f64) -> Result<Self> {
+        let device_index = device_index.unwrap_or(recording.device.index);
+        if !recording.devices.iter().any(|device| device.index == device_index) {
+            return Err(AppError::InvalidArgument(format!("GPU {} is not in the recording", device_index)).into());
+        }
+        let mut timed: Vec<(u128, &TelemetryFrame)> = recording.samples.iter()
+            .filter(|frame| frame.device_index == device_index)
+            .map(|frame| (recording.steady_timestamp(frame), frame))
+            .collect();
+        timed.sort_by_key(|(timestamp, _)| *timestamp);
+
+        let timestamps: Vec<u64> = timed.iter().map(|(timestamp, _)| *timestamp as u64).collect();
+        let gap_starts: BTreeSet<u64> = gaps::detect(&timestamps, None).into_iter().map(|gap| gap.start_ms).collect();
+        Ok(Side {
+            gap_after: timestamps.iter().enumerate()
+                .filter(|(_, timestamp)| gap_starts.contains(timestamp))
+                .map(|(index, _)| index)
+                .collect(),
+            frames: timed.into_iter().map(|(timestamp, frame)| (to_offset(timestamp as f64), frame)).collect(),
+        })
+    }
+
+    fn span(&self) -> Option<(f64, f64)> {
+        Some((self.frames.first()?.0, self.frames.last()?.0))
+    }
+
+    // Linearly interpolated values at each offset; `None` outside the
+    // recording and inside sampling gaps
+    fn resample(&self, value: ColumnValue, offsets_ms: &[f64]) -> Vec<Option<f64>> {
+        offsets_ms.iter()
+            .map(|&offset| {
+                let after = self.frames.partition_point(|(time, _)| *time < offset);
+                let (time_after, frame_after) = self.frames.get(after)?;
+                if *time_after == offset {
+                    return Some(value(frame_after));
+                }
+                let before = after.checked_sub(1)?;
+                if self.gap_after.contains(&before) {
+                    return None;
+                }
+                let (time_before, frame_before) = self.frames[before];
+                let fraction = (offset - time_before) / (time_after - time_before);
+                Some(value(frame_before) + (value(frame_after) - value(frame_before)) * fraction)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvml::StaticDeviceInfo;
+
+    fn marker(name: &str, start_ms: u128) -> MarkerRange {
+        MarkerRange { name: name.to_string(), pid: 1, tid: None, domain: None, start_ms, end_ms: start_ms + 10, depth: 0 }
+    }
+
+    fn recording(start_ms: u128, period_ms: u128, utilization: &[u32], markers: Vec<MarkerRange>) -> RecordingFile {
+        RecordingFile {
+            schema_version: schema::CURRENT_SCHEMA_VERSION,
+            device: StaticDeviceInfo::default(),
+            devices: vec![StaticDeviceInfo::default()],
+            samples: utilization.iter().enumerate()
+                .map(|(i, &util_gpu)| TelemetryFrame { timestamp: start_ms + i as u128 * period_ms, util_gpu, ..Default::default() })
+                .collect(),
+            markers,
+            timing: None,
+            process: None,
+            violations: Vec::new(),
+            clock: None,
+        }
+    }
+
+    #[test]
+    fn test_match_anchors_pairs_occurrences_in_order() {
+        let a = [marker("epoch start", 100), marker("epoch start", 300), marker("eval", 250), marker("only a", 50)];
+        let b = [marker("epoch start", 1_100), marker("eval", 1_050), marker("epoch start", 1_500)];
+        let anchors = match_anchors(&a, &b, None);
+        let pairs: Vec<(&str, usize, u128, u128)> = anchors.iter().map(|anchor| (anchor.label.as_str(), anchor.occurrence, anchor.a_ms, anchor.b_ms)).collect();
+        // "eval" runs backwards relative to the first epoch and is dropped
+        assert_eq!(pairs, vec![("epoch start", 0, 100, 1_100), ("epoch start", 1, 300, 1_500)]);
+
+        assert!(match_anchors(&a, &b, Some(&["only a".to_string()])).is_empty());
+    }
+
+    #[test]
+    fn test_compare_stretches_second_recording_between_anchors() {
+        // B runs at half speed: its epochs take 200 ms where A's take 100 ms
+        let a = recording(1_000, 50, &[0, 10, 20, 30, 40], vec![marker("epoch", 1_000), marker("epoch", 1_100)]);
+        let b = recording(5_000, 100, &[0, 10, 20, 30, 40], vec![marker("epoch", 5_000), marker("epoch", 5_200)]);
+        let options = ComparisonOptions { metrics: vec!["util_gpu".to_string()], step_ms: Some(50), ..Default::default() };
+        let comparison = compare(&a, &b, &options).unwrap();
+
+        assert_eq!(comparison.offsets_ms, vec![0.0, 50.0, 100.0, 150.0, 200.0, 250.0, 300.0]);
+        let series = &comparison.series[0];
+        assert_eq!(series.a, vec![Some(0.0), Some(10.0), Some(20.0), Some(30.0), Some(40.0), None, None]);
+        // Inside the anchors B is compressed; after the last one it is only shifted
+        assert_eq!(series.b, vec![Some(0.0), Some(10.0), Some(20.0), Some(25.0), Some(30.0), Some(35.0), Some(40.0)]);
+
+        let unknown = ComparisonOptions { metrics: vec!["voltage".to_string()], ..Default::default() };
+        assert!(compare(&a, &b, &unknown).is_err());
+        assert!(compare(&a, &recording(0, 100, &[1], Vec::new()), &options).is_err());
+    }
+}
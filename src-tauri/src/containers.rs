@@ -0,0 +1,152 @@
+//! Container attribution from cgroup paths
+//!
+//! On Linux every process belongs to a cgroup whose path encodes the
+//! container runtime and container ID, e.g.
+//! `/system.slice/docker-<id>.scope` or
+//! `/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod<uid>.slice/cri-containerd-<id>.scope`.
+//! Parsing it lets GPU memory be attributed to the container that owns it.
+
+use serde::Serialize;
+use std::fs;
+
+/// Container runtime that created a container
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerRuntime {
+    Docker,
+    Podman,
+    Containerd,
+    CriO,
+    /// Container ID found without a runtime prefix (cgroup v1 Kubernetes)
+    Unknown,
+}
+
+/// Container a process runs in
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ContainerInfo {
+    pub runtime: ContainerRuntime,
+    /// Full 64-character container ID
+    pub id: String,
+    /// Kubernetes pod UID, when the container belongs to a pod
+    pub pod_uid: Option<String>,
+}
+
+/// Read the cgroup path of a process
+///
+/// Prefers the unified (v2) hierarchy; on cgroup v1 hosts the first
+/// hierarchy naming a container is used.
+///
+/// # Returns
+/// * `Option<String>` - Cgroup path, or `None` if unavailable (not Linux, or the process exited)
+pub fn read_cgroup(pid: u32) -> Option<String> {
+    let contents = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    select_cgroup_path(&contents)
+}
+
+// Lines are `hierarchy-id:controllers:path`; v2 is `0::path`
+fn select_cgroup_path(contents: &str) -> Option<String> {
+    let paths: Vec<(&str, &str)> = contents.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ':');
+            let hierarchy = fields.next()?;
+            let _controllers = fields.next()?;
+            Some((hierarchy, fields.next()?))
+        })
+        .collect();
+    paths.iter()
+        .find(|(hierarchy, path)| *hierarchy == "0" && parse_container(path).is_some())
+        .or_else(|| paths.iter().find(|(_, path)| parse_container(path).is_some()))
+        .or_else(|| paths.iter().find(|(hierarchy, _)| *hierarchy == "0"))
+        .or(paths.first())
+        .map(|(_, path)| path.to_string())
+}
+
+/// Identify the container from a cgroup path
+///
+/// # Returns
+/// * `Option<ContainerInfo>` - Innermost container on the path, or `None` for host processes
+pub fn parse_container(cgroup_path: &str) -> Option<ContainerInfo> {
+    let mut container: Option<ContainerInfo> = None;
+    let mut pod_uid = None;
+    let mut previous = "";
+
+    for segment in cgroup_path.split('/').filter(|segment| !segment.is_empty()) {
+        let unit = segment.trim_end_matches(".scope").trim_end_matches(".slice");
+        if let Some(uid) = pod_uid_of(unit) {
+            pod_uid = Some(uid);
+        }
+
+        let prefixed = [
+            ("docker-", ContainerRuntime::Docker),
+            ("libpod-", ContainerRuntime::Podman),
+            ("cri-containerd-", ContainerRuntime::Containerd),
+            ("crio-", ContainerRuntime::CriO),
+        ];
+        let found = prefixed.iter()
+            .find_map(|(prefix, runtime)| unit.strip_prefix(prefix).filter(|id| is_container_id(id)).map(|id| (*runtime, id)))
+            .or_else(|| {
+                // cgroup v1 layouts: `/docker/<id>` and `/kubepods/<qos>/pod<uid>/<id>`
+                is_container_id(unit).then(|| {
+                    let runtime = if previous == "docker" { ContainerRuntime::Docker } else { ContainerRuntime::Unknown };
+                    (runtime, unit)
+                })
+            });
+        if let Some((runtime, id)) = found {
+            container = Some(ContainerInfo { runtime, id: id.to_string(), pod_uid: None });
+        }
+        previous = unit;
+    }
+
+    container.map(|container| ContainerInfo { pod_uid, ..container })
+}
+
+// `pod<uid>` (cgroup v1) or `kubepods-<qos>-pod<uid with underscores>` (systemd driver)
+fn pod_uid_of(unit: &str) -> Option<String> {
+    let uid = unit.rsplit_once("-pod").map(|(_, uid)| uid)
+        .or_else(|| unit.strip_prefix("pod"))?;
+    let uid = uid.replace('_', "-");
+    (uid.len() == 36 && uid.chars().all(|c| c.is_ascii_hexdigit() || c == '-')).then_some(uid)
+}
+
+fn is_container_id(id: &str) -> bool {
+    id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "4f1c2a3b5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708";
+    const POD: &str = "0d3c9e2a-7b1f-4c2e-9a8d-5f6e7d8c9b0a";
+
+    #[test]
+    fn test_parse_container_runtimes() {
+        let parsed = |path: String| parse_container(&path).map(|c| (c.runtime, c.id, c.pod_uid));
+        assert_eq!(parsed(format!("/system.slice/docker-{}.scope", ID)), Some((ContainerRuntime::Docker, ID.to_string(), None)));
+        assert_eq!(parsed(format!("/docker/{}", ID)), Some((ContainerRuntime::Docker, ID.to_string(), None)));
+        assert_eq!(
+            parsed(format!("/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-{}.scope/container", ID)),
+            Some((ContainerRuntime::Podman, ID.to_string(), None))
+        );
+        assert_eq!(
+            parsed(format!(
+                "/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod{}.slice/cri-containerd-{}.scope",
+                POD.replace('-', "_"), ID
+            )),
+            Some((ContainerRuntime::Containerd, ID.to_string(), Some(POD.to_string())))
+        );
+        assert_eq!(
+            parsed(format!("/kubepods/besteffort/pod{}/{}", POD, ID)),
+            Some((ContainerRuntime::Unknown, ID.to_string(), Some(POD.to_string())))
+        );
+        assert_eq!(parsed("/user.slice/user-1000.slice/session-3.scope".to_string()), None);
+    }
+
+    #[test]
+    fn test_select_cgroup_path_prefers_container_hierarchy() {
+        let v1 = format!("12:pids:/docker/{}\n1:name=systemd:/docker/{}\n", ID, ID);
+        assert_eq!(select_cgroup_path(&v1), Some(format!("/docker/{}", ID)));
+        assert_eq!(select_cgroup_path("0::/init.scope\n"), Some("/init.scope".to_string()));
+        assert_eq!(select_cgroup_path(""), None);
+    }
+}
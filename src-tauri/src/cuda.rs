@@ -0,0 +1,231 @@
+//! Minimal CUDA driver API bindings
+//!
+//! The driver library ships with the NVIDIA driver and is loaded at
+//! runtime, so builds with the `cuda` feature need no CUDA toolkit and
+//! still start on machines without a GPU. Only the calls needed by the
+//! benchmarks and the stress test are bound. Resources are released on
+//! drop, and borrows tie buffers and modules to the context that owns them.
+
+use anyhow::{Context as _, Result};
+use libloading::{Library, Symbol};
+use std::ffi::{c_char, c_int, c_uint, c_void, CString};
+
+use crate::error::AppError;
+
+type CuResult = c_int;
+type CuDevice = c_int;
+type CuContext = *mut c_void;
+type CuModule = *mut c_void;
+type CuFunction = *mut c_void;
+type CuDevicePtr = u64;
+
+/// `CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT`
+const ATTRIBUTE_MULTIPROCESSOR_COUNT: c_int = 16;
+
+#[cfg(windows)]
+const LIBRARY: &str = "nvcuda.dll";
+#[cfg(not(windows))]
+const LIBRARY: &str = "libcuda.so.1";
+
+/// The loaded driver library
+pub struct Cuda {
+    library: Library,
+}
+
+impl Cuda {
+    /// Load and initialize the CUDA driver
+    ///
+    /// # Returns
+    /// * `Result<Cuda>` - Driver handle, or `NotSupported` if no driver is installed
+    pub fn load() -> Result<Self> {
+        let library = unsafe { Library::new(LIBRARY) }
+            .map_err(|e| AppError::NotSupported(format!("CUDA driver library {} not found: {}", LIBRARY, e)))?;
+        let cuda = Cuda { library };
+        unsafe { cuda.call::<unsafe extern "C" fn(c_uint) -> CuResult>(b"cuInit\0", |f| f(0))? };
+        Ok(cuda)
+    }
+
+    /// Create a context on the device with the given PCI bus ID
+    ///
+    /// CUDA orders devices differently from NVML; the bus ID is shared.
+    pub fn create_context(&self, bus_id: &str) -> Result<Context<'_>> {
+        let bus_id = CString::new(bus_id).context("PCI bus ID contains a NUL byte")?;
+        let mut device: CuDevice = 0;
+        let mut context: CuContext = std::ptr::null_mut();
+        unsafe {
+            self.call::<unsafe extern "C" fn(*mut CuDevice, *const c_char) -> CuResult>(
+                b"cuDeviceGetByPCIBusId\0", |f| f(&mut device, bus_id.as_ptr()))?;
+            self.call::<unsafe extern "C" fn(*mut CuContext, c_uint, CuDevice) -> CuResult>(
+                b"cuCtxCreate_v2\0", |f| f(&mut context, 0, device))?;
+        }
+        Ok(Context { cuda: self, context, device })
+    }
+
+    // Look up a driver function and call it, turning a CUDA error code into an error
+    unsafe fn call<F>(&self, name: &[u8], invoke: impl FnOnce(Symbol<F>) -> CuResult) -> Result<()> {
+        let display = String::from_utf8_lossy(&name[..name.len() - 1]);
+        let function: Symbol<F> = self.library.get(name)
+            .with_context(|| format!("CUDA driver lacks {}", display))?;
+        match invoke(function) {
+            0 => Ok(()),
+            code => anyhow::bail!("{} failed with CUDA error {}", display, code),
+        }
+    }
+}
+
+/// A context current on the thread that created it
+///
+/// CUDA contexts are bound to the creating thread, so a context must be
+/// used and dropped on the same thread.
+pub struct Context<'lib> {
+    cuda: &'lib Cuda,
+    context: CuContext,
+    device: CuDevice,
+}
+
+impl Context<'_> {
+    /// Number of streaming multiprocessors on the device
+    pub fn multiprocessor_count(&self) -> Result<u32> {
+        let mut count: c_int = 0;
+        unsafe {
+            self.cuda.call::<unsafe extern "C" fn(*mut c_int, c_int, CuDevice) -> CuResult>(
+                b"cuDeviceGetAttribute\0", |f| f(&mut count, ATTRIBUTE_MULTIPROCESSOR_COUNT, self.device))?;
+        }
+        Ok(count.max(1) as u32)
+    }
+
+    /// Allocate device memory
+    pub fn alloc_device(&self, bytes: usize) -> Result<Buffer<'_>> {
+        let mut ptr: CuDevicePtr = 0;
+        unsafe {
+            self.cuda.call::<unsafe extern "C" fn(*mut CuDevicePtr, usize) -> CuResult>(
+                b"cuMemAlloc_v2\0", |f| f(&mut ptr, bytes))?;
+        }
+        Ok(Buffer { memory: Memory::Device(ptr), cuda: self.cuda })
+    }
+
+    /// Allocate pinned host memory
+    pub fn alloc_host(&self, bytes: usize) -> Result<Buffer<'_>> {
+        let mut ptr: *mut c_void = std::ptr::null_mut();
+        unsafe {
+            self.cuda.call::<unsafe extern "C" fn(*mut *mut c_void, usize) -> CuResult>(
+                b"cuMemAllocHost_v2\0", |f| f(&mut ptr, bytes))?;
+        }
+        Ok(Buffer { memory: Memory::Host(ptr), cuda: self.cuda })
+    }
+
+    /// Queue a copy of `bytes` from `src` to `dst`
+    ///
+    /// Device-to-device copies return before they finish; call
+    /// `synchronize` to wait for them.
+    pub fn copy(&self, dst: &Buffer, src: &Buffer, bytes: usize) -> Result<()> {
+        unsafe {
+            match (dst.memory, src.memory) {
+                (Memory::Device(dst), Memory::Host(src)) => self.cuda.call::<unsafe extern "C" fn(CuDevicePtr, *const c_void, usize) -> CuResult>(
+                    b"cuMemcpyHtoD_v2\0", |f| f(dst, src, bytes)),
+                (Memory::Host(dst), Memory::Device(src)) => self.cuda.call::<unsafe extern "C" fn(*mut c_void, CuDevicePtr, usize) -> CuResult>(
+                    b"cuMemcpyDtoH_v2\0", |f| f(dst, src, bytes)),
+                (Memory::Device(dst), Memory::Device(src)) => self.cuda.call::<unsafe extern "C" fn(CuDevicePtr, CuDevicePtr, usize) -> CuResult>(
+                    b"cuMemcpyDtoD_v2\0", |f| f(dst, src, bytes)),
+                (Memory::Host(_), Memory::Host(_)) => anyhow::bail!("Host-to-host copies do not involve the GPU"),
+            }
+        }
+    }
+
+    /// Load a module from null-terminated PTX, compiled by the driver for this device
+    pub fn load_ptx(&self, ptx: &str) -> Result<Module<'_>> {
+        anyhow::ensure!(ptx.ends_with('\0'), "PTX must be null-terminated");
+        let mut module: CuModule = std::ptr::null_mut();
+        unsafe {
+            self.cuda.call::<unsafe extern "C" fn(*mut CuModule, *const c_void) -> CuResult>(
+                b"cuModuleLoadData\0", |f| f(&mut module, ptx.as_ptr() as *const c_void))?;
+        }
+        Ok(Module { cuda: self.cuda, module })
+    }
+
+    /// Wait for all queued work on the context
+    pub fn synchronize(&self) -> Result<()> {
+        unsafe { self.cuda.call::<unsafe extern "C" fn() -> CuResult>(b"cuCtxSynchronize\0", |f| f()) }
+    }
+}
+
+impl Drop for Context<'_> {
+    fn drop(&mut self) {
+        // Nothing can be done about a failure while tearing down
+        unsafe {
+            self.cuda.call::<unsafe extern "C" fn(CuContext) -> CuResult>(b"cuCtxDestroy_v2\0", |f| f(self.context)).ok();
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Memory {
+    Host(*mut c_void),
+    Device(CuDevicePtr),
+}
+
+/// Host or device memory owned by a context
+pub struct Buffer<'ctx> {
+    memory: Memory,
+    cuda: &'ctx Cuda,
+}
+
+impl Buffer<'_> {
+    /// Device address, for passing to kernels
+    pub fn device_ptr(&self) -> Option<u64> {
+        match self.memory {
+            Memory::Device(ptr) => Some(ptr),
+            Memory::Host(_) => None,
+        }
+    }
+}
+
+impl Drop for Buffer<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            match self.memory {
+                Memory::Host(ptr) => self.cuda.call::<unsafe extern "C" fn(*mut c_void) -> CuResult>(
+                    b"cuMemFreeHost\0", |f| f(ptr)).ok(),
+                Memory::Device(ptr) => self.cuda.call::<unsafe extern "C" fn(CuDevicePtr) -> CuResult>(
+                    b"cuMemFree_v2\0", |f| f(ptr)).ok(),
+            };
+        }
+    }
+}
+
+/// A loaded PTX module
+pub struct Module<'ctx> {
+    cuda: &'ctx Cuda,
+    module: CuModule,
+}
+
+impl Module<'_> {
+    /// Launch a kernel taking `(u64 pointer, u32 count)` on a one-dimensional grid
+    ///
+    /// The launch is queued; call `Context::synchronize` to wait for it.
+    pub fn launch(&self, kernel: &str, blocks: u32, threads: u32, pointer: u64, count: u32) -> Result<()> {
+        let name = CString::new(kernel).context("Kernel name contains a NUL byte")?;
+        let mut function: CuFunction = std::ptr::null_mut();
+        let (mut pointer, mut count) = (pointer, count);
+        let mut params = [&mut pointer as *mut u64 as *mut c_void, &mut count as *mut u32 as *mut c_void];
+        unsafe {
+            self.cuda.call::<unsafe extern "C" fn(*mut CuFunction, CuModule, *const c_char) -> CuResult>(
+                b"cuModuleGetFunction\0", |f| f(&mut function, self.module, name.as_ptr()))?;
+            #[allow(clippy::type_complexity)]
+            self.cuda.call::<unsafe extern "C" fn(
+                CuFunction, c_uint, c_uint, c_uint, c_uint, c_uint, c_uint, c_uint, *mut c_void, *mut *mut c_void, *mut *mut c_void,
+            ) -> CuResult>(
+                b"cuLaunchKernel\0",
+                |f| f(function, blocks, 1, 1, threads, 1, 1, 0, std::ptr::null_mut(), params.as_mut_ptr(), std::ptr::null_mut()),
+            )
+        }
+    }
+}
+
+impl Drop for Module<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            self.cuda.call::<unsafe extern "C" fn(CuModule) -> CuResult>(b"cuModuleUnload\0", |f| f(self.module)).ok();
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Displays driven by each GPU
+//!
+//! A GPU driving a desktop never idles fully: composition keeps clocks and
+//! power above their floor. NVML only reports whether a display is
+//! connected and whether a display surface is active; on Linux, the DRM
+//! connectors in sysfs (exposed by the NVIDIA driver with
+//! `nvidia-drm.modeset=1`) add per-display detail: connector name, the
+//! modes the display supports, and its preferred mode and refresh rate
+//! decoded from the EDID. Elsewhere only the NVML flags are reported.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::Nvml;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::nvml;
+
+/// Where PCI devices appear in sysfs
+const SYSFS_PCI_DEVICES: &str = "/sys/bus/pci/devices";
+
+/// A display mode
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct DisplayMode {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_hz: f64,
+}
+
+/// One display output of a GPU
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct DisplayInfo {
+    /// DRM connector name, e.g. `DP-1` or `HDMI-A-1`
+    pub connector: String,
+    /// Whether the output is lit (a mode is set on it)
+    pub enabled: bool,
+    /// Mode the display prefers, from its EDID
+    pub preferred_mode: Option<DisplayMode>,
+    /// Resolutions the display supports, as `WIDTHxHEIGHT`, best first
+    pub supported_resolutions: Vec<String>,
+}
+
+/// Displays attached to one GPU
+#[derive(Serialize, Clone, Debug)]
+pub struct GpuDisplays {
+    pub device_index: u32,
+    /// A physical display is attached to one of the GPU's outputs (NVML)
+    pub display_connected: Option<bool>,
+    /// A display surface is allocated on the GPU, e.g. by a desktop (NVML)
+    pub display_active: Option<bool>,
+    /// Connected displays; empty where the platform does not expose them
+    pub displays: Vec<DisplayInfo>,
+}
+
+/// Report the displays driven by every GPU
+///
+/// # Returns
+/// * `Result<Vec<GpuDisplays>>` - One entry per device or error if NVML is unavailable
+pub async fn get_gpu_displays() -> Result<Vec<GpuDisplays>> {
+    nvml::blocking(get_gpu_displays_blocking).await
+}
+
+fn get_gpu_displays_blocking() -> Result<Vec<GpuDisplays>> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let count = nvml.device_count().context("Failed to get device count")?;
+    (0..count)
+        .map(|index| {
+            let device = nvml::device_at(&nvml, index)?;
+            let displays = device.pci_info().ok()
+                .map(|pci| drm_connectors(&Path::new(SYSFS_PCI_DEVICES).join(sysfs_bus_id(&pci.bus_id))))
+                .unwrap_or_default();
+            Ok(GpuDisplays {
+                device_index: index,
+                display_connected: device.is_display_connected().ok(),
+                display_active: device.is_display_active().ok(),
+                displays,
+            })
+        })
+        .collect()
+}
+
+// NVML bus IDs carry an 8-digit PCI domain ("00000000:01:00.0"); sysfs uses 4 ("0000:01:00.0")
+fn sysfs_bus_id(bus_id: &str) -> String {
+    let bus_id = bus_id.trim_end_matches('\0').to_ascii_lowercase();
+    match bus_id.split_once(':') {
+        Some((domain, rest)) if domain.len() > 4 => format!("{}:{}", &domain[domain.len() - 4..], rest),
+        _ => bus_id,
+    }
+}
+
+// Connected displays under `<pci device>/drm/card*/card*-<connector>`
+fn drm_connectors(pci_dir: &Path) -> Vec<DisplayInfo> {
+    let read = |path: PathBuf| fs::read_to_string(path).map(|text| text.trim().to_string()).unwrap_or_default();
+    let mut displays: Vec<DisplayInfo> = subdirectories(&pci_dir.join("drm"))
+        .into_iter()
+        .flat_map(|card| {
+            let card_name = card.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            subdirectories(&card).into_iter()
+                .filter_map(move |connector| {
+                    let name = connector.file_name()?.to_string_lossy().strip_prefix(&format!("{}-", card_name))?.to_string();
+                    Some((name, connector))
+                })
+        })
+        .filter(|(_, connector)| read(connector.join("status")) == "connected")
+        .map(|(name, connector)| {
+            let mut supported_resolutions: Vec<String> = Vec::new();
+            for mode in read(connector.join("modes")).lines() {
+                if !supported_resolutions.iter().any(|known| known == mode) {
+                    supported_resolutions.push(mode.to_string());
+                }
+            }
+            DisplayInfo {
+                connector: name,
+                enabled: read(connector.join("enabled")) == "enabled",
+                preferred_mode: fs::read(connector.join("edid")).ok().and_then(|edid| preferred_mode(&edid)),
+                supported_resolutions,
+            }
+        })
+        .collect();
+    displays.sort_by(|a, b| a.connector.cmp(&b.connector));
+    displays
+}
+
+fn subdirectories(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.is_dir()).collect())
+        .unwrap_or_default()
+}
+
+// The first detailed timing descriptor of an EDID block is the preferred mode
+fn preferred_mode(edid: &[u8]) -> Option<DisplayMode> {
+    const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+    if edid.len() < 128 || edid[..8] != HEADER {
+        return None;
+    }
+    let timing = &edid[54..72];
+    let pixel_clock_hz = u16::from_le_bytes([timing[0], timing[1]]) as f64 * 10_000.0;
+    let width = timing[2] as u32 | ((timing[4] as u32 >> 4) << 8);
+    let h_blank = timing[3] as u32 | ((timing[4] as u32 & 0x0f) << 8);
+    let height = timing[5] as u32 | ((timing[7] as u32 >> 4) << 8);
+    let v_blank = timing[6] as u32 | ((timing[7] as u32 & 0x0f) << 8);
+    let total_pixels = (width + h_blank) * (height + v_blank);
+    // A zero pixel clock marks a display descriptor, not a timing
+    if pixel_clock_hz == 0.0 || total_pixels == 0 {
+        return None;
+    }
+    Some(DisplayMode {
+        width,
+        height,
+        refresh_hz: (pixel_clock_hz / total_pixels as f64 * 100.0).round() / 100.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // EDID block whose preferred timing is 1920x1080 at 60 Hz (148.5 MHz, 2200x1125 total)
+    fn edid_1080p60() -> Vec<u8> {
+        let mut edid = vec![0u8; 128];
+        edid[..8].copy_from_slice(&[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]);
+        edid[54..62].copy_from_slice(&[0x02, 0x3a, 0x80, 0x18, 0x71, 0x38, 0x2d, 0x40]);
+        edid
+    }
+
+    #[test]
+    fn test_sysfs_bus_id() {
+        assert_eq!(sysfs_bus_id("00000000:0A:00.0"), "0000:0a:00.0");
+        assert_eq!(sysfs_bus_id("0000:01:00.0"), "0000:01:00.0");
+    }
+
+    #[test]
+    fn test_preferred_mode_from_edid() {
+        assert_eq!(preferred_mode(&edid_1080p60()), Some(DisplayMode { width: 1920, height: 1080, refresh_hz: 60.0 }));
+        assert_eq!(preferred_mode(&[0u8; 128]), None);
+        assert_eq!(preferred_mode(&edid_1080p60()[..100]), None);
+    }
+
+    #[test]
+    fn test_reads_connected_drm_connectors() {
+        let pci = std::env::temp_dir().join(format!("nsightful_displays_{}", std::process::id()));
+        let card = pci.join("drm/card1");
+        for (connector, status) in [("card1-DP-1", "connected"), ("card1-HDMI-A-1", "disconnected")] {
+            fs::create_dir_all(card.join(connector)).unwrap();
+            fs::write(card.join(connector).join("status"), format!("{}\n", status)).unwrap();
+        }
+        fs::write(card.join("card1-DP-1/enabled"), "enabled\n").unwrap();
+        fs::write(card.join("card1-DP-1/modes"), "1920x1080\n1920x1080\n1280x720\n").unwrap();
+        fs::write(card.join("card1-DP-1/edid"), edid_1080p60()).unwrap();
+
+        let displays = drm_connectors(&pci);
+        assert_eq!(displays.len(), 1);
+        assert_eq!((displays[0].connector.as_str(), displays[0].enabled), ("DP-1", true));
+        assert_eq!(displays[0].supported_resolutions, vec!["1920x1080", "1280x720"]);
+        assert_eq!(displays[0].preferred_mode.as_ref().map(|mode| mode.refresh_hz), Some(60.0));
+        assert!(drm_connectors(&pci.join("missing")).is_empty());
+        fs::remove_dir_all(&pci).ok();
+    }
+}
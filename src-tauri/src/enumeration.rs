@@ -0,0 +1,94 @@
+//! Device re-enumeration
+//!
+//! NVML lists devices when it is initialized, so a stream started before an
+//! eGPU was plugged in or a device was passed through never sees it. Active
+//! streams re-enumerate periodically with a fresh NVML handle and compare
+//! the devices by UUID; indices can shift when devices come and go, so a
+//! device whose index changed counts as removed and added again.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::Nvml;
+use serde::Serialize;
+use std::time::Duration;
+
+/// How often active streams look for added and removed devices
+pub const ENUMERATION_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Device as identified across enumerations
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub index: u32,
+    pub uuid: String,
+}
+
+/// Difference between two enumerations
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DeviceChanges {
+    pub added: Vec<DeviceIdentity>,
+    pub removed: Vec<DeviceIdentity>,
+}
+
+impl DeviceChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// List the devices present now
+///
+/// Initializes NVML afresh, since an existing handle keeps the device list
+/// it was created with. Devices whose UUID cannot be read are left out.
+pub fn enumerate() -> Result<Vec<DeviceIdentity>> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let count = nvml.device_count().context("Failed to get device count")?;
+    Ok((0..count)
+        .filter_map(|index| {
+            let uuid = nvml.device_by_index(index).and_then(|device| device.uuid()).ok()?;
+            Some(DeviceIdentity { index, uuid })
+        })
+        .collect())
+}
+
+/// Compare a previous enumeration with the current one
+///
+/// # Arguments
+/// * `known` - Devices from the previous enumeration
+/// * `current` - Devices present now
+///
+/// # Returns
+/// * `DeviceChanges` - Devices to start and stop streaming, in index order
+pub fn diff(known: &[DeviceIdentity], current: &[DeviceIdentity]) -> DeviceChanges {
+    DeviceChanges {
+        added: current.iter().filter(|device| !known.contains(device)).cloned().collect(),
+        removed: known.iter().filter(|device| !current.contains(device)).cloned().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(index: u32, uuid: &str) -> DeviceIdentity {
+        DeviceIdentity { index, uuid: uuid.to_string() }
+    }
+
+    #[test]
+    fn test_diff_detects_added_and_removed_devices() {
+        let known = [device(0, "GPU-a"), device(1, "GPU-b")];
+        assert!(diff(&known, &known).is_empty());
+
+        let changes = diff(&known, &[device(0, "GPU-a"), device(1, "GPU-b"), device(2, "GPU-egpu")]);
+        assert_eq!(changes, DeviceChanges { added: vec![device(2, "GPU-egpu")], removed: Vec::new() });
+
+        let changes = diff(&known, &[device(0, "GPU-a")]);
+        assert_eq!(changes, DeviceChanges { added: Vec::new(), removed: vec![device(1, "GPU-b")] });
+    }
+
+    #[test]
+    fn test_index_shift_restarts_device() {
+        // Removing GPU 0 moves GPU-b to index 0
+        let changes = diff(&[device(0, "GPU-a"), device(1, "GPU-b")], &[device(0, "GPU-b")]);
+        assert_eq!(changes.added, vec![device(0, "GPU-b")]);
+        assert_eq!(changes.removed, vec![device(0, "GPU-a"), device(1, "GPU-b")]);
+    }
+}
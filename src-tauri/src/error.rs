@@ -0,0 +1,189 @@
+//! Application error types
+//!
+//! Defines the structured error returned by every Tauri command. Errors are
+//! serialized as `{ "code": "...", "message": "..." }` so the frontend can
+//! branch on the error kind instead of matching on message text.
+
+use nvml_wrapper::error::NvmlError;
+use serde::Serialize;
+use std::fmt;
+
+/// Structured error surfaced to the frontend
+///
+/// Each variant carries a human-readable message; the variant itself is
+/// serialized as a stable `SCREAMING_SNAKE_CASE` error code.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "code", content = "message", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AppError {
+    /// NVML could not be loaded or initialized (no driver, no library)
+    NvmlUnavailable(String),
+    /// The GPU fell off the bus or needs a reset
+    DeviceLost(String),
+    /// The operation requires elevated privileges
+    PermissionDenied(String),
+    /// The device or driver does not support the requested query
+    NotSupported(String),
+    /// A recording or streaming operation was requested while no session is active
+    NotRecording(String),
+    /// A command argument was out of range or malformed
+    InvalidArgument(String),
+    /// Filesystem or other I/O failure
+    Io(String),
+    /// Any other failure that does not fit a more specific kind
+    Internal(String),
+}
+
+impl AppError {
+    /// Stable error code string, matching the serialized `code` field
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NvmlUnavailable(_) => "NVML_UNAVAILABLE",
+            AppError::DeviceLost(_) => "DEVICE_LOST",
+            AppError::PermissionDenied(_) => "PERMISSION_DENIED",
+            AppError::NotSupported(_) => "NOT_SUPPORTED",
+            AppError::NotRecording(_) => "NOT_RECORDING",
+            AppError::InvalidArgument(_) => "INVALID_ARGUMENT",
+            AppError::Io(_) => "IO",
+            AppError::Internal(_) => "INTERNAL",
+        }
+    }
+
+    /// Human-readable error message
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::NvmlUnavailable(m)
+            | AppError::DeviceLost(m)
+            | AppError::PermissionDenied(m)
+            | AppError::NotSupported(m)
+            | AppError::NotRecording(m)
+            | AppError::InvalidArgument(m)
+            | AppError::Io(m)
+            | AppError::Internal(m) => m,
+        }
+    }
+
+    /// Replace the message while keeping the error kind
+    fn with_message(self, message: String) -> Self {
+        match self {
+            AppError::NvmlUnavailable(_) => AppError::NvmlUnavailable(message),
+            AppError::DeviceLost(_) => AppError::DeviceLost(message),
+            AppError::PermissionDenied(_) => AppError::PermissionDenied(message),
+            AppError::NotSupported(_) => AppError::NotSupported(message),
+            AppError::NotRecording(_) => AppError::NotRecording(message),
+            AppError::InvalidArgument(_) => AppError::InvalidArgument(message),
+            AppError::Io(_) => AppError::Io(message),
+            AppError::Internal(_) => AppError::Internal(message),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<&NvmlError> for AppError {
+    fn from(err: &NvmlError) -> Self {
+        let message = err.to_string();
+        match err {
+            NvmlError::LibloadingError(_)
+            | NvmlError::FailedToLoadSymbol(_)
+            | NvmlError::Uninitialized
+            | NvmlError::DriverNotLoaded
+            | NvmlError::LibraryNotFound
+            | NvmlError::FunctionNotFound
+            | NvmlError::LibRmVersionMismatch => AppError::NvmlUnavailable(message),
+            NvmlError::GpuLost | NvmlError::ResetRequired => AppError::DeviceLost(message),
+            NvmlError::NoPermission => AppError::PermissionDenied(message),
+            NvmlError::NotSupported => AppError::NotSupported(message),
+            NvmlError::InvalidArg | NvmlError::NotFound => AppError::InvalidArgument(message),
+            _ => AppError::Internal(message),
+        }
+    }
+}
+
+impl From<NvmlError> for AppError {
+    fn from(err: NvmlError) -> Self {
+        AppError::from(&err)
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::PermissionDenied => AppError::PermissionDenied(err.to_string()),
+            _ => AppError::Io(err.to_string()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    /// Classify an error chain by its most specific known cause
+    ///
+    /// The error kind comes from the first `AppError`, `NvmlError` or
+    /// `io::Error` found in the chain; the message keeps the full context.
+    fn from(err: anyhow::Error) -> Self {
+        let message = format!("{:#}", err);
+        let kind = err.chain().find_map(|cause| {
+            if let Some(app_err) = cause.downcast_ref::<AppError>() {
+                Some(app_err.clone())
+            } else if let Some(nvml_err) = cause.downcast_ref::<NvmlError>() {
+                Some(AppError::from(nvml_err))
+            } else {
+                cause.downcast_ref::<std::io::Error>().map(|io_err| {
+                    AppError::from(std::io::Error::new(io_err.kind(), io_err.to_string()))
+                })
+            }
+        });
+        kind.unwrap_or(AppError::Internal(String::new())).with_message(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_serializes_code_and_message() {
+        let err = AppError::InvalidArgument("bad index".to_string());
+        let value = serde_json::to_value(&err).unwrap();
+        assert_eq!(value["code"], "INVALID_ARGUMENT");
+        assert_eq!(value["message"], "bad index");
+        assert_eq!(err.code(), "INVALID_ARGUMENT");
+    }
+
+    #[test]
+    fn test_classifies_nvml_errors() {
+        assert_eq!(AppError::from(NvmlError::GpuLost).code(), "DEVICE_LOST");
+        assert_eq!(AppError::from(NvmlError::NoPermission).code(), "PERMISSION_DENIED");
+        assert_eq!(AppError::from(NvmlError::DriverNotLoaded).code(), "NVML_UNAVAILABLE");
+        assert_eq!(AppError::from(NvmlError::Unknown).code(), "INTERNAL");
+    }
+
+    #[test]
+    fn test_classifies_anyhow_chain_with_context() {
+        let result: anyhow::Result<()> = Err(NvmlError::GpuLost.into());
+        let err = AppError::from(result.context("Failed to read temperature").unwrap_err());
+        assert_eq!(err.code(), "DEVICE_LOST");
+        assert!(err.message().starts_with("Failed to read temperature"));
+    }
+
+    #[test]
+    fn test_preserves_app_error_kind_through_anyhow() {
+        let err: anyhow::Error = AppError::NotRecording("No active recording".to_string()).into();
+        assert_eq!(AppError::from(err).code(), "NOT_RECORDING");
+
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        assert_eq!(AppError::from(anyhow::Error::from(io)).code(), "IO");
+    }
+
+    #[test]
+    fn test_unclassified_errors_are_internal() {
+        let err = AppError::from(anyhow::anyhow!("something odd"));
+        assert_eq!(err, AppError::Internal("something odd".to_string()));
+    }
+}
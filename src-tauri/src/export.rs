@@ -0,0 +1,223 @@
+//! Export a time window of a recording
+//!
+//! Slices a JSON or columnar recording down to `[start_ms, end_ms]` and
+//! writes it as JSON or CSV, so the interesting part of a long session can
+//! be shared on its own.
+//!
+//! A JSON export of a JSON recording is itself a recording (current schema,
+//! with the NVTX ranges that overlap the window) and loads like any other.
+//! A JSON recording exported as CSV, or any columnar recording, carries
+//! only the scalar metric columns.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::columnar::{self, RangeData, COLUMNAR_EXTENSION, METRIC_COLUMNS};
+use crate::error::AppError;
+use crate::nvml::RecordingFile;
+use crate::schema;
+
+/// CSV column holding the device index of each sample
+const DEVICE_COLUMN: &str = "device_index";
+
+/// Output format of an export
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+/// What an export wrote
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ExportSummary {
+    pub output_file: String,
+    pub sample_count: usize,
+    /// Timestamps of the first and last exported samples
+    pub first_ms: u64,
+    pub last_ms: u64,
+}
+
+/// Export the samples of a recording within a time window
+///
+/// # Arguments
+/// * `path` - JSON recording (any schema version) or `.gpurec` columnar recording
+/// * `start_ms` - Inclusive window start (Unix milliseconds)
+/// * `end_ms` - Inclusive window end (Unix milliseconds)
+/// * `format` - Output format
+/// * `out_path` - File to write
+///
+/// # Returns
+/// * `Result<ExportSummary>` - What was written, or error if the window holds no samples
+pub fn export_range(path: &Path, start_ms: u64, end_ms: u64, format: ExportFormat, out_path: &Path) -> Result<ExportSummary> {
+    if start_ms > end_ms {
+        return Err(AppError::InvalidArgument(format!("Window start {} is after its end {}", start_ms, end_ms)).into());
+    }
+
+    let columnar_input = path.extension().is_some_and(|ext| ext == COLUMNAR_EXTENSION);
+    let slice = if columnar_input {
+        Slice::Columns(columnar::read_range(path, start_ms, end_ms, &[])?)
+    } else {
+        Slice::Recording(Box::new(slice_recording(schema::load_recording(path)?, start_ms, end_ms)))
+    };
+    let timestamps = slice.timestamps();
+    let (Some(&first_ms), Some(&last_ms)) = (timestamps.first(), timestamps.last()) else {
+        return Err(AppError::InvalidArgument(format!("No samples between {} and {}", start_ms, end_ms)).into());
+    };
+    let sample_count = timestamps.len();
+
+    let file = File::create(out_path)
+        .with_context(|| format!("Failed to create {}", out_path.display()))?;
+    let mut writer = BufWriter::new(file);
+    match (format, &slice) {
+        (ExportFormat::Json, Slice::Recording(recording)) => serde_json::to_writer_pretty(&mut writer, recording)
+            .context("Failed to serialize recording")?,
+        (ExportFormat::Json, Slice::Columns(data)) => serde_json::to_writer_pretty(&mut writer, data)
+            .context("Failed to serialize recording")?,
+        (ExportFormat::Csv, _) => write_csv(&mut writer, &slice.columns())?,
+    }
+    writer.flush().with_context(|| format!("Failed to write {}", out_path.display()))?;
+
+    Ok(ExportSummary {
+        output_file: out_path.display().to_string(),
+        sample_count,
+        first_ms,
+        last_ms,
+    })
+}
+
+// Samples of a window, in the shape they were read
+enum Slice {
+    Recording(Box<RecordingFile>),
+    Columns(RangeData),
+}
+
+impl Slice {
+    fn timestamps(&self) -> Vec<u64> {
+        match self {
+            Slice::Recording(recording) => recording.samples.iter().map(|frame| frame.timestamp as u64).collect(),
+            Slice::Columns(data) => data.timestamps.clone(),
+        }
+    }
+
+    // Scalar metrics as columns, for CSV
+    fn columns(&self) -> RangeData {
+        match self {
+            Slice::Columns(data) => data.clone(),
+            Slice::Recording(recording) => {
+                let mut columns: BTreeMap<String, Vec<f64>> = METRIC_COLUMNS.iter()
+                    .map(|(name, value)| (name.to_string(), recording.samples.iter().map(value).collect()))
+                    .collect();
+                // Frames of multi-device recordings are interleaved
+                columns.insert(
+                    DEVICE_COLUMN.to_string(),
+                    recording.samples.iter().map(|frame| frame.device_index as f64).collect(),
+                );
+                RangeData { device: recording.device.clone(), timestamps: self.timestamps(), columns, gaps: Vec::new() }
+            }
+        }
+    }
+}
+
+fn slice_recording(recording: RecordingFile, start_ms: u64, end_ms: u64) -> RecordingFile {
+    let (start, end) = (start_ms as u128, end_ms as u128);
+    RecordingFile {
+        schema_version: schema::CURRENT_SCHEMA_VERSION,
+        samples: recording.samples.into_iter()
+            .filter(|frame| (start..=end).contains(&frame.timestamp))
+            .collect(),
+        markers: recording.markers.into_iter()
+            .filter(|range| range.end_ms >= start && range.start_ms <= end)
+            .collect(),
+        ..recording
+    }
+}
+
+// One row per sample: timestamp, device when known, then the metric columns in file order
+fn write_csv<W: Write>(writer: W, data: &RangeData) -> Result<()> {
+    let mut csv = csv::Writer::from_writer(writer);
+    let names: Vec<&str> = std::iter::once(DEVICE_COLUMN)
+        .chain(METRIC_COLUMNS.iter().map(|(name, _)| *name))
+        .filter(|name| data.columns.contains_key(*name))
+        .collect();
+    csv.write_record(std::iter::once("timestamp_ms").chain(names.iter().copied()))
+        .context("Failed to write CSV header")?;
+    for (row, timestamp) in data.timestamps.iter().enumerate() {
+        let values = names.iter().map(|name| data.columns[*name][row].to_string());
+        csv.write_record(std::iter::once(timestamp.to_string()).chain(values))
+            .context("Failed to write CSV row")?;
+    }
+    csv.flush().context("Failed to write CSV")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markers::MarkerRange;
+    use crate::nvml::{StaticDeviceInfo, TelemetryFrame};
+
+    fn recording() -> RecordingFile {
+        let marker = |name: &str, start_ms: u128, end_ms: u128| MarkerRange {
+            name: name.to_string(), pid: 1, tid: Some(1), domain: None, start_ms, end_ms, depth: 0,
+        };
+        RecordingFile {
+            schema_version: schema::CURRENT_SCHEMA_VERSION,
+            device: StaticDeviceInfo::default(),
+            devices: vec![StaticDeviceInfo::default()],
+            samples: (0..10u128)
+                .map(|i| TelemetryFrame { timestamp: 1_000 + i * 100, util_gpu: i as u32 * 10, ..Default::default() })
+                .collect(),
+            markers: vec![marker("warmup", 0, 1_150), marker("train", 1_500, 1_900)],
+            timing: None,
+            process: None,
+            violations: Vec::new(),
+            clock: None,
+        }
+    }
+
+    #[test]
+    fn test_slice_recording_keeps_window_and_overlapping_markers() {
+        let sliced = slice_recording(recording(), 1_200, 1_400);
+        let timestamps: Vec<u128> = sliced.samples.iter().map(|frame| frame.timestamp).collect();
+        assert_eq!(timestamps, vec![1_200, 1_300, 1_400]);
+        assert!(sliced.markers.is_empty());
+
+        let sliced = slice_recording(recording(), 1_100, 1_500);
+        let names: Vec<&str> = sliced.markers.iter().map(|range| range.name.as_str()).collect();
+        assert_eq!(names, vec!["warmup", "train"]);
+    }
+
+    #[test]
+    fn test_csv_has_timestamp_and_metric_columns() {
+        let slice = Slice::Recording(Box::new(slice_recording(recording(), 1_000, 1_100)));
+        let mut output = Vec::new();
+        write_csv(&mut output, &slice.columns()).unwrap();
+
+        let text = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("timestamp_ms,device_index,util_gpu,util_memory,"));
+        assert!(lines[2].starts_with("1100,0,10,0,"));
+    }
+
+    #[test]
+    fn test_export_rejects_empty_or_inverted_window() {
+        let dir = std::env::temp_dir().join(format!("nsightful_export_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("recording.json");
+        std::fs::write(&input, serde_json::to_string(&recording()).unwrap()).unwrap();
+        let output = dir.join("slice.csv");
+
+        let summary = export_range(&input, 1_250, 1_550, ExportFormat::Csv, &output).unwrap();
+        assert_eq!((summary.sample_count, summary.first_ms, summary.last_ms), (3, 1_300, 1_500));
+        let err = export_range(&input, 5_000, 6_000, ExportFormat::Json, &output).unwrap_err();
+        assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+        assert!(export_range(&input, 2_000, 1_000, ExportFormat::Json, &output).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
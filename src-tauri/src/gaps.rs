@@ -0,0 +1,165 @@
+//! Sampling gaps in recorded data
+//!
+//! A recording that was paused, a stream that restarted, or a device that
+//! stopped answering leaves a stretch without samples. Drawn naively, a
+//! chart joins the samples on either side with a straight line that looks
+//! like real data. Gaps are detected from the sample intervals and marked
+//! explicitly: listed as ranges, and, in column data, by a row of `NaN`
+//! values inside each gap, which serializes as `null` and breaks the line.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::columnar::{self, RangeData, COLUMNAR_EXTENSION};
+use crate::error::AppError;
+use crate::schema;
+
+/// An interval this many times the typical interval counts as a gap
+pub const GAP_FACTOR: u64 = 3;
+
+/// A stretch of time without samples
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleGap {
+    /// Timestamp of the last sample before the gap (Unix milliseconds)
+    pub start_ms: u64,
+    /// Timestamp of the first sample after the gap (Unix milliseconds)
+    pub end_ms: u64,
+}
+
+/// Find the gaps in a sequence of sample timestamps
+///
+/// # Arguments
+/// * `timestamps` - Sample timestamps in time order
+/// * `min_gap_ms` - Shortest interval that counts as a gap; `GAP_FACTOR`
+///   times the median interval if unset
+///
+/// # Returns
+/// * `Vec<SampleGap>` - Gaps in time order
+pub fn detect(timestamps: &[u64], min_gap_ms: Option<u64>) -> Vec<SampleGap> {
+    gap_rows(timestamps, min_gap_ms).into_iter()
+        .map(|row| SampleGap { start_ms: timestamps[row], end_ms: timestamps[row + 1] })
+        .collect()
+}
+
+/// Shortest interval that counts as a gap when none is given
+///
+/// # Arguments
+/// * `intervals` - Intervals between consecutive samples; must not be empty
+///
+/// # Returns
+/// * `u64` - `GAP_FACTOR` times the median interval
+pub fn default_threshold(intervals: &[u64]) -> u64 {
+    let mut sorted = intervals.to_vec();
+    let middle = sorted.len() / 2;
+    sorted.select_nth_unstable(middle).1.saturating_mul(GAP_FACTOR)
+}
+
+// Rows followed by a gap
+fn gap_rows(timestamps: &[u64], min_gap_ms: Option<u64>) -> Vec<usize> {
+    let intervals: Vec<u64> = timestamps.windows(2).map(|pair| pair[1].saturating_sub(pair[0])).collect();
+    if intervals.is_empty() {
+        return Vec::new();
+    }
+    let threshold = min_gap_ms.unwrap_or_else(|| default_threshold(&intervals)).max(1);
+    (0..intervals.len()).filter(|&row| intervals[row] >= threshold).collect()
+}
+
+/// Record the gaps of column data and break its columns at each gap
+///
+/// Inserts one row in the middle of every gap with `NaN` in every column.
+///
+/// # Arguments
+/// * `data` - Column data in time order
+/// * `min_gap_ms` - Shortest interval that counts as a gap (see `detect`)
+pub fn mark(data: &mut RangeData, min_gap_ms: Option<u64>) {
+    let rows = gap_rows(&data.timestamps, min_gap_ms);
+    data.gaps = rows.iter()
+        .map(|&row| SampleGap { start_ms: data.timestamps[row], end_ms: data.timestamps[row + 1] })
+        .collect();
+    if rows.is_empty() {
+        return;
+    }
+
+    let timestamps = &data.timestamps;
+    let marked = with_breaks(timestamps, &rows, |row| timestamps[row] + (timestamps[row + 1] - timestamps[row]) / 2);
+    for values in data.columns.values_mut() {
+        *values = with_breaks(values, &rows, |_| f64::NAN);
+    }
+    data.timestamps = marked;
+}
+
+/// Find the gaps of one device in a saved recording
+///
+/// # Arguments
+/// * `path` - JSON recording (any schema version) or `.gpurec` columnar recording
+/// * `device_index` - Device of a multi-device recording (the primary device if unset)
+/// * `min_gap_ms` - Shortest interval that counts as a gap (see `detect`)
+///
+/// # Returns
+/// * `Result<Vec<SampleGap>>` - Gaps in time order or error for an unreadable recording or unknown device
+pub fn recording_gaps(path: &Path, device_index: Option<u32>, min_gap_ms: Option<u64>) -> Result<Vec<SampleGap>> {
+    let timestamps = if path.extension().is_some_and(|ext| ext == COLUMNAR_EXTENSION) {
+        let data = columnar::read_range(path, 0, u64::MAX, &[])?;
+        if device_index.is_some_and(|index| index != data.device.index) {
+            return Err(AppError::InvalidArgument(format!("GPU {} is not in {}", device_index.unwrap_or_default(), path.display())).into());
+        }
+        data.timestamps
+    } else {
+        let recording = schema::load_recording(path)?;
+        let device_index = device_index.unwrap_or(recording.device.index);
+        if !recording.devices.iter().any(|device| device.index == device_index) {
+            return Err(AppError::InvalidArgument(format!("GPU {} is not in {}", device_index, path.display())).into());
+        }
+        recording.samples.iter()
+            .filter(|frame| frame.device_index == device_index)
+            .map(|frame| recording.steady_timestamp(frame) as u64)
+            .collect()
+    };
+    Ok(detect(&timestamps, min_gap_ms))
+}
+
+// Copy of `values` with an extra value after each of `rows`
+fn with_breaks<T: Copy>(values: &[T], rows: &[usize], gap_value: impl Fn(usize) -> T) -> Vec<T> {
+    let mut marked = Vec::with_capacity(values.len() + rows.len());
+    let mut pending = rows.iter().peekable();
+    for (row, &value) in values.iter().enumerate() {
+        marked.push(value);
+        if pending.next_if(|&&after| after == row).is_some() {
+            marked.push(gap_value(row));
+        }
+    }
+    marked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_gaps_from_typical_interval() {
+        let timestamps = [0, 100, 200, 300, 2_300, 2_400, 2_500, 2_800];
+        assert_eq!(detect(&timestamps, None), vec![
+            SampleGap { start_ms: 300, end_ms: 2_300 },
+            SampleGap { start_ms: 2_500, end_ms: 2_800 },
+        ]);
+        assert_eq!(detect(&timestamps, Some(1_000)), vec![SampleGap { start_ms: 300, end_ms: 2_300 }]);
+        assert!(detect(&[0], None).is_empty());
+    }
+
+    #[test]
+    fn test_marks_gaps_with_null_rows() {
+        let mut data = RangeData { timestamps: vec![0, 100, 200, 1_200, 1_300], ..Default::default() };
+        data.columns.insert("util_gpu".to_string(), vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        mark(&mut data, None);
+
+        assert_eq!(data.gaps, vec![SampleGap { start_ms: 200, end_ms: 1_200 }]);
+        assert_eq!(data.timestamps, vec![0, 100, 200, 700, 1_200, 1_300]);
+        let values = &data.columns["util_gpu"];
+        assert!(values[3].is_nan());
+        assert_eq!(values.len(), 6);
+        assert_eq!(values[4], 4.0);
+        let json = serde_json::to_value(&data).unwrap();
+        assert!(json["columns"]["util_gpu"][3].is_null());
+    }
+}
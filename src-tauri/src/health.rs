@@ -0,0 +1,313 @@
+//! GPU health check diagnostics
+//!
+//! Runs a battery of quick checks against a single device and reports each
+//! as pass/warn/fail, so users can spot overheating, degraded PCIe links,
+//! pending ECC page retirements or active throttling at a glance. The
+//! throttle check puts the stress test's compute load on the device, so it
+//! needs the `cuda` feature and is skipped without it.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::bitmasks::device::ThrottleReasons;
+use nvml_wrapper::enum_wrappers::device::{TemperatureSensor, TemperatureThreshold};
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::{device::Device, Nvml};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::nvml;
+use crate::stress::{self, StressLoad};
+use crate::validation;
+
+/// Temperature margin below the slowdown threshold that triggers a warning
+const TEMPERATURE_WARN_MARGIN_C: u32 = 10;
+/// Slowdown threshold assumed when the driver does not report one
+const DEFAULT_SLOWDOWN_THRESHOLD_C: u32 = 90;
+/// Number of throttle-reason samples taken under load during the throttle check
+const THROTTLE_SAMPLES: u32 = 10;
+/// Interval between throttle-reason samples
+const THROTTLE_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Outcome of a single health check
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    /// The check could not run on this device (unsupported query)
+    Skipped,
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Result of one diagnostic check
+#[derive(Serialize, Clone, Debug)]
+pub struct HealthCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Complete health report for a device
+#[derive(Serialize, Clone, Debug)]
+pub struct HealthReport {
+    pub device_index: u32,
+    pub name: String,
+    pub overall: CheckStatus,
+    pub checks: Vec<HealthCheck>,
+}
+
+impl HealthCheck {
+    fn new(name: &str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        HealthCheck {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    // Skip on unsupported queries, fail on any other NVML error
+    fn from_error(name: &str, err: NvmlError) -> Self {
+        match err {
+            NvmlError::NotSupported => HealthCheck::new(name, CheckStatus::Skipped, "Not supported on this device"),
+            err => HealthCheck::new(name, CheckStatus::Fail, format!("Query failed: {}", err)),
+        }
+    }
+}
+
+/// Run all health checks against a device
+///
+/// The throttle check runs a compute load for about a second and samples
+/// throttle reasons while it runs; it is skipped if no load can be generated.
+///
+/// # Arguments
+/// * `device_index` - Device to check (defaults to 0)
+///
+/// # Returns
+/// * `Result<HealthReport>` - Report, or error if NVML or the device is unreachable
+pub async fn run_health_check(device_index: Option<u32>) -> Result<HealthReport> {
+    nvml::blocking(move || run_health_check_blocking(device_index)).await
+}
+
+fn run_health_check_blocking(device_index: Option<u32>) -> Result<HealthReport> {
+    let device_index = device_index.unwrap_or(0);
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let device = nvml::device_at(&nvml, device_index)?;
+    let name = device.name().context("Failed to read device name")?;
+
+    let mut checks = vec![
+        HealthCheck::new("nvml", CheckStatus::Pass, format!("NVML reachable, driver {}", driver_version(&nvml))),
+        check_temperature(&device),
+        check_ecc_retirements(&device),
+        check_pcie_width(&device),
+    ];
+    checks.push(check_throttling(&device));
+
+    Ok(HealthReport {
+        device_index,
+        name,
+        overall: overall_status(&checks),
+        checks,
+    })
+}
+
+fn driver_version(nvml: &Nvml) -> String {
+    nvml.sys_driver_version().unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn check_temperature(device: &Device) -> HealthCheck {
+    let temperature = match device.temperature(TemperatureSensor::Gpu) {
+        Ok(temperature) => temperature,
+        Err(e) => return HealthCheck::from_error("temperature", e),
+    };
+    // A glitched reading would otherwise fail the check and raise alerts
+    if !validation::temperature_plausible(temperature) {
+        return HealthCheck::new("temperature", CheckStatus::Skipped, format!("Implausible reading of {} °C ignored", temperature));
+    }
+    let slowdown = device.temperature_threshold(TemperatureThreshold::Slowdown)
+        .unwrap_or(DEFAULT_SLOWDOWN_THRESHOLD_C);
+    let (status, detail) = evaluate_temperature(temperature, slowdown);
+    HealthCheck::new("temperature", status, detail)
+}
+
+// Fail at or above the slowdown threshold, warn within the margin below it
+fn evaluate_temperature(temperature_c: u32, slowdown_c: u32) -> (CheckStatus, String) {
+    let detail = format!("{} °C (slowdown at {} °C)", temperature_c, slowdown_c);
+    let status = if temperature_c >= slowdown_c {
+        CheckStatus::Fail
+    } else if temperature_c + TEMPERATURE_WARN_MARGIN_C >= slowdown_c {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    };
+    (status, detail)
+}
+
+fn check_ecc_retirements(device: &Device) -> HealthCheck {
+    match device.are_pages_pending_retired() {
+        Ok(true) => HealthCheck::new("ecc_retirements", CheckStatus::Fail, "Pages pending retirement, reboot required"),
+        Ok(false) => HealthCheck::new("ecc_retirements", CheckStatus::Pass, "No pages pending retirement"),
+        Err(e) => HealthCheck::from_error("ecc_retirements", e),
+    }
+}
+
+fn check_pcie_width(device: &Device) -> HealthCheck {
+    let widths = device.current_pcie_link_width()
+        .and_then(|current| device.max_pcie_link_width().map(|max| (current, max)));
+    match widths {
+        Ok((current, max)) => {
+            let (status, detail) = evaluate_pcie_width(current, max);
+            HealthCheck::new("pcie_width", status, detail)
+        }
+        Err(e) => HealthCheck::from_error("pcie_width", e),
+    }
+}
+
+// Link width (unlike link generation) should not drop at idle, so any
+// reduction points at a riser, slot or seating problem
+fn evaluate_pcie_width(current: u32, max: u32) -> (CheckStatus, String) {
+    let detail = format!("x{} of x{}", current, max);
+    let status = if current >= max { CheckStatus::Pass } else { CheckStatus::Warn };
+    (status, detail)
+}
+
+// An idle GPU shows no thermal or power throttling, so the reasons are only
+// sampled while the stress test's compute load runs
+fn check_throttling(device: &Device) -> HealthCheck {
+    let bus_id = match device.pci_info() {
+        Ok(info) => info.bus_id,
+        Err(e) => return HealthCheck::from_error("throttling", e),
+    };
+    let mut observed = ThrottleReasons::empty();
+    let mut samples = 0;
+    let mut last_sample: Option<Instant> = None;
+    let mut query_error = None;
+
+    // Called between load steps; the first call comes before any load
+    let mut sample = || {
+        let Some(sampled) = last_sample else {
+            last_sample = Some(Instant::now());
+            return true;
+        };
+        if sampled.elapsed() < THROTTLE_SAMPLE_INTERVAL {
+            return true;
+        }
+        last_sample = Some(Instant::now());
+        match device.current_throttle_reasons() {
+            Ok(reasons) => observed |= reasons,
+            Err(e) => {
+                query_error = Some(e);
+                return false;
+            }
+        }
+        samples += 1;
+        samples < THROTTLE_SAMPLES
+    };
+    let loaded = stress::run_load(StressLoad::Compute, &bus_id, &mut sample);
+
+    if let Some(e) = query_error {
+        return HealthCheck::from_error("throttling", e);
+    }
+    if let Err(e) = loaded {
+        return HealthCheck::new("throttling", CheckStatus::Skipped, format!("Could not put the GPU under load: {:#}", e));
+    }
+    let (status, detail) = evaluate_throttle_reasons(observed);
+    HealthCheck::new("throttling", status, detail)
+}
+
+// Thermal and hardware slowdowns fail, a power cap warns, and benign
+// reasons (idle, application clocks, sync boost, display) pass
+fn evaluate_throttle_reasons(reasons: ThrottleReasons) -> (CheckStatus, String) {
+    let failing = ThrottleReasons::HW_SLOWDOWN
+        | ThrottleReasons::SW_THERMAL_SLOWDOWN
+        | ThrottleReasons::HW_THERMAL_SLOWDOWN
+        | ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN;
+    let status = if reasons.intersects(failing) {
+        CheckStatus::Fail
+    } else if reasons.contains(ThrottleReasons::SW_POWER_CAP) {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    };
+
+    let names = throttle_reason_names(reasons);
+    let detail = if names.is_empty() {
+        "No throttling observed".to_string()
+    } else {
+        format!("Observed: {}", names.join(", "))
+    };
+    (status, detail)
+}
+
+/// Human-readable names for a set of throttle reasons
+pub fn throttle_reason_names(reasons: ThrottleReasons) -> Vec<&'static str> {
+    [
+        (ThrottleReasons::GPU_IDLE, "gpu_idle"),
+        (ThrottleReasons::APPLICATIONS_CLOCKS_SETTING, "applications_clocks"),
+        (ThrottleReasons::SW_POWER_CAP, "sw_power_cap"),
+        (ThrottleReasons::HW_SLOWDOWN, "hw_slowdown"),
+        (ThrottleReasons::SYNC_BOOST, "sync_boost"),
+        (ThrottleReasons::SW_THERMAL_SLOWDOWN, "sw_thermal_slowdown"),
+        (ThrottleReasons::HW_THERMAL_SLOWDOWN, "hw_thermal_slowdown"),
+        (ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN, "hw_power_brake"),
+        (ThrottleReasons::DISPLAY_CLOCK_SETTING, "display_clocks"),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| reasons.contains(*flag))
+    .map(|(_, name)| name)
+    .collect()
+}
+
+// The worst status among checks that actually ran
+fn overall_status(checks: &[HealthCheck]) -> CheckStatus {
+    checks.iter()
+        .map(|check| check.status)
+        .filter(|status| *status != CheckStatus::Skipped)
+        .max()
+        .unwrap_or(CheckStatus::Skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_temperature() {
+        assert_eq!(evaluate_temperature(60, 90).0, CheckStatus::Pass);
+        assert_eq!(evaluate_temperature(82, 90).0, CheckStatus::Warn);
+        assert_eq!(evaluate_temperature(90, 90).0, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_evaluate_pcie_width() {
+        assert_eq!(evaluate_pcie_width(16, 16).0, CheckStatus::Pass);
+        let (status, detail) = evaluate_pcie_width(8, 16);
+        assert_eq!(status, CheckStatus::Warn);
+        assert_eq!(detail, "x8 of x16");
+    }
+
+    #[test]
+    fn test_evaluate_throttle_reasons() {
+        assert_eq!(evaluate_throttle_reasons(ThrottleReasons::empty()).0, CheckStatus::Pass);
+        assert_eq!(evaluate_throttle_reasons(ThrottleReasons::GPU_IDLE).0, CheckStatus::Pass);
+        assert_eq!(evaluate_throttle_reasons(ThrottleReasons::SW_POWER_CAP).0, CheckStatus::Warn);
+
+        let (status, detail) = evaluate_throttle_reasons(ThrottleReasons::HW_THERMAL_SLOWDOWN | ThrottleReasons::GPU_IDLE);
+        assert_eq!(status, CheckStatus::Fail);
+        assert_eq!(detail, "Observed: gpu_idle, hw_thermal_slowdown");
+    }
+
+    #[test]
+    fn test_overall_status_ignores_skipped() {
+        let checks = vec![
+            HealthCheck::new("a", CheckStatus::Pass, ""),
+            HealthCheck::new("b", CheckStatus::Skipped, ""),
+            HealthCheck::new("c", CheckStatus::Warn, ""),
+        ];
+        assert_eq!(overall_status(&checks), CheckStatus::Warn);
+        assert_eq!(overall_status(&checks[1..2]), CheckStatus::Skipped);
+    }
+
+    #[test]
+    fn test_check_status_serializes_lowercase() {
+        assert_eq!(serde_json::to_value(CheckStatus::Warn).unwrap(), "warn");
+    }
+}
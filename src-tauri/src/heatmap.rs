@@ -0,0 +1,176 @@
+//! Per-SM utilization history for the SM grid view
+//!
+//! Streamed frames carry a utilization value per SM. The history keeps
+//! them for a rolling window so the SM view can show a time × SM heatmap
+//! of recent activity rather than only the latest frame. Rows are averaged
+//! into time bins on query so the frontend receives a bounded matrix.
+//!
+//! NVML has no per-SM utilization query; unless a frame is marked
+//! `sm_utilizations_measured`, its values are estimated from overall
+//! utilization and are only returned when explicitly requested.
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+use crate::nvml::TelemetryFrame;
+
+/// How far back per-SM rows are kept
+pub const HEATMAP_RETENTION_MS: u128 = 5 * 60 * 1000;
+/// Window used when a heatmap query does not specify one
+pub const DEFAULT_WINDOW_SECONDS: u64 = 60;
+/// Time bins returned when a heatmap query does not specify a count
+pub const DEFAULT_MAX_ROWS: usize = 120;
+
+/// Time × SM utilization matrix of one device
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SmHeatmap {
+    pub device_index: u32,
+    pub sm_count: usize,
+    /// Start of each time bin (Unix milliseconds)
+    pub timestamps: Vec<u64>,
+    /// One row per time bin, one utilization (0.0-1.0) per SM
+    pub rows: Vec<Vec<f32>>,
+    /// Whether every row came from measured rather than estimated values
+    pub measured: bool,
+}
+
+// Per-SM values of one frame
+#[derive(Clone, Debug)]
+struct HeatmapRow {
+    timestamp: u128,
+    /// Monotonic time of the frame (see `TelemetryFrame::clock_ms`), used for binning
+    clock_ms: f64,
+    values: Vec<f32>,
+    measured: bool,
+}
+
+/// Rolling per-device history of per-SM utilization
+#[derive(Default)]
+pub struct SmHeatmapHistory {
+    devices: HashMap<u32, VecDeque<HeatmapRow>>,
+}
+
+impl SmHeatmapHistory {
+    /// Add a streamed frame, dropping rows older than `HEATMAP_RETENTION_MS`
+    pub fn record(&mut self, frame: &TelemetryFrame) {
+        if frame.sm_utilizations.is_empty() {
+            return;
+        }
+        let rows = self.devices.entry(frame.device_index).or_default();
+        rows.push_back(HeatmapRow {
+            timestamp: frame.timestamp,
+            clock_ms: frame.clock_ms(),
+            values: frame.sm_utilizations.clone(),
+            measured: frame.sm_utilizations_measured,
+        });
+        let cutoff = frame.clock_ms() - HEATMAP_RETENTION_MS as f64;
+        while rows.front().is_some_and(|row| row.clock_ms < cutoff) {
+            rows.pop_front();
+        }
+    }
+
+    /// Heatmap over the most recent `window_ms` of a device's rows
+    ///
+    /// # Arguments
+    /// * `device_index` - Device to report on
+    /// * `window_ms` - How far back from the latest row to look
+    /// * `max_rows` - Number of time bins the window is averaged into
+    /// * `include_estimated` - Also use rows whose values were estimated
+    ///
+    /// # Returns
+    /// * `Option<SmHeatmap>` - Heatmap, or `None` if the device has no usable rows
+    pub fn heatmap(&self, device_index: u32, window_ms: u64, max_rows: usize, include_estimated: bool) -> Option<SmHeatmap> {
+        let rows: Vec<&HeatmapRow> = self.devices.get(&device_index)?.iter()
+            .filter(|row| include_estimated || row.measured)
+            .collect();
+        let latest = *rows.last()?;
+        let start = (latest.clock_ms - window_ms as f64).max(0.0);
+        let rows: Vec<&HeatmapRow> = rows.into_iter().filter(|row| row.clock_ms >= start).collect();
+        let sm_count = rows.iter().map(|row| row.values.len()).max()?;
+
+        // Bins of equal width across the window; empty bins are skipped
+        let bins = max_rows.max(1);
+        let bin_ms = window_ms.div_ceil(bins as u64).max(1) as f64;
+        let bin_of = |row: &HeatmapRow| (((row.clock_ms - start) / bin_ms) as usize).min(bins - 1);
+        let mut heatmap = SmHeatmap {
+            device_index,
+            sm_count,
+            timestamps: Vec::new(),
+            rows: Vec::new(),
+            measured: rows.iter().all(|row| row.measured),
+        };
+        let mut index = 0;
+        while index < rows.len() {
+            let bin = bin_of(rows[index]);
+            let members: Vec<&HeatmapRow> = rows[index..].iter()
+                .take_while(|row| bin_of(row) == bin)
+                .copied()
+                .collect();
+            index += members.len();
+
+            let mut sums = vec![0.0f32; sm_count];
+            let mut counts = vec![0u32; sm_count];
+            for row in &members {
+                for (sm, value) in row.values.iter().enumerate() {
+                    sums[sm] += value;
+                    counts[sm] += 1;
+                }
+            }
+            // Bins are placed on the wall clock as it read at the latest row
+            let bin_start_ms = start + bin as f64 * bin_ms;
+            heatmap.timestamps.push((latest.timestamp as f64 - (latest.clock_ms - bin_start_ms)).round().max(0.0) as u64);
+            heatmap.rows.push(sums.iter().zip(&counts)
+                .map(|(sum, &count)| if count > 0 { sum / count as f32 } else { 0.0 })
+                .collect());
+        }
+        Some(heatmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp: u128, values: Vec<f32>, measured: bool) -> TelemetryFrame {
+        TelemetryFrame { timestamp, sm_utilizations: values, sm_utilizations_measured: measured, ..Default::default() }
+    }
+
+    #[test]
+    fn test_averages_rows_into_time_bins() {
+        let mut history = SmHeatmapHistory::default();
+        for (timestamp, values) in [(0, vec![0.0, 1.0]), (400, vec![0.5, 0.5]), (1_000, vec![1.0, 0.0]), (1_900, vec![0.5, 0.0])] {
+            history.record(&frame(timestamp, values, true));
+        }
+
+        let heatmap = history.heatmap(0, 2_000, 2, false).unwrap();
+        assert_eq!(heatmap.sm_count, 2);
+        assert_eq!(heatmap.timestamps, vec![0, 1_000]);
+        assert_eq!(heatmap.rows, vec![vec![0.25, 0.75], vec![0.75, 0.0]]);
+        assert!(heatmap.measured);
+
+        let recent = history.heatmap(0, 500, 10, false).unwrap();
+        assert_eq!(recent.rows.len(), 1);
+    }
+
+    #[test]
+    fn test_estimated_rows_only_on_request() {
+        let mut history = SmHeatmapHistory::default();
+        history.record(&frame(0, vec![0.3; 4], false));
+        history.record(&frame(100, Vec::new(), true));
+        assert!(history.heatmap(0, 1_000, 10, false).is_none());
+
+        let estimated = history.heatmap(0, 1_000, 10, true).unwrap();
+        assert!(!estimated.measured);
+        assert_eq!(estimated.rows, vec![vec![0.3; 4]]);
+        assert!(history.heatmap(1, 1_000, 10, true).is_none());
+    }
+
+    #[test]
+    fn test_drops_rows_past_retention() {
+        let mut history = SmHeatmapHistory::default();
+        history.record(&frame(0, vec![1.0], true));
+        history.record(&frame(HEATMAP_RETENTION_MS + 1, vec![0.0], true));
+        let heatmap = history.heatmap(0, HEATMAP_RETENTION_MS as u64 * 2, 1, false).unwrap();
+        assert_eq!(heatmap.rows, vec![vec![0.0]]);
+    }
+}
@@ -0,0 +1,223 @@
+//! Metric distributions
+//!
+//! Histograms of one scalar metric (any column of a columnar recording,
+//! e.g. `temperature_c` or `sm_clock_mhz`), computed from the live stream
+//! history or from a saved recording, so the UI can show a distribution
+//! without receiving the raw samples.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::columnar::{self, COLUMNAR_EXTENSION, METRIC_COLUMNS};
+use crate::error::AppError;
+use crate::schema;
+
+/// Window used when a stream histogram query does not specify one
+pub const DEFAULT_WINDOW_SECONDS: u64 = 60;
+/// Bins used when a histogram query does not specify a count
+pub const DEFAULT_BINS: usize = 20;
+/// Most bins a histogram may have
+pub const MAX_BINS: usize = 1_000;
+
+/// How values are binned
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HistogramSpec {
+    pub bins: usize,
+    /// Lower edge of the first bin; the smallest value if unset
+    pub min: Option<f64>,
+    /// Upper edge of the last bin; the largest value if unset
+    pub max: Option<f64>,
+}
+
+impl Default for HistogramSpec {
+    fn default() -> Self {
+        HistogramSpec { bins: DEFAULT_BINS, min: None, max: None }
+    }
+}
+
+/// One bin of a histogram, covering `[low, high)` (the last bin includes `high`)
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct HistogramBin {
+    pub low: f64,
+    pub high: f64,
+    pub count: usize,
+    /// Share of all samples, including those outside the range
+    pub percent: f64,
+}
+
+/// Distribution of one metric
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub metric: String,
+    pub sample_count: usize,
+    /// Smallest, largest and mean value of all samples
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub bins: Vec<HistogramBin>,
+    /// Samples below the first or above the last bin
+    pub below: usize,
+    pub above: usize,
+}
+
+/// Bin a metric's values
+///
+/// # Arguments
+/// * `metric` - Metric name, for display
+/// * `values` - Samples of the metric
+/// * `spec` - Bin count and range
+///
+/// # Returns
+/// * `Result<Histogram>` - Histogram or error for an empty sample set or an invalid spec
+pub fn histogram(metric: &str, values: &[f64], spec: HistogramSpec) -> Result<Histogram> {
+    if !(1..=MAX_BINS).contains(&spec.bins) {
+        return Err(AppError::InvalidArgument(format!("Bin count must be between 1 and {}, got {}", MAX_BINS, spec.bins)).into());
+    }
+    if let (Some(min), Some(max)) = (spec.min, spec.max) {
+        if min >= max {
+            return Err(AppError::InvalidArgument(format!("Histogram range start {} must be below its end {}", min, max)).into());
+        }
+    }
+    if values.is_empty() {
+        return Err(AppError::InvalidArgument(format!("No samples of {}", metric)).into());
+    }
+
+    let smallest = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let largest = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let low = spec.min.unwrap_or(smallest);
+    let mut high = spec.max.unwrap_or(largest);
+    // All values equal, or a one-sided range with nothing above it
+    if high <= low {
+        high = low + 1.0;
+    }
+    let width = (high - low) / spec.bins as f64;
+
+    let mut counts = vec![0usize; spec.bins];
+    let (mut below, mut above) = (0, 0);
+    for &value in values {
+        if value < low {
+            below += 1;
+        } else if value > high {
+            above += 1;
+        } else {
+            counts[(((value - low) / width) as usize).min(spec.bins - 1)] += 1;
+        }
+    }
+
+    Ok(Histogram {
+        metric: metric.to_string(),
+        sample_count: values.len(),
+        min: smallest,
+        max: largest,
+        mean: values.iter().sum::<f64>() / values.len() as f64,
+        bins: counts.into_iter().enumerate()
+            .map(|(bin, count)| HistogramBin {
+                low: low + bin as f64 * width,
+                high: low + (bin + 1) as f64 * width,
+                count,
+                percent: count as f64 * 100.0 / values.len() as f64,
+            })
+            .collect(),
+        below,
+        above,
+    })
+}
+
+/// Histogram of a metric over a saved recording
+///
+/// # Arguments
+/// * `path` - JSON recording (any schema version) or `.gpurec` columnar recording
+/// * `metric` - Recording column name
+/// * `device_index` - Device of a multi-device recording (the primary device if unset)
+/// * `spec` - Bin count and range
+///
+/// # Returns
+/// * `Result<Histogram>` - Histogram or error for an unknown metric or device
+pub fn recording_histogram(path: &Path, metric: &str, device_index: Option<u32>, spec: HistogramSpec) -> Result<Histogram> {
+    let Some((_, value)) = METRIC_COLUMNS.iter().find(|(name, _)| *name == metric) else {
+        return Err(AppError::InvalidArgument(format!("Unknown recording metric: {}", metric)).into());
+    };
+
+    let values = if path.extension().is_some_and(|ext| ext == COLUMNAR_EXTENSION) {
+        let mut data = columnar::read_range(path, 0, u64::MAX, &[metric.to_string()])?;
+        if device_index.is_some_and(|index| index != data.device.index) {
+            return Err(AppError::InvalidArgument(format!("GPU {} is not in {}", device_index.unwrap_or_default(), path.display())).into());
+        }
+        data.columns.remove(metric).unwrap_or_default()
+    } else {
+        let recording = schema::load_recording(path)?;
+        let device_index = device_index.unwrap_or(recording.device.index);
+        if !recording.devices.iter().any(|device| device.index == device_index) {
+            return Err(AppError::InvalidArgument(format!("GPU {} is not in {}", device_index, path.display())).into());
+        }
+        recording.samples.iter()
+            .filter(|frame| frame.device_index == device_index)
+            .map(value)
+            .collect()
+    };
+    histogram(metric, &values, spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvml::{RecordingFile, StaticDeviceInfo, TelemetryFrame};
+
+    fn histogram_of(values: &[f64], spec: HistogramSpec) -> Histogram {
+        histogram("power_w", values, spec).unwrap()
+    }
+
+    #[test]
+    fn test_bins_values_over_their_range() {
+        let distribution = histogram("temperature_c", &[40.0, 45.0, 50.0, 59.0, 60.0], HistogramSpec { bins: 2, ..Default::default() }).unwrap();
+        assert_eq!((distribution.min, distribution.max, distribution.mean), (40.0, 60.0, 50.8));
+        let bins: Vec<(f64, f64, usize)> = distribution.bins.iter().map(|bin| (bin.low, bin.high, bin.count)).collect();
+        assert_eq!(bins, vec![(40.0, 50.0, 2), (50.0, 60.0, 3)]);
+        assert_eq!(distribution.bins[1].percent, 60.0);
+
+        let constant = histogram_of(&[70.0, 70.0], HistogramSpec::default());
+        assert_eq!(constant.bins[0].count, 2);
+    }
+
+    #[test]
+    fn test_fixed_range_counts_outliers() {
+        let spec = HistogramSpec { bins: 4, min: Some(0.0), max: Some(100.0) };
+        let distribution = histogram_of(&[-5.0, 10.0, 30.0, 99.0, 100.0, 150.0], spec);
+        assert_eq!(distribution.bins.iter().map(|bin| bin.count).collect::<Vec<_>>(), vec![1, 1, 0, 2]);
+        assert_eq!((distribution.below, distribution.above), (1, 1));
+
+        for spec in [HistogramSpec { bins: 0, ..spec }, HistogramSpec { min: Some(100.0), ..spec }] {
+            let err = histogram("power_w", &[1.0], spec).unwrap_err();
+            assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+        }
+        assert!(histogram("power_w", &[], HistogramSpec::default()).is_err());
+    }
+
+    #[test]
+    fn test_recording_histogram_of_device() {
+        let path = std::env::temp_dir().join(format!("nsightful_histogram_{}.json", std::process::id()));
+        let device = |index| StaticDeviceInfo { index, ..Default::default() };
+        let frame = |device_index, sm_clock_mhz| TelemetryFrame { device_index, sm_clock_mhz, ..Default::default() };
+        let recording = RecordingFile {
+            schema_version: schema::CURRENT_SCHEMA_VERSION,
+            device: device(0),
+            devices: vec![device(0), device(1)],
+            samples: vec![frame(0, 1_500), frame(1, 900), frame(0, 1_700)],
+            markers: Vec::new(),
+            timing: None,
+            process: None,
+            violations: Vec::new(),
+            clock: None,
+        };
+        std::fs::write(&path, serde_json::to_string(&recording).unwrap()).unwrap();
+
+        let primary = recording_histogram(&path, "sm_clock_mhz", None, HistogramSpec::default()).unwrap();
+        assert_eq!((primary.sample_count, primary.min, primary.max), (2, 1_500.0, 1_700.0));
+        let second = recording_histogram(&path, "sm_clock_mhz", Some(1), HistogramSpec::default()).unwrap();
+        assert_eq!(second.sample_count, 1);
+        assert!(recording_histogram(&path, "sm_clock_mhz", Some(2), HistogramSpec::default()).is_err());
+        assert!(recording_histogram(&path, "clock", None, HistogramSpec::default()).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
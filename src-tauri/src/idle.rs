@@ -0,0 +1,169 @@
+//! Idle detection for the telemetry stream
+//!
+//! Streaming at full rate while the GPU sits idle costs the app's own
+//! power, which matters on laptops. With an idle policy, a device whose
+//! utilization stays at or below a threshold for long enough is sampled at
+//! a slower interval, and optionally its frames stop being emitted to the
+//! frontend. Sampling never stops entirely, so the first busy sample
+//! restores the full rate. Transitions are emitted as `stream-suspended`
+//! and `stream-resumed` events.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::nvml::TelemetryFrame;
+
+/// Default interval while idle
+pub const DEFAULT_IDLE_PERIOD_MS: u64 = 2_000;
+
+/// When and how a stream slows down for an idle device
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IdlePolicy {
+    /// Idle time before the stream slows down
+    pub idle_after_seconds: u64,
+    /// GPU utilization (%) at or below which the device counts as idle
+    #[serde(default)]
+    pub utilization_threshold: u32,
+    /// Sampling interval while idle
+    #[serde(default = "default_idle_period_ms")]
+    pub idle_period_ms: u64,
+    /// Stop emitting `telemetry-update` while idle
+    #[serde(default)]
+    pub suspend_emission: bool,
+}
+
+fn default_idle_period_ms() -> u64 {
+    DEFAULT_IDLE_PERIOD_MS
+}
+
+impl IdlePolicy {
+    /// Check the policy before streaming with it
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.idle_after_seconds == 0 {
+            return Err(AppError::InvalidArgument("Idle time must be at least 1 second".to_string()));
+        }
+        if self.utilization_threshold > 100 {
+            return Err(AppError::InvalidArgument(format!(
+                "Idle utilization threshold must be at most 100%, got {}", self.utilization_threshold
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Payload of the `stream-suspended` and `stream-resumed` events
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct IdleTransition {
+    pub device_index: u32,
+    /// Whether the device is now idle
+    pub idle: bool,
+    /// How long the device had been idle
+    pub idle_seconds: u64,
+    /// Sampling interval from now on
+    pub period_ms: u64,
+    /// Whether frames are emitted from now on
+    pub emitting: bool,
+}
+
+/// Tracks whether one device has been idle long enough
+pub struct IdleDetector {
+    policy: IdlePolicy,
+    active_period_ms: u64,
+    /// Monotonic time the device became idle (see `TelemetryFrame::clock_ms`)
+    idle_since: Option<f64>,
+    idle: bool,
+}
+
+impl IdleDetector {
+    /// # Arguments
+    /// * `policy` - Idle policy of the stream
+    /// * `active_period_ms` - Sampling interval while the device is busy
+    pub fn new(policy: IdlePolicy, active_period_ms: u64) -> Self {
+        IdleDetector { policy, active_period_ms, idle_since: None, idle: false }
+    }
+
+    /// Sampling interval for the current state
+    pub fn period_ms(&self) -> u64 {
+        if self.idle { self.policy.idle_period_ms.max(self.active_period_ms) } else { self.active_period_ms }
+    }
+
+    /// Whether frames should be emitted in the current state
+    pub fn emitting(&self) -> bool {
+        !(self.idle && self.policy.suspend_emission)
+    }
+
+    /// Feed a frame, returning the transition it causes, if any
+    pub fn observe(&mut self, frame: &TelemetryFrame) -> Option<IdleTransition> {
+        let idle_seconds = |since: Option<f64>| since.map_or(0, |since| ((frame.clock_ms() - since) / 1000.0).max(0.0) as u64);
+        if frame.util_gpu > self.policy.utilization_threshold {
+            let was_idle = std::mem::replace(&mut self.idle, false);
+            let since = self.idle_since.take();
+            return was_idle.then(|| self.transition(frame.device_index, idle_seconds(since)));
+        }
+
+        let since = *self.idle_since.get_or_insert(frame.clock_ms());
+        let seconds = idle_seconds(Some(since));
+        if !self.idle && seconds >= self.policy.idle_after_seconds {
+            self.idle = true;
+            return Some(self.transition(frame.device_index, seconds));
+        }
+        None
+    }
+
+    fn transition(&self, device_index: u32, idle_seconds: u64) -> IdleTransition {
+        IdleTransition {
+            device_index,
+            idle: self.idle,
+            idle_seconds,
+            period_ms: self.period_ms(),
+            emitting: self.emitting(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp: u128, util_gpu: u32) -> TelemetryFrame {
+        TelemetryFrame { timestamp, util_gpu, ..Default::default() }
+    }
+
+    fn policy() -> IdlePolicy {
+        IdlePolicy { idle_after_seconds: 60, utilization_threshold: 5, idle_period_ms: 2_000, suspend_emission: true }
+    }
+
+    #[test]
+    fn test_suspends_after_sustained_idle_and_resumes_on_activity() {
+        let mut detector = IdleDetector::new(policy(), 100);
+        assert_eq!(detector.observe(&frame(0, 3)), None);
+        assert_eq!(detector.observe(&frame(59_900, 5)), None);
+
+        let suspended = detector.observe(&frame(60_000, 0)).unwrap();
+        assert_eq!((suspended.idle, suspended.idle_seconds, suspended.period_ms, suspended.emitting), (true, 60, 2_000, false));
+        assert_eq!(detector.observe(&frame(62_000, 0)), None);
+
+        let resumed = detector.observe(&frame(64_000, 80)).unwrap();
+        assert_eq!((resumed.idle, resumed.idle_seconds, resumed.period_ms, resumed.emitting), (false, 64, 100, true));
+        assert_eq!(detector.period_ms(), 100);
+    }
+
+    #[test]
+    fn test_activity_restarts_idle_timer() {
+        let mut detector = IdleDetector::new(policy(), 100);
+        detector.observe(&frame(0, 0));
+        assert_eq!(detector.observe(&frame(30_000, 50)), None);
+        assert_eq!(detector.observe(&frame(31_000, 0)), None);
+        assert_eq!(detector.observe(&frame(61_000, 0)), None);
+        assert!(detector.observe(&frame(91_000, 0)).is_some());
+    }
+
+    #[test]
+    fn test_policy_validation_and_defaults() {
+        let parsed: IdlePolicy = serde_json::from_str(r#"{"idle_after_seconds": 300}"#).unwrap();
+        assert_eq!((parsed.idle_period_ms, parsed.utilization_threshold, parsed.suspend_emission), (DEFAULT_IDLE_PERIOD_MS, 0, false));
+        assert!(parsed.validate().is_ok());
+        assert!(IdlePolicy { idle_after_seconds: 0, ..policy() }.validate().is_err());
+        assert_eq!(IdlePolicy { utilization_threshold: 101, ..policy() }.validate().unwrap_err().code(), "INVALID_ARGUMENT");
+    }
+}
@@ -0,0 +1,297 @@
+//! Crash-safe journal of an in-progress recording
+//!
+//! While a recording runs, its frames are appended to a JSON-lines journal
+//! next to the output file (`gpu_recording_x.journal` for
+//! `gpu_recording_x.json`). The first line is a `JournalHeader`; every
+//! further line is one `TelemetryFrame`. The journal is flushed and synced
+//! to disk every `SYNC_INTERVAL`, so a crash loses at most that much data.
+//!
+//! A finished recording removes its journal. A journal left behind belongs
+//! to a session that never finished; `recover_recordings` turns it into a
+//! regular recording file.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::nvml::{self, ClockMapping, RecordingFile, StaticDeviceInfo, TelemetryFrame};
+use crate::schema;
+
+/// Extension of journal files
+pub const JOURNAL_EXTENSION: &str = "journal";
+/// How often appended frames are synced to disk
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// First line of a journal, describing the session
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JournalHeader {
+    pub session_id: String,
+    /// Recording file the session was writing
+    pub output_file: String,
+    pub sample_rate_hz: u64,
+    /// Recorded devices, primary first
+    pub devices: Vec<StaticDeviceInfo>,
+    /// Clock readings at the start of the session
+    #[serde(default)]
+    pub clock: Option<ClockMapping>,
+}
+
+/// An incomplete session turned into a recording file
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RecoveredRecording {
+    pub session_id: String,
+    pub output_file: String,
+    pub sample_count: usize,
+    /// Lines that could not be read, usually one cut off by the crash
+    pub discarded_lines: usize,
+}
+
+/// Append-only journal of a running recording
+pub struct RecordingJournal {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    bytes_written: u64,
+    last_sync: Instant,
+}
+
+impl RecordingJournal {
+    /// Create the journal for a recording and write its header
+    ///
+    /// # Arguments
+    /// * `header` - Session description; the journal is placed next to `header.output_file`
+    ///
+    /// # Returns
+    /// * `Result<RecordingJournal>` - Open journal or error
+    pub fn create(header: &JournalHeader) -> Result<Self> {
+        let path = journal_path(Path::new(&header.output_file));
+        let file = OpenOptions::new().write(true).create(true).truncate(true).open(&path)
+            .with_context(|| format!("Failed to create journal {}", path.display()))?;
+        let mut journal = RecordingJournal {
+            path,
+            writer: BufWriter::new(file),
+            bytes_written: 0,
+            last_sync: Instant::now(),
+        };
+        journal.write_line(header)?;
+        journal.sync()?;
+        Ok(journal)
+    }
+
+    /// Append frames, syncing to disk if `SYNC_INTERVAL` has passed
+    pub fn append(&mut self, frames: &[TelemetryFrame]) -> Result<()> {
+        for frame in frames {
+            self.write_line(frame)?;
+        }
+        if self.last_sync.elapsed() >= SYNC_INTERVAL {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Bytes written to the journal so far
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Delete the journal once its recording has been saved
+    pub fn finish(self) -> Result<()> {
+        drop(self.writer);
+        std::fs::remove_file(&self.path)
+            .with_context(|| format!("Failed to remove journal {}", self.path.display()))
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        let mut line = serde_json::to_vec(value).context("Failed to serialize journal entry")?;
+        line.push(b'\n');
+        self.writer.write_all(&line).context("Failed to write journal")?;
+        self.bytes_written += line.len() as u64;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.writer.flush().context("Failed to flush journal")?;
+        self.writer.get_ref().sync_data().context("Failed to sync journal")?;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+}
+
+/// Journal path of a recording file
+pub fn journal_path(output_file: &Path) -> PathBuf {
+    output_file.with_extension(JOURNAL_EXTENSION)
+}
+
+/// Finalize the sessions whose journals were left in a directory
+///
+/// Each journal is written out as the recording file its session would
+/// have produced, then removed. Journals whose recording file already
+/// exists (the app died between saving and cleaning up) are only removed.
+///
+/// # Arguments
+/// * `dir` - Directory holding recordings
+/// * `active_output` - Output file of the recording in progress, whose journal is left alone
+///
+/// # Returns
+/// * `Result<Vec<RecoveredRecording>>` - Recovered sessions or error if the directory cannot be read
+pub fn recover_recordings(dir: &Path, active_output: Option<&Path>) -> Result<Vec<RecoveredRecording>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let active_journal = active_output.map(journal_path);
+    let mut journals: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == JOURNAL_EXTENSION))
+        .filter(|path| active_journal.as_deref() != Some(path.as_path()))
+        .collect();
+    journals.sort();
+
+    let mut recovered = Vec::new();
+    for path in journals {
+        match recover_journal(&path) {
+            Ok(Some(recording)) => recovered.push(recording),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Failed to recover {}: {:#}", path.display(), e);
+                continue;
+            }
+        }
+        if let Err(e) = std::fs::remove_file(&path) {
+            eprintln!("Failed to remove journal {}: {}", path.display(), e);
+        }
+    }
+    Ok(recovered)
+}
+
+// Write out one journal's recording; `None` if it was already saved
+fn recover_journal(path: &Path) -> Result<Option<RecoveredRecording>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut lines = BufReader::new(file).lines();
+    let header_line = lines.next()
+        .context("Journal is empty")?
+        .context("Failed to read journal")?;
+    let header: JournalHeader = serde_json::from_str(&header_line).context("Journal header is corrupt")?;
+    if Path::new(&header.output_file).exists() {
+        return Ok(None);
+    }
+
+    let mut samples = Vec::new();
+    let mut discarded_lines = 0;
+    for line in lines {
+        match line.ok().and_then(|line| serde_json::from_str::<TelemetryFrame>(&line).ok()) {
+            Some(frame) => samples.push(frame),
+            None => discarded_lines += 1,
+        }
+    }
+    let Some(primary) = header.devices.first().cloned() else {
+        anyhow::bail!("Journal lists no devices");
+    };
+
+    let primary_samples: Vec<&TelemetryFrame> = samples.iter()
+        .filter(|frame| frame.device_index == primary.index)
+        .collect();
+    let sample_times_ms: Vec<f64> = primary_samples.iter()
+        .map(|frame| frame.ms_since(primary_samples[0]))
+        .collect();
+    let recording = RecordingFile {
+        schema_version: schema::CURRENT_SCHEMA_VERSION,
+        device: primary,
+        devices: header.devices,
+        timing: Some(nvml::sampling_timing(header.sample_rate_hz, &sample_times_ms)),
+        process: None,
+        violations: Vec::new(),
+        clock: header.clock,
+        samples,
+        // Ranges were held in memory by the session that died
+        markers: Vec::new(),
+    };
+    let json = serde_json::to_string_pretty(&recording).context("Failed to serialize recording data")?;
+    std::fs::write(&header.output_file, json)
+        .with_context(|| format!("Failed to write {}", header.output_file))?;
+
+    Ok(Some(RecoveredRecording {
+        session_id: header.session_id,
+        output_file: header.output_file,
+        sample_count: recording.samples.len(),
+        discarded_lines,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nsightful_journal_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn header(dir: &Path, session_id: &str) -> JournalHeader {
+        JournalHeader {
+            session_id: session_id.to_string(),
+            output_file: dir.join(format!("gpu_recording_{}.json", session_id)).display().to_string(),
+            sample_rate_hz: 10,
+            devices: vec![StaticDeviceInfo::default()],
+            clock: None,
+        }
+    }
+
+    fn frames(count: u128) -> Vec<TelemetryFrame> {
+        (0..count).map(|i| TelemetryFrame { timestamp: 1_000 + i * 100, ..Default::default() }).collect()
+    }
+
+    #[test]
+    fn test_recovers_journal_with_truncated_tail() {
+        let dir = temp_dir("truncated");
+        let header = header(&dir, "rec_1");
+        let mut journal = RecordingJournal::create(&header).unwrap();
+        journal.append(&frames(5)).unwrap();
+        let path = journal.path.clone();
+        // Simulate a crash: flush without cleanup, then a half-written line
+        journal.sync().unwrap();
+        std::mem::forget(journal);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"timestamp\":15").unwrap();
+
+        let recovered = recover_recordings(&dir, None).unwrap();
+        assert_eq!(recovered, vec![RecoveredRecording {
+            session_id: "rec_1".to_string(),
+            output_file: header.output_file.clone(),
+            sample_count: 5,
+            discarded_lines: 1,
+        }]);
+        let recording = schema::load_recording(Path::new(&header.output_file)).unwrap();
+        assert_eq!(recording.samples.len(), 5);
+        assert_eq!(recording.timing.unwrap().achieved_rate_hz, 10.0);
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_skips_active_and_removes_saved_sessions() {
+        let dir = temp_dir("active");
+        let active = header(&dir, "rec_active");
+        let mut journal = RecordingJournal::create(&active).unwrap();
+        journal.append(&frames(2)).unwrap();
+        assert!(journal.bytes_written() > 0);
+
+        // Saved but not cleaned up: only the journal goes
+        let saved = header(&dir, "rec_saved");
+        RecordingJournal::create(&saved).unwrap();
+        std::fs::write(&saved.output_file, "{}").unwrap();
+
+        let recovered = recover_recordings(&dir, Some(Path::new(&active.output_file))).unwrap();
+        assert!(recovered.is_empty());
+        assert_eq!(std::fs::read_to_string(&saved.output_file).unwrap(), "{}");
+        assert!(!journal_path(Path::new(&saved.output_file)).exists());
+        assert!(journal_path(Path::new(&active.output_file)).exists());
+
+        journal.finish().unwrap();
+        assert!(!journal_path(Path::new(&active.output_file)).exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,333 @@
+//! Browsing and managing saved recordings
+//!
+//! Lists, describes, renames and deletes the recordings in the recordings
+//! directory, so the frontend never touches the filesystem itself. Paths
+//! passed in must name a recording directly inside that directory; an
+//! analysis summary written next to a recording moves and goes with it.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::analysis;
+use crate::columnar::{self, COLUMNAR_EXTENSION};
+use crate::error::AppError;
+use crate::nvml::{ClockMapping, SamplingTiming, StaticDeviceInfo};
+use crate::schema;
+use crate::violations::SessionViolations;
+
+/// Storage format of a recording
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingFormat {
+    Json,
+    Columnar,
+}
+
+/// A recording file in the library
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RecordingEntry {
+    pub path: String,
+    /// File name without extension
+    pub name: String,
+    pub format: RecordingFormat,
+    pub size_bytes: u64,
+    /// Last modification time (Unix milliseconds)
+    pub modified_ms: u64,
+}
+
+/// Contents of a recording, as shown in a session browser
+#[derive(Serialize, Clone, Debug)]
+pub struct RecordingMetadata {
+    #[serde(flatten)]
+    pub entry: RecordingEntry,
+    /// Recorded devices, primary first
+    pub devices: Vec<StaticDeviceInfo>,
+    pub sample_count: u64,
+    /// Timestamps of the first and last samples; `None` for an empty recording
+    pub first_ms: Option<u64>,
+    pub last_ms: Option<u64>,
+    /// Sampling rate achieved; only kept by JSON recordings
+    pub timing: Option<SamplingTiming>,
+    pub marker_count: usize,
+    /// Time each device spent throttled; only kept by JSON recordings
+    pub violations: Vec<SessionViolations>,
+    /// Clock readings at the start; only kept by JSON recordings
+    pub clock: Option<ClockMapping>,
+    /// Analysis summary written next to the recording, if any
+    pub analysis_file: Option<String>,
+}
+
+/// List the recordings in a directory, newest first
+///
+/// # Arguments
+/// * `dir` - Recordings directory; a missing directory holds no recordings
+///
+/// # Returns
+/// * `Result<Vec<RecordingEntry>>` - Recordings or error if the directory cannot be read
+pub fn list_recordings(dir: &Path) -> Result<Vec<RecordingEntry>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for path in std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = path.context("Failed to read recordings directory entry")?.path();
+        if let Some(format) = recording_format(&path) {
+            entries.push(entry(&path, format)?);
+        }
+    }
+    entries.sort_by(|a, b| b.modified_ms.cmp(&a.modified_ms).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+/// Describe one recording
+///
+/// JSON recordings are loaded in full; columnar recordings only have their
+/// header and index read.
+///
+/// # Arguments
+/// * `dir` - Recordings directory
+/// * `path` - Recording inside `dir`
+///
+/// # Returns
+/// * `Result<RecordingMetadata>` - Metadata or error if `path` is not a readable recording in `dir`
+pub fn get_recording_metadata(dir: &Path, path: &Path) -> Result<RecordingMetadata> {
+    let (path, format) = resolve(dir, path)?;
+    let entry = entry(&path, format)?;
+    let analysis_file = analysis_files(&path, format).into_iter()
+        .find(|(_, extension)| *extension == "json")
+        .map(|(analysis, _)| analysis.display().to_string());
+
+    match format {
+        RecordingFormat::Json => {
+            let recording = schema::load_recording(&path)?;
+            let first_ms = recording.samples.first().map(|frame| recording.steady_timestamp(frame) as u64);
+            let last_ms = recording.samples.last().map(|frame| recording.steady_timestamp(frame) as u64);
+            Ok(RecordingMetadata {
+                entry,
+                devices: recording.devices,
+                sample_count: recording.samples.len() as u64,
+                first_ms,
+                last_ms,
+                timing: recording.timing,
+                marker_count: recording.markers.len(),
+                violations: recording.violations,
+                clock: recording.clock,
+                analysis_file,
+            })
+        }
+        RecordingFormat::Columnar => {
+            let summary = columnar::read_summary(&path)?;
+            Ok(RecordingMetadata {
+                entry,
+                devices: vec![summary.header.device],
+                sample_count: summary.frames,
+                first_ms: summary.first_ms,
+                last_ms: summary.last_ms,
+                timing: None,
+                marker_count: 0,
+                violations: Vec::new(),
+                clock: None,
+                analysis_file,
+            })
+        }
+    }
+}
+
+/// Delete a recording and its analysis summary
+///
+/// The summary goes first, so a failure never leaves one behind without
+/// its recording.
+///
+/// # Arguments
+/// * `dir` - Recordings directory
+/// * `path` - Recording inside `dir`
+pub fn delete_recording(dir: &Path, path: &Path) -> Result<()> {
+    let (path, format) = resolve(dir, path)?;
+    for (analysis, _) in analysis_files(&path, format) {
+        std::fs::remove_file(&analysis).with_context(|| format!("Failed to delete {}", analysis.display()))?;
+    }
+    std::fs::remove_file(&path).with_context(|| format!("Failed to delete {}", path.display()))
+}
+
+/// Rename a recording, keeping its extension, and move its analysis summary along
+///
+/// The summary moves first and the recording last; if any move fails, the
+/// files already moved are put back.
+///
+/// # Arguments
+/// * `dir` - Recordings directory
+/// * `path` - Recording inside `dir`
+/// * `name` - New file name, without extension
+///
+/// # Returns
+/// * `Result<RecordingEntry>` - The renamed recording or error if the name is invalid or taken
+pub fn rename_recording(dir: &Path, path: &Path, name: &str) -> Result<RecordingEntry> {
+    let (path, format) = resolve(dir, path)?;
+    let name = name.trim();
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) || name.contains("..") {
+        return Err(AppError::InvalidArgument(format!("Invalid recording name: {:?}", name)).into());
+    }
+    let extension = path.extension().map(|ext| ext.to_string_lossy().into_owned()).unwrap_or_default();
+    let target = path.with_file_name(format!("{}.{}", name, extension));
+    if target == path {
+        return entry(&path, format);
+    }
+    if recording_format(&target).is_none() {
+        return Err(AppError::InvalidArgument(format!("Invalid recording name: {:?}", name)).into());
+    }
+    if target.exists() {
+        return Err(AppError::InvalidArgument(format!("A recording named {} already exists", name)).into());
+    }
+
+    let moves: Vec<(PathBuf, PathBuf)> = analysis_files(&path, format).into_iter()
+        .map(|(analysis, extension)| (analysis, analysis::report_path(&target, extension)))
+        .chain(std::iter::once((path.clone(), target.clone())))
+        .collect();
+    if let Some((_, taken)) = moves.iter().find(|(_, to)| to.exists()) {
+        return Err(AppError::InvalidArgument(format!("{} already exists", taken.display())).into());
+    }
+    for (moved, (from, to)) in moves.iter().enumerate() {
+        if let Err(e) = std::fs::rename(from, to) {
+            for (from, to) in moves[..moved].iter().rev() {
+                if let Err(e) = std::fs::rename(to, from) {
+                    eprintln!("Failed to move {} back: {}", to.display(), e);
+                }
+            }
+            return Err(e).with_context(|| format!("Failed to rename {}", from.display()));
+        }
+    }
+    entry(&target, format)
+}
+
+// Format of a recording file; `None` for analysis summaries, journals and anything else
+fn recording_format(path: &Path) -> Option<RecordingFormat> {
+    let extension = path.extension()?;
+    if extension == COLUMNAR_EXTENSION {
+        Some(RecordingFormat::Columnar)
+    } else if extension == "json" && !path.file_stem()?.to_string_lossy().ends_with(".analysis") {
+        Some(RecordingFormat::Json)
+    } else {
+        None
+    }
+}
+
+// Existing analysis summary files of a recording, with their report
+// extension; only JSON recordings are analyzed
+fn analysis_files(path: &Path, format: RecordingFormat) -> Vec<(PathBuf, &'static str)> {
+    if format != RecordingFormat::Json {
+        return Vec::new();
+    }
+    ["json", "html"].into_iter()
+        .map(|extension| (analysis::report_path(path, extension), extension))
+        .filter(|(analysis, _)| analysis.exists())
+        .collect()
+}
+
+// The recording `path` names, confined to `dir`
+fn resolve(dir: &Path, path: &Path) -> Result<(PathBuf, RecordingFormat)> {
+    let dir = dir.canonicalize().with_context(|| format!("Failed to open {}", dir.display()))?;
+    let resolved = path.canonicalize().with_context(|| format!("Recording {} not found", path.display()))?;
+    match recording_format(&resolved) {
+        Some(format) if resolved.parent() == Some(dir.as_path()) && resolved.is_file() => Ok((resolved, format)),
+        _ => Err(AppError::InvalidArgument(format!("{} is not a recording in {}", path.display(), dir.display())).into()),
+    }
+}
+
+fn entry(path: &Path, format: RecordingFormat) -> Result<RecordingEntry> {
+    let metadata = std::fs::metadata(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let modified_ms = metadata.modified().ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_millis() as u64);
+    Ok(RecordingEntry {
+        path: path.display().to_string(),
+        name: path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+        format,
+        size_bytes: metadata.len(),
+        modified_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvml::{RecordingFile, TelemetryFrame};
+
+    fn library(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nsightful_library_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let recording = RecordingFile {
+            schema_version: schema::CURRENT_SCHEMA_VERSION,
+            device: StaticDeviceInfo::default(),
+            devices: vec![StaticDeviceInfo::default()],
+            samples: (0..3u128).map(|i| TelemetryFrame { timestamp: 1_000 + i * 100, ..Default::default() }).collect(),
+            markers: Vec::new(),
+            timing: None,
+            process: None,
+            violations: Vec::new(),
+            clock: None,
+        };
+        std::fs::write(dir.join("run.json"), serde_json::to_string(&recording).unwrap()).unwrap();
+        std::fs::write(dir.join("run.analysis.json"), "{}").unwrap();
+        std::fs::write(dir.join("run.analysis.html"), "").unwrap();
+        std::fs::write(dir.join("rec_1.journal"), "").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_lists_and_describes_recordings() {
+        let dir = library("list");
+        columnar::convert_json_recording(&dir.join("run.json")).unwrap();
+
+        let mut names: Vec<(String, RecordingFormat)> = list_recordings(&dir).unwrap().into_iter()
+            .map(|entry| (entry.name, entry.format))
+            .collect();
+        names.sort_by_key(|(_, format)| *format as u8);
+        assert_eq!(names, vec![("run".to_string(), RecordingFormat::Json), ("run".to_string(), RecordingFormat::Columnar)]);
+
+        let json = get_recording_metadata(&dir, &dir.join("run.json")).unwrap();
+        assert_eq!((json.sample_count, json.first_ms, json.last_ms), (3, Some(1_000), Some(1_200)));
+        assert!(json.analysis_file.is_some());
+        let columnar = get_recording_metadata(&dir, &dir.join("run.gpurec")).unwrap();
+        assert_eq!((columnar.sample_count, columnar.last_ms), (3, Some(1_200)));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rename_and_delete_move_analysis_files() {
+        let dir = library("rename");
+        let renamed = rename_recording(&dir, &dir.join("run.json"), "baseline").unwrap();
+        assert_eq!(renamed.name, "baseline");
+        assert!(dir.join("baseline.analysis.html").exists());
+        assert!(!dir.join("run.analysis.json").exists());
+
+        // A stray summary under the new name blocks the rename before anything moves
+        std::fs::write(dir.join("final.analysis.json"), "{}").unwrap();
+        assert!(rename_recording(&dir, &dir.join("baseline.json"), "final").is_err());
+        assert!(dir.join("baseline.json").exists() && dir.join("baseline.analysis.json").exists());
+
+        delete_recording(&dir, &dir.join("baseline.json")).unwrap();
+        assert!(!dir.join("baseline.json").exists());
+        assert!(!dir.join("baseline.analysis.json").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rejects_paths_and_names_outside_library() {
+        let dir = library("confine");
+        let outside = dir.parent().unwrap().join(format!("nsightful_outside_{}.json", std::process::id()));
+        std::fs::write(&outside, "{}").unwrap();
+
+        for path in [outside.clone(), dir.join("run.analysis.json"), dir.join("rec_1.journal")] {
+            let err = delete_recording(&dir, &path).unwrap_err();
+            assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+        }
+        for name in ["", "../escape", ".hidden", "run.analysis"] {
+            assert!(rename_recording(&dir, &dir.join("run.json"), name).is_err(), "{}", name);
+        }
+        assert!(outside.exists());
+        std::fs::remove_file(&outside).ok();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -6,201 +6,1619 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use tauri::{command, State, Window};
+use tauri::{command, AppHandle, Manager, RunEvent, State, Window};
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use tokio::sync::{Mutex, broadcast};
-use serde_json::json;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
+mod alerts;
+mod allocations;
+mod analysis;
+mod automation;
+mod benchmark;
+mod burst;
+mod capabilities;
+mod columnar;
+mod comparison;
+mod containers;
+mod displays;
+mod enumeration;
+#[cfg(feature = "cuda")]
+mod cuda;
+mod error;
+mod export;
+mod gaps;
+mod health;
+mod heatmap;
+mod histogram;
+mod idle;
+mod journal;
+mod library;
+mod markers;
+mod measurement;
+mod modes;
+mod ncu;
+mod nsys;
 mod nvml;
+mod overhead;
+mod otlp;
+mod processes;
+mod profiler;
+mod providers;
+mod rankings;
+mod recommendations;
+mod residency;
+mod sample_buffers;
+mod sampler;
+mod schema;
+mod sessions;
+mod stress;
+mod stream_stats;
+mod subscription;
+mod triggers;
+mod tuning;
+mod validation;
+mod violations;
+mod virtualization;
+mod watch;
+mod watchdog;
+
+use error::AppError;
+use subscription::{TelemetryBatch, TelemetrySubscriptions};
 
 /// Global application state for telemetry streaming
 /// 
-/// Manages the lifecycle and communication channels for real-time
-/// GPU telemetry data streaming to the frontend.
-#[derive(Default)]
-pub struct TelemetryState {
-    pub is_streaming: Arc<Mutex<bool>>,
-    pub sender: Arc<Mutex<Option<broadcast::Sender<nvml::TelemetryFrame>>>>,
+/// Manages the lifecycle and communication channels for real-time
+/// GPU telemetry data streaming to the frontend.
+#[derive(Default)]
+pub struct TelemetryState {
+    pub stream: Arc<Mutex<Option<StreamHandle>>>,
+    pub sender: Arc<Mutex<Option<broadcast::Sender<nvml::TelemetryFrame>>>>,
+    pub subscriptions: Arc<TelemetrySubscriptions>,
+    pub devices: Arc<Mutex<Vec<nvml::StaticDeviceInfo>>>,
+    pub history: nvml::StreamHistory,
+    pub stats: Arc<stream_stats::StreamStats>,
+    pub burst: Arc<burst::BurstControl>,
+}
+
+/// Handle to the running background streaming task
+/// 
+/// Cancelling the token asks the stream loop to exit at its next
+/// await point; the join handle lets callers wait for it to finish.
+pub struct StreamHandle {
+    pub cancel: CancellationToken,
+    pub task: JoinHandle<()>,
+}
+
+impl StreamHandle {
+    /// Whether the streaming task is still running
+    pub fn is_active(&self) -> bool {
+        !self.task.is_finished()
+    }
+}
+
+impl TelemetryState {
+    /// Snapshot the current streaming state
+    pub async fn status(&self) -> StreamStatus {
+        let streaming = self.stream.lock().await.as_ref().is_some_and(StreamHandle::is_active);
+        let consumers = self.sender.lock().await.as_ref().map_or(0, broadcast::Sender::receiver_count);
+        StreamStatus {
+            streaming,
+            devices: if streaming { self.devices.lock().await.clone() } else { Vec::new() },
+            consumers,
+            counters: self.stats.snapshot(),
+        }
+    }
+
+    /// Cancel the streaming task and wait for it to terminate
+    /// 
+    /// Returns `true` if a stream was running.
+    pub async fn stop_stream(&self) -> bool {
+        let handle = self.stream.lock().await.take();
+        *self.sender.lock().await = None;
+        self.subscriptions.clear().await;
+        
+        let Some(handle) = handle else {
+            return false;
+        };
+        handle.cancel.cancel();
+        if let Err(e) = handle.task.await {
+            eprintln!("NVML streaming task ended abnormally: {}", e);
+        }
+        true
+    }
+}
+
+/// Response payload for `get_gpu_telemetry`
+/// 
+/// Device list plus the current telemetry frame of the selected device.
+#[derive(Serialize, Clone, Debug)]
+pub struct GpuInfoResponse {
+    pub status: String,
+    pub gpus: Vec<nvml::GPUDevice>,
+    pub telemetry: Option<nvml::TelemetryFrame>,
+}
+
+impl From<nvml::GPUInfo> for GpuInfoResponse {
+    fn from(info: nvml::GPUInfo) -> Self {
+        GpuInfoResponse {
+            status: "connected".to_string(),
+            gpus: info.devices,
+            telemetry: info.current_telemetry,
+        }
+    }
+}
+
+/// Response payload for `get_stream_status`
+#[derive(Serialize, Clone, Debug)]
+pub struct StreamStatus {
+    pub streaming: bool,
+    /// Devices being streamed, kept current as devices are added or removed
+    pub devices: Vec<nvml::StaticDeviceInfo>,
+    /// Receivers on the telemetry channel: the stall watchdog plus each subscription
+    pub consumers: usize,
+    #[serde(flatten)]
+    pub counters: stream_stats::StreamCounters,
+}
+
+/// Tauri command to retrieve GPU information and initial telemetry
+/// 
+/// Provides comprehensive GPU device information along with current
+/// performance metrics for frontend initialization.
+/// 
+/// # Arguments
+/// * `device_index` - Device to sample current telemetry from (defaults to 0)
+/// 
+/// # Returns
+/// * `Result<GpuInfoResponse, AppError>` - GPU data or error message
+#[command]
+async fn get_gpu_telemetry(device_index: Option<u32>) -> Result<GpuInfoResponse, AppError> {
+    let gpu_info = nvml::get_gpu_info(device_index).await
+        .context("Failed to get GPU telemetry")?;
+    Ok(gpu_info.into())
+}
+
+/// Tauri command to start real-time NVML streaming
+/// 
+/// Initiates background telemetry collection and streaming to the frontend.
+/// Creates broadcast channels for data distribution and manages streaming lifecycle.
+/// If a device sends no frames for several of its periods, a `stream-stalled`
+/// event is emitted and that device's task is restarted; if no device does,
+/// the whole stream is.
+/// 
+/// # Arguments
+/// * `period_ms` - Update interval in milliseconds
+/// * `device_periods_ms` - Interval overrides keyed by device index
+/// * `derived` - Include frame-to-frame deltas in each frame (default false)
+/// * `idle` - Slow down, and optionally stop emitting, for devices that stay idle;
+///   transitions are emitted as `stream-suspended` and `stream-resumed`
+/// * `metrics` - Metrics to read, e.g. `["utilization", "temperature"]` (default all);
+///   the NVML calls for the rest are skipped and their fields stay zero. Utilization
+///   is always read with an idle policy, which decides idleness from it
+/// * `state` - Application telemetry state
+/// * `window` - Tauri window handle for events
+/// 
+/// # Returns
+/// * `Result<String, AppError>` - Success message or error
+#[command]
+async fn start_nvml_stream(
+    period_ms: u64,
+    device_periods_ms: Option<HashMap<u32, u64>>,
+    derived: Option<bool>,
+    idle: Option<idle::IdlePolicy>,
+    metrics: Option<Vec<sampler::SampledMetric>>,
+    state: State<'_, TelemetryState>,
+    window: Window,
+) -> Result<String, AppError> {
+    if let Some(policy) = &idle {
+        policy.validate()?;
+    }
+    if metrics.as_ref().is_some_and(Vec::is_empty) {
+        return Err(AppError::InvalidArgument("At least one metric must be streamed".to_string()));
+    }
+    let mut stream = state.stream.lock().await;
+    
+    if stream.as_ref().is_some_and(StreamHandle::is_active) {
+        return Ok("Stream already active".to_string());
+    }
+
+    // Create broadcast channel for telemetry data
+    let (tx, _rx) = broadcast::channel(1000);
+    {
+        let mut sender_guard = state.sender.lock().await;
+        *sender_guard = Some(tx.clone());
+    }
+
+    // Clone necessary data for the background task
+    let cancel = CancellationToken::new();
+    let frames = tx.subscribe();
+    let devices = state.devices.clone();
+    let history = state.history.clone();
+    let stats = state.stats.clone();
+    stats.reset();
+    let metrics = metrics.map_or_else(sampler::MetricSet::all, |metrics| sampler::MetricSet::of(&metrics));
+    // Without utilization every device would look permanently idle
+    let metrics = if idle.is_some() { metrics.with(sampler::SampledMetric::Utilization) } else { metrics };
+    let config = nvml::StreamConfig {
+        period_ms,
+        device_periods_ms: device_periods_ms.unwrap_or_default(),
+        derived: derived.unwrap_or(false),
+        idle,
+        metrics,
+        burst: state.burst.clone(),
+    };
+    let stall_timeout = watchdog::stall_timeout(config.longest_period_ms());
+    let window_clone = window.clone();
+    let stream_stats = stats.clone();
+    let spawn_stream = move |stream_cancel: CancellationToken, restarts| {
+        let (config, tx, devices, history, window) = (config.clone(), tx.clone(), devices.clone(), history.clone(), window_clone.clone());
+        let stats = stream_stats.clone();
+        tokio::spawn(async move {
+            if let Err(e) = nvml::nvml_stream_with_broadcast(config, tx, devices, history, stats.clone(), nvml::StreamControl { cancel: stream_cancel, restarts }, window).await {
+                let err = AppError::from(e);
+                eprintln!("NVML streaming error [{}]: {}", err.code(), err);
+                stats.record_error(format!("[{}] {}", err.code(), err));
+            }
+        })
+    };
+    let device_timeouts = {
+        let stats = stats.clone();
+        move || stats.periods_ms().into_iter()
+            .map(|(device_index, period_ms)| (device_index, watchdog::stall_timeout(period_ms)))
+            .collect()
+    };
+    let lag_stats = stats.clone();
+    let on_stall = move |incident: &watchdog::StallIncident| {
+        if incident.restarting {
+            stats.record_restart();
+        }
+        stats.record_error(format!("Stream stalled: {}", incident.reason));
+        if let Err(e) = window.emit("stream-stalled", incident) {
+            eprintln!("Failed to emit stream stalled event: {}", e);
+        }
+    };
+
+    // Start the streaming task under a watchdog that restarts it if it stalls
+    let task = tokio::spawn(watchdog::supervise(
+        spawn_stream,
+        frames,
+        stall_timeout,
+        device_timeouts,
+        cancel.clone(),
+        on_stall,
+        move |missed| lag_stats.record_dropped(missed),
+    ));
+    *stream = Some(StreamHandle { cancel, task });
+
+    Ok("Stream started".to_string())
+}
+
+/// Tauri command to stop NVML streaming
+/// 
+/// Gracefully shuts down telemetry streaming and cleans up resources.
+/// Resolves only once the background task has actually terminated.
+/// 
+/// # Arguments
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<String, AppError>` - Success message or error
+#[command]
+async fn stop_nvml_stream(state: State<'_, TelemetryState>) -> Result<String, AppError> {
+    if state.stop_stream().await {
+        Ok("Stream stopped".to_string())
+    } else {
+        Ok("Stream not active".to_string())
+    }
+}
+
+/// Tauri command to get current streaming status
+/// 
+/// Returns the current state of telemetry streaming for frontend status updates:
+/// streamed devices, their effective intervals, frame and drop counts,
+/// watchdog restarts, the last error, uptime and the number of consumers.
+/// 
+/// # Arguments
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<StreamStatus, AppError>` - Streaming status or error
+#[command]
+async fn get_stream_status(state: State<'_, TelemetryState>) -> Result<StreamStatus, AppError> {
+    Ok(state.status().await)
+}
+
+/// Tauri command to sample the running stream faster for a short time
+/// 
+/// Every streamed device is sampled at `period_ms`, which may be below the
+/// normal minimum, until `duration_ms` has passed; the stream then falls
+/// back to its normal periods and emits `burst-finished` with the achieved
+/// rate per device.
+/// 
+/// # Arguments
+/// * `period_ms` - Sampling period during the burst (at least 10 ms)
+/// * `duration_ms` - How long the burst lasts (at most 30 s)
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<String, AppError>` - Success message or error if no stream is running
+#[command]
+async fn start_burst_capture(period_ms: u64, duration_ms: u64, state: State<'_, TelemetryState>) -> Result<String, AppError> {
+    if !state.stream.lock().await.as_ref().is_some_and(StreamHandle::is_active) {
+        return Err(AppError::InvalidArgument("Start a stream before a burst capture".to_string()));
+    }
+    state.burst.start(period_ms, duration_ms)?;
+    Ok(format!("Sampling every {} ms for {} ms", period_ms, duration_ms))
+}
+
+/// Tauri command to get the state and measured rates of the current or last burst
+/// 
+/// A device is reported as NVML-limited when its NVML calls alone take
+/// longer than the requested period.
+/// 
+/// # Arguments
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<BurstStatus, AppError>` - Burst state and achieved rate per device
+#[command]
+async fn get_burst_status(state: State<'_, TelemetryState>) -> Result<burst::BurstStatus, AppError> {
+    Ok(state.burst.status())
+}
+
+/// Tauri command to get what monitoring costs the visualizer itself
+/// 
+/// Reports the process's CPU usage, the time each NVML metric group takes
+/// to read and each frontend event takes to emit, so users can check that
+/// monitoring does not perturb the workload being profiled.
+/// 
+/// # Arguments
+/// * `reset` - Start measuring afresh after this snapshot (default false)
+/// 
+/// # Returns
+/// * `Result<OverheadStats, AppError>` - CPU usage and per-operation costs
+#[command]
+async fn get_overhead_stats(reset: Option<bool>) -> Result<overhead::OverheadStats, AppError> {
+    Ok(overhead::get_overhead_stats(reset.unwrap_or(false)))
+}
+
+/// Tauri command to get static info for the streamed devices
+/// 
+/// Telemetry frames only carry a device index; this returns the name, UUID,
+/// memory size and other fixed properties gathered when the stream started.
+/// 
+/// # Arguments
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<Vec<StaticDeviceInfo>, AppError>` - Device info (empty before the first stream)
+#[command]
+async fn get_stream_devices(state: State<'_, TelemetryState>) -> Result<Vec<nvml::StaticDeviceInfo>, AppError> {
+    Ok(state.devices.lock().await.clone())
+}
+
+/// Tauri command to query streaming capabilities
+/// 
+/// Reports the enforced minimum streaming period and, per device, which
+/// metrics and control features are supported.
+/// 
+/// # Returns
+/// * `Result<StreamCapabilities, AppError>` - Capability report or error
+#[command]
+async fn get_stream_capabilities() -> Result<nvml::StreamCapabilities, AppError> {
+    let capabilities = nvml::get_stream_capabilities().await
+        .context("Failed to query stream capabilities")?;
+    Ok(capabilities)
+}
+
+/// Tauri command to subscribe to the telemetry broadcast channel
+/// 
+/// Registers a pull-based subscription so a frontend component can fetch
+/// frames on demand via `poll_telemetry` instead of listening to global events.
+/// 
+/// # Arguments
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<u64, AppError>` - Subscription ID, or `NotRecording` if no stream is active
+#[command]
+async fn subscribe_telemetry(state: State<'_, TelemetryState>) -> Result<u64, AppError> {
+    let sender = state.sender.lock().await.clone().ok_or_else(|| {
+        AppError::NotRecording("Telemetry stream is not active".to_string())
+    })?;
+    Ok(state.subscriptions.subscribe(&sender).await)
+}
+
+/// Tauri command to pull buffered frames from a telemetry subscription
+/// 
+/// # Arguments
+/// * `subscription_id` - ID returned by `subscribe_telemetry`
+/// * `max_frames` - Maximum frames to return (defaults to 256)
+/// * `wait_ms` - Wait up to this long for a frame if none are buffered
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<TelemetryBatch, AppError>` - Frames plus dropped/closed flags, or error
+#[command]
+async fn poll_telemetry(
+    subscription_id: u64,
+    max_frames: Option<usize>,
+    wait_ms: Option<u64>,
+    state: State<'_, TelemetryState>,
+) -> Result<TelemetryBatch, AppError> {
+    let batch = state.subscriptions.poll(
+        subscription_id,
+        max_frames.unwrap_or(subscription::DEFAULT_MAX_FRAMES),
+        wait_ms.map(Duration::from_millis),
+    ).await?;
+    state.stats.record_dropped(batch.dropped);
+    Ok(batch)
+}
+
+/// Tauri command to remove a telemetry subscription
+/// 
+/// # Arguments
+/// * `subscription_id` - ID returned by `subscribe_telemetry`
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<bool, AppError>` - Whether the subscription existed
+#[command]
+async fn unsubscribe_telemetry(subscription_id: u64, state: State<'_, TelemetryState>) -> Result<bool, AppError> {
+    Ok(state.subscriptions.unsubscribe(subscription_id).await)
+}
+
+/// Tauri command to get P-state and SM clock residency of a streamed device
+/// 
+/// Built from the frames of the current or most recent stream, which are
+/// kept for up to 15 minutes.
+/// 
+/// # Arguments
+/// * `device_index` - Device to report on
+/// * `window_seconds` - How far back to look (defaults to 60 seconds)
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<Residency, AppError>` - Time per P-state and clock bin, or error if the device has not been streamed
+#[command]
+async fn get_pstate_residency(
+    device_index: u32,
+    window_seconds: Option<u64>,
+    state: State<'_, TelemetryState>,
+) -> Result<residency::Residency, AppError> {
+    let window_ms = window_seconds.unwrap_or(residency::DEFAULT_WINDOW_SECONDS).saturating_mul(1000);
+    state.history.residency.lock().await
+        .residency(device_index, window_ms)
+        .ok_or_else(|| AppError::InvalidArgument(format!("No streamed samples for GPU {}", device_index)))
+}
+
+/// Tauri command to get a time × SM utilization heatmap of a streamed device
+/// 
+/// Built from the per-SM values of the current or most recent stream, which
+/// are kept for up to 5 minutes and averaged into at most `max_rows` time
+/// bins. NVML cannot measure per-SM utilization, so values estimated from
+/// overall utilization are only used when `include_estimated` is set.
+/// 
+/// # Arguments
+/// * `device_index` - Device to report on
+/// * `window_seconds` - How far back to look (defaults to 60 seconds)
+/// * `max_rows` - Number of time bins (defaults to 120)
+/// * `include_estimated` - Also use estimated per-SM values
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<SmHeatmap, AppError>` - Heatmap, or error if the device has no usable per-SM samples
+#[command]
+async fn get_sm_heatmap(
+    device_index: u32,
+    window_seconds: Option<u64>,
+    max_rows: Option<usize>,
+    include_estimated: Option<bool>,
+    state: State<'_, TelemetryState>,
+) -> Result<heatmap::SmHeatmap, AppError> {
+    let window_ms = window_seconds.unwrap_or(heatmap::DEFAULT_WINDOW_SECONDS).saturating_mul(1000);
+    let max_rows = max_rows.unwrap_or(heatmap::DEFAULT_MAX_ROWS);
+    if max_rows == 0 {
+        return Err(AppError::InvalidArgument("Heatmap must have at least one row".to_string()));
+    }
+    state.history.heatmap.lock().await
+        .heatmap(device_index, window_ms, max_rows, include_estimated.unwrap_or(false))
+        .ok_or_else(|| AppError::InvalidArgument(format!("No per-SM samples for GPU {}", device_index)))
+}
+
+/// Tauri command to rank streamed devices by a metric
+/// 
+/// Orders devices by the mean of the metric over the most recent window of
+/// the current or most recent stream, for summary widgets such as hottest
+/// GPU or most VRAM used.
+/// 
+/// # Arguments
+/// * `metric` - Metric to rank by, e.g. "temperature_c", "power_w" or "memory_used_mb"
+/// * `window_seconds` - How far back to look (defaults to 30 seconds)
+/// * `limit` - Return only the top `limit` devices
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<Vec<DeviceRanking>, AppError>` - Devices, highest mean first; empty if nothing has been streamed
+#[command]
+async fn get_device_rankings(
+    metric: triggers::TriggerMetric,
+    window_seconds: Option<u64>,
+    limit: Option<usize>,
+    state: State<'_, TelemetryState>,
+) -> Result<Vec<rankings::DeviceRanking>, AppError> {
+    let window_ms = window_seconds.unwrap_or(rankings::DEFAULT_WINDOW_SECONDS).saturating_mul(1000);
+    Ok(state.history.rankings.lock().await.rankings(metric, window_ms, limit))
+}
+
+/// Tauri command to get the distribution of a metric over the stream history
+/// 
+/// Built from the current or most recent stream, whose values are kept for
+/// up to 5 minutes.
+/// 
+/// # Arguments
+/// * `device_index` - Device to report on
+/// * `metric` - Recording column name, e.g. "temperature_c" or "sm_clock_mhz"
+/// * `window_seconds` - How far back to look (defaults to 60 seconds)
+/// * `bins` - Number of bins (defaults to 20)
+/// * `min` - Lower edge of the first bin (defaults to the smallest value)
+/// * `max` - Upper edge of the last bin (defaults to the largest value)
+/// * `state` - Application telemetry state
+/// 
+/// # Returns
+/// * `Result<Histogram, AppError>` - Histogram or error for an unknown metric or unstreamed device
+#[command]
+async fn get_metric_histogram(
+    device_index: u32,
+    metric: String,
+    window_seconds: Option<u64>,
+    bins: Option<usize>,
+    min: Option<f64>,
+    max: Option<f64>,
+    state: State<'_, TelemetryState>,
+) -> Result<histogram::Histogram, AppError> {
+    let window_ms = window_seconds.unwrap_or(histogram::DEFAULT_WINDOW_SECONDS).saturating_mul(1000);
+    let values = state.history.rankings.lock().await.values(device_index, &metric, window_ms)?;
+    let spec = histogram::HistogramSpec { bins: bins.unwrap_or(histogram::DEFAULT_BINS), min, max };
+    Ok(histogram::histogram(&metric, &values, spec)?)
+}
+
+/// Tauri command to list the metric providers compiled into this build
+/// 
+/// # Returns
+/// * `Result<Vec<ProviderInfo>, AppError>` - Providers with availability, metrics and whether they are enabled
+#[command]
+async fn list_metric_providers() -> Result<Vec<providers::ProviderInfo>, AppError> {
+    Ok(nvml::blocking(|| Ok(providers::list_providers())).await?)
+}
+
+/// Tauri command to choose the metric providers merged into frames
+/// 
+/// Applies to streams, recordings and triggers started afterwards; restart
+/// a running stream to pick up the change.
+/// 
+/// # Arguments
+/// * `names` - Provider names to enable; empty disables all
+/// 
+/// # Returns
+/// * `Result<Vec<ProviderInfo>, AppError>` - Updated provider list or error for an unknown name
+#[command]
+async fn set_metric_providers(names: Vec<String>) -> Result<Vec<providers::ProviderInfo>, AppError> {
+    Ok(nvml::blocking(move || providers::set_enabled_providers(names)).await?)
+}
+
+/// Tauri command to list processes using the GPUs
+/// 
+/// Each process is attributed to the script or program it runs and grouped
+/// with the other GPU processes of its process tree and, on Linux, its container.
+/// 
+/// # Arguments
+/// * `device_index` - Device to list (defaults to all devices)
+/// 
+/// # Returns
+/// * `Result<GpuProcessReport, AppError>` - Processes and process trees or error
+#[command]
+async fn get_gpu_processes(device_index: Option<u32>) -> Result<processes::GpuProcessReport, AppError> {
+    let report = processes::get_gpu_processes(device_index).await
+        .context("Failed to list GPU processes")?;
+    Ok(report)
+}
+
+/// Tauri command to start listening for NVTX ranges
+///
+/// The NVTX injection library (`nvtx-injection/`), loaded into the profiled
+/// application through `NVTX_INJECTION64_PATH`, sends range events to this
+/// port (a non-default port is passed to it in `NSIGHTFUL_MARKER_PORT`).
+/// Completed ranges are emitted as `marker-range` events and saved with
+/// overlapping recordings.
+///
+/// # Arguments
+/// * `port` - Local UDP port to listen on (defaults to 47021)
+/// * `window` - Tauri window handle for events
+///
+/// # Returns
+/// * `Result<u16, AppError>` - Port the listener is bound to or error
+#[command]
+async fn start_marker_listener(port: Option<u16>, window: Window) -> Result<u16, AppError> {
+    let port = markers::start_marker_listener(port, window)
+        .context("Failed to start marker listener")?;
+    Ok(port)
+}
+
+/// Tauri command to stop listening for NVTX ranges
+///
+/// # Returns
+/// * `Result<bool, AppError>` - Whether a listener was running, or error
+#[command]
+async fn stop_marker_listener() -> Result<bool, AppError> {
+    Ok(nvml::blocking(|| Ok(markers::stop_marker_listener())).await?)
+}
+
+/// Tauri command to get received NVTX ranges overlapping a time window
+///
+/// # Arguments
+/// * `start_ms` - Window start in milliseconds since Unix epoch (defaults to the oldest kept range)
+/// * `end_ms` - Window end in milliseconds since Unix epoch (defaults to now)
+///
+/// # Returns
+/// * `Result<Vec<MarkerRange>, AppError>` - Completed ranges or error
+#[command]
+async fn get_marker_ranges(start_ms: Option<u64>, end_ms: Option<u64>) -> Result<Vec<markers::MarkerRange>, AppError> {
+    let start_ms = start_ms.map_or(0, u128::from);
+    let end_ms = end_ms.map_or_else(nvml::now_ms, u128::from);
+    Ok(markers::ranges_between(start_ms, end_ms))
+}
+
+/// Tauri command to get detailed GPU architecture information
+/// 
+/// Provides comprehensive hardware architecture details including
+/// core counts, memory specifications, and performance characteristics.
+/// 
+/// # Arguments
+/// * `device_index` - Device to describe (defaults to 0)
+/// 
+/// # Returns
+/// * `Result<GPUArchitecture, AppError>` - Architecture data or error message
+#[command]
+async fn get_gpu_architecture(device_index: Option<u32>) -> Result<nvml::GPUArchitecture, AppError> {
+    let arch_info = nvml::get_detailed_gpu_info(device_index).await
+        .context("Failed to get GPU architecture")?;
+    Ok(arch_info)
+}
+
+/// Tauri command to get driver versions and virtualization environment
+/// 
+/// Reports whether the app runs on bare metal, with GPU passthrough, on a
+/// vGPU or under WSL, so the UI can explain metrics that are unavailable.
+/// 
+/// # Returns
+/// * `Result<SystemInfo, AppError>` - System information or error
+#[command]
+async fn get_system_info() -> Result<nvml::SystemInfo, AppError> {
+    let info = nvml::get_system_info().await
+        .context("Failed to get system info")?;
+    Ok(info)
+}
+
+/// Tauri command to run GPU health diagnostics
+/// 
+/// Checks NVML reachability, temperature headroom, pending ECC page
+/// retirements, PCIe link width and throttling on a single device.
+/// 
+/// # Arguments
+/// * `device_index` - Device to check (defaults to 0)
+/// 
+/// # Returns
+/// * `Result<HealthReport, AppError>` - Pass/warn/fail report or error
+#[command]
+async fn run_health_check(device_index: Option<u32>) -> Result<health::HealthReport, AppError> {
+    let report = health::run_health_check(device_index).await
+        .context("Failed to run health check")?;
+    Ok(report)
+}
+
+/// Tauri command to start pushing health alerts to webhooks
+/// 
+/// Re-runs the health check on the watched devices every interval and
+/// sends an alert when a check reaches the configured severity, changes
+/// severity, or recovers. Alerts are also emitted as `gpu-alert` events.
+/// 
+/// # Arguments
+/// * `config` - Webhooks, devices, interval and alert severity
+/// * `window` - Tauri window handle for alert events
+/// 
+/// # Returns
+/// * `Result<AlertStatus, AppError>` - Status of the started monitor or error
+#[command]
+async fn start_alert_monitor(config: alerts::AlertConfig, window: Window) -> Result<alerts::AlertStatus, AppError> {
+    Ok(alerts::start_alert_monitor(config, window).await?)
+}
+
+/// Tauri command to stop the alert monitor
+#[command]
+async fn stop_alert_monitor() -> Result<alerts::AlertStatus, AppError> {
+    Ok(alerts::stop_alert_monitor().await?)
+}
+
+/// Tauri command to get the status of the alert monitor
+/// 
+/// # Returns
+/// * `Result<Option<AlertStatus>, AppError>` - Status with recent deliveries, or `None` if never started
+#[command]
+async fn get_alert_status() -> Result<Option<alerts::AlertStatus>, AppError> {
+    Ok(alerts::get_alert_status())
+}
+
+/// Tauri command to start exporting device metrics to an OpenTelemetry collector
+/// 
+/// Samples the selected devices every interval and POSTs the readings as
+/// OTLP/HTTP JSON gauges to `<endpoint>/v1/metrics`, one resource per
+/// device carrying the host name and GPU UUID.
+/// 
+/// # Arguments
+/// * `config` - Endpoint, headers, resource attributes, devices and interval
+/// 
+/// # Returns
+/// * `Result<OtlpStatus, AppError>` - Status of the started exporter or error
+#[command]
+async fn start_otlp_export(config: otlp::OtlpConfig) -> Result<otlp::OtlpStatus, AppError> {
+    Ok(otlp::start_export(config).await?)
+}
+
+/// Tauri command to stop the OpenTelemetry export
+#[command]
+async fn stop_otlp_export() -> Result<otlp::OtlpStatus, AppError> {
+    Ok(otlp::stop_export().await?)
+}
+
+/// Tauri command to get the status of the OpenTelemetry export
+/// 
+/// # Returns
+/// * `Result<Option<OtlpStatus>, AppError>` - Export counters and the last error, or `None` if never started
+#[command]
+async fn get_otlp_status() -> Result<Option<otlp::OtlpStatus>, AppError> {
+    Ok(otlp::get_export_status())
+}
+
+/// Tauri command to run a Rhai script as automation hooks
+/// 
+/// The script's `on_frame(frame)` sees frames of the running telemetry
+/// stream and its `on_alert(alert)` sees `gpu-alert` events; both may only
+/// start recordings, add markers, set power limits and send notifications.
+/// 
+/// # Arguments
+/// * `config` - Script to run
+/// * `state` - Application telemetry state
+/// * `window` - Tauri window handle for recordings, markers and notifications
+/// 
+/// # Returns
+/// * `Result<AutomationStatus, AppError>` - Status of the started hooks or error for an invalid script
+#[command]
+async fn start_automation(
+    config: automation::AutomationConfig,
+    state: State<'_, TelemetryState>,
+    window: Window,
+) -> Result<automation::AutomationStatus, AppError> {
+    let frames = state.sender.lock().await.as_ref().map(|sender| sender.subscribe());
+    Ok(automation::start_automation(config, frames, state.stats.clone(), window).await?)
+}
+
+/// Tauri command to stop the automation hooks
+#[command]
+async fn stop_automation() -> Result<automation::AutomationStatus, AppError> {
+    Ok(automation::stop_automation().await?)
+}
+
+/// Tauri command to get the status of the automation hooks
+/// 
+/// # Returns
+/// * `Result<Option<AutomationStatus>, AppError>` - Counters, recent actions and the last script error, or `None` if never started
+#[command]
+async fn get_automation_status() -> Result<Option<automation::AutomationStatus>, AppError> {
+    Ok(automation::get_automation_status())
+}
+
+/// Tauri command to send a test alert to a webhook
+/// 
+/// # Arguments
+/// * `webhook` - Webhook to try
+/// 
+/// # Returns
+/// * `Result<Delivery, AppError>` - Attempts made and the last error, if delivery failed
+#[command]
+async fn send_test_alert(webhook: alerts::Webhook) -> Result<alerts::Delivery, AppError> {
+    Ok(alerts::send_test_alert(webhook).await?)
+}
+
+/// Tauri command to list the displays driven by each GPU
+/// 
+/// Explains baseline clocks and power on a GPU that looks idle but is
+/// compositing a desktop. Per-display detail is available on Linux with
+/// DRM modesetting; elsewhere only NVML's connected/active flags are set.
+/// 
+/// # Returns
+/// * `Result<Vec<GpuDisplays>, AppError>` - Displays per device or error
+#[command]
+async fn get_gpu_displays() -> Result<Vec<displays::GpuDisplays>, AppError> {
+    let displays = displays::get_gpu_displays().await
+        .context("Failed to read GPU displays")?;
+    Ok(displays)
+}
+
+/// Tauri command to read how long the clocks have been held down, per reason
+/// 
+/// Counters are cumulative since the driver loaded; recordings store how
+/// much they grew during the session.
+/// 
+/// # Arguments
+/// * `device_index` - Device to query (defaults to 0)
+/// 
+/// # Returns
+/// * `Result<ViolationStats, AppError>` - Power, thermal, sync-boost and total violation time or error
+#[command]
+async fn get_violation_stats(device_index: Option<u32>) -> Result<violations::ViolationStats, AppError> {
+    Ok(violations::get_violation_stats(device_index).await?)
+}
+
+/// Tauri command to read the driver's buffered samples of a metric
+/// 
+/// The driver samples power, utilization and clocks more often than we
+/// poll; its buffers hold the last few seconds of readings.
+/// 
+/// # Arguments
+/// * `device_index` - Device to query (defaults to 0)
+/// * `metric` - Buffered metric, e.g. "power" or "gpu_utilization"
+/// * `since_ms` - Only samples after this time (Unix milliseconds); the whole buffer if omitted
+/// 
+/// # Returns
+/// * `Result<BufferedSeries, AppError>` - Samples in time order or error
+#[command]
+async fn get_sample_history(
+    device_index: Option<u32>,
+    metric: sample_buffers::BufferedMetric,
+    since_ms: Option<u64>,
+) -> Result<sample_buffers::BufferedSeries, AppError> {
+    Ok(sample_buffers::get_sample_history(device_index, metric, since_ms).await?)
+}
+
+/// Tauri command to get which restricted NVML features each device supports
+/// 
+/// Devices are probed at startup; the results are cached until refreshed.
+/// 
+/// # Arguments
+/// * `refresh` - Probe again instead of returning the cached results
+/// 
+/// # Returns
+/// * `Result<Vec<DeviceCapabilities>, AppError>` - Capability statuses per device or error
+#[command]
+async fn get_device_capabilities(refresh: Option<bool>) -> Result<Vec<capabilities::DeviceCapabilities>, AppError> {
+    Ok(capabilities::get_device_capabilities(refresh.unwrap_or(false)).await?)
+}
+
+/// Tauri command to start tracking device memory allocations of running processes
+/// 
+/// Allocations are inferred from changes in the memory NVML reports per
+/// process; use `get_report_allocations` for exact driver events.
+/// 
+/// # Arguments
+/// * `pid` - Process to track; every GPU process if omitted
+/// * `sample_rate_hz` - Polling frequency (defaults to 10 Hz)
+/// 
+/// # Returns
+/// * `Result<AllocationTracking, AppError>` - Initial tracking state or error
+#[command]
+async fn start_allocation_tracking(pid: Option<u32>, sample_rate_hz: Option<u64>) -> Result<allocations::AllocationTracking, AppError> {
+    Ok(allocations::start_tracking(pid, sample_rate_hz.unwrap_or(allocations::DEFAULT_SAMPLE_RATE_HZ)).await?)
+}
+
+/// Tauri command to stop tracking device memory allocations
+/// 
+/// # Returns
+/// * `Result<AllocationTracking, AppError>` - Final timeline and peaks or error
+#[command]
+async fn stop_allocation_tracking() -> Result<allocations::AllocationTracking, AppError> {
+    Ok(allocations::stop_tracking().await?)
+}
+
+/// Tauri command to get the live allocation timeline
+/// 
+/// # Arguments
+/// * `start_ms` - Earliest event to include, in Unix milliseconds like recording samples
+/// * `end_ms` - Latest event to include
+/// 
+/// # Returns
+/// * `Result<Option<AllocationTracking>, AppError>` - Timeline and peaks, or None if tracking was never started
+#[command]
+async fn get_allocation_timeline(start_ms: Option<f64>, end_ms: Option<f64>) -> Result<Option<allocations::AllocationTracking>, AppError> {
+    Ok(allocations::get_tracking(start_ms, end_ms))
+}
+
+/// Tauri command to read the exact allocation timeline of an NSight Systems report
+/// 
+/// # Arguments
+/// * `file_path` - `.nsys-rep` file collected with `--cuda-memory-usage=true`
+/// * `pid` - Process to include; every process if omitted
+/// 
+/// # Returns
+/// * `Result<AllocationTimeline, AppError>` - Allocations, frees and per-device peaks or error
+#[command]
+async fn get_report_allocations(file_path: String, pid: Option<u32>) -> Result<allocations::AllocationTimeline, AppError> {
+    Ok(nvml::blocking(move || allocations::report_timeline(std::path::Path::new(&file_path), pid)).await?)
+}
+
+/// Tauri command to read persistence mode, compute mode and driver model
+/// 
+/// # Arguments
+/// * `device_index` - Device to query (defaults to 0)
+/// 
+/// # Returns
+/// * `Result<DeviceModes, AppError>` - Current modes or error
+#[command]
+async fn get_device_modes(device_index: Option<u32>) -> Result<modes::DeviceModes, AppError> {
+    let modes = modes::get_device_modes(device_index).await
+        .context("Failed to read device modes")?;
+    Ok(modes)
+}
+
+/// Tauri command to enable or disable persistence mode
+/// 
+/// Linux only; requires root. Permission failures are returned as
+/// `PERMISSION_DENIED`.
+/// 
+/// # Arguments
+/// * `device_index` - Device to change (defaults to 0)
+/// * `enabled` - New persistence mode
+/// 
+/// # Returns
+/// * `Result<DeviceModes, AppError>` - Modes after the change or error
+#[command]
+async fn set_persistence_mode(device_index: Option<u32>, enabled: bool) -> Result<modes::DeviceModes, AppError> {
+    Ok(modes::set_persistence_mode(device_index, enabled).await?)
+}
+
+/// Tauri command to change the compute mode
+/// 
+/// Requires root. Permission failures are returned as `PERMISSION_DENIED`.
+/// 
+/// # Arguments
+/// * `device_index` - Device to change (defaults to 0)
+/// * `mode` - `default`, `exclusive_process` or `prohibited`
+/// 
+/// # Returns
+/// * `Result<DeviceModes, AppError>` - Modes after the change or error
+#[command]
+async fn set_compute_mode(device_index: Option<u32>, mode: modes::ComputeModeSetting) -> Result<modes::DeviceModes, AppError> {
+    Ok(modes::set_compute_mode(device_index, mode).await?)
+}
+
+/// Tauri command to list the stored tuning profiles and auto-apply settings
+/// 
+/// # Returns
+/// * `Result<TuningConfig, AppError>` - Stored config or error for an unreadable config file
+#[command]
+async fn list_tuning_profiles() -> Result<tuning::TuningConfig, AppError> {
+    Ok(tuning::get_config().await?)
+}
+
+/// Tauri command to create a tuning profile or replace one of the same name
+/// 
+/// # Arguments
+/// * `profile` - Power limit, clock offsets and fan curve to store
+/// 
+/// # Returns
+/// * `Result<TuningConfig, AppError>` - Stored config or error for an invalid profile
+#[command]
+async fn save_tuning_profile(profile: tuning::TuningProfile) -> Result<tuning::TuningConfig, AppError> {
+    Ok(tuning::update_config(|config| tuning::upsert_profile(config, profile)).await?)
+}
+
+/// Tauri command to delete a tuning profile
+/// 
+/// Launch and process auto-apply settings using the profile are removed too.
+/// 
+/// # Arguments
+/// * `name` - Profile to delete
+/// 
+/// # Returns
+/// * `Result<TuningConfig, AppError>` - Stored config or error for an unknown profile
+#[command]
+async fn delete_tuning_profile(name: String) -> Result<tuning::TuningConfig, AppError> {
+    Ok(tuning::update_config(|config| tuning::remove_profile(config, &name)).await?)
+}
+
+/// Tauri command to apply a tuning profile now
+/// 
+/// Requires root or administrator rights; refused settings return
+/// `PERMISSION_DENIED` or `NOT_SUPPORTED`.
+/// 
+/// # Arguments
+/// * `name` - Profile to apply
+/// 
+/// # Returns
+/// * `Result<TuningStatus, AppError>` - Tuning in effect or error
+#[command]
+async fn apply_tuning_profile(name: String) -> Result<tuning::TuningStatus, AppError> {
+    Ok(tuning::apply_profile(&name).await?)
+}
+
+/// Tauri command to choose when profiles are applied automatically
+/// 
+/// # Arguments
+/// * `launch_profile` - Profile applied when the app starts, if any
+/// * `process_rules` - Profiles applied when a named process appears on a GPU
+/// 
+/// # Returns
+/// * `Result<TuningConfig, AppError>` - Stored config or error for an unknown profile
+#[command]
+async fn set_tuning_auto_apply(
+    launch_profile: Option<String>,
+    process_rules: Vec<tuning::ProcessRule>,
+) -> Result<tuning::TuningConfig, AppError> {
+    Ok(tuning::update_config(|config| tuning::set_auto_apply(config, launch_profile, process_rules)).await?)
+}
+
+/// Tauri command to get the tuning currently in effect
+/// 
+/// # Returns
+/// * `TuningStatus` - Active profile, fan curve devices and process watching
+#[command]
+fn get_tuning_status() -> tuning::TuningStatus {
+    tuning::get_tuning_status()
 }
 
-/// Tauri command to retrieve GPU information and initial telemetry
+/// Tauri command to switch the Windows driver model
 /// 
-/// Provides comprehensive GPU device information along with current
-/// performance metrics for frontend initialization.
+/// Windows only; requires administrator rights and takes effect after a
+/// reboot. Other platforms return `NOT_SUPPORTED`.
+/// 
+/// # Arguments
+/// * `device_index` - Device to change (defaults to 0)
+/// * `model` - `wddm` or `tcc`
+/// * `force` - Switch even if the driver would refuse (defaults to false)
 /// 
 /// # Returns
-/// * `Result<String, String>` - JSON response with GPU data or error message
+/// * `Result<DeviceModes, AppError>` - Modes after the change, with the new model pending, or error
 #[command]
-async fn get_gpu_telemetry() -> Result<String, String> {
-    match nvml::get_gpu_info().await {
-        Ok(gpu_info) => {
-            let response = json!({
-                "status": "connected",
-                "gpus": gpu_info.devices,
-                "telemetry": gpu_info.current_telemetry
-            });
-            Ok(response.to_string())
-        }
-        Err(e) => Err(format!("Failed to get GPU telemetry: {}", e)),
-    }
+async fn set_driver_model(
+    device_index: Option<u32>,
+    model: modes::DriverModelSetting,
+    force: Option<bool>,
+) -> Result<modes::DeviceModes, AppError> {
+    Ok(modes::set_driver_model(device_index, model, force.unwrap_or(false)).await?)
 }
 
-/// Tauri command to start real-time NVML streaming
+/// Tauri command to measure memory copy bandwidth
+///
+/// Only available in builds with the `cuda` feature; other builds
+/// return `NOT_SUPPORTED`.
+///
+/// # Arguments
+/// * `kind` - `host_to_device`, `device_to_host` or `device_to_device`
+/// * `device_index` - Device to benchmark (defaults to 0)
+/// * `transfer_size_mb` - Size of each copy (defaults to 256 MB)
+///
+/// # Returns
+/// * `Result<BenchmarkResult, AppError>` - Measured and reference bandwidth or error
+#[command]
+async fn run_benchmark(
+    kind: benchmark::BenchmarkKind,
+    device_index: Option<u32>,
+    transfer_size_mb: Option<u64>,
+) -> Result<benchmark::BenchmarkResult, AppError> {
+    Ok(benchmark::run_benchmark(kind, device_index, transfer_size_mb).await?)
+}
+
+/// Tauri command to start a stress test
+///
+/// Loads the GPU until the duration elapses, `stop_stress_test` is called,
+/// or a temperature or power limit is crossed. Emits `stress-test-finished`
+/// with the final status. Only available in builds with the `cuda` feature.
+///
+/// # Arguments
+/// * `load` - `compute`, `memory` or `combined`
+/// * `duration_seconds` - Test length (defaults to 60 s, at most 30 minutes)
+/// * `device_index` - Device to load (defaults to 0)
+/// * `max_temperature_c` - Temperature that aborts the test (defaults to 85°C)
+/// * `max_power_w` - Power draw that aborts the test (defaults to none)
+/// * `window` - Tauri window handle for events
+///
+/// # Returns
+/// * `Result<StressStatus, AppError>` - Status of the started test or error
+#[command]
+async fn start_stress_test(
+    load: stress::StressLoad,
+    duration_seconds: Option<u64>,
+    device_index: Option<u32>,
+    max_temperature_c: Option<u32>,
+    max_power_w: Option<f64>,
+    window: Window,
+) -> Result<stress::StressStatus, AppError> {
+    let limits = stress::StressLimits {
+        max_temperature_c: max_temperature_c.unwrap_or(stress::DEFAULT_MAX_TEMPERATURE_C),
+        max_power_w,
+    };
+    let status = stress::start_stress_test(load, duration_seconds, device_index, limits, window).await
+        .context("Failed to start stress test")?;
+    Ok(status)
+}
+
+/// Tauri command to stop the running stress test
+///
+/// # Returns
+/// * `Result<StressStatus, AppError>` - Status of the stopping test or error
+#[command]
+async fn stop_stress_test() -> Result<stress::StressStatus, AppError> {
+    Ok(stress::stop_stress_test().await?)
+}
+
+/// Tauri command to get the status of the current or most recent stress test
+///
+/// # Returns
+/// * `Result<Option<StressStatus>, AppError>` - Status, or `None` if no test has been run
+#[command]
+async fn get_stress_status() -> Result<Option<stress::StressStatus>, AppError> {
+    Ok(stress::get_stress_status())
+}
+
+/// Tauri command to start GPU interval recording
 /// 
-/// Initiates background telemetry collection and streaming to the frontend.
-/// Creates broadcast channels for data distribution and manages streaming lifecycle.
+/// Initiates recording of GPU performance metrics for a specified duration
+/// at the given sample rate. Data is stored for later analysis.
 /// 
 /// # Arguments
-/// * `period_ms` - Update interval in milliseconds
-/// * `state` - Application telemetry state
+/// * `duration_seconds` - Recording duration in seconds
+/// * `sample_rate_hz` - Sampling frequency in Hz
+/// * `metrics` - List of metrics to record
+/// * `device_index` - Device to record from (defaults to 0)
+/// * `device_indices` - Devices to record together, overriding `device_index`
+/// * `analyze` - Write an analysis summary when the recording finishes (defaults to false)
+/// * `window` - Tauri window handle for `recording-progress` and `recording-complete` events
+/// 
+/// # Returns
+/// * `Result<String, AppError>` - Recording session ID or error message
+#[command]
+async fn start_gpu_recording(
+    duration_seconds: u64,
+    sample_rate_hz: u64,
+    metrics: Vec<String>,
+    device_index: Option<u32>,
+    device_indices: Option<Vec<u32>>,
+    analyze: Option<bool>,
+    window: Window,
+) -> Result<String, AppError> {
+    let device_indices = device_indices.unwrap_or_else(|| vec![device_index.unwrap_or(0)]);
+    let recording_id = nvml::start_interval_recording(duration_seconds, sample_rate_hz, metrics, device_indices, analyze.unwrap_or(false), window).await
+        .context("Failed to start GPU recording")?;
+    Ok(recording_id)
+}
+
+/// Tauri command to stop GPU interval recording
+/// 
+/// Stops the current recording session and returns the path to recorded data.
+/// 
+/// # Returns
+/// * `Result<String, AppError>` - Path to recorded data file or error message
+#[command]
+async fn stop_gpu_recording() -> Result<String, AppError> {
+    let data_path = nvml::stop_interval_recording().await
+        .context("Failed to stop GPU recording")?;
+    Ok(data_path)
+}
+
+/// Tauri command to get current recording status
+/// 
+/// Returns information about any active recording session including
+/// progress, duration remaining, and metrics being collected.
+/// 
+/// # Returns
+/// * `Result<RecordingStatus, AppError>` - Recording status or error message
+#[command]
+async fn get_recording_status() -> Result<nvml::RecordingStatus, AppError> {
+    let status = nvml::get_recording_status().await
+        .context("Failed to get recording status")?;
+    Ok(status)
+}
+
+/// Tauri command to record at locked base clocks
+/// 
+/// Locks GPU and memory clocks of the recorded devices to their base
+/// frequencies and disables auto boost where permitted, runs the recording,
+/// then restores the previous settings and emits `measurement-finished`.
+/// 
+/// # Arguments
+/// * `duration_seconds` - Recording duration in seconds
+/// * `sample_rate_hz` - Sampling frequency in Hz
+/// * `metrics` - List of metrics to record
+/// * `device_indices` - Devices to lock and record (defaults to device 0)
+/// * `analyze` - Write an analysis summary when the recording finishes (defaults to false)
 /// * `window` - Tauri window handle for events
 /// 
 /// # Returns
-/// * `Result<String, String>` - Success message or error
+/// * `Result<MeasurementStatus, AppError>` - Applied clock settings or error
 #[command]
-async fn start_nvml_stream(
-    period_ms: u64,
-    state: State<'_, TelemetryState>,
+async fn start_measurement_recording(
+    duration_seconds: u64,
+    sample_rate_hz: u64,
+    metrics: Vec<String>,
+    device_indices: Option<Vec<u32>>,
+    analyze: Option<bool>,
     window: Window,
-) -> Result<String, String> {
-    let mut is_streaming = state.is_streaming.lock().await;
-    
-    if *is_streaming {
-        return Ok("Stream already active".to_string());
-    }
+) -> Result<measurement::MeasurementStatus, AppError> {
+    let status = measurement::start_measurement_recording(
+        duration_seconds, sample_rate_hz, metrics, device_indices.unwrap_or_default(), analyze.unwrap_or(false), window,
+    ).await
+        .context("Failed to start measurement")?;
+    Ok(status)
+}
 
-    *is_streaming = true;
-    drop(is_streaming);
+/// Tauri command to get the status of the current or most recent measurement
+/// 
+/// # Returns
+/// * `Result<Option<MeasurementStatus>, AppError>` - Status, or `None` if no measurement has been run
+#[command]
+async fn get_measurement_status() -> Result<Option<measurement::MeasurementStatus>, AppError> {
+    Ok(measurement::get_measurement_status())
+}
 
-    // Create broadcast channel for telemetry data
-    let (tx, _rx) = broadcast::channel(1000);
-    {
-        let mut sender_guard = state.sender.lock().await;
-        *sender_guard = Some(tx.clone());
-    }
+/// Tauri command to recover recordings interrupted by a crash
+/// 
+/// Meant to be run at startup: every session that left its journal behind
+/// is written out as a regular recording file.
+/// 
+/// # Returns
+/// * `Result<Vec<RecoveredRecording>, AppError>` - Recovered sessions or error
+#[command]
+async fn recover_recordings() -> Result<Vec<journal::RecoveredRecording>, AppError> {
+    let active = nvml::get_recording_status().await?.output_file;
+    let recovered = nvml::blocking(move || {
+        journal::recover_recordings(std::path::Path::new(nvml::RECORDING_DIR), active.as_deref().map(std::path::Path::new))
+    }).await
+        .context("Failed to recover recordings")?;
+    Ok(recovered)
+}
 
-    // Clone necessary data for the background task
-    let is_streaming_clone = state.is_streaming.clone();
-    let window_clone = window.clone();
+/// Tauri command to arm a recording trigger
+///
+/// Samples the device into a ring buffer and, once the condition has held
+/// for its sustain time, saves a recording covering the pre-trigger buffer
+/// and the post-trigger window. Emits `recording-triggered` when the
+/// condition fires and `trigger-recording-saved` when the file is written.
+///
+/// # Arguments
+/// * `condition` - Metric, comparison, threshold and sustain time
+/// * `device_index` - Device to watch (defaults to 0)
+/// * `pre_trigger_seconds` - Samples kept from before the trigger (defaults to 30 s)
+/// * `post_trigger_seconds` - Recording time after the trigger (defaults to 60 s)
+/// * `sample_rate_hz` - Sampling frequency (defaults to 10 Hz)
+/// * `window` - Tauri window handle for events
+///
+/// # Returns
+/// * `Result<TriggerStatus, AppError>` - Status of the armed trigger or error
+#[command]
+async fn arm_recording_trigger(
+    condition: triggers::TriggerCondition,
+    device_index: Option<u32>,
+    pre_trigger_seconds: Option<u64>,
+    post_trigger_seconds: Option<u64>,
+    sample_rate_hz: Option<u64>,
+    window: Window,
+) -> Result<triggers::TriggerStatus, AppError> {
+    let config = triggers::TriggerConfig {
+        device_index: device_index.unwrap_or(0),
+        condition,
+        pre_trigger_seconds: pre_trigger_seconds.unwrap_or(triggers::DEFAULT_PRE_TRIGGER_SECONDS),
+        post_trigger_seconds: post_trigger_seconds.unwrap_or(triggers::DEFAULT_POST_TRIGGER_SECONDS),
+        sample_rate_hz: sample_rate_hz.unwrap_or(triggers::DEFAULT_SAMPLE_RATE_HZ),
+    };
+    let status = triggers::arm_trigger(config, window).await
+        .context("Failed to arm recording trigger")?;
+    Ok(status)
+}
 
-    // Start background streaming task
-    tokio::spawn(async move {
-        if let Err(e) = nvml::nvml_stream_with_broadcast(period_ms, tx, is_streaming_clone, window_clone).await {
-            eprintln!("NVML streaming error: {}", e);
-        }
-    });
+/// Tauri command to disarm the recording trigger
+///
+/// A capture already in progress is cut short and saved.
+///
+/// # Returns
+/// * `Result<TriggerStatus, AppError>` - Status of the trigger or error
+#[command]
+async fn disarm_recording_trigger() -> Result<triggers::TriggerStatus, AppError> {
+    Ok(triggers::disarm_trigger().await?)
+}
 
-    Ok("Stream started".to_string())
+/// Tauri command to get the status of the current or most recent recording trigger
+///
+/// # Returns
+/// * `Result<Option<TriggerStatus>, AppError>` - Status, or `None` if no trigger has been armed
+#[command]
+async fn get_trigger_status() -> Result<Option<triggers::TriggerStatus>, AppError> {
+    Ok(triggers::get_trigger_status())
 }
 
-/// Tauri command to stop NVML streaming
+/// Tauri command to record while a process runs
+///
+/// Waits for the process to appear on a GPU, records every device it uses
+/// until it exits, and tags the saved recording with the process. Emits
+/// `process-watch-started` and `process-watch-finished`.
+///
+/// # Arguments
+/// * `name_or_pid` - PID, or process, executable or script name
+/// * `sample_rate_hz` - Sampling frequency (defaults to 10 Hz)
+/// * `window` - Tauri window handle for events
+///
+/// # Returns
+/// * `Result<WatchStatus, AppError>` - Status of the waiting watch or error
+#[command]
+async fn watch_process(name_or_pid: String, sample_rate_hz: Option<u64>, window: Window) -> Result<watch::WatchStatus, AppError> {
+    let status = watch::watch_process(&name_or_pid, sample_rate_hz.unwrap_or(watch::DEFAULT_SAMPLE_RATE_HZ), window).await
+        .context("Failed to watch process")?;
+    Ok(status)
+}
+
+/// Tauri command to stop watching for a process
+///
+/// A recording in progress is saved.
+///
+/// # Returns
+/// * `Result<WatchStatus, AppError>` - Final status or error if no watch is active
+#[command]
+async fn stop_process_watch() -> Result<watch::WatchStatus, AppError> {
+    Ok(watch::stop_watch().await?)
+}
+
+/// Tauri command to get the status of the current or most recent process watch
+///
+/// # Returns
+/// * `Result<Option<WatchStatus>, AppError>` - Status, or `None` if no process has been watched
+#[command]
+async fn get_process_watch_status() -> Result<Option<watch::WatchStatus>, AppError> {
+    Ok(watch::get_watch_status())
+}
+
+/// Tauri command to load a saved recording
 /// 
-/// Gracefully shuts down telemetry streaming and cleans up resources.
+/// Recordings made with older frame formats are migrated to the current
+/// schema before being returned.
 /// 
 /// # Arguments
-/// * `state` - Application telemetry state
+/// * `file_path` - Path to the recording JSON file
 /// 
 /// # Returns
-/// * `Result<String, String>` - Success message or error
+/// * `Result<RecordingFile, AppError>` - Recording in the current schema or error
 #[command]
-async fn stop_nvml_stream(state: State<'_, TelemetryState>) -> Result<String, String> {
-    let mut is_streaming = state.is_streaming.lock().await;
-    *is_streaming = false;
-    
-    let mut sender_guard = state.sender.lock().await;
-    *sender_guard = None;
-    
-    Ok("Stream stopped".to_string())
+async fn load_recording(file_path: String) -> Result<nvml::RecordingFile, AppError> {
+    Ok(schema::load_recording(std::path::Path::new(&file_path))?)
 }
 
-/// Tauri command to get current streaming status
+/// Tauri command to list saved recordings, newest first
 /// 
-/// Returns the current state of telemetry streaming for frontend status updates.
+/// # Returns
+/// * `Result<Vec<RecordingEntry>, AppError>` - Recordings in the recordings directory or error
+#[command]
+async fn list_recordings() -> Result<Vec<library::RecordingEntry>, AppError> {
+    let recordings = nvml::blocking(|| library::list_recordings(std::path::Path::new(nvml::RECORDING_DIR))).await
+        .context("Failed to list recordings")?;
+    Ok(recordings)
+}
+
+/// Tauri command to describe a saved recording
 /// 
 /// # Arguments
-/// * `state` - Application telemetry state
+/// * `path` - Recording in the recordings directory
 /// 
 /// # Returns
-/// * `Result<String, String>` - JSON status response or error
+/// * `Result<RecordingMetadata, AppError>` - Devices, sample count, time span and timing or error
 #[command]
-async fn get_stream_status(state: State<'_, TelemetryState>) -> Result<String, String> {
-    let is_streaming = state.is_streaming.lock().await;
-    let response = json!({
-        "streaming": *is_streaming
-    });
-    Ok(response.to_string())
+async fn get_recording_metadata(path: String) -> Result<library::RecordingMetadata, AppError> {
+    Ok(nvml::blocking(move || {
+        library::get_recording_metadata(std::path::Path::new(nvml::RECORDING_DIR), std::path::Path::new(&path))
+    }).await?)
 }
 
-/// Tauri command to get detailed GPU architecture information
+/// Tauri command to delete a saved recording and its analysis summary
 /// 
-/// Provides comprehensive hardware architecture details including
-/// core counts, memory specifications, and performance characteristics.
+/// # Arguments
+/// * `path` - Recording in the recordings directory
+#[command]
+async fn delete_recording(path: String) -> Result<(), AppError> {
+    Ok(nvml::blocking(move || {
+        library::delete_recording(std::path::Path::new(nvml::RECORDING_DIR), std::path::Path::new(&path))
+    }).await?)
+}
+
+/// Tauri command to rename a saved recording
+/// 
+/// # Arguments
+/// * `path` - Recording in the recordings directory
+/// * `name` - New file name, without extension
 /// 
 /// # Returns
-/// * `Result<String, String>` - JSON architecture data or error message
+/// * `Result<RecordingEntry, AppError>` - The renamed recording or error
 #[command]
-async fn get_gpu_architecture() -> Result<String, String> {
-    match nvml::get_detailed_gpu_info().await {
-        Ok(arch_info) => Ok(serde_json::to_string(&arch_info).unwrap()),
-        Err(e) => Err(format!("Failed to get GPU architecture: {}", e)),
-    }
+async fn rename_recording(path: String, name: String) -> Result<library::RecordingEntry, AppError> {
+    Ok(nvml::blocking(move || {
+        library::rename_recording(std::path::Path::new(nvml::RECORDING_DIR), std::path::Path::new(&path), &name)
+    }).await?)
 }
 
-/// Tauri command to start GPU interval recording
+/// Tauri command to convert a JSON recording to the columnar format
 /// 
-/// Initiates recording of GPU performance metrics for a specified duration
-/// at the given sample rate. Data is stored for later analysis.
+/// # Arguments
+/// * `file_path` - Path to the JSON recording
+/// 
+/// # Returns
+/// * `Result<String, AppError>` - Path of the `.gpurec` file or error
+#[command]
+async fn convert_recording_to_columnar(file_path: String) -> Result<String, AppError> {
+    let output = columnar::convert_json_recording(std::path::Path::new(&file_path))
+        .context("Failed to convert recording")?;
+    Ok(output.display().to_string())
+}
+
+/// Tauri command to read a time window from a columnar recording
+/// 
+/// Reads only the chunks and columns needed, so large recordings can be
+/// scrubbed without loading them in full. Sampling gaps are listed and, by
+/// default, broken by a row of `null` values so charts do not draw a line
+/// across them.
 /// 
 /// # Arguments
-/// * `duration_seconds` - Recording duration in seconds
-/// * `sample_rate_hz` - Sampling frequency in Hz
-/// * `metrics` - List of metrics to record
+/// * `file_path` - Path to the `.gpurec` file
+/// * `start_ms` - Inclusive window start (Unix milliseconds)
+/// * `end_ms` - Inclusive window end (Unix milliseconds)
+/// * `metrics` - Metrics to read (defaults to all)
+/// * `mark_gaps` - Detect and mark sampling gaps (defaults to true)
+/// * `min_gap_ms` - Shortest interval that counts as a gap (defaults to 3x the typical interval)
 /// 
 /// # Returns
-/// * `Result<String, String>` - Recording session ID or error message
+/// * `Result<RangeData, AppError>` - Timestamps and metric columns or error
 #[command]
-async fn start_gpu_recording(
-    duration_seconds: u64,
-    sample_rate_hz: u64,
-    metrics: Vec<String>,
-) -> Result<String, String> {
-    match nvml::start_interval_recording(duration_seconds, sample_rate_hz, metrics).await {
-        Ok(recording_id) => Ok(recording_id),
-        Err(e) => Err(format!("Failed to start GPU recording: {}", e))
+async fn read_recording_range(
+    file_path: String,
+    start_ms: u64,
+    end_ms: u64,
+    metrics: Option<Vec<String>>,
+    mark_gaps: Option<bool>,
+    min_gap_ms: Option<u64>,
+) -> Result<columnar::RangeData, AppError> {
+    let metrics = metrics.unwrap_or_default();
+    let mut data = columnar::read_range(std::path::Path::new(&file_path), start_ms, end_ms, &metrics)?;
+    if mark_gaps.unwrap_or(true) {
+        gaps::mark(&mut data, min_gap_ms);
     }
+    Ok(data)
 }
 
-/// Tauri command to stop GPU interval recording
+/// Tauri command to find the sampling gaps of a recording
 /// 
-/// Stops the current recording session and returns the path to recorded data.
+/// Lets replay of a JSON recording break its charts where sampling paused
+/// or the stream restarted.
+/// 
+/// # Arguments
+/// * `file_path` - JSON or `.gpurec` recording
+/// * `device_index` - Device of a multi-device recording (defaults to the primary device)
+/// * `min_gap_ms` - Shortest interval that counts as a gap (defaults to 3x the typical interval)
 /// 
 /// # Returns
-/// * `Result<String, String>` - Path to recorded data file or error message
+/// * `Result<Vec<SampleGap>, AppError>` - Gaps in time order or error
 #[command]
-async fn stop_gpu_recording() -> Result<String, String> {
-    match nvml::stop_interval_recording().await {
-        Ok(data_path) => Ok(data_path),
-        Err(e) => Err(format!("Failed to stop GPU recording: {}", e))
-    }
+async fn get_recording_gaps(
+    file_path: String,
+    device_index: Option<u32>,
+    min_gap_ms: Option<u64>,
+) -> Result<Vec<gaps::SampleGap>, AppError> {
+    let gaps = gaps::recording_gaps(std::path::Path::new(&file_path), device_index, min_gap_ms)
+        .context("Failed to find recording gaps")?;
+    Ok(gaps)
 }
 
-/// Tauri command to get current recording status
+/// Tauri command to export a time window of a recording
+///
+/// # Arguments
+/// * `path` - JSON or `.gpurec` recording
+/// * `t0` - Inclusive window start (Unix milliseconds)
+/// * `t1` - Inclusive window end (Unix milliseconds)
+/// * `format` - `json` or `csv`
+/// * `out_path` - File to write
+///
+/// # Returns
+/// * `Result<ExportSummary, AppError>` - Output file and exported sample range or error
+#[command]
+async fn export_range(
+    path: String,
+    t0: u64,
+    t1: u64,
+    format: export::ExportFormat,
+    out_path: String,
+) -> Result<export::ExportSummary, AppError> {
+    let summary = export::export_range(std::path::Path::new(&path), t0, t1, format, std::path::Path::new(&out_path))
+        .context("Failed to export recording range")?;
+    Ok(summary)
+}
+
+/// Tauri command to get the distribution of a metric over a saved recording
 /// 
-/// Returns information about any active recording session including
-/// progress, duration remaining, and metrics being collected.
+/// # Arguments
+/// * `path` - JSON or columnar recording
+/// * `metric` - Recording column name, e.g. "temperature_c" or "sm_clock_mhz"
+/// * `device_index` - Device of a multi-device recording (defaults to the primary device)
+/// * `bins` - Number of bins (defaults to 20)
+/// * `min` - Lower edge of the first bin (defaults to the smallest value)
+/// * `max` - Upper edge of the last bin (defaults to the largest value)
 /// 
 /// # Returns
-/// * `Result<String, String>` - JSON recording status or error message
+/// * `Result<Histogram, AppError>` - Histogram or error
 #[command]
-async fn get_recording_status() -> Result<String, String> {
-    match nvml::get_recording_status().await {
-        Ok(status) => {
-            let json = serde_json::to_string(&status)
-                .map_err(|e| format!("Failed to serialize recording status: {}", e))?;
-            Ok(json)
-        }
-        Err(e) => Err(format!("Failed to get recording status: {}", e))
-    }
+async fn get_recording_histogram(
+    path: String,
+    metric: String,
+    device_index: Option<u32>,
+    bins: Option<usize>,
+    min: Option<f64>,
+    max: Option<f64>,
+) -> Result<histogram::Histogram, AppError> {
+    let spec = histogram::HistogramSpec { bins: bins.unwrap_or(histogram::DEFAULT_BINS), min, max };
+    let histogram = histogram::recording_histogram(std::path::Path::new(&path), &metric, device_index, spec)
+        .context("Failed to compute recording histogram")?;
+    Ok(histogram)
+}
+
+/// Tauri command to align two saved recordings on their shared markers
+/// 
+/// The k-th marker of a label in one recording is paired with the k-th of
+/// that label in the other; the second recording is stretched between
+/// these anchors onto the first one's time base, then both are resampled
+/// onto one grid for side-by-side or overlay charts.
+/// 
+/// # Arguments
+/// * `path_a` - Reference recording (JSON)
+/// * `path_b` - Recording aligned onto the reference (JSON)
+/// * `labels` - Marker labels to align on (defaults to every shared label)
+/// * `metrics` - Recording column names (defaults to every metric)
+/// * `device_a` - Device of recording A (defaults to its primary device)
+/// * `device_b` - Device of recording B (defaults to its primary device)
+/// * `step_ms` - Grid spacing (defaults to about 1000 points)
+/// 
+/// # Returns
+/// * `Result<RecordingComparison, AppError>` - Anchors, grid offsets and paired series or error
+#[command]
+async fn compare_recordings(
+    path_a: String,
+    path_b: String,
+    labels: Option<Vec<String>>,
+    metrics: Option<Vec<String>>,
+    device_a: Option<u32>,
+    device_b: Option<u32>,
+    step_ms: Option<u64>,
+) -> Result<comparison::RecordingComparison, AppError> {
+    let options = comparison::ComparisonOptions { labels, metrics: metrics.unwrap_or_default(), device_a, device_b, step_ms };
+    let comparison = comparison::compare_recordings(std::path::Path::new(&path_a), std::path::Path::new(&path_b), &options)
+        .context("Failed to compare recordings")?;
+    Ok(comparison)
+}
+
+/// Tauri command to analyze a saved recording
+/// 
+/// Summarizes utilization distribution, thermal behavior, throttle time,
+/// sustained clocks and idle gaps, and writes the summary as
+/// `<recording>.analysis.json` and `<recording>.analysis.html`.
+/// 
+/// # Arguments
+/// * `file_path` - Path to the recording JSON file
+/// 
+/// # Returns
+/// * `Result<RecordingAnalysis, AppError>` - Analysis summary or error
+#[command]
+async fn analyze_recording(file_path: String) -> Result<analysis::RecordingAnalysis, AppError> {
+    let analysis = analysis::analyze_recording(std::path::Path::new(&file_path))
+        .context("Failed to analyze recording")?;
+    Ok(analysis)
 }
 
 /// Tauri command to process NSight report files
@@ -212,76 +1630,313 @@ async fn get_recording_status() -> Result<String, String> {
 /// * `file_path` - Path to the NSight report file
 /// 
 /// # Returns
-/// * `Result<String, String>` - JSON analysis results or error message
+/// * `Result<NSightAnalysis, AppError>` - Analysis results or error message
+#[command]
+async fn process_nsight_report(file_path: String) -> Result<nvml::NSightAnalysis, AppError> {
+    let analysis = nvml::process_nsight_report(file_path).await
+        .context("Failed to process NSight report")?;
+    Ok(analysis)
+}
+
+/// Tauri command to profile an application under `ncu` or `nsys`
+///
+/// When the profiler exits, its report is analyzed as by
+/// `process_nsight_report` and a `profiling-finished` event is emitted.
+///
+/// # Arguments
+/// * `tool` - `ncu` or `nsys`
+/// * `program` - Application to launch; omit to attach to an application started with `ncu --mode launch`
+/// * `args` - Arguments for the application
+/// * `tool_args` - Extra profiler options
+/// * `window` - Tauri window handle for events
+///
+/// # Returns
+/// * `Result<ProfilingStatus, AppError>` - Status of the started session or error
+#[command]
+async fn start_profiling_session(
+    tool: profiler::ProfilerTool,
+    program: Option<String>,
+    args: Option<Vec<String>>,
+    tool_args: Option<Vec<String>>,
+    window: Window,
+) -> Result<profiler::ProfilingStatus, AppError> {
+    let target = match program {
+        Some(program) => profiler::ProfileTarget::Launch { program, args: args.unwrap_or_default() },
+        None => profiler::ProfileTarget::Attach,
+    };
+    let status = profiler::start_profiling_session(tool, target, tool_args.unwrap_or_default(), window).await
+        .context("Failed to start profiling session")?;
+    Ok(status)
+}
+
+/// Tauri command to kill the running profiler
+///
+/// # Returns
+/// * `Result<ProfilingStatus, AppError>` - Status of the stopping session or error
+#[command]
+async fn stop_profiling_session() -> Result<profiler::ProfilingStatus, AppError> {
+    Ok(profiler::stop_profiling_session().await?)
+}
+
+/// Tauri command to get the status of the current or most recent profiling session
+///
+/// # Returns
+/// * `Result<Option<ProfilingStatus>, AppError>` - Status with progress output, or `None` if no session has been run
+#[command]
+async fn get_profiling_status() -> Result<Option<profiler::ProfilingStatus>, AppError> {
+    Ok(profiler::get_profiling_status())
+}
+
+/// Tauri command to merge kernel statistics from several NSight reports
+/// 
+/// Computes mean and standard deviation per kernel across repeated runs
+/// and flags kernels whose duration is unstable.
+/// 
+/// # Arguments
+/// * `paths` - Paths to the NSight report files
+/// 
+/// # Returns
+/// * `Result<NSightAggregate, AppError>` - Aggregated statistics or error
 #[command]
-async fn process_nsight_report(file_path: String) -> Result<String, String> {
-    match nvml::process_nsight_report(file_path).await {
-        Ok(analysis) => {
-            let json = serde_json::to_string(&analysis)
-                .map_err(|e| format!("Failed to serialize analysis: {}", e))?;
-            Ok(json)
+async fn aggregate_nsight_reports(paths: Vec<String>) -> Result<nvml::NSightAggregate, AppError> {
+    let aggregate = nvml::aggregate_nsight_reports(paths).await
+        .context("Failed to aggregate NSight reports")?;
+    Ok(aggregate)
+}
+
+/// Upper bound on how long exit may be delayed by background cleanup
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Upper bound on each step that puts hardware settings back on exit
+const HARDWARE_RESTORE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Set once shutdown cleanup has been started, so it only runs once
+static SHUTDOWN_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Stop background tasks before the process exits
+/// 
+/// Puts locked clocks and fan overrides back, then stops telemetry
+/// streaming and finalizes any active recording so that samples collected
+/// so far are written to disk rather than lost.
+/// 
+/// # Arguments
+/// * `app` - Tauri application handle
+async fn shutdown_background_tasks(app: &AppHandle) {
+    // Locked clocks and fan overrides outlive the process, so they are put
+    // back first, each under its own timeout, before any slower cleanup
+    // can use up the shutdown budget
+    match tokio::time::timeout(HARDWARE_RESTORE_TIMEOUT, measurement::finish_active_measurement()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!("Failed to restore measurement clocks on exit: {:#}", e),
+        Err(_) => eprintln!("Restoring measurement clocks timed out after {:?}", HARDWARE_RESTORE_TIMEOUT),
+    }
+    // Fans following a profile's curve go back to driver control
+    match tokio::time::timeout(HARDWARE_RESTORE_TIMEOUT, tuning::finish_tuning()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!("Failed to stop tuning controllers on exit: {:#}", e),
+        Err(_) => eprintln!("Stopping tuning controllers timed out after {:?}", HARDWARE_RESTORE_TIMEOUT),
+    }
+
+    let state = app.state::<TelemetryState>();
+    let cleanup = async {
+        // Hooks go first so they cannot act on a half-stopped app
+        if let Err(e) = automation::finish_active_automation().await {
+            eprintln!("Failed to stop automation hooks on exit: {:#}", e);
+        }
+        state.stop_stream().await;
+        if let Err(e) = nvml::blocking(|| Ok(markers::stop_marker_listener())).await {
+            eprintln!("Failed to stop marker listener on exit: {:#}", e);
+        }
+        if let Err(e) = alerts::finish_active_alert_monitor().await {
+            eprintln!("Failed to stop alert monitor on exit: {:#}", e);
+        }
+        if let Err(e) = otlp::finish_active_export().await {
+            eprintln!("Failed to stop OpenTelemetry export on exit: {:#}", e);
+        }
+        if let Err(e) = allocations::finish_active_tracking().await {
+            eprintln!("Failed to stop allocation tracking on exit: {:#}", e);
         }
-        Err(e) => Err(format!("Failed to process NSight report: {}", e))
+        if let Err(e) = stress::finish_active_stress_test().await {
+            eprintln!("Failed to stop stress test on exit: {:#}", e);
+        }
+        if let Err(e) = profiler::finish_active_profiling_session().await {
+            eprintln!("Failed to stop profiling session on exit: {:#}", e);
+        }
+        if let Err(e) = triggers::finish_active_trigger().await {
+            eprintln!("Failed to finish recording trigger on exit: {:#}", e);
+        }
+        // Saves and tags the watched recording, so it runs before finalizing
+        if let Err(e) = watch::finish_active_watch().await {
+            eprintln!("Failed to finish process watch on exit: {:#}", e);
+        }
+        match nvml::finalize_active_recording().await {
+            Ok(Some(path)) => println!("Finalized recording on exit: {}", path),
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to finalize recording on exit: {:#}", e),
+        }
+    };
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, cleanup).await.is_err() {
+        eprintln!("Shutdown cleanup timed out after {:?}", SHUTDOWN_TIMEOUT);
+    }
+}
+
+/// Handle application lifecycle events
+/// 
+/// Defers exit requests (e.g. last window closed) until background
+/// cleanup has completed, then exits explicitly.
+fn handle_run_event(app: &AppHandle, event: RunEvent) {
+    match event {
+        RunEvent::ExitRequested { api, .. } if !SHUTDOWN_STARTED.swap(true, Ordering::SeqCst) => {
+            api.prevent_exit();
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                shutdown_background_tasks(&app).await;
+                app.exit(0);
+            });
+        }
+        RunEvent::Exit if !SHUTDOWN_STARTED.swap(true, Ordering::SeqCst) => {
+            // Event loop is ending without an exit request, clean up inline
+            tauri::async_runtime::block_on(shutdown_background_tasks(app));
+        }
+        _ => {}
     }
 }
 
 fn main() {
+    nvml::start_monotonic_clock();
     tauri::Builder::default()
         .manage(TelemetryState::default())
+        .setup(|_app| {
+            tauri::async_runtime::spawn(capabilities::probe_on_launch());
+            tauri::async_runtime::spawn(tuning::apply_on_launch());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             get_gpu_telemetry,
             start_nvml_stream,
             stop_nvml_stream,
             get_stream_status,
+            get_stream_devices,
+            start_burst_capture,
+            get_burst_status,
+            get_overhead_stats,
+            get_stream_capabilities,
+            subscribe_telemetry,
+            poll_telemetry,
+            unsubscribe_telemetry,
+            get_pstate_residency,
+            get_sm_heatmap,
+            get_device_rankings,
+            get_metric_histogram,
+            get_recording_histogram,
+            compare_recordings,
+            list_metric_providers,
+            set_metric_providers,
+            get_gpu_processes,
+            start_marker_listener,
+            stop_marker_listener,
+            get_marker_ranges,
             get_gpu_architecture,
+            get_system_info,
+            run_health_check,
+            get_gpu_displays,
+            start_alert_monitor,
+            stop_alert_monitor,
+            get_alert_status,
+            send_test_alert,
+            start_otlp_export,
+            stop_otlp_export,
+            get_otlp_status,
+            start_automation,
+            stop_automation,
+            get_automation_status,
+            get_violation_stats,
+            get_sample_history,
+            get_device_capabilities,
+            start_allocation_tracking,
+            stop_allocation_tracking,
+            get_allocation_timeline,
+            get_report_allocations,
+            get_device_modes,
+            set_persistence_mode,
+            set_compute_mode,
+            set_driver_model,
+            list_tuning_profiles,
+            save_tuning_profile,
+            delete_tuning_profile,
+            apply_tuning_profile,
+            set_tuning_auto_apply,
+            get_tuning_status,
+            run_benchmark,
+            start_stress_test,
+            stop_stress_test,
+            get_stress_status,
             start_gpu_recording,
             stop_gpu_recording,
             get_recording_status,
-            process_nsight_report
+            start_measurement_recording,
+            get_measurement_status,
+            recover_recordings,
+            arm_recording_trigger,
+            disarm_recording_trigger,
+            get_trigger_status,
+            watch_process,
+            stop_process_watch,
+            get_process_watch_status,
+            load_recording,
+            list_recordings,
+            get_recording_metadata,
+            delete_recording,
+            rename_recording,
+            analyze_recording,
+            convert_recording_to_columnar,
+            read_recording_range,
+            get_recording_gaps,
+            export_range,
+            process_nsight_report,
+            aggregate_nsight_reports,
+            start_profiling_session,
+            stop_profiling_session,
+            get_profiling_status
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(handle_run_event);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tokio_test;
     
     #[tokio::test]
     async fn test_get_gpu_architecture_command() {
-        // Test that the command returns properly formatted JSON
-        let result = get_gpu_architecture().await;
+        let result = get_gpu_architecture(None).await;
         
         match result {
-            Ok(json_str) => {
-                // Should be valid JSON
-                let parsed: Result<serde_json::Value, _> = serde_json::from_str(&json_str);
-                assert!(parsed.is_ok(), "GPU architecture should return valid JSON");
+            Ok(arch) => {
+                // Should serialize to a JSON object for the frontend
+                let value = serde_json::to_value(&arch).unwrap();
+                assert!(value.get("sm_count").is_some(), "Should have sm_count field");
             }
             Err(e) => {
                 // Error is acceptable if no GPU is available
-                assert!(e.contains("GPU"), "Error should mention GPU: {}", e);
+                assert!(e.message().contains("GPU"), "Error should mention GPU: {}", e);
             }
         }
     }
     
     #[tokio::test]
     async fn test_get_gpu_telemetry_command() {
-        let result = get_gpu_telemetry().await;
+        let result = get_gpu_telemetry(None).await;
         
         match result {
-            Ok(json_str) => {
-                // Should be valid JSON with expected structure
-                let parsed: Result<serde_json::Value, _> = serde_json::from_str(&json_str);
-                assert!(parsed.is_ok(), "GPU telemetry should return valid JSON");
-                
-                let data = parsed.unwrap();
-                assert!(data.get("status").is_some(), "Should have status field");
+            Ok(response) => {
+                let value = serde_json::to_value(&response).unwrap();
+                assert_eq!(value["status"], "connected");
+                assert!(value.get("gpus").is_some(), "Should have gpus field");
             }
             Err(e) => {
                 // Error is acceptable if no GPU is available
-                assert!(e.contains("GPU") || e.contains("NVML"), "Error should mention GPU or NVML: {}", e);
+                assert!(e.message().contains("GPU") || e.message().contains("NVML"), "Error should mention GPU or NVML: {}", e);
             }
         }
     }
@@ -290,22 +1945,33 @@ mod tests {
     fn test_telemetry_state_default() {
         let state = TelemetryState::default();
         // State should initialize properly
-        assert!(state.is_streaming.try_lock().is_ok());
+        assert!(state.stream.try_lock().unwrap().is_none());
         assert!(state.sender.try_lock().is_ok());
     }
     
     #[tokio::test]
-    async fn test_get_stream_status_command() {
+    async fn test_stop_stream_awaits_task() {
         let state = TelemetryState::default();
-        let result = get_stream_status(tauri::State::from(&state)).await;
+        assert!(!state.stop_stream().await, "Nothing to stop initially");
         
-        assert!(result.is_ok(), "Stream status should always succeed");
+        let cancel = CancellationToken::new();
+        let token = cancel.clone();
+        let task = tokio::spawn(async move { token.cancelled().await });
+        *state.stream.lock().await = Some(StreamHandle { cancel, task });
+        assert!(state.status().await.streaming);
         
-        let json_str = result.unwrap();
-        let parsed: Result<serde_json::Value, _> = serde_json::from_str(&json_str);
-        assert!(parsed.is_ok(), "Stream status should return valid JSON");
+        assert!(state.stop_stream().await);
+        assert!(!state.status().await.streaming);
+        assert!(state.stream.lock().await.is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_stream_status() {
+        let state = TelemetryState::default();
+        let status = state.status().await;
+        assert!(!status.streaming);
         
-        let data = parsed.unwrap();
-        assert!(data.get("streaming").is_some(), "Should have streaming field");
+        let value = serde_json::to_value(&status).unwrap();
+        assert_eq!(value["streaming"], false);
     }
 }
@@ -0,0 +1,334 @@
+//! NVTX range markers from profiled applications
+//!
+//! NVTX calls in a target process are forwarded as JSON datagrams to a
+//! local UDP port. The `nsightful-nvtx` crate in `nvtx-injection/` builds
+//! the NVTX injection library that does this: running an unmodified
+//! application with `NVTX_INJECTION64_PATH` set to it forwards its NVTX
+//! ranges here. Other tools may send the same datagrams. Each datagram is
+//! one event:
+//!
+//! ```json
+//! {"type": "push", "name": "forward pass", "pid": 4242, "tid": 1, "timestamp_ns": 1700000000000000000}
+//! {"type": "pop", "pid": 4242, "tid": 1, "timestamp_ns": 1700000000250000000}
+//! {"type": "start", "id": 7, "name": "epoch 3", "pid": 4242}
+//! {"type": "end", "id": 7, "pid": 4242}
+//! ```
+//!
+//! `push`/`pop` are nested per thread; `start`/`end` ranges are matched by
+//! ID and may cross threads. Timestamps are Unix epoch nanoseconds, the same
+//! clock telemetry frames use, so ranges line up with samples; events
+//! without one are stamped on arrival. Completed ranges are emitted as
+//! `marker-range` events and saved with recordings that overlap them.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::Window;
+
+use crate::error::AppError;
+use crate::nvml;
+
+/// Port the listener binds when none is requested
+pub const DEFAULT_PORT: u16 = 47_021;
+/// How far back completed ranges are kept
+pub const RANGE_RETENTION_MS: u128 = 15 * 60 * 1000;
+/// Open ranges kept per thread, and for start/end ranges overall, before the oldest are dropped
+const MAX_OPEN_RANGES: usize = 1024;
+/// How often the listener thread checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// One event sent by the NVTX injection library
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MarkerEvent {
+    /// `nvtxRangePush`
+    Push {
+        name: String,
+        pid: u32,
+        #[serde(default)]
+        tid: u32,
+        domain: Option<String>,
+        timestamp_ns: Option<u64>,
+    },
+    /// `nvtxRangePop`
+    Pop {
+        pid: u32,
+        #[serde(default)]
+        tid: u32,
+        timestamp_ns: Option<u64>,
+    },
+    /// `nvtxRangeStart`
+    Start {
+        id: u64,
+        name: String,
+        pid: u32,
+        domain: Option<String>,
+        timestamp_ns: Option<u64>,
+    },
+    /// `nvtxRangeEnd`
+    End {
+        id: u64,
+        pid: u32,
+        timestamp_ns: Option<u64>,
+    },
+}
+
+/// A completed NVTX range
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MarkerRange {
+    pub name: String,
+    pub pid: u32,
+    /// Thread of a push/pop range; `None` for start/end ranges
+    pub tid: Option<u32>,
+    pub domain: Option<String>,
+    /// Start and end in milliseconds since Unix epoch, like frame timestamps
+    pub start_ms: u128,
+    pub end_ms: u128,
+    /// Nesting depth of a push/pop range on its thread (0 = outermost)
+    pub depth: u32,
+}
+
+// A range that has begun but not yet ended
+#[derive(Clone, Debug)]
+struct OpenRange {
+    name: String,
+    domain: Option<String>,
+    start_ms: u128,
+}
+
+/// Pairs begin and end events into ranges
+#[derive(Default)]
+pub struct MarkerCollector {
+    stacks: HashMap<(u32, u32), Vec<OpenRange>>,
+    started: HashMap<(u32, u64), OpenRange>,
+    completed: VecDeque<MarkerRange>,
+}
+
+impl MarkerCollector {
+    /// Apply an event, returning the range it completes, if any
+    ///
+    /// # Arguments
+    /// * `event` - Event from the injection library
+    /// * `received_ms` - Arrival time, used when the event has no timestamp
+    pub fn apply(&mut self, event: MarkerEvent, received_ms: u128) -> Option<MarkerRange> {
+        let at = |timestamp_ns: Option<u64>| timestamp_ns.map_or(received_ms, |ns| u128::from(ns / 1_000_000));
+        let range = match event {
+            MarkerEvent::Push { name, pid, tid, domain, timestamp_ns } => {
+                let stack = self.stacks.entry((pid, tid)).or_default();
+                if stack.len() >= MAX_OPEN_RANGES {
+                    stack.remove(0);
+                }
+                stack.push(OpenRange { name, domain, start_ms: at(timestamp_ns) });
+                None
+            }
+            MarkerEvent::Pop { pid, tid, timestamp_ns } => {
+                let stack = self.stacks.get_mut(&(pid, tid))?;
+                let open = stack.pop()?;
+                Some(MarkerRange {
+                    name: open.name,
+                    pid,
+                    tid: Some(tid),
+                    domain: open.domain,
+                    start_ms: open.start_ms,
+                    end_ms: at(timestamp_ns),
+                    depth: stack.len() as u32,
+                })
+            }
+            MarkerEvent::Start { id, name, pid, domain, timestamp_ns } => {
+                if self.started.len() >= MAX_OPEN_RANGES {
+                    // Ranges that never ended; the oldest are the likeliest leaks
+                    if let Some(oldest) = self.started.iter().min_by_key(|(_, open)| open.start_ms).map(|(key, _)| *key) {
+                        self.started.remove(&oldest);
+                    }
+                }
+                self.started.insert((pid, id), OpenRange { name, domain, start_ms: at(timestamp_ns) });
+                None
+            }
+            MarkerEvent::End { id, pid, timestamp_ns } => {
+                let open = self.started.remove(&(pid, id))?;
+                Some(MarkerRange {
+                    name: open.name,
+                    pid,
+                    tid: None,
+                    domain: open.domain,
+                    start_ms: open.start_ms,
+                    end_ms: at(timestamp_ns),
+                    depth: 0,
+                })
+            }
+        }?;
+
+        self.complete(range.clone());
+        Some(range)
+    }
+
+    /// Keep a completed range, dropping those past retention
+    pub fn complete(&mut self, range: MarkerRange) {
+        let cutoff = range.end_ms.saturating_sub(RANGE_RETENTION_MS);
+        self.completed.push_back(range);
+        while self.completed.front().is_some_and(|kept| kept.end_ms < cutoff) {
+            self.completed.pop_front();
+        }
+    }
+
+    /// Completed ranges overlapping `[start_ms, end_ms]`, in completion order
+    pub fn ranges_between(&self, start_ms: u128, end_ms: u128) -> Vec<MarkerRange> {
+        self.completed.iter()
+            .filter(|range| range.end_ms >= start_ms && range.start_ms <= end_ms)
+            .cloned()
+            .collect()
+    }
+}
+
+// Ranges received by the listener, shared with recordings
+static COLLECTOR: Mutex<Option<MarkerCollector>> = Mutex::new(None);
+
+// Stop flag and thread of the running listener
+static LISTENER: Mutex<Option<(Arc<AtomicBool>, std::thread::JoinHandle<()>)>> = Mutex::new(None);
+
+/// Start listening for NVTX events from injected applications
+///
+/// # Arguments
+/// * `port` - Local UDP port to listen on (defaults to 47021)
+/// * `window` - Tauri window handle for `marker-range` events
+///
+/// # Returns
+/// * `Result<u16>` - Port the listener is bound to or error
+pub fn start_marker_listener(port: Option<u16>, window: Window) -> Result<u16> {
+    let mut listener = LISTENER.lock().unwrap();
+    if listener.as_ref().is_some_and(|(_, thread)| !thread.is_finished()) {
+        return Err(AppError::InvalidArgument("Marker listener is already running".to_string()).into());
+    }
+
+    // Loopback only: events carry process details and are not authenticated
+    let socket = UdpSocket::bind(("127.0.0.1", port.unwrap_or(DEFAULT_PORT)))
+        .context("Failed to bind marker listener")?;
+    socket.set_read_timeout(Some(POLL_INTERVAL)).context("Failed to configure marker listener")?;
+    let bound = socket.local_addr().context("Failed to read marker listener address")?.port();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    let thread = std::thread::Builder::new()
+        .name("nvtx-listener".to_string())
+        .spawn(move || listen(socket, thread_stop, window))
+        .context("Failed to start marker listener thread")?;
+    *listener = Some((stop, thread));
+    Ok(bound)
+}
+
+/// Stop the listener and wait for its thread to exit
+///
+/// Returns `true` if a listener was running. Received ranges are kept.
+pub fn stop_marker_listener() -> bool {
+    let Some((stop, thread)) = LISTENER.lock().unwrap().take() else {
+        return false;
+    };
+    stop.store(true, Ordering::SeqCst);
+    if thread.join().is_err() {
+        eprintln!("Marker listener thread panicked");
+    }
+    true
+}
+
+/// Record an instantaneous marker raised by the app itself
+///
+/// # Arguments
+/// * `name` - Marker label
+/// * `domain` - Domain the marker is filed under, e.g. "automation"
+///
+/// # Returns
+/// * `MarkerRange` - The marker, a range starting and ending now
+pub fn add_marker(name: &str, domain: &str) -> MarkerRange {
+    let now = nvml::now_ms();
+    let range = MarkerRange {
+        name: name.to_string(),
+        pid: std::process::id(),
+        tid: None,
+        domain: Some(domain.to_string()),
+        start_ms: now,
+        end_ms: now,
+        depth: 0,
+    };
+    COLLECTOR.lock().unwrap().get_or_insert_with(MarkerCollector::default).complete(range.clone());
+    range
+}
+
+/// Completed ranges overlapping a time window
+///
+/// # Arguments
+/// * `start_ms` - Window start in milliseconds since Unix epoch
+/// * `end_ms` - Window end in milliseconds since Unix epoch
+pub fn ranges_between(start_ms: u128, end_ms: u128) -> Vec<MarkerRange> {
+    COLLECTOR.lock().unwrap().as_ref()
+        .map(|collector| collector.ranges_between(start_ms, end_ms))
+        .unwrap_or_default()
+}
+
+fn listen(socket: UdpSocket, stop: Arc<AtomicBool>, window: Window) {
+    let mut buffer = [0u8; 64 * 1024];
+    while !stop.load(Ordering::SeqCst) {
+        let length = match socket.recv(&mut buffer) {
+            Ok(length) => length,
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+            Err(e) => {
+                eprintln!("Marker listener error: {}", e);
+                continue;
+            }
+        };
+        let event = match serde_json::from_slice::<MarkerEvent>(&buffer[..length]) {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("Ignoring malformed marker event: {}", e);
+                continue;
+            }
+        };
+
+        let range = COLLECTOR.lock().unwrap()
+            .get_or_insert_with(MarkerCollector::default)
+            .apply(event, nvml::now_ms());
+        if let Some(range) = range {
+            if let Err(e) = window.emit("marker-range", &range) {
+                eprintln!("Failed to emit marker range: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> MarkerEvent {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_pairs_nested_push_pop_per_thread() {
+        let mut collector = MarkerCollector::default();
+        assert_eq!(collector.apply(parse(r#"{"type":"push","name":"step","pid":1,"tid":1,"timestamp_ns":1000000000}"#), 0), None);
+        collector.apply(parse(r#"{"type":"push","name":"forward pass","pid":1,"tid":1,"timestamp_ns":1100000000}"#), 0);
+        // Another thread's pop must not close this thread's ranges
+        assert_eq!(collector.apply(parse(r#"{"type":"pop","pid":1,"tid":2}"#), 0), None);
+
+        let inner = collector.apply(parse(r#"{"type":"pop","pid":1,"tid":1,"timestamp_ns":1400000000}"#), 0).unwrap();
+        assert_eq!((inner.name.as_str(), inner.start_ms, inner.end_ms, inner.depth), ("forward pass", 1100, 1400, 1));
+        let outer = collector.apply(parse(r#"{"type":"pop","pid":1,"tid":1}"#), 2000).unwrap();
+        assert_eq!((outer.name.as_str(), outer.start_ms, outer.end_ms, outer.depth), ("step", 1000, 2000, 0));
+    }
+
+    #[test]
+    fn test_start_end_ranges_and_window_query() {
+        let mut collector = MarkerCollector::default();
+        collector.apply(parse(r#"{"type":"start","id":7,"name":"epoch","pid":1,"domain":"train"}"#), 5_000);
+        assert_eq!(collector.apply(parse(r#"{"type":"end","id":8,"pid":1}"#), 6_000), None);
+        let range = collector.apply(parse(r#"{"type":"end","id":7,"pid":1}"#), 9_000).unwrap();
+        assert_eq!((range.tid, range.domain.as_deref(), range.start_ms, range.end_ms), (None, Some("train"), 5_000, 9_000));
+
+        assert_eq!(collector.ranges_between(8_000, 20_000).len(), 1);
+        assert!(collector.ranges_between(9_001, 20_000).is_empty());
+    }
+}
@@ -0,0 +1,284 @@
+//! Measurement mode: recordings at locked clocks
+//!
+//! Boost clocks move with temperature and power headroom, so two runs of
+//! the same workload rarely see the same clocks. Measurement mode locks the
+//! GPU and memory clocks of the recorded devices to their base (default
+//! application) frequencies and disables auto boost, runs a recording, and
+//! restores the previous settings when it ends.
+//!
+//! Locking clocks usually requires root and is not supported on every GPU.
+//! Settings that cannot be applied are reported as warnings and the
+//! recording runs anyway.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::enum_wrappers::device::Clock;
+use nvml_wrapper::enums::device::GpuLockedClocksSetting;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::{device::Device, Nvml};
+use serde::Serialize;
+use std::time::Duration;
+use tauri::Window;
+
+use crate::error::AppError;
+use crate::nvml;
+use crate::sessions;
+
+/// How often the recording is checked for completion
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Clock settings applied to one device
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ClockLock {
+    pub device_index: u32,
+    /// Base clocks the device was locked to
+    pub graphics_clock_mhz: Option<u32>,
+    pub memory_clock_mhz: Option<u32>,
+    pub gpu_clocks_locked: bool,
+    pub memory_clocks_locked: bool,
+    /// Auto boost was on and has been turned off
+    pub auto_boost_disabled: bool,
+    /// Settings that could not be applied or restored
+    pub warnings: Vec<String>,
+}
+
+impl ClockLock {
+    // Whether any setting applied by measurement mode is still in effect
+    fn holds_settings(&self) -> bool {
+        self.gpu_clocks_locked || self.memory_clocks_locked || self.auto_boost_disabled
+    }
+}
+
+/// State of the current or most recent measurement
+#[derive(Serialize, Clone, Debug)]
+pub struct MeasurementStatus {
+    pub running: bool,
+    /// ID of the recording session
+    pub session_id: String,
+    pub locks: Vec<ClockLock>,
+    /// Previous clock settings have been put back
+    pub restored: bool,
+}
+
+// Current or most recent measurement; kept after it ends so its result can be read
+static MEASUREMENT_STATE: std::sync::RwLock<Option<MeasurementStatus>> = std::sync::RwLock::new(None);
+
+// Handle to the background task, used to wait for clocks to be restored
+static MEASUREMENT_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+/// Lock clocks, record, and restore the clocks when the recording ends
+///
+/// Takes the same arguments as a regular recording. Emits
+/// `measurement-finished` with the final status once clocks are restored.
+///
+/// # Arguments
+/// * `duration_seconds` - Recording duration in seconds
+/// * `sample_rate_hz` - Sampling frequency in Hz
+/// * `metrics` - List of metrics to record
+/// * `device_indices` - Devices to lock and record (device 0 if empty)
+/// * `analyze` - Write an analysis summary when the recording finishes
+/// * `window` - Tauri window handle for recording and measurement events
+///
+/// # Returns
+/// * `Result<MeasurementStatus>` - Applied clock settings or error if the recording could not start
+pub async fn start_measurement_recording(
+    duration_seconds: u64,
+    sample_rate_hz: u64,
+    metrics: Vec<String>,
+    device_indices: Vec<u32>,
+    analyze: bool,
+    window: Window,
+) -> Result<MeasurementStatus> {
+    let starting = MeasurementStatus { running: true, session_id: String::new(), locks: Vec::new(), restored: false };
+    let claim = sessions::claim(&MEASUREMENT_STATE, |status| status.running, starting, "A measurement is already running")?;
+    if nvml::get_recording_status().await?.is_recording {
+        return Err(AppError::InvalidArgument("Recording already in progress".to_string()).into());
+    }
+
+    let device_indices = if device_indices.is_empty() { vec![0] } else { device_indices };
+    let lock_indices = device_indices.clone();
+    let locks = nvml::blocking(move || lock_clocks(&lock_indices)).await?;
+
+    let session_id = match nvml::start_interval_recording(
+        duration_seconds, sample_rate_hz, metrics, device_indices, analyze, window.clone(),
+    ).await {
+        Ok(session_id) => session_id,
+        Err(e) => {
+            let restore = locks.clone();
+            if let Err(restore_error) = nvml::blocking(move || restore_clocks(&restore)).await {
+                eprintln!("Failed to restore clocks: {:#}", restore_error);
+            }
+            return Err(e);
+        }
+    };
+
+    let status = claim.commit(MeasurementStatus { running: true, session_id: session_id.clone(), locks, restored: false });
+
+    let task = tokio::spawn(async move {
+        while nvml::get_recording_status().await
+            .is_ok_and(|recording| recording.session_id.as_deref() == Some(session_id.as_str()))
+        {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        let finished = end_measurement().await;
+        if let Err(e) = window.emit("measurement-finished", &finished) {
+            eprintln!("Failed to emit measurement finished event: {}", e);
+        }
+    });
+    *MEASUREMENT_TASK.lock().unwrap() = Some(task);
+
+    Ok(status)
+}
+
+/// Restore the clocks of a running measurement without waiting for its recording
+///
+/// Used on exit, where the clocks are put back before anything else; the
+/// recording keeps running until it is finalized.
+pub async fn finish_active_measurement() -> Result<()> {
+    let task = MEASUREMENT_TASK.lock().unwrap().take();
+    let Some(task) = task else { return Ok(()) };
+    task.abort();
+    match task.await {
+        Err(e) if !e.is_cancelled() => return Err(e).context("Measurement task ended abnormally"),
+        _ => {}
+    }
+    if MEASUREMENT_STATE.read().unwrap().as_ref().is_some_and(|status| status.running) {
+        end_measurement().await;
+    }
+    Ok(())
+}
+
+// Restore the measurement's clocks and mark it as ended
+async fn end_measurement() -> MeasurementStatus {
+    let locks = MEASUREMENT_STATE.read().unwrap().as_ref()
+        .map(|status| status.locks.clone())
+        .unwrap_or_default();
+    let restored = nvml::blocking(move || restore_clocks(&locks)).await;
+    let mut state = MEASUREMENT_STATE.write().unwrap();
+    let status = state.as_mut().expect("measurement state is set while a measurement runs");
+    status.running = false;
+    match restored {
+        Ok(locks) => {
+            status.restored = locks.iter().all(|lock| !lock.holds_settings());
+            status.locks = locks;
+        }
+        Err(e) => eprintln!("Failed to restore clocks: {:#}", e),
+    }
+    status.clone()
+}
+
+/// Get the status of the current or most recent measurement
+///
+/// # Returns
+/// * `Option<MeasurementStatus>` - Status, or `None` if no measurement has been run
+pub fn get_measurement_status() -> Option<MeasurementStatus> {
+    MEASUREMENT_STATE.read().unwrap().clone()
+}
+
+fn lock_clocks(device_indices: &[u32]) -> Result<Vec<ClockLock>> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    // Resolve every device first so a bad index leaves no clocks locked
+    let devices = device_indices.iter()
+        .map(|&index| Ok((index, nvml::device_at(&nvml, index)?)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(devices.into_iter()
+        .map(|(index, mut device)| lock_device(&mut device, index))
+        .collect())
+}
+
+fn lock_device(device: &mut Device, device_index: u32) -> ClockLock {
+    let mut lock = ClockLock { device_index, ..Default::default() };
+
+    match device.default_applications_clock(Clock::Graphics) {
+        Ok(base) => match device.set_gpu_locked_clocks(GpuLockedClocksSetting::Numeric { min_clock_mhz: base, max_clock_mhz: base }) {
+            Ok(()) => {
+                lock.graphics_clock_mhz = Some(base);
+                lock.gpu_clocks_locked = true;
+            }
+            Err(e) => lock.warnings.push(clock_warning("lock GPU clocks", &e)),
+        },
+        Err(e) => lock.warnings.push(clock_warning("read base GPU clock", &e)),
+    }
+    match device.default_applications_clock(Clock::Memory) {
+        Ok(base) => match device.set_mem_locked_clocks(base, base) {
+            Ok(()) => {
+                lock.memory_clock_mhz = Some(base);
+                lock.memory_clocks_locked = true;
+            }
+            Err(e) => lock.warnings.push(clock_warning("lock memory clocks", &e)),
+        },
+        Err(e) => lock.warnings.push(clock_warning("read base memory clock", &e)),
+    }
+    match device.auto_boosted_clocks_enabled() {
+        Ok(info) if info.is_enabled => match device.set_auto_boosted_clocks(false) {
+            Ok(()) => lock.auto_boost_disabled = true,
+            Err(e) => lock.warnings.push(clock_warning("disable auto boost", &e)),
+        },
+        Ok(_) => {}
+        // Most current GPUs no longer expose auto boost control
+        Err(NvmlError::NotSupported) => {}
+        Err(e) => lock.warnings.push(clock_warning("read auto boost state", &e)),
+    }
+    lock
+}
+
+// Undo what `lock_clocks` applied; restore failures are added to the warnings
+fn restore_clocks(locks: &[ClockLock]) -> Result<Vec<ClockLock>> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let mut restored = Vec::with_capacity(locks.len());
+    for lock in locks {
+        let mut lock = lock.clone();
+        let mut device = nvml::device_at(&nvml, lock.device_index)?;
+        if lock.gpu_clocks_locked {
+            match device.reset_gpu_locked_clocks() {
+                Ok(()) => lock.gpu_clocks_locked = false,
+                Err(e) => lock.warnings.push(clock_warning("restore GPU clocks", &e)),
+            }
+        }
+        if lock.memory_clocks_locked {
+            match device.reset_mem_locked_clocks() {
+                Ok(()) => lock.memory_clocks_locked = false,
+                Err(e) => lock.warnings.push(clock_warning("restore memory clocks", &e)),
+            }
+        }
+        if lock.auto_boost_disabled {
+            match device.set_auto_boosted_clocks(true) {
+                Ok(()) => lock.auto_boost_disabled = false,
+                Err(e) => lock.warnings.push(clock_warning("re-enable auto boost", &e)),
+            }
+        }
+        restored.push(lock);
+    }
+    Ok(restored)
+}
+
+fn clock_warning(action: &str, error: &NvmlError) -> String {
+    match error {
+        NvmlError::NoPermission => format!("Could not {}: requires root", action),
+        NvmlError::NotSupported => format!("Could not {}: not supported by this GPU", action),
+        e => format!("Could not {}: {}", action, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_warning_explains_common_failures() {
+        assert_eq!(clock_warning("lock GPU clocks", &NvmlError::NoPermission), "Could not lock GPU clocks: requires root");
+        assert_eq!(
+            clock_warning("disable auto boost", &NvmlError::NotSupported),
+            "Could not disable auto boost: not supported by this GPU"
+        );
+    }
+
+    #[test]
+    fn test_holds_settings_until_everything_is_restored() {
+        // Nothing could be applied, so there is nothing to restore
+        let unprivileged = ClockLock { warnings: vec!["Could not lock GPU clocks: requires root".to_string()], ..Default::default() };
+        assert!(!unprivileged.holds_settings());
+        let boost_only = ClockLock { auto_boost_disabled: true, ..Default::default() };
+        assert!(boost_only.holds_settings());
+    }
+}
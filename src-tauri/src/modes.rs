@@ -0,0 +1,283 @@
+//! Persistence mode, compute mode and driver model management
+//!
+//! Reads and changes the driver-level modes that matter on shared machines:
+//! persistence mode keeps the driver loaded between jobs, and compute mode
+//! controls how many processes may hold a context on the device. On
+//! Windows, the driver model decides whether the device goes through the
+//! display driver stack (WDDM) or runs as a compute device (TCC, MCDM),
+//! which affects launch latency and which NVML metrics are available.
+//! Changing any of them requires root or administrator rights; NVML
+//! permission failures surface as `PERMISSION_DENIED` errors.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::enum_wrappers::device::ComputeMode;
+use nvml_wrapper::{device::Device, Nvml};
+use serde::{Deserialize, Serialize};
+
+use crate::nvml;
+
+/// Compute modes that can be selected from the UI
+///
+/// The deprecated exclusive-thread mode is reported as `ExclusiveProcess`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ComputeModeSetting {
+    /// Multiple processes may use the device at once
+    Default,
+    /// Only one process may hold a context on the device
+    ExclusiveProcess,
+    /// No process may create a context on the device
+    Prohibited,
+}
+
+impl From<ComputeMode> for ComputeModeSetting {
+    fn from(mode: ComputeMode) -> Self {
+        match mode {
+            ComputeMode::Default => ComputeModeSetting::Default,
+            ComputeMode::ExclusiveThread | ComputeMode::ExclusiveProcess => ComputeModeSetting::ExclusiveProcess,
+            ComputeMode::Prohibited => ComputeModeSetting::Prohibited,
+        }
+    }
+}
+
+impl From<ComputeModeSetting> for ComputeMode {
+    fn from(mode: ComputeModeSetting) -> Self {
+        match mode {
+            ComputeModeSetting::Default => ComputeMode::Default,
+            ComputeModeSetting::ExclusiveProcess => ComputeMode::ExclusiveProcess,
+            ComputeModeSetting::Prohibited => ComputeMode::Prohibited,
+        }
+    }
+}
+
+/// Windows driver models
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DriverModelSetting {
+    /// Windows Display Driver Model; the device can drive displays
+    Wddm,
+    /// Tesla Compute Cluster; compute only, lowest launch latency
+    Tcc,
+    /// Microsoft Compute Driver Model; compute only, managed by Windows
+    Mcdm,
+}
+
+/// Current driver modes of a device
+#[derive(Serialize, Clone, Debug)]
+pub struct DeviceModes {
+    pub device_index: u32,
+    /// `None` when the platform or device does not report it
+    pub persistence_mode: Option<bool>,
+    /// `None` when the device does not report it
+    pub compute_mode: Option<ComputeModeSetting>,
+    /// Active driver model; `None` outside Windows
+    pub driver_model: Option<DriverModelSetting>,
+    /// Driver model that takes effect after the next reboot
+    pub pending_driver_model: Option<DriverModelSetting>,
+}
+
+/// Read persistence and compute mode of a device
+///
+/// # Arguments
+/// * `device_index` - Device to query (defaults to 0)
+///
+/// # Returns
+/// * `Result<DeviceModes>` - Current modes or error if the device is unreachable
+pub async fn get_device_modes(device_index: Option<u32>) -> Result<DeviceModes> {
+    nvml::blocking(move || get_device_modes_blocking(device_index)).await
+}
+
+fn get_device_modes_blocking(device_index: Option<u32>) -> Result<DeviceModes> {
+    let device_index = device_index.unwrap_or(0);
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let device = nvml::device_at(&nvml, device_index)?;
+    Ok(read_modes(&device, device_index))
+}
+
+/// Enable or disable persistence mode (Linux only, requires root)
+///
+/// # Arguments
+/// * `device_index` - Device to change (defaults to 0)
+/// * `enabled` - Whether the driver should stay loaded while idle
+///
+/// # Returns
+/// * `Result<DeviceModes>` - Modes after the change or error
+pub async fn set_persistence_mode(device_index: Option<u32>, enabled: bool) -> Result<DeviceModes> {
+    nvml::blocking(move || set_persistence_mode_blocking(device_index, enabled)).await
+}
+
+fn set_persistence_mode_blocking(device_index: Option<u32>, enabled: bool) -> Result<DeviceModes> {
+    let device_index = device_index.unwrap_or(0);
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let mut device = nvml::device_at(&nvml, device_index)?;
+    apply_persistence_mode(&mut device, enabled)
+        .with_context(|| format!("Failed to set persistence mode on GPU {} (requires root)", device_index))?;
+    Ok(read_modes(&device, device_index))
+}
+
+/// Change the compute mode (requires root)
+///
+/// # Arguments
+/// * `device_index` - Device to change (defaults to 0)
+/// * `mode` - New compute mode
+///
+/// # Returns
+/// * `Result<DeviceModes>` - Modes after the change or error
+pub async fn set_compute_mode(device_index: Option<u32>, mode: ComputeModeSetting) -> Result<DeviceModes> {
+    nvml::blocking(move || set_compute_mode_blocking(device_index, mode)).await
+}
+
+fn set_compute_mode_blocking(device_index: Option<u32>, mode: ComputeModeSetting) -> Result<DeviceModes> {
+    let device_index = device_index.unwrap_or(0);
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let mut device = nvml::device_at(&nvml, device_index)?;
+    device.set_compute_mode(mode.into())
+        .with_context(|| format!("Failed to set compute mode on GPU {} (requires root)", device_index))?;
+    Ok(read_modes(&device, device_index))
+}
+
+/// Switch the Windows driver model (requires administrator rights)
+///
+/// The change takes effect after a reboot and is reported as the pending
+/// driver model until then.
+///
+/// # Arguments
+/// * `device_index` - Device to change (defaults to 0)
+/// * `model` - `wddm` or `tcc`; switching to MCDM is not supported
+/// * `force` - Switch even if the driver would refuse, e.g. with a display attached
+///
+/// # Returns
+/// * `Result<DeviceModes>` - Modes after the change or error
+pub async fn set_driver_model(device_index: Option<u32>, model: DriverModelSetting, force: bool) -> Result<DeviceModes> {
+    check_driver_model_switch(model)?;
+    nvml::blocking(move || set_driver_model_blocking(device_index, model, force)).await
+}
+
+fn set_driver_model_blocking(device_index: Option<u32>, model: DriverModelSetting, force: bool) -> Result<DeviceModes> {
+    let device_index = device_index.unwrap_or(0);
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let mut device = nvml::device_at(&nvml, device_index)?;
+    apply_driver_model(&mut device, model, force)
+        .with_context(|| format!("Failed to set driver model on GPU {} (requires administrator)", device_index))?;
+    Ok(read_modes(&device, device_index))
+}
+
+// Reject switches NVML cannot make before touching the device
+fn check_driver_model_switch(model: DriverModelSetting) -> Result<()> {
+    if cfg!(not(target_os = "windows")) {
+        return Err(crate::error::AppError::NotSupported("Driver models only exist on Windows".to_string()).into());
+    }
+    if model == DriverModelSetting::Mcdm {
+        return Err(crate::error::AppError::NotSupported("NVML cannot switch a device to MCDM".to_string()).into());
+    }
+    Ok(())
+}
+
+fn read_modes(device: &Device, device_index: u32) -> DeviceModes {
+    let (driver_model, pending_driver_model) = read_driver_model(device);
+    DeviceModes {
+        device_index,
+        persistence_mode: read_persistence_mode(device),
+        compute_mode: device.compute_mode().ok().map(ComputeModeSetting::from),
+        driver_model,
+        pending_driver_model,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_persistence_mode(device: &Device) -> Option<bool> {
+    device.is_in_persistent_mode().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_persistence_mode(_device: &Device) -> Option<bool> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn apply_persistence_mode(device: &mut Device, enabled: bool) -> Result<()> {
+    device.set_persistent(enabled)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply_persistence_mode(_device: &mut Device, _enabled: bool) -> Result<()> {
+    Err(crate::error::AppError::NotSupported("Persistence mode is only available on Linux".to_string()).into())
+}
+
+#[cfg(target_os = "windows")]
+fn read_driver_model(device: &Device) -> (Option<DriverModelSetting>, Option<DriverModelSetting>) {
+    use nvml_wrapper::enum_wrappers::device::DriverModel;
+    use nvml_wrapper::error::NvmlError;
+
+    let setting = |model: DriverModel| match model {
+        DriverModel::WDDM => DriverModelSetting::Wddm,
+        DriverModel::WDM => DriverModelSetting::Tcc,
+    };
+    match device.driver_model() {
+        Ok(state) => (Some(setting(state.current)), Some(setting(state.pending))),
+        // nvml-wrapper predates MCDM (NVML_DRIVER_MCDM = 2) and rejects the
+        // whole state; a device that reports it is running MCDM
+        Err(NvmlError::UnexpectedVariant(2)) => (Some(DriverModelSetting::Mcdm), None),
+        Err(_) => (None, None),
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn read_driver_model(_device: &Device) -> (Option<DriverModelSetting>, Option<DriverModelSetting>) {
+    (None, None)
+}
+
+#[cfg(target_os = "windows")]
+fn apply_driver_model(device: &mut Device, model: DriverModelSetting, force: bool) -> Result<()> {
+    use nvml_wrapper::bitmasks::Behavior;
+    use nvml_wrapper::enum_wrappers::device::DriverModel;
+
+    let model = match model {
+        DriverModelSetting::Wddm => DriverModel::WDDM,
+        DriverModelSetting::Tcc => DriverModel::WDM,
+        DriverModelSetting::Mcdm => unreachable!("rejected by check_driver_model_switch"),
+    };
+    device.set_driver_model(model, if force { Behavior::FORCE } else { Behavior::DEFAULT })?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn apply_driver_model(_device: &mut Device, _model: DriverModelSetting, _force: bool) -> Result<()> {
+    Err(crate::error::AppError::NotSupported("Driver models only exist on Windows".to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_mode_round_trip() {
+        for setting in [
+            ComputeModeSetting::Default,
+            ComputeModeSetting::ExclusiveProcess,
+            ComputeModeSetting::Prohibited,
+        ] {
+            assert_eq!(ComputeModeSetting::from(ComputeMode::from(setting)), setting);
+        }
+        assert_eq!(ComputeModeSetting::from(ComputeMode::ExclusiveThread), ComputeModeSetting::ExclusiveProcess);
+    }
+
+    #[test]
+    fn test_compute_mode_serde_names() {
+        let mode: ComputeModeSetting = serde_json::from_str("\"exclusive_process\"").unwrap();
+        assert_eq!(mode, ComputeModeSetting::ExclusiveProcess);
+        assert_eq!(serde_json::to_value(ComputeModeSetting::Prohibited).unwrap(), "prohibited");
+    }
+
+    #[test]
+    fn test_driver_model_switch_checks() {
+        let model: DriverModelSetting = serde_json::from_str("\"tcc\"").unwrap();
+        assert_eq!(model, DriverModelSetting::Tcc);
+        let err = check_driver_model_switch(DriverModelSetting::Mcdm).unwrap_err();
+        assert_eq!(crate::error::AppError::from(err).code(), "NOT_SUPPORTED");
+        if cfg!(not(target_os = "windows")) {
+            assert!(check_driver_model_switch(DriverModelSetting::Wddm).is_err());
+        }
+    }
+}
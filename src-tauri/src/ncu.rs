@@ -0,0 +1,408 @@
+//! NSight Compute CLI exports
+//!
+//! `.ncu-rep` files are read by running `ncu --import` and parsing its CSV
+//! output. When `ncu` is not installed, or a report does not contain the
+//! requested data, extraction returns nothing instead of failing the whole
+//! report analysis. Kernel launches are listed from the raw page, one row
+//! per launch, and each launch is then exported on its own for source and
+//! memory data.
+
+use serde::Serialize;
+use std::path::Path;
+use std::process::Command;
+
+use crate::nvml::KernelAnalysis;
+
+/// Maximum number of source lines kept per kernel
+pub const HOT_LINE_LIMIT: usize = 50;
+
+/// Stall and instruction statistics for one source or SASS line
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SourceLineStats {
+    /// CUDA source line number, when the report has line info (`-lineinfo`)
+    pub line: Option<u32>,
+    /// SASS instruction address, when correlating at the SASS level
+    pub address: Option<String>,
+    pub source: String,
+    pub instructions_executed: u64,
+    /// Warp stall samples attributed to this line
+    pub stall_samples: u64,
+}
+
+/// NCU metrics read for every kernel launch in a report
+const KERNEL_METRICS: [&str; 8] = [
+    "gpu__time_duration.sum",
+    "launch__registers_per_thread",
+    "launch__shared_mem_per_block_static",
+    "launch__shared_mem_per_block_dynamic",
+    "sm__warps_active.avg.pct_of_peak_sustained_active",
+    "sm__cycles_active.avg.pct_of_peak_sustained_elapsed",
+    "gpu__compute_memory_throughput.avg.pct_of_peak_sustained_elapsed",
+    "device__attribute_display_name",
+];
+
+/// Kernel launches listed in an NSight Compute report
+#[derive(Clone, Debug, Default)]
+pub struct ReportKernels {
+    /// Name of the profiled GPU, when the report records it
+    pub gpu_name: Option<String>,
+    /// One entry per launch, in launch order, without source or memory data
+    pub kernels: Vec<KernelAnalysis>,
+}
+
+/// NCU metrics read for the Memory Workload Analysis of a kernel
+const MEMORY_METRICS: [&str; 6] = [
+    "l1tex__t_sector_hit_rate.pct",
+    "lts__t_sector_hit_rate.pct",
+    "dram__throughput.avg.pct_of_peak_sustained_elapsed",
+    "dram__bytes.sum.per_second",
+    "smsp__sass_average_data_bytes_per_sector_mem_global_op_ld.pct",
+    "smsp__sass_average_data_bytes_per_sector_mem_global_op_st.pct",
+];
+
+/// Memory Workload Analysis metrics of one kernel
+///
+/// Metrics missing from the report (section not collected) are `None`.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct MemoryWorkload {
+    pub l1_hit_rate_percent: Option<f64>,
+    pub l2_hit_rate_percent: Option<f64>,
+    /// DRAM throughput as a share of peak sustained bandwidth
+    pub dram_throughput_percent: Option<f64>,
+    pub dram_bandwidth_gbps: Option<f64>,
+    /// Share of each fetched sector actually used by global loads
+    pub load_sector_efficiency_percent: Option<f64>,
+    /// Share of each written sector actually used by global stores
+    pub store_sector_efficiency_percent: Option<f64>,
+}
+
+/// List the kernel launches of an NSight Compute report
+///
+/// # Arguments
+/// * `report` - Path to the `.ncu-rep` file
+///
+/// # Returns
+/// * `Option<ReportKernels>` - Launches, or `None` if ncu is unavailable or the report has none
+pub fn extract_kernels(report: &Path) -> Option<ReportKernels> {
+    let metrics = KERNEL_METRICS.join(",");
+    run_ncu_export(report, &["--page", "raw", "--metrics", metrics.as_str()])
+        .map(|csv| parse_kernels_csv(&csv))
+        .filter(|kernels| !kernels.kernels.is_empty())
+}
+
+/// Parse the raw-page CSV export into one `KernelAnalysis` per launch
+///
+/// Values are converted from the units in the export's units row, so
+/// exports with and without `--print-units base` parse alike.
+pub fn parse_kernels_csv(output: &str) -> ReportKernels {
+    let Some(table) = RawTable::parse(output) else {
+        return ReportKernels::default();
+    };
+    let gpu_name = table.launches.first()
+        .and_then(|launch| table.text(launch, "device__attribute_display_name"))
+        .map(str::to_string);
+    let kernels = table.launches.iter()
+        .map(|launch| {
+            let metric = |name| table.value(launch, name);
+            let dimensions = |name| table.text(launch, name).and_then(parse_dim3);
+            KernelAnalysis {
+                name: table.text(launch, "Kernel Name").unwrap_or_default().to_string(),
+                duration_ms: metric(KERNEL_METRICS[0]).map_or(0.0, |seconds| seconds * 1e3),
+                grid_size: dimensions("Grid Size").unwrap_or((0, 0, 0)),
+                block_size: dimensions("Block Size").unwrap_or((0, 0, 0)),
+                registers_per_thread: metric(KERNEL_METRICS[1]).unwrap_or(0.0) as u32,
+                shared_memory_bytes: (metric(KERNEL_METRICS[2]).unwrap_or(0.0) + metric(KERNEL_METRICS[3]).unwrap_or(0.0)) as u64,
+                occupancy_percent: metric(KERNEL_METRICS[4]).unwrap_or(0.0),
+                sm_efficiency: metric(KERNEL_METRICS[5]).unwrap_or(0.0),
+                memory_efficiency: metric(KERNEL_METRICS[6]).unwrap_or(0.0),
+                source_hotspots: Vec::new(),
+                memory_workload: None,
+            }
+        })
+        .filter(|kernel| !kernel.name.is_empty())
+        .collect();
+    ReportKernels { gpu_name, kernels }
+}
+
+/// Extract Memory Workload Analysis metrics of a kernel
+///
+/// # Arguments
+/// * `report` - Path to the `.ncu-rep` file
+/// * `kernel_name` - Kernel to export metrics for, as listed by `extract_kernels`
+/// * `launch` - Which launch of the kernel to read, counting from zero
+///
+/// # Returns
+/// * `Option<MemoryWorkload>` - Metrics, or `None` if ncu or the metrics are unavailable
+pub fn extract_memory_workload(report: &Path, kernel_name: &str, launch: usize) -> Option<MemoryWorkload> {
+    let metrics = MEMORY_METRICS.join(",");
+    let mut args = vec!["--page", "raw", "--metrics", metrics.as_str()];
+    let skip = launch.to_string();
+    args.extend(launch_filter(kernel_name, &skip));
+    run_ncu_export(report, &args).and_then(|csv| parse_memory_workload_csv(&csv))
+}
+
+/// Parse the raw-page CSV export into Memory Workload Analysis metrics
+///
+/// The export has a header row of metric names, a row of units, and one
+/// row per kernel launch; only the first launch is read. Values are
+/// converted from the units row, so scaled units such as `Gbyte/second`
+/// read correctly.
+pub fn parse_memory_workload_csv(output: &str) -> Option<MemoryWorkload> {
+    let table = RawTable::parse(output)?;
+    let launch = table.launches.first()?;
+    let metric = |name: &str| table.value(launch, name);
+    let workload = MemoryWorkload {
+        l1_hit_rate_percent: metric(MEMORY_METRICS[0]),
+        l2_hit_rate_percent: metric(MEMORY_METRICS[1]),
+        dram_throughput_percent: metric(MEMORY_METRICS[2]),
+        dram_bandwidth_gbps: metric(MEMORY_METRICS[3]).map(|bytes_per_second| bytes_per_second / 1e9),
+        load_sector_efficiency_percent: metric(MEMORY_METRICS[4]),
+        store_sector_efficiency_percent: metric(MEMORY_METRICS[5]),
+    };
+    (workload != MemoryWorkload::default()).then_some(workload)
+}
+
+/// Extract the hottest source lines of a kernel from an NSight Compute report
+///
+/// # Arguments
+/// * `report` - Path to the `.ncu-rep` file
+/// * `kernel_name` - Kernel to export source statistics for, as listed by `extract_kernels`
+/// * `launch` - Which launch of the kernel to read, counting from zero
+///
+/// # Returns
+/// * `Vec<SourceLineStats>` - Up to `HOT_LINE_LIMIT` lines, most stalled first; empty if unavailable
+pub fn extract_source_hotspots(report: &Path, kernel_name: &str, launch: usize) -> Vec<SourceLineStats> {
+    let mut args = vec!["--page", "source", "--print-source", "cuda,sass"];
+    let skip = launch.to_string();
+    args.extend(launch_filter(kernel_name, &skip));
+    run_ncu_export(report, &args)
+        .map(|csv| parse_source_csv(&csv))
+        .unwrap_or_default()
+}
+
+// Select one launch of a kernel; names are matched as the raw page prints them
+fn launch_filter<'a>(kernel_name: &'a str, skip: &'a str) -> [&'a str; 8] {
+    [
+        "--kernel-name-base", "demangled",
+        "--kernel-name", kernel_name,
+        "--launch-skip", skip,
+        "--launch-count", "1",
+    ]
+}
+
+// Run `ncu --import <report> --csv <args>` and return stdout on success;
+// values are printed in base units (bytes, seconds) rather than scaled
+fn run_ncu_export(report: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("ncu")
+        .arg("--import")
+        .arg(report)
+        .arg("--csv")
+        .args(["--print-units", "base"])
+        .args(args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+// ncu prefixes its CSV with `==PROF==` log lines; the table starts at the
+// first quoted header row
+fn csv_body(output: &str) -> &str {
+    if output.starts_with('"') {
+        return output;
+    }
+    output.find("\n\"").map_or("", |pos| &output[pos + 1..])
+}
+
+// ncu formats large counts with thousands separators
+fn parse_count(value: &str) -> Option<u64> {
+    value.trim().replace(',', "").parse().ok()
+}
+
+// Parse a launch dimension such as `(256, 1, 1)`
+fn parse_dim3(value: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = value.trim().trim_start_matches('(').trim_end_matches(')').split(',').map(parse_count);
+    match (parts.next()??, parts.next()??, parts.next()??, parts.next()) {
+        (x, y, z, None) => Some((x as u32, y as u32, z as u32)),
+        _ => None,
+    }
+}
+
+// Factor converting a value to its base unit, e.g. Gbyte/second to
+// byte/second or msecond to second
+fn unit_scale(unit: &str) -> f64 {
+    const PREFIXES: [(&str, f64); 7] = [
+        ("T", 1e12), ("G", 1e9), ("M", 1e6), ("K", 1e3), ("m", 1e-3), ("u", 1e-6), ("n", 1e-9),
+    ];
+    PREFIXES.iter()
+        .find(|(prefix, _)| {
+            unit.trim().strip_prefix(prefix).is_some_and(|base| base.starts_with("byte") || base.starts_with("second"))
+        })
+        .map_or(1.0, |(_, scale)| *scale)
+}
+
+// Raw-page export: a header row of column and metric names, an optional
+// row of units, and one row per kernel launch
+struct RawTable {
+    headers: csv::StringRecord,
+    units: Option<csv::StringRecord>,
+    launches: Vec<csv::StringRecord>,
+}
+
+impl RawTable {
+    fn parse(output: &str) -> Option<RawTable> {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(csv_body(output).as_bytes());
+        let headers = reader.headers().ok()?.clone();
+        let (mut units, mut launches) = (None, Vec::new());
+        for record in reader.records().filter_map(|record| record.ok()) {
+            match record.get(0).map(str::trim) {
+                Some("") if launches.is_empty() => units = Some(record),
+                Some(id) if parse_count(id).is_some() => launches.push(record),
+                _ => {}
+            }
+        }
+        Some(RawTable { headers, units, launches })
+    }
+
+    fn column(&self, name: &str) -> Option<usize> {
+        self.headers.iter().position(|header| header.trim() == name)
+    }
+
+    fn text<'a>(&self, launch: &'a csv::StringRecord, name: &str) -> Option<&'a str> {
+        self.column(name).and_then(|index| launch.get(index)).map(str::trim).filter(|text| !text.is_empty())
+    }
+
+    // Numeric value in base units
+    fn value(&self, launch: &csv::StringRecord, name: &str) -> Option<f64> {
+        let index = self.column(name)?;
+        let value = launch.get(index)?.trim().replace(',', "").parse::<f64>().ok()?;
+        let unit = self.units.as_ref().and_then(|units| units.get(index)).unwrap_or_default();
+        Some(value * unit_scale(unit))
+    }
+}
+
+/// Parse the CSV export of the source page into per-line statistics
+///
+/// Columns are located by header name, so both CUDA and SASS views parse.
+pub fn parse_source_csv(output: &str) -> Vec<SourceLineStats> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(csv_body(output).as_bytes());
+    let Ok(headers) = reader.headers().cloned() else {
+        return Vec::new();
+    };
+    let column = |names: &[&str]| headers.iter().position(|header| names.contains(&header.trim()));
+    let stall_column = headers.iter()
+        .position(|header| header.starts_with("Warp Stall Sampling (All"))
+        .or_else(|| headers.iter().position(|header| header.starts_with("Warp Stall Sampling")));
+
+    let line_column = column(&["# Line", "Line", "#"]);
+    let address_column = column(&["# Address", "Address"]);
+    let source_column = column(&["Source"]);
+    let instructions_column = column(&["Instructions Executed"]);
+    if source_column.is_none() || (stall_column.is_none() && instructions_column.is_none()) {
+        return Vec::new();
+    }
+
+    let field = |record: &csv::StringRecord, index: Option<usize>| {
+        index.and_then(|index| record.get(index)).map(str::trim).unwrap_or_default().to_string()
+    };
+    let mut lines: Vec<SourceLineStats> = reader.records()
+        .filter_map(|record| record.ok())
+        .map(|record| SourceLineStats {
+            line: field(&record, line_column).parse().ok(),
+            address: Some(field(&record, address_column)).filter(|address| !address.is_empty()),
+            source: field(&record, source_column),
+            instructions_executed: parse_count(&field(&record, instructions_column)).unwrap_or(0),
+            stall_samples: parse_count(&field(&record, stall_column)).unwrap_or(0),
+        })
+        .filter(|line| line.instructions_executed > 0 || line.stall_samples > 0)
+        .collect();
+
+    lines.sort_by(|a, b| {
+        b.stall_samples.cmp(&a.stall_samples)
+            .then(b.instructions_executed.cmp(&a.instructions_executed))
+    });
+    lines.truncate(HOT_LINE_LIMIT);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_source_csv_sorts_by_stalls() {
+        let output = "==PROF== Connected to process\n\
+            \"# Line\",\"Source\",\"Warp Stall Sampling (All Samples)\",\"Warp Stall Sampling (Not-issued Samples)\",\"Instructions Executed\"\n\
+            \"10\",\"int i = threadIdx.x;\",\"12\",\"3\",\"1,024\"\n\
+            \"11\",\"// comment\",\"0\",\"0\",\"0\"\n\
+            \"12\",\"out[i] = a[i] * b[i];\",\"4,500\",\"4,000\",\"2,048\"\n";
+
+        let lines = parse_source_csv(output);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].line, Some(12));
+        assert_eq!(lines[0].stall_samples, 4500);
+        assert_eq!(lines[0].instructions_executed, 2048);
+        assert_eq!(lines[1].source, "int i = threadIdx.x;");
+        assert_eq!(lines[1].address, None);
+    }
+
+    #[test]
+    fn test_parse_memory_workload_csv() {
+        let output = "\"ID\",\"Kernel Name\",\"l1tex__t_sector_hit_rate.pct\",\"lts__t_sector_hit_rate.pct\",\"dram__bytes.sum.per_second\"\n\
+            \"\",\"\",\"%\",\"%\",\"byte/second\"\n\
+            \"0\",\"gemm\",\"12.5\",\"81.0\",\"450,000,000,000\"\n";
+
+        let workload = parse_memory_workload_csv(output).unwrap();
+        assert_eq!(workload.l1_hit_rate_percent, Some(12.5));
+        assert_eq!(workload.l2_hit_rate_percent, Some(81.0));
+        assert_eq!(workload.dram_bandwidth_gbps, Some(450.0));
+        assert_eq!(workload.dram_throughput_percent, None);
+        assert!(parse_memory_workload_csv("\"ID\",\"Kernel Name\"\n\"0\",\"gemm\"\n").is_none());
+    }
+
+    #[test]
+    fn test_parse_memory_workload_csv_scaled_units() {
+        let output = "\"ID\",\"Kernel Name\",\"dram__bytes.sum.per_second\",\"dram__throughput.avg.pct_of_peak_sustained_elapsed\"\n\
+            \"\",\"\",\"Gbyte/second\",\"%\"\n\
+            \"0\",\"gemm\",\"1.55\",\"72.5\"\n";
+
+        let workload = parse_memory_workload_csv(output).unwrap();
+        assert!((workload.dram_bandwidth_gbps.unwrap() - 1.55).abs() < 1e-9);
+        assert_eq!(workload.dram_throughput_percent, Some(72.5));
+        assert_eq!(unit_scale("Mbyte/second"), 1e6);
+        assert_eq!(unit_scale("msecond"), 1e-3);
+        assert_eq!(unit_scale("%"), 1.0);
+    }
+
+    #[test]
+    fn test_parse_kernels_csv_converts_units() {
+        let output = "==PROF== Disconnected from process\n\
+            \"ID\",\"Kernel Name\",\"Block Size\",\"Grid Size\",\"device__attribute_display_name\",\"gpu__time_duration.sum\",\"launch__registers_per_thread\",\"launch__shared_mem_per_block_static\",\"sm__warps_active.avg.pct_of_peak_sustained_active\"\n\
+            \"\",\"\",\"\",\"\",\"\",\"usecond\",\"register/thread\",\"Kbyte/block\",\"%\"\n\
+            \"0\",\"gemm(float *, int)\",\"(256, 1, 1)\",\"(1024, 2, 1)\",\"NVIDIA A100\",\"1,500\",\"64\",\"4\",\"45.5\"\n\
+            \"1\",\"relu(float *)\",\"(128, 1, 1)\",\"(64, 1, 1)\",\"NVIDIA A100\",\"20\",\"16\",\"0\",\"90\"\n";
+
+        let report = parse_kernels_csv(output);
+        assert_eq!(report.gpu_name.as_deref(), Some("NVIDIA A100"));
+        assert_eq!(report.kernels.len(), 2);
+        let gemm = &report.kernels[0];
+        assert_eq!(gemm.name, "gemm(float *, int)");
+        assert!((gemm.duration_ms - 1.5).abs() < 1e-9);
+        assert_eq!((gemm.block_size, gemm.grid_size), ((256, 1, 1), (1024, 2, 1)));
+        assert_eq!((gemm.registers_per_thread, gemm.shared_memory_bytes), (64, 4000));
+        assert_eq!((gemm.occupancy_percent, gemm.sm_efficiency), (45.5, 0.0));
+        assert!(parse_kernels_csv("").kernels.is_empty());
+    }
+
+    #[test]
+    fn test_parse_source_csv_without_source_columns() {
+        assert!(parse_source_csv("").is_empty());
+        assert!(parse_source_csv("\"ID\",\"Kernel Name\"\n\"0\",\"gemm\"\n").is_empty());
+    }
+}
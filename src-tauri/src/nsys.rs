@@ -0,0 +1,477 @@
+//! NSight Systems CPU/GPU timelines
+//!
+//! `.nsys-rep` files are exported to SQLite with `nsys export` and queried
+//! with the `sqlite3` CLI. GPU activity, CUDA API calls, OS runtime calls
+//! and thread scheduling are combined into one timeline, and every idle
+//! stretch of a GPU is attributed to what the launching host thread was
+//! doing at the time. When either tool is missing, extraction returns
+//! nothing instead of failing the whole report analysis.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Shortest GPU idle stretch that counts as a gap
+pub const DEFAULT_MIN_GAP_NS: u64 = 100_000;
+/// Most GPU activities kept in a timeline
+pub const MAX_GPU_ACTIVITIES: usize = 100_000;
+/// Most host events kept per thread
+pub const MAX_THREAD_EVENTS: usize = 10_000;
+
+// Queries against the nsys SQLite export schema; names are resolved
+// through the `StringIds` table
+const KERNEL_QUERY: &str = "SELECT k.start, k.end, k.deviceId AS device_id, k.streamId AS stream_id, \
+    k.correlationId AS correlation_id, s.value AS name \
+    FROM CUPTI_ACTIVITY_KIND_KERNEL k JOIN StringIds s ON s.id = k.shortName";
+const MEMCPY_QUERY: &str = "SELECT start, end, deviceId AS device_id, streamId AS stream_id, \
+    correlationId AS correlation_id, 'memcpy' AS name FROM CUPTI_ACTIVITY_KIND_MEMCPY";
+const CUDA_API_QUERY: &str = "SELECT r.start, r.end, r.globalTid AS global_tid, r.correlationId AS correlation_id, \
+    s.value AS name FROM CUPTI_ACTIVITY_KIND_RUNTIME r JOIN StringIds s ON s.id = r.nameId";
+const OS_RUNTIME_QUERY: &str = "SELECT o.start, o.end, o.globalTid AS global_tid, 0 AS correlation_id, \
+    s.value AS name FROM OSRT_API o JOIN StringIds s ON s.id = o.nameId";
+const SCHED_QUERY: &str = "SELECT start, globalTid AS global_tid, isSchedIn AS sched_in \
+    FROM SCHED_EVENTS ORDER BY start";
+const THREAD_NAME_QUERY: &str = "SELECT t.globalTid AS global_tid, s.value AS name \
+    FROM ThreadNames t JOIN StringIds s ON s.id = t.nameId";
+
+/// One kernel or memory copy on a GPU
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct GpuActivity {
+    pub device_id: u32,
+    pub stream_id: u32,
+    pub name: String,
+    pub start_ns: u64,
+    pub end_ns: u64,
+    /// Thread that issued the launch, when the CUDA API call was traced
+    pub launch_tid: Option<u32>,
+}
+
+/// Source of a host-side event
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HostEventKind {
+    /// CUDA runtime API call, e.g. `cudaLaunchKernel`
+    CudaApi,
+    /// OS runtime call, e.g. `pthread_mutex_lock` or `read`
+    OsRuntime,
+}
+
+/// One traced call on a host thread
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct HostEvent {
+    pub kind: HostEventKind,
+    pub name: String,
+    pub start_ns: u64,
+    pub end_ns: u64,
+}
+
+/// A stretch of time on one thread
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interval {
+    pub start_ns: u64,
+    pub end_ns: u64,
+}
+
+/// One host thread of the profiled process
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CpuThread {
+    pub pid: u32,
+    pub tid: u32,
+    pub name: String,
+    /// CUDA API and OS runtime calls, in time order
+    pub events: Vec<HostEvent>,
+    /// Stretches the thread was scheduled off its CPU
+    pub descheduled: Vec<Interval>,
+}
+
+/// What the host was doing while a GPU sat idle
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StallCause {
+    /// The launching thread was blocked in an OS runtime call
+    OsRuntime,
+    /// The launching thread was waiting for a CPU
+    Descheduled,
+    /// The launching thread was running application code
+    HostCompute,
+    /// The next launch was already issued; the delay is driver or GPU side
+    LaunchLatency,
+    /// The launching thread is not in the trace
+    Unknown,
+}
+
+/// An idle stretch of one GPU and its likely host-side cause
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct GpuGap {
+    pub device_id: u32,
+    pub start_ns: u64,
+    pub end_ns: u64,
+    /// Thread that launched the activity ending the gap
+    pub tid: Option<u32>,
+    pub cause: StallCause,
+    /// OS runtime call the thread was blocked in, for `OsRuntime`
+    pub call: Option<String>,
+    /// Part of the gap explained by the cause
+    pub attributed_ns: u64,
+}
+
+/// Combined CPU/GPU timeline of an NSight Systems report
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct CpuGpuTimeline {
+    pub start_ns: u64,
+    pub end_ns: u64,
+    pub gpu: Vec<GpuActivity>,
+    pub threads: Vec<CpuThread>,
+    pub gaps: Vec<GpuGap>,
+    /// Activities or host events were dropped to stay within the size limits
+    pub truncated: bool,
+}
+
+/// GPU activity row of the SQLite export
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct GpuRow {
+    pub start: u64,
+    pub end: u64,
+    pub device_id: u32,
+    pub stream_id: u32,
+    pub correlation_id: u64,
+    pub name: String,
+}
+
+/// CUDA API or OS runtime call row of the SQLite export
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct CallRow {
+    pub start: u64,
+    pub end: u64,
+    pub global_tid: u64,
+    pub correlation_id: u64,
+    pub name: String,
+}
+
+/// Scheduling event row of the SQLite export
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct SchedRow {
+    pub start: u64,
+    pub global_tid: u64,
+    pub sched_in: u8,
+}
+
+/// Thread name row of the SQLite export
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ThreadNameRow {
+    pub global_tid: u64,
+    pub name: String,
+}
+
+/// Tables read from one report
+#[derive(Clone, Debug, Default)]
+pub struct TraceTables {
+    pub gpu: Vec<GpuRow>,
+    pub cuda_api: Vec<CallRow>,
+    pub os_runtime: Vec<CallRow>,
+    pub sched: Vec<SchedRow>,
+    pub thread_names: Vec<ThreadNameRow>,
+}
+
+/// Extract the combined CPU/GPU timeline of a report
+///
+/// # Arguments
+/// * `report` - Path to the `.nsys-rep` file
+/// * `min_gap_ns` - Shortest GPU idle stretch reported as a gap
+///
+/// # Returns
+/// * `Option<CpuGpuTimeline>` - Timeline, or `None` if nsys or sqlite3 are unavailable
+pub fn extract_timeline(report: &Path, min_gap_ns: u64) -> Option<CpuGpuTimeline> {
+    let database = export_sqlite(report)?;
+    let mut tables = TraceTables {
+        gpu: parse_rows(&query(&database, KERNEL_QUERY)?),
+        ..Default::default()
+    };
+    // Tables only exist when the matching trace was collected
+    let optional = |sql| query(&database, sql).unwrap_or_default();
+    tables.gpu.extend(parse_rows(&optional(MEMCPY_QUERY)));
+    tables.cuda_api = parse_rows(&optional(CUDA_API_QUERY));
+    tables.os_runtime = parse_rows(&optional(OS_RUNTIME_QUERY));
+    tables.sched = parse_rows(&optional(SCHED_QUERY));
+    tables.thread_names = parse_rows(&optional(THREAD_NAME_QUERY));
+    Some(build_timeline(tables, min_gap_ns))
+}
+
+/// Parse `sqlite3 -csv -header` output into rows, skipping malformed ones
+pub fn parse_rows<T: for<'de> Deserialize<'de>>(output: &str) -> Vec<T> {
+    csv::Reader::from_reader(output.as_bytes())
+        .deserialize()
+        .filter_map(|row| row.ok())
+        .collect()
+}
+
+/// Combine exported tables into a timeline and attribute GPU gaps
+///
+/// # Arguments
+/// * `tables` - Rows read from the report
+/// * `min_gap_ns` - Shortest GPU idle stretch reported as a gap
+///
+/// # Returns
+/// * `CpuGpuTimeline` - Timeline with activities and events in time order
+pub fn build_timeline(mut tables: TraceTables, min_gap_ns: u64) -> CpuGpuTimeline {
+    tables.gpu.sort_by_key(|row| row.start);
+    let launches: HashMap<u64, &CallRow> = tables.cuda_api.iter()
+        .map(|call| (call.correlation_id, call))
+        .collect();
+
+    let mut threads: BTreeMap<u64, CpuThread> = BTreeMap::new();
+    let calls = tables.cuda_api.iter().map(|call| (HostEventKind::CudaApi, call))
+        .chain(tables.os_runtime.iter().map(|call| (HostEventKind::OsRuntime, call)));
+    for (kind, call) in calls {
+        thread_entry(&mut threads, call.global_tid).events.push(HostEvent {
+            kind,
+            name: call.name.clone(),
+            start_ns: call.start,
+            end_ns: call.end,
+        });
+    }
+    for row in &tables.thread_names {
+        if let Some(thread) = threads.get_mut(&row.global_tid) {
+            thread.name = row.name.clone();
+        }
+    }
+    // Scheduling is traced system-wide; keep only the traced threads
+    let mut switched_out: HashMap<u64, u64> = HashMap::new();
+    for event in &tables.sched {
+        let Some(thread) = threads.get_mut(&event.global_tid) else { continue };
+        if event.sched_in == 0 {
+            switched_out.insert(event.global_tid, event.start);
+        } else if let Some(start_ns) = switched_out.remove(&event.global_tid) {
+            thread.descheduled.push(Interval { start_ns, end_ns: event.start });
+        }
+    }
+    for thread in threads.values_mut() {
+        thread.events.sort_by_key(|event| event.start_ns);
+        thread.descheduled.sort_by_key(|interval| interval.start_ns);
+    }
+
+    let mut gpu: Vec<GpuActivity> = tables.gpu.iter()
+        .map(|row| GpuActivity {
+            device_id: row.device_id,
+            stream_id: row.stream_id,
+            name: row.name.clone(),
+            start_ns: row.start,
+            end_ns: row.end,
+            launch_tid: launches.get(&row.correlation_id).map(|call| split_global_tid(call.global_tid).1),
+        })
+        .collect();
+
+    let mut gaps = Vec::new();
+    let mut busy_until: HashMap<u32, u64> = HashMap::new();
+    for (activity, row) in gpu.iter().zip(&tables.gpu) {
+        let previous_end = busy_until.entry(activity.device_id).or_insert(activity.end_ns);
+        if activity.start_ns >= previous_end.saturating_add(min_gap_ns) {
+            let launch = launches.get(&row.correlation_id).copied();
+            let thread = launch.and_then(|call| threads.get(&call.global_tid));
+            gaps.push(attribute_gap(activity.device_id, *previous_end, activity.start_ns, launch, thread));
+        }
+        *previous_end = (*previous_end).max(activity.end_ns);
+    }
+
+    let start_ns = gpu.iter().map(|activity| activity.start_ns)
+        .chain(threads.values().flat_map(|thread| thread.events.iter().map(|event| event.start_ns)))
+        .min()
+        .unwrap_or_default();
+    let end_ns = gpu.iter().map(|activity| activity.end_ns)
+        .chain(threads.values().flat_map(|thread| thread.events.iter().map(|event| event.end_ns)))
+        .max()
+        .unwrap_or_default();
+
+    let mut truncated = gpu.len() > MAX_GPU_ACTIVITIES;
+    gpu.truncate(MAX_GPU_ACTIVITIES);
+    let threads: Vec<CpuThread> = threads.into_values()
+        .map(|mut thread| {
+            if thread.events.len() > MAX_THREAD_EVENTS {
+                // Keep the longest calls, which are the ones worth seeing
+                thread.events.sort_by_key(|event| std::cmp::Reverse(event.end_ns - event.start_ns));
+                thread.events.truncate(MAX_THREAD_EVENTS);
+                thread.events.sort_by_key(|event| event.start_ns);
+                truncated = true;
+            }
+            thread
+        })
+        .collect();
+
+    CpuGpuTimeline { start_ns, end_ns, gpu, threads, gaps, truncated }
+}
+
+// Attribute the idle stretch `[start_ns, end_ns)` of a device to the
+// thread that launched the activity ending it. Only the part before the
+// launch call was issued is the host's doing.
+fn attribute_gap(device_id: u32, start_ns: u64, end_ns: u64, launch: Option<&CallRow>, thread: Option<&CpuThread>) -> GpuGap {
+    let mut gap = GpuGap {
+        device_id,
+        start_ns,
+        end_ns,
+        tid: thread.map(|thread| thread.tid),
+        cause: StallCause::Unknown,
+        call: None,
+        attributed_ns: 0,
+    };
+    let (Some(launch), Some(thread)) = (launch, thread) else {
+        return gap;
+    };
+    let window_end = launch.start.min(end_ns);
+    if window_end <= start_ns {
+        gap.cause = StallCause::LaunchLatency;
+        gap.attributed_ns = end_ns - start_ns;
+        return gap;
+    }
+
+    let overlap = |from: u64, to: u64| to.min(window_end).saturating_sub(from.max(start_ns));
+    let mut blocked: HashMap<&str, u64> = HashMap::new();
+    for event in thread.events.iter().filter(|event| event.kind == HostEventKind::OsRuntime) {
+        *blocked.entry(event.name.as_str()).or_default() += overlap(event.start_ns, event.end_ns);
+    }
+    let blocked_total: u64 = blocked.values().sum();
+    let descheduled: u64 = thread.descheduled.iter().map(|interval| overlap(interval.start_ns, interval.end_ns)).sum();
+    let computing = (window_end - start_ns).saturating_sub(blocked_total + descheduled);
+    let top_call = blocked.into_iter()
+        .filter(|&(_, time)| time > 0)
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)));
+
+    let os_runtime = top_call.map_or(0, |(_, time)| time);
+    (gap.cause, gap.attributed_ns) = if os_runtime >= descheduled && os_runtime >= computing && os_runtime > 0 {
+        (StallCause::OsRuntime, os_runtime)
+    } else if descheduled >= computing {
+        (StallCause::Descheduled, descheduled)
+    } else {
+        (StallCause::HostCompute, computing)
+    };
+    if gap.cause == StallCause::OsRuntime {
+        gap.call = top_call.map(|(name, _)| name.to_string());
+    }
+    gap
+}
+
+fn thread_entry(threads: &mut BTreeMap<u64, CpuThread>, global_tid: u64) -> &mut CpuThread {
+    threads.entry(global_tid).or_insert_with(|| {
+        let (pid, tid) = split_global_tid(global_tid);
+        CpuThread { pid, tid, name: format!("Thread {}", tid), events: Vec::new(), descheduled: Vec::new() }
+    })
+}
+
+// nsys packs the process and thread id into one `globalTid`
+fn split_global_tid(global_tid: u64) -> (u32, u32) {
+    (((global_tid >> 24) & 0xFF_FFFF) as u32, (global_tid & 0xFF_FFFF) as u32)
+}
+
+/// Export a report to SQLite next to itself, reusing an export newer than the report
+///
+/// # Returns
+/// * `Option<PathBuf>` - Path of the export, or `None` if nsys is unavailable or failed
+pub fn export_sqlite(report: &Path) -> Option<PathBuf> {
+    let database = report.with_extension("sqlite");
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    if modified(&database).is_some_and(|exported| modified(report).is_some_and(|source| exported >= source)) {
+        return Some(database);
+    }
+    let status = Command::new("nsys")
+        .args(["export", "--type", "sqlite", "--force-overwrite", "true", "--output"])
+        .arg(&database)
+        .arg(report)
+        .output()
+        .ok()?
+        .status;
+    status.success().then_some(database)
+}
+
+/// Run a query against an exported report with the `sqlite3` CLI
+///
+/// # Returns
+/// * `Option<String>` - CSV output with a header row, or `None` if sqlite3 or the query failed
+pub fn query(database: &Path, sql: &str) -> Option<String> {
+    let output = Command::new("sqlite3")
+        .args(["-csv", "-header"])
+        .arg(database)
+        .arg(sql)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Thread 7 of process 100
+    const THREAD: u64 = (100 << 24) | 7;
+
+    fn kernel(start: u64, end: u64, correlation_id: u64) -> GpuRow {
+        GpuRow { start, end, device_id: 0, stream_id: 7, correlation_id, name: "gemm".to_string() }
+    }
+
+    fn call(start: u64, end: u64, correlation_id: u64, name: &str) -> CallRow {
+        CallRow { start, end, global_tid: THREAD, correlation_id, name: name.to_string() }
+    }
+
+    #[test]
+    fn test_parses_sqlite_csv_rows() {
+        let output = "start,end,device_id,stream_id,correlation_id,name\n\
+            100,200,0,7,1,\"void gemm<float>(int, int)\"\n\
+            bad,row,0,7,2,x\n";
+        let rows: Vec<GpuRow> = parse_rows(output);
+        assert_eq!(rows, vec![GpuRow { start: 100, end: 200, device_id: 0, stream_id: 7, correlation_id: 1, name: "void gemm<float>(int, int)".to_string() }]);
+        assert_eq!(split_global_tid(THREAD), (100, 7));
+    }
+
+    #[test]
+    fn test_attributes_gaps_to_host_activity() {
+        let tables = TraceTables {
+            gpu: vec![kernel(0, 1_000, 1), kernel(500_000, 501_000, 2), kernel(900_000, 901_000, 3), kernel(1_300_000, 1_301_000, 4)],
+            cuda_api: vec![
+                call(0, 10, 1, "cudaLaunchKernel"),
+                call(490_000, 495_000, 2, "cudaLaunchKernel"),
+                call(880_000, 890_000, 3, "cudaLaunchKernel"),
+                call(800, 900, 4, "cudaLaunchKernel"),
+            ],
+            os_runtime: vec![call(2_000, 400_000, 0, "pthread_mutex_lock")],
+            sched: vec![
+                SchedRow { start: 510_000, global_tid: THREAD, sched_in: 0 },
+                SchedRow { start: 850_000, global_tid: THREAD, sched_in: 1 },
+                SchedRow { start: 600_000, global_tid: 99, sched_in: 0 },
+            ],
+            thread_names: vec![ThreadNameRow { global_tid: THREAD, name: "trainer".to_string() }],
+        };
+        let timeline = build_timeline(tables, DEFAULT_MIN_GAP_NS);
+
+        assert_eq!((timeline.start_ns, timeline.end_ns), (0, 1_301_000));
+        assert_eq!(timeline.threads.len(), 1);
+        assert_eq!((timeline.threads[0].pid, timeline.threads[0].tid, timeline.threads[0].name.as_str()), (100, 7, "trainer"));
+        assert_eq!(timeline.threads[0].descheduled, vec![Interval { start_ns: 510_000, end_ns: 850_000 }]);
+        assert_eq!(timeline.gpu[1].launch_tid, Some(7));
+
+        let causes: Vec<(StallCause, Option<&str>)> = timeline.gaps.iter().map(|gap| (gap.cause, gap.call.as_deref())).collect();
+        assert_eq!(causes, vec![
+            (StallCause::OsRuntime, Some("pthread_mutex_lock")),
+            (StallCause::Descheduled, None),
+            (StallCause::LaunchLatency, None),
+        ]);
+        assert_eq!(timeline.gaps[0].attributed_ns, 398_000);
+        assert_eq!((timeline.gaps[1].start_ns, timeline.gaps[1].end_ns), (501_000, 900_000));
+    }
+
+    #[test]
+    fn test_host_compute_and_unknown_launcher() {
+        let tables = TraceTables {
+            gpu: vec![kernel(0, 1_000, 1), kernel(300_000, 301_000, 2), kernel(600_000, 601_000, 3), kernel(650_000, 651_000, 4)],
+            cuda_api: vec![call(290_000, 299_000, 2, "cudaLaunchKernel")],
+            ..Default::default()
+        };
+        let timeline = build_timeline(tables, DEFAULT_MIN_GAP_NS);
+        let causes: Vec<StallCause> = timeline.gaps.iter().map(|gap| gap.cause).collect();
+        // The 49 µs stretch before the last kernel is too short to count
+        assert_eq!(causes, vec![StallCause::HostCompute, StallCause::Unknown]);
+        assert_eq!(timeline.gaps[0].attributed_ns, 289_000);
+        assert!(!timeline.truncated);
+    }
+}
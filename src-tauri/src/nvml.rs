@@ -6,33 +6,182 @@
 
 use anyhow::{Result, Context};
 use nvml_wrapper::{Nvml, device::Device};
-use serde::Serialize;
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, OnceLock};
 use tokio::sync::{Mutex, broadcast};
+use tokio_util::sync::CancellationToken;
 use tauri::Window;
 
+use crate::analysis;
+use crate::burst::{self, BurstControl};
+use crate::enumeration;
+use crate::error::AppError;
+use crate::idle::{IdleDetector, IdlePolicy};
+use crate::journal;
+use crate::markers;
+use crate::ncu;
+use crate::nsys;
+use crate::overhead;
+use crate::recommendations;
+use crate::heatmap::SmHeatmapHistory;
+use crate::rankings::RankingHistory;
+use crate::residency::ResidencyHistory;
+use crate::schema;
+use crate::stream_stats::StreamStats;
+use crate::virtualization::{self, VirtualizationInfo};
+use crate::violations;
+use crate::watch;
+use crate::sampler::{DeviceSampler, MetricSet, SamplingThread};
+
 /// Real-time telemetry data frame containing comprehensive GPU metrics
 /// 
 /// This structure captures all essential GPU performance data including
 /// utilization, memory usage, thermal data, and per-SM statistics.
-#[derive(Serialize, Clone, Debug)]
+/// Static properties of the device live in `StaticDeviceInfo`, keyed by
+/// `device_index`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct TelemetryFrame {
+    /// Wall-clock time in milliseconds since the Unix epoch
     pub timestamp: u128,
+    /// Milliseconds on the process's monotonic clock (see `monotonic_ms`);
+    /// 0 in frames recorded before it was added
+    #[serde(default)]
+    pub monotonic_ms: f64,
     pub device_index: u32,
-    pub name: String,
     pub util_gpu: u32,      
     pub util_memory: u32,      
     pub memory_used_mb: u64,
-    pub memory_total_mb: u64,
     pub sm_clock_mhz: u32,
     pub memory_clock_mhz: u32,
     pub temperature_c: u32,
     pub power_w: f32,
+    /// Speed of the first fan, kept for compatibility with older consumers
     pub fan_speed_percent: u32,
+    /// Speed of every fan on the device, indexed by fan
+    #[serde(default)]
+    pub fan_speeds_percent: Vec<u32>,
+    /// Speed the driver is steering each fan towards, if the device reports it
+    #[serde(default)]
+    pub target_fan_speeds_percent: Option<Vec<u32>>,
+    #[serde(default)]
+    pub engine_utilization: EngineUtilization,
+    /// Active clock throttle reasons, named as in the health check
+    #[serde(default)]
+    pub throttle_reasons: Vec<String>,
     pub sm_utilizations: Vec<f32>, // Per-SM utilization if available
+    /// Whether `sm_utilizations` was measured; otherwise it is estimated
+    /// from overall utilization
+    #[serde(default)]
+    pub sm_utilizations_measured: bool,
     pub memory_bandwidth_gbps: f32,
     pub pcie_utilization: u32,
+    /// Performance state number (0 = P0, maximum performance); `None` if unsupported
+    #[serde(default)]
+    pub performance_state: Option<u32>,
+    /// Change since the previous frame of the same device, when the stream
+    /// was started with derived fields enabled
+    #[serde(default)]
+    pub deltas: Option<FrameDeltas>,
+    /// Values of enabled metric providers, keyed `<provider>.<metric>`
+    #[serde(default)]
+    pub provider_metrics: BTreeMap<String, f64>,
+    /// Fields that failed plausibility checks, named as serialized;
+    /// bounded values among them were clamped
+    #[serde(default)]
+    pub suspect_fields: Vec<String>,
+    /// Highest value since the previous frame from the driver's sample
+    /// buffers, keyed by field; catches spikes shorter than the poll interval
+    #[serde(default)]
+    pub interval_max: BTreeMap<String, f64>,
+}
+
+/// Frame-to-frame changes of one device, computed server-side
+/// 
+/// Lets the frontend show rates without keeping its own frame history.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct FrameDeltas {
+    /// Time since the previous frame
+    pub interval_ms: u64,
+    pub memory_used_delta_mb: i64,
+    pub power_slope_w_per_s: f32,
+    pub temperature_rate_c_per_min: f32,
+}
+
+impl TelemetryFrame {
+    /// Time of this frame for measuring intervals
+    /// 
+    /// The monotonic clock, or the wall clock for frames recorded without
+    /// it; only comparable between frames of the same stream or recording.
+    pub fn clock_ms(&self) -> f64 {
+        if self.monotonic_ms > 0.0 { self.monotonic_ms } else { self.timestamp as f64 }
+    }
+
+    /// Milliseconds from `earlier` to this frame
+    /// 
+    /// Uses the monotonic clock, so the result is unaffected by wall-clock
+    /// adjustments; falls back to wall-clock timestamps for frames without it.
+    pub fn ms_since(&self, earlier: &TelemetryFrame) -> f64 {
+        if self.monotonic_ms > 0.0 && earlier.monotonic_ms > 0.0 {
+            self.monotonic_ms - earlier.monotonic_ms
+        } else {
+            self.timestamp as f64 - earlier.timestamp as f64
+        }
+    }
+}
+
+impl FrameDeltas {
+    /// Compute the changes from `previous` to `current`
+    /// 
+    /// # Returns
+    /// * `Option<FrameDeltas>` - Deltas, or `None` if no time has passed between the frames
+    pub fn between(previous: &TelemetryFrame, current: &TelemetryFrame) -> Option<Self> {
+        let interval_ms = current.ms_since(previous).round();
+        if interval_ms < 1.0 {
+            return None;
+        }
+        let interval_ms = interval_ms as u64;
+        let seconds = interval_ms as f32 / 1000.0;
+        Some(Self {
+            interval_ms,
+            memory_used_delta_mb: current.memory_used_mb as i64 - previous.memory_used_mb as i64,
+            power_slope_w_per_s: (current.power_w - previous.power_w) / seconds,
+            temperature_rate_c_per_min: (current.temperature_c as f32 - previous.temperature_c as f32) / seconds * 60.0,
+        })
+    }
+}
+
+/// Utilization broken down by hardware engine
+/// 
+/// Engines the device does not report are `None`. Copy engines are
+/// omitted: NVML has no utilization sample type or field for them.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct EngineUtilization {
+    /// Graphics/compute engine (same source as `util_gpu`)
+    pub graphics: u32,
+    pub encoder: Option<u32>,
+    pub decoder: Option<u32>,
+    /// JPEG decode engine
+    #[serde(default)]
+    pub jpeg: Option<u32>,
+    /// Optical flow accelerator
+    #[serde(default)]
+    pub ofa: Option<u32>,
+}
+
+/// Static device properties read once when sampling starts
+/// 
+/// Sent alongside (not inside) telemetry frames so that values which never
+/// change are not repeated in every frame.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StaticDeviceInfo {
+    pub index: u32,
+    pub uuid: String,
+    pub name: String,
+    pub memory_total_mb: u64,
+    pub compute_capability: String,
+    pub sm_count: u32,
 }
 
 /// GPU device information and hardware specifications
@@ -94,13 +243,113 @@ pub struct GPUArchitecture {
     pub thermal_design_power_w: f32,
 }
 
+/// Minimum streaming update period enforced by the stream loops
+pub const MIN_STREAM_PERIOD_MS: u64 = 50;
+
+/// Streaming capabilities and optional feature support
+/// 
+/// Lets the frontend disable controls for features a device or driver
+/// does not support instead of failing when they are used.
+#[derive(Serialize, Clone, Debug)]
+pub struct StreamCapabilities {
+    pub min_period_ms: u64,
+    /// Shortest period a burst capture may sample at
+    pub min_burst_period_ms: u64,
+    pub devices: Vec<DeviceFeatureSupport>,
+}
+
+/// Per-device metric and feature support
+#[derive(Serialize, Clone, Debug)]
+pub struct DeviceFeatureSupport {
+    pub index: u32,
+    pub name: String,
+    /// Telemetry frame fields backed by a working NVML query
+    pub metrics: Vec<String>,
+    /// The driver can set fan speeds manually and the device has fans
+    pub fan_control: bool,
+    pub power_control: bool,
+    pub ecc: bool,
+    pub nvlink: bool,
+}
+
+/// Driver, library and environment information for the whole system
+#[derive(Serialize, Clone, Debug)]
+pub struct SystemInfo {
+    pub driver_version: String,
+    pub nvml_version: String,
+    /// CUDA version supported by the driver, e.g. "12.2"
+    pub cuda_driver_version: String,
+    pub device_count: u32,
+    pub virtualization: VirtualizationInfo,
+}
+
+/// Run blocking NVML work on the blocking thread pool
+/// 
+/// NVML calls block the calling thread; running them here keeps the async
+/// runtime free to serve other commands and events.
+/// 
+/// # Arguments
+/// * `work` - Closure performing the NVML calls
+/// 
+/// # Returns
+/// * `Result<T>` - Result of the closure, or error if it panicked
+pub async fn blocking<T, F>(work: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work).await.context("NVML task panicked")?
+}
+
 /// Get current timestamp in milliseconds since Unix epoch
 /// 
 /// Returns the current system time as milliseconds for telemetry timestamping.
-fn now_ms() -> u128 {
+pub fn now_ms() -> u128 {
     SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()
 }
 
+// Origin of the monotonic clock, fixed by `start_monotonic_clock` or the first read
+static MONOTONIC_ORIGIN: OnceLock<Instant> = OnceLock::new();
+
+/// Fix the origin of the monotonic clock
+/// 
+/// Called at startup, so every frame sampled afterwards reads a positive
+/// `monotonic_ms`; a zero value marks frames recorded without the clock.
+pub fn start_monotonic_clock() {
+    MONOTONIC_ORIGIN.get_or_init(Instant::now);
+}
+
+/// Get milliseconds elapsed on a monotonic clock
+/// 
+/// Unlike `now_ms`, never jumps when the system time is adjusted (e.g. by
+/// NTP), so it orders and spaces samples reliably. The origin is fixed per
+/// process; `ClockMapping` relates it to wall-clock time.
+pub fn monotonic_ms() -> f64 {
+    MONOTONIC_ORIGIN.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+}
+
+/// Wall-clock and monotonic readings taken at the same moment
+/// 
+/// Stored with recordings so monotonic sample times can be placed on the
+/// wall clock as it read when the recording started.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ClockMapping {
+    pub wall_clock_ms: u128,
+    pub monotonic_ms: f64,
+}
+
+impl ClockMapping {
+    /// Read both clocks now
+    pub fn capture() -> Self {
+        ClockMapping { monotonic_ms: monotonic_ms(), wall_clock_ms: now_ms() }
+    }
+
+    /// Wall-clock time of a monotonic reading, free of later clock adjustments
+    pub fn wall_clock_at(&self, monotonic_ms: f64) -> u128 {
+        (self.wall_clock_ms as f64 + (monotonic_ms - self.monotonic_ms)).round().max(0.0) as u128
+    }
+}
+
 /// Enumerate all available NVIDIA GPU devices
 /// 
 /// Discovers and returns a list of all NVIDIA GPU devices available
@@ -116,29 +365,76 @@ pub fn list_devices(nvml: &Nvml) -> Result<Vec<Device<'_>>> {
     (0..count).map(|i| nvml.device_by_index(i)).collect::<Result<Vec<_>, _>>().map_err(Into::into)
 }
 
+/// Look up a single GPU device by index
+/// 
+/// Validates the index against the number of devices NVML reports so callers
+/// get a descriptive error instead of a raw NVML invalid-argument failure.
+/// 
+/// # Arguments
+/// * `nvml` - Initialized NVML instance
+/// * `device_index` - Zero-based device index
+/// 
+/// # Returns
+/// * `Result<Device>` - The requested device or error if the index is out of range
+pub fn device_at(nvml: &Nvml, device_index: u32) -> Result<Device<'_>> {
+    let count = nvml.device_count().context("Failed to get device count")?;
+    check_device_index(device_index, count)?;
+    nvml.device_by_index(device_index)
+        .with_context(|| format!("Failed to get GPU device {}", device_index))
+}
+
+// Validate a device index against the number of available devices
+fn check_device_index(device_index: u32, device_count: u32) -> Result<()> {
+    if device_count == 0 {
+        return Err(AppError::NvmlUnavailable("No NVIDIA GPUs found".to_string()).into());
+    }
+    if device_index >= device_count {
+        return Err(AppError::InvalidArgument(format!(
+            "GPU device index {} is out of range ({} device{} available)",
+            device_index,
+            device_count,
+            if device_count == 1 { "" } else { "s" }
+        )).into());
+    }
+    Ok(())
+}
+
 /// Retrieve comprehensive GPU information and current telemetry
 /// 
 /// Gathers complete GPU device information including hardware specifications
-/// and current performance telemetry for all available devices.
+/// for all available devices, plus current telemetry for the selected device.
+/// 
+/// # Arguments
+/// * `device_index` - Device to sample current telemetry from (defaults to 0)
 /// 
 /// # Returns
 /// * `Result<GPUInfo>` - Complete GPU information or error if collection fails
-pub async fn get_gpu_info() -> Result<GPUInfo> {
+pub async fn get_gpu_info(device_index: Option<u32>) -> Result<GPUInfo> {
+    blocking(move || get_gpu_info_blocking(device_index)).await
+}
+
+fn get_gpu_info_blocking(device_index: Option<u32>) -> Result<GPUInfo> {
     let nvml = Nvml::init().context("Failed to initialize NVML")?;
     let devices = list_devices(&nvml).context("Failed to enumerate GPU devices")?;
+    let telemetry_index = device_index.unwrap_or(0);
+    if device_index.is_some() {
+        check_device_index(telemetry_index, devices.len() as u32)?;
+    }
     
     let mut gpu_devices = Vec::new();
     let mut current_telemetry = None;
     
-    for (index, device) in devices.iter().enumerate() {
-        let gpu_device = create_gpu_device_info(device, index as u32)
+    for (index, device) in devices.into_iter().enumerate() {
+        let gpu_device = create_gpu_device_info(&device, index as u32)
             .with_context(|| format!("Failed to create device info for GPU {}", index))?;
         gpu_devices.push(gpu_device);
         
-        // Get current telemetry for the first device
-        if index == 0 {
-            current_telemetry = Some(create_simple_telemetry_frame(device, index as u32)
-                .context("Failed to create initial telemetry frame")?);
+        // Get current telemetry for the selected device
+        if index as u32 == telemetry_index {
+            let frame = DeviceSampler::new(device, index as u32)
+                .and_then(|sampler| sampler.sample())
+                .context("Failed to create initial telemetry frame")?;
+            current_telemetry = Some(frame);
         }
     }
     
@@ -164,7 +460,7 @@ fn create_gpu_device_info(device: &Device, index: u32) -> Result<GPUDevice> {
     let uuid = device.uuid()?.to_string();
     let pci_info = format!("{:?}", device.pci_info()?);
     let memory_info = device.memory_info()?;
-    let memory_total_mb = (memory_info.total / (1024 * 1024)) as u64;
+    let memory_total_mb = memory_info.total / (1024 * 1024);
     
     // Get compute capability
     let compute_capability = format!("{}.{}", 
@@ -198,7 +494,7 @@ fn create_gpu_device_info(device: &Device, index: u32) -> Result<GPUDevice> {
 }
 
 // Estimate GPU specifications based on name
-fn estimate_gpu_specs(name: &str) -> (u32, u32) {
+pub fn estimate_gpu_specs(name: &str) -> (u32, u32) {
     // This is a simplified estimation - in a real app you'd have a database
     if name.contains("RTX 4090") {
         (128, 128) // 128 SMs, 128 cores per SM
@@ -245,16 +541,14 @@ fn estimate_memory_bus_width(name: &str) -> u32 {
     }
 }
 
-// Get detailed GPU architecture information
-pub async fn get_detailed_gpu_info() -> Result<GPUArchitecture> {
-    let nvml = Nvml::init()?;
-    let devices = list_devices(&nvml)?;
-    
-    if devices.is_empty() {
-        return Err(anyhow::anyhow!("No GPU devices found"));
-    }
-    
-    let device = &devices[0]; // Use first device
+// Get detailed GPU architecture information for the given device (defaults to 0)
+pub async fn get_detailed_gpu_info(device_index: Option<u32>) -> Result<GPUArchitecture> {
+    blocking(move || get_detailed_gpu_info_blocking(device_index)).await
+}
+
+fn get_detailed_gpu_info_blocking(device_index: Option<u32>) -> Result<GPUArchitecture> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let device = device_at(&nvml, device_index.unwrap_or(0))?;
     let name = device.name()?;
     let memory_info = device.memory_info()?;
     let compute_capability = device.cuda_compute_capability()?;
@@ -285,6 +579,119 @@ pub async fn get_detailed_gpu_info() -> Result<GPUArchitecture> {
     })
 }
 
+/// Query streaming capabilities for all devices
+/// 
+/// Probes each device once for the metrics that make up a telemetry frame
+/// and for optional control features (power limits, ECC, NVLink).
+/// 
+/// # Returns
+/// * `Result<StreamCapabilities>` - Capability report or error if NVML is unavailable
+pub async fn get_stream_capabilities() -> Result<StreamCapabilities> {
+    blocking(get_stream_capabilities_blocking).await
+}
+
+fn get_stream_capabilities_blocking() -> Result<StreamCapabilities> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let devices = list_devices(&nvml).context("Failed to enumerate GPU devices")?;
+    
+    let devices = devices.iter().enumerate()
+        .map(|(index, device)| probe_device_features(device, index as u32))
+        .collect();
+    
+    Ok(StreamCapabilities {
+        min_period_ms: MIN_STREAM_PERIOD_MS,
+        min_burst_period_ms: burst::MIN_BURST_PERIOD_MS,
+        devices,
+    })
+}
+
+/// Get driver versions and the virtualization environment
+/// 
+/// Version queries that fail (common in guests) are reported as "unknown"
+/// rather than failing the whole call.
+/// 
+/// # Returns
+/// * `Result<SystemInfo>` - System information or error if NVML is unavailable
+pub async fn get_system_info() -> Result<SystemInfo> {
+    blocking(get_system_info_blocking).await
+}
+
+fn get_system_info_blocking() -> Result<SystemInfo> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let device_count = nvml.device_count().context("Failed to get device count")?;
+    let devices: Vec<Device> = (0..device_count)
+        .filter_map(|index| nvml.device_by_index(index).ok())
+        .collect();
+    let gpu_names: Vec<String> = devices.iter().filter_map(|device| device.name().ok()).collect();
+    let reported_modes: Vec<_> = devices.iter().map(virtualization::query_mode).collect();
+    
+    let cuda_driver_version = nvml.sys_cuda_driver_version()
+        .map(|version| format!(
+            "{}.{}",
+            nvml_wrapper::cuda_driver_version_major(version),
+            nvml_wrapper::cuda_driver_version_minor(version)
+        ))
+        .unwrap_or_else(|_| "unknown".to_string());
+    
+    Ok(SystemInfo {
+        driver_version: nvml.sys_driver_version().unwrap_or_else(|_| "unknown".to_string()),
+        nvml_version: nvml.sys_nvml_version().unwrap_or_else(|_| "unknown".to_string()),
+        cuda_driver_version,
+        device_count,
+        virtualization: virtualization::detect(&gpu_names, &reported_modes),
+    })
+}
+
+// Probe which telemetry metrics and control features a device supports
+fn probe_device_features(device: &Device, index: u32) -> DeviceFeatureSupport {
+    use nvml_wrapper::enum_wrappers::device::{Clock, TemperatureSensor};
+    
+    let utilization = device.utilization_rates().is_ok();
+    let memory = device.memory_info().is_ok();
+    let probes: [(&str, bool); 16] = [
+        ("util_gpu", utilization),
+        ("util_memory", utilization),
+        ("memory_used_mb", memory),
+        ("sm_clock_mhz", device.clock_info(Clock::Graphics).is_ok()),
+        ("memory_clock_mhz", device.clock_info(Clock::Memory).is_ok()),
+        ("temperature_c", device.temperature(TemperatureSensor::Gpu).is_ok()),
+        ("power_w", device.power_usage().is_ok()),
+        ("fan_speed_percent", device.fan_speed(0).is_ok()),
+        ("fan_speeds_percent", device.num_fans().is_ok()),
+        ("target_fan_speeds_percent", crate::sampler::read_target_fan_speed(device, 0).is_ok()),
+        ("encoder_utilization", device.encoder_utilization().is_ok()),
+        ("decoder_utilization", device.decoder_utilization().is_ok()),
+        ("jpeg_utilization", crate::sampler::read_jpeg_utilization(device).is_ok()),
+        ("ofa_utilization", crate::sampler::read_ofa_utilization(device).is_ok()),
+        ("throttle_reasons", device.current_throttle_reasons().is_ok()),
+        ("performance_state", device.performance_state().is_ok()),
+    ];
+    let metrics = probes.iter()
+        .filter(|(_, supported)| *supported)
+        .map(|(metric, _)| metric.to_string())
+        .collect();
+    
+    let fan_control = crate::tuning::fan_control_supported() && device.num_fans().is_ok_and(|fans| fans > 0);
+    let power_control = device.power_management_limit_constraints()
+        .map(|limits| limits.max_limit > limits.min_limit)
+        .unwrap_or(false);
+    
+    DeviceFeatureSupport {
+        index,
+        name: device.name().unwrap_or_else(|_| format!("GPU {}", index)),
+        metrics,
+        fan_control,
+        power_control,
+        ecc: device.is_ecc_enabled().is_ok(),
+        nvlink: device.link_wrapper_for(0).is_active().is_ok(),
+    }
+}
+
+// Clamp a requested streaming period to the enforced minimum
+fn effective_period_ms(period_ms: u64) -> u64 {
+    period_ms.max(MIN_STREAM_PERIOD_MS)
+}
+
 // Estimate specialized cores based on GPU generation
 fn estimate_specialized_cores(name: &str) -> (u32, u32) {
     if name.contains("RTX 40") {
@@ -300,9 +707,7 @@ fn estimate_specialized_cores(name: &str) -> (u32, u32) {
 
 // Estimate memory type based on GPU generation
 fn estimate_memory_type(name: &str) -> String {
-    if name.contains("RTX 40") {
-        "GDDR6X".to_string()
-    } else if name.contains("RTX 30") {
+    if name.contains("RTX 40") || name.contains("RTX 30") {
         "GDDR6X".to_string()
     } else if name.contains("RTX 20") {
         "GDDR6".to_string()
@@ -314,7 +719,7 @@ fn estimate_memory_type(name: &str) -> String {
 /// Stream NVML telemetry data in real-time
 /// 
 /// Continuously collects and prints GPU telemetry data to stdout
-/// at the specified interval. Minimum update period is 50ms.
+/// at the specified interval. Minimum update period is `MIN_STREAM_PERIOD_MS`.
 /// 
 /// # Arguments
 /// * `period_ms` - Update interval in milliseconds (minimum 50ms)
@@ -322,40 +727,15 @@ fn estimate_memory_type(name: &str) -> String {
 /// # Returns
 /// * `Result<()>` - Success or error if streaming fails
 #[allow(dead_code)]
-pub async fn nvml_stream(mut period_ms: u64) -> Result<()> {
-    if period_ms < 50 {
-        period_ms = 50;
-    }
+pub async fn nvml_stream(period_ms: u64) -> Result<()> {
+    let period_ms = effective_period_ms(period_ms);
 
     let nvml = Nvml::init()?;
-    let devices = list_devices(&nvml)?;
+    let samplers = DeviceSampler::for_all_devices(&nvml)?;
 
     loop {
-        for (i, d) in devices.iter().enumerate() {
-            let util = d.utilization_rates()?;
-            let name = d.name()?;
-            let temp = d.temperature(nvml_wrapper::enum_wrappers::device::TemperatureSensor::Gpu)?;
-            let clocks = (d.clock_info(nvml_wrapper::enum_wrappers::device::Clock::Graphics)?,
-                          d.clock_info(nvml_wrapper::enum_wrappers::device::Clock::Memory)?);
-            let mem = d.memory_info()?;
-            let power = d.power_usage().unwrap_or(0) as f32 / 1000.0; // Convert mW to W
-            let frame = TelemetryFrame {
-                timestamp: now_ms(),
-                device_index: i as u32,
-                name: name.clone(),
-                util_gpu: util.gpu,
-                util_memory: util.memory,
-                memory_used_mb: (mem.used / (1024 * 1024)) as u64,
-                memory_total_mb: (mem.total / (1024 * 1024)) as u64,
-                sm_clock_mhz: clocks.0,
-                memory_clock_mhz: clocks.1,
-                temperature_c: temp,
-                power_w: power,
-                fan_speed_percent: d.fan_speed(0).unwrap_or(0),
-                sm_utilizations: vec![util.gpu as f32 / 100.0; 32], // Simplified
-                memory_bandwidth_gbps: estimate_memory_bandwidth(&name, util.memory),
-                pcie_utilization: ((util.gpu + util.memory) as f32 * 0.3) as u32,
-            };
+        for sampler in &samplers {
+            let frame = sampler.sample()?;
             println!("{}", serde_json::to_string(&frame)?);
         }
         tokio::time::sleep(std::time::Duration::from_millis(period_ms)).await;
@@ -363,145 +743,368 @@ pub async fn nvml_stream(mut period_ms: u64) -> Result<()> {
     
 }
 
+/// Settings for a telemetry stream
+#[derive(Clone, Debug, Default)]
+pub struct StreamConfig {
+    /// Update interval in milliseconds for devices without an override (minimum 50ms)
+    pub period_ms: u64,
+    /// Interval overrides keyed by device index, e.g. a slower rate for idle secondary cards
+    pub device_periods_ms: HashMap<u32, u64>,
+    /// Fill `deltas` in each frame against the device's previous frame
+    pub derived: bool,
+    /// Slow down or stop emitting for devices that stay idle
+    pub idle: Option<IdlePolicy>,
+    /// Metrics to read; the NVML calls for the rest are skipped
+    pub metrics: MetricSet,
+    /// Burst capture, which temporarily overrides every device's period
+    pub burst: Arc<BurstControl>,
+}
+
+impl StreamConfig {
+    /// Interval for a device after applying its override and the minimum period
+    pub fn period_for(&self, device_index: u32) -> u64 {
+        effective_period_ms(self.device_periods_ms.get(&device_index).copied().unwrap_or(self.period_ms))
+    }
+
+    /// Longest interval of any device, idle or not, which bounds how often frames arrive
+    pub fn longest_period_ms(&self) -> u64 {
+        self.device_periods_ms.values()
+            .chain([&self.period_ms])
+            .chain(self.idle.as_ref().map(|idle| &idle.idle_period_ms))
+            .map(|&period_ms| effective_period_ms(period_ms))
+            .max()
+            .unwrap_or(MIN_STREAM_PERIOD_MS)
+    }
+}
+
+/// Signals from the watchdog to a running stream
+pub struct StreamControl {
+    /// Stops the stream
+    pub cancel: CancellationToken,
+    /// Devices whose task should be restarted because they stalled
+    pub restarts: tokio::sync::mpsc::UnboundedReceiver<u32>,
+}
+
 /// Enhanced streaming function with broadcast channel and Tauri integration
 /// 
 /// Streams telemetry data via broadcast channel and Tauri events for frontend updates.
-/// Supports graceful shutdown through the is_streaming flag.
+/// Each device is sampled by its own task at its own interval; all tasks
+/// feed the same channel. Exits promptly once the cancellation token is
+/// triggered, even mid-sleep.
+/// 
+/// Devices are re-enumerated every `ENUMERATION_INTERVAL`: new devices
+/// start streaming and are announced with `device-added` (their static
+/// info), devices that are gone stop and are announced with `device-removed`.
+/// A device whose index arrives on `control.restarts` has its task replaced,
+/// leaving the other devices streaming.
 /// 
 /// # Arguments
-/// * `period_ms` - Update interval in milliseconds (minimum 50ms)
+/// * `config` - Intervals and derived-field settings
 /// * `sender` - Broadcast channel sender for telemetry data
-/// * `is_streaming` - Shared flag to control streaming lifecycle
+/// * `device_info` - Static info of the streamed devices, kept current as devices come and go
+/// * `history` - Rolling histories each frame is recorded into
+/// * `stats` - Counters updated with every frame and device change
+/// * `control` - Stop and device restart signals
 /// * `window` - Tauri window handle for frontend events
 /// 
 /// # Returns
-/// * `Result<()>` - Success or error if streaming fails
+/// * `Result<()>` - Success or error if streaming a device that is still present fails
 pub async fn nvml_stream_with_broadcast(
-    mut period_ms: u64,
+    config: StreamConfig,
     sender: broadcast::Sender<TelemetryFrame>,
-    is_streaming: Arc<Mutex<bool>>,
+    device_info: Arc<Mutex<Vec<StaticDeviceInfo>>>,
+    history: StreamHistory,
+    stats: Arc<StreamStats>,
+    control: StreamControl,
     window: Window,
 ) -> Result<()> {
-    if period_ms < 50 {
-        period_ms = 50;
+    let StreamControl { cancel, mut restarts } = control;
+    let device_count = blocking(|| {
+        let nvml = Nvml::init().context("Failed to initialize NVML")?;
+        nvml.device_count().context("Failed to get device count")
+    }).await?;
+    for &index in config.device_periods_ms.keys().filter(|&&index| index >= device_count) {
+        eprintln!("Ignoring streaming interval for unknown GPU {}", index);
     }
 
-    let nvml = Nvml::init()?;
-    let devices = list_devices(&nvml)?;
+    let mut samplers = Vec::with_capacity(device_count as usize);
+    for index in 0..device_count {
+        samplers.push(SamplingThread::start_with_metrics(index, config.metrics).await?);
+    }
+    
+    // Publish static device info once; frames only carry the device index
+    let static_info: Vec<StaticDeviceInfo> = samplers.iter()
+        .flat_map(|sampler| sampler.devices().iter().cloned())
+        .collect();
+    if let Err(e) = window.emit("device-info", &static_info) {
+        eprintln!("Failed to emit device info event: {}", e);
+    }
+    *device_info.lock().await = static_info;
 
-    println!("Started NVML streaming with {} devices", devices.len());
+    let sink = FrameSink { sender, history, stats, window };
+    let mut streams = DeviceStreams {
+        tasks: tokio::task::JoinSet::new(),
+        running: HashMap::new(),
+        config,
+        sink,
+        device_info,
+        cancel: cancel.clone(),
+    };
+    for sampler in samplers {
+        streams.start(sampler);
+    }
 
+    let mut rescan = tokio::time::interval(enumeration::ENUMERATION_INTERVAL);
+    rescan.tick().await;
     loop {
-        // Check if we should continue streaming
-        {
-            let streaming = is_streaming.lock().await;
-            if !*streaming {
-                println!("NVML streaming stopped");
-                break;
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = rescan.tick() => streams.reconcile().await,
+            Some(device_index) = restarts.recv() => streams.restart(device_index).await,
+            Some(joined) = streams.tasks.join_next() => {
+                let (device, result) = match joined {
+                    Ok(ended) => ended,
+                    Err(e) if e.is_cancelled() => continue,
+                    Err(e) => return Err(e).context("Device streaming task panicked"),
+                };
+                let Err(e) = result else { continue };
+                // Sampling fails once a device is unplugged; only a device
+                // that is still present ends the whole stream
+                streams.reconcile().await;
+                if streams.running.get(&device.uuid).is_some_and(|(running, _)| *running == device) {
+                    return Err(e);
+                }
             }
         }
+    }
+    while streams.tasks.join_next().await.is_some() {}
+    
+    println!("NVML streaming stopped");
+    Ok(())
+}
 
-        // Collect telemetry from all devices
-        for (i, device) in devices.iter().enumerate() {
-            let util = device.utilization_rates()?;
-            let name = device.name()?;
-            let temp = device.temperature(nvml_wrapper::enum_wrappers::device::TemperatureSensor::Gpu)?;
-            let clocks = (device.clock_info(nvml_wrapper::enum_wrappers::device::Clock::Graphics)?,
-                          device.clock_info(nvml_wrapper::enum_wrappers::device::Clock::Memory)?);
-            let mem = device.memory_info()?;
-            let power = device.power_usage().unwrap_or(0) as f32 / 1000.0;
-            
-            let frame = TelemetryFrame {
-                timestamp: now_ms(),
-                device_index: i as u32,
-                name: name.clone(),
-                util_gpu: util.gpu,
-                util_memory: util.memory,
-                memory_used_mb: (mem.used / (1024 * 1024)) as u64,
-                memory_total_mb: (mem.total / (1024 * 1024)) as u64,
-                sm_clock_mhz: clocks.0,
-                memory_clock_mhz: clocks.1,
-                temperature_c: temp,
-                power_w: power,
-                fan_speed_percent: device.fan_speed(0).unwrap_or(0),
-                sm_utilizations: generate_sm_utilizations(util.gpu, estimate_gpu_specs(&name).0),
-                memory_bandwidth_gbps: estimate_memory_bandwidth(&name, util.memory),
-                pcie_utilization: ((util.gpu + util.memory) as f32 * 0.3) as u32,
+// Per-device stream tasks, started and stopped as devices come and go
+struct DeviceStreams {
+    tasks: tokio::task::JoinSet<(enumeration::DeviceIdentity, Result<()>)>,
+    /// Running tasks keyed by device UUID
+    running: HashMap<String, (enumeration::DeviceIdentity, tokio::task::AbortHandle)>,
+    config: StreamConfig,
+    sink: FrameSink,
+    device_info: Arc<Mutex<Vec<StaticDeviceInfo>>>,
+    cancel: CancellationToken,
+}
+
+impl DeviceStreams {
+    fn start(&mut self, sampler: SamplingThread) {
+        let info = &sampler.devices()[0];
+        let device = enumeration::DeviceIdentity { index: info.index, uuid: info.uuid.clone() };
+        let period_ms = self.config.period_for(device.index);
+        println!("Streaming GPU {} every {} ms ({} queries per tick)", device.index, period_ms, sampler.queries_per_sample());
+        let idle = self.config.idle.clone().map(|policy| IdleDetector::new(policy, period_ms));
+        let task = stream_device(sampler, period_ms, self.config.derived, idle, self.config.burst.clone(), self.sink.clone(), self.cancel.clone());
+        let identity = device.clone();
+        let handle = self.tasks.spawn(async move { (identity, task.await) });
+        self.running.insert(device.uuid.clone(), (device, handle));
+    }
+
+    // Replace a stalled device's task; a task blocked inside NVML ignores
+    // cancellation, so abort detaches it along with its sampling thread
+    async fn restart(&mut self, device_index: u32) {
+        let Some(uuid) = self.running.iter()
+            .find(|(_, (device, _))| device.index == device_index)
+            .map(|(uuid, _)| uuid.clone()) else { return };
+        if let Some((_, handle)) = self.running.remove(&uuid) {
+            handle.abort();
+        }
+        match SamplingThread::start_with_metrics(device_index, self.config.metrics).await {
+            Ok(sampler) => self.start(sampler),
+            // Picked up as an added device on the next enumeration
+            Err(e) => eprintln!("Failed to restart streaming GPU {}: {:#}", device_index, e),
+        }
+    }
+
+    // Stop streaming removed devices and start streaming added ones
+    async fn reconcile(&mut self) {
+        let current = match blocking(enumeration::enumerate).await {
+            Ok(current) => current,
+            Err(e) => {
+                eprintln!("Device re-enumeration failed: {:#}", e);
+                return;
+            }
+        };
+        let known: Vec<enumeration::DeviceIdentity> = self.running.values().map(|(device, _)| device.clone()).collect();
+        let changes = enumeration::diff(&known, &current);
+        if changes.is_empty() {
+            return;
+        }
+
+        for device in changes.removed {
+            if let Some((_, handle)) = self.running.remove(&device.uuid) {
+                handle.abort();
+            }
+            self.sink.stats.remove_device(device.index);
+            self.device_info.lock().await.retain(|info| info.uuid != device.uuid);
+            println!("GPU {} ({}) removed", device.index, device.uuid);
+            if let Err(e) = self.sink.window.emit("device-removed", &device) {
+                eprintln!("Failed to emit device removed event: {}", e);
+            }
+        }
+        for device in changes.added {
+            let sampler = match SamplingThread::start_with_metrics(device.index, self.config.metrics).await {
+                Ok(sampler) => sampler,
+                Err(e) => {
+                    // Retried on the next enumeration
+                    eprintln!("Failed to start streaming added GPU {}: {:#}", device.index, e);
+                    continue;
+                }
             };
-            
-            // Send to broadcast channel
-            if let Err(_) = sender.send(frame.clone()) {
-                // No receivers, but continue
+            let info = sampler.devices()[0].clone();
+            {
+                let mut device_info = self.device_info.lock().await;
+                device_info.push(info.clone());
+                device_info.sort_by_key(|info| info.index);
             }
-            
-            // Send to frontend via Tauri event
-            if let Err(e) = window.emit("telemetry-update", &frame) {
+            if let Err(e) = self.sink.window.emit("device-added", &info) {
+                eprintln!("Failed to emit device added event: {}", e);
+            }
+            self.start(sampler);
+        }
+    }
+}
+
+/// Rolling histories of streamed frames, kept after the stream stops
+/// 
+/// Each answers queries about the recent past without the frontend keeping
+/// frames itself.
+#[derive(Clone, Default)]
+pub struct StreamHistory {
+    pub residency: Arc<Mutex<ResidencyHistory>>,
+    pub heatmap: Arc<Mutex<SmHeatmapHistory>>,
+    pub rankings: Arc<Mutex<RankingHistory>>,
+}
+
+impl StreamHistory {
+    async fn record(&self, frame: &TelemetryFrame) {
+        self.residency.lock().await.record(frame);
+        self.heatmap.lock().await.record(frame);
+        self.rankings.lock().await.record(frame);
+    }
+}
+
+// Destinations every per-device stream task publishes frames to
+#[derive(Clone)]
+struct FrameSink {
+    sender: broadcast::Sender<TelemetryFrame>,
+    history: StreamHistory,
+    stats: Arc<StreamStats>,
+    window: Window,
+}
+
+impl FrameSink {
+    // Frames always reach the channel, which the watchdog and subscribers
+    // read; `emit` controls only the frontend event
+    async fn publish(&self, frame: TelemetryFrame, emit: bool) {
+        self.history.record(&frame).await;
+        self.stats.record_frame(emit);
+        
+        // Send to broadcast channel
+        // No receivers is fine, keep streaming
+        let _ = self.sender.send(frame.clone());
+        
+        // Send to frontend via Tauri event
+        if emit {
+            let started = Instant::now();
+            let emitted = self.window.emit("telemetry-update", &frame);
+            overhead::record_event("telemetry-update", started.elapsed());
+            if let Err(e) = emitted {
                 eprintln!("Failed to emit telemetry event: {}", e);
             }
         }
+    }
+}
 
-        tokio::time::sleep(std::time::Duration::from_millis(period_ms)).await;
+// Sample one device at its own interval until cancelled, slowing down
+// while the idle detector reports it idle and speeding up during a burst
+async fn stream_device(
+    sampler: SamplingThread,
+    mut period_ms: u64,
+    derived: bool,
+    mut idle: Option<IdleDetector>,
+    burst: Arc<BurstControl>,
+    sink: FrameSink,
+    cancel: CancellationToken,
+) -> Result<()> {
+    // Previous frame, only kept when derived fields are requested
+    let mut previous: Option<TelemetryFrame> = None;
+    let device_index = sampler.devices()[0].index;
+    sink.stats.set_period(device_index, period_ms);
+
+    while !cancel.is_cancelled() {
+        let tick_started = Instant::now();
+        let frames = tokio::select! {
+            _ = cancel.cancelled() => break,
+            frames = sampler.sample() => frames?,
+        };
+        burst.record(device_index, tick_started.elapsed());
+        for mut frame in frames {
+            if derived {
+                frame.deltas = previous.as_ref().and_then(|prev| FrameDeltas::between(prev, &frame));
+                previous = Some(frame.clone());
+            }
+            let transition = idle.as_mut().and_then(|detector| detector.observe(&frame));
+            // The first busy frame is emitted along with the resume event
+            sink.publish(frame, idle.as_ref().is_none_or(IdleDetector::emitting)).await;
+            if let Some(transition) = transition {
+                period_ms = transition.period_ms;
+                let event = if transition.idle { "stream-suspended" } else { "stream-resumed" };
+                if let Err(e) = sink.window.emit(event, &transition) {
+                    eprintln!("Failed to emit {} event: {}", event, e);
+                }
+            }
+        }
+
+        if let Some(status) = burst.finish_if_expired() {
+            if let Err(e) = sink.window.emit("burst-finished", &status) {
+                eprintln!("Failed to emit burst finished event: {}", e);
+            }
+        }
+        // A burst counts the sampling time against its period so the
+        // achieved rate stays close to the requested one
+        let pause = match burst.period_ms() {
+            Some(burst_period_ms) => {
+                sink.stats.set_period(device_index, burst_period_ms);
+                std::time::Duration::from_millis(burst_period_ms).saturating_sub(tick_started.elapsed())
+            }
+            None => {
+                sink.stats.set_period(device_index, period_ms);
+                std::time::Duration::from_millis(period_ms)
+            }
+        };
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(pause) => {}
+        }
     }
-    
     Ok(())
 }
 
 // Generate per-SM utilization data (simulated)
-fn generate_sm_utilizations(overall_util: u32, sm_count: u32) -> Vec<f32> {
+pub fn generate_sm_utilizations(overall_util: u32, sm_count: u32) -> Vec<f32> {
     let mut utilizations = Vec::with_capacity(sm_count as usize);
     let base_util = overall_util as f32 / 100.0;
     
     for i in 0..sm_count {
         // Add some variance to make it realistic using a deterministic pattern
         let variance = (i as f32 * 0.1).sin() * 0.2 + ((i * 17) % 100) as f32 / 500.0 - 0.1;
-        let sm_util = (base_util + variance).max(0.0).min(1.0);
+        let sm_util = (base_util + variance).clamp(0.0, 1.0);
         utilizations.push(sm_util);
     }
     
     utilizations
 }
 
-// Create a simple telemetry frame for current implementation
-fn create_simple_telemetry_frame(device: &Device, index: u32) -> Result<TelemetryFrame> {
-    let util = device.utilization_rates()?;
-    let name = device.name()?;
-    let temp = device.temperature(nvml_wrapper::enum_wrappers::device::TemperatureSensor::Gpu)?;
-    let clocks = (
-        device.clock_info(nvml_wrapper::enum_wrappers::device::Clock::Graphics)?,
-        device.clock_info(nvml_wrapper::enum_wrappers::device::Clock::Memory)?
-    );
-    let mem = device.memory_info()?;
-    let power = device.power_usage().unwrap_or(0) as f32 / 1000.0;
-    let fan_speed = device.fan_speed(0).unwrap_or(0);
-    
-    // Generate per-SM utilization (simulated for now)
-    let (sm_count, _) = estimate_gpu_specs(&name);
-    let sm_utilizations = generate_sm_utilizations(util.gpu, sm_count);
-    
-    // Calculate memory bandwidth (estimated)
-    let memory_bandwidth = estimate_memory_bandwidth(&name, util.memory);
-    
-    Ok(TelemetryFrame {
-        timestamp: now_ms(),
-        device_index: index,
-        name,
-        util_gpu: util.gpu,
-        util_memory: util.memory,
-        memory_used_mb: (mem.used / (1024 * 1024)) as u64,
-        memory_total_mb: (mem.total / (1024 * 1024)) as u64,
-        sm_clock_mhz: clocks.0,
-        memory_clock_mhz: clocks.1,
-        temperature_c: temp,
-        power_w: power,
-        fan_speed_percent: fan_speed,
-        sm_utilizations,
-        memory_bandwidth_gbps: memory_bandwidth,
-        pcie_utilization: estimate_pcie_utilization(util.gpu, util.memory),
-    })
-}
-
 // Estimate memory bandwidth based on GPU and utilization
-fn estimate_memory_bandwidth(name: &str, memory_util: u32) -> f32 {
+pub fn estimate_memory_bandwidth(name: &str, memory_util: u32) -> f32 {
     let max_bandwidth = if name.contains("RTX 4090") {
         1008.0 // GB/s
     } else if name.contains("RTX 4080") {
@@ -520,7 +1123,7 @@ fn estimate_memory_bandwidth(name: &str, memory_util: u32) -> f32 {
 }
 
 /// Estimate PCIe utilization
-fn estimate_pcie_utilization(gpu_util: u32, memory_util: u32) -> u32 {
+pub fn estimate_pcie_utilization(gpu_util: u32, memory_util: u32) -> u32 {
     // Simple heuristic: PCIe usage correlates with data movement
     ((gpu_util + memory_util) as f32 * 0.3) as u32
 }
@@ -536,6 +1139,26 @@ mod tests {
         assert!(timestamp > 1577836800000); // Jan 1, 2020 in ms
     }
     
+    #[test]
+    fn test_check_device_index() {
+        assert!(check_device_index(0, 1).is_ok());
+        assert!(check_device_index(1, 2).is_ok());
+        
+        let err = AppError::from(check_device_index(2, 2).unwrap_err());
+        assert_eq!(err.code(), "INVALID_ARGUMENT");
+        assert!(err.message().contains("out of range"), "unexpected error: {}", err);
+        
+        let err = check_device_index(0, 0).unwrap_err().to_string();
+        assert!(err.contains("No NVIDIA GPUs"), "unexpected error: {}", err);
+    }
+    
+    #[test]
+    fn test_effective_period_ms_enforces_minimum() {
+        assert_eq!(effective_period_ms(0), MIN_STREAM_PERIOD_MS);
+        assert_eq!(effective_period_ms(10), MIN_STREAM_PERIOD_MS);
+        assert_eq!(effective_period_ms(250), 250);
+    }
+    
     #[test]
     fn test_estimate_gpu_specs_rtx_4090() {
         let (sm_count, cores_per_sm) = estimate_gpu_specs("RTX 4090");
@@ -604,7 +1227,7 @@ mod tests {
         
         // All values should be between 0.0 and 1.0
         for util in utilizations {
-            assert!(util >= 0.0 && util <= 1.0);
+            assert!((0.0..=1.0).contains(&util));
         }
     }
     
@@ -612,26 +1235,214 @@ mod tests {
     fn test_telemetry_frame_serialization() {
         let frame = TelemetryFrame {
             timestamp: now_ms(),
+            monotonic_ms: monotonic_ms(),
             device_index: 0,
-            name: "Test GPU".to_string(),
             util_gpu: 50,
             util_memory: 60,
             memory_used_mb: 8192,
-            memory_total_mb: 24576,
             sm_clock_mhz: 1500,
             memory_clock_mhz: 7000,
             temperature_c: 65,
             power_w: 250.0,
             fan_speed_percent: 70,
+            fan_speeds_percent: vec![70, 72],
+            target_fan_speeds_percent: Some(vec![75, 75]),
+            engine_utilization: EngineUtilization {
+                graphics: 50,
+                encoder: Some(10),
+                decoder: None,
+                jpeg: None,
+                ofa: Some(5),
+            },
+            throttle_reasons: vec!["sw_power_cap".to_string()],
             sm_utilizations: vec![0.5, 0.6, 0.4],
+            sm_utilizations_measured: false,
             memory_bandwidth_gbps: 500.0,
             pcie_utilization: 30,
+            performance_state: Some(2),
+            deltas: None,
+            provider_metrics: BTreeMap::new(),
+            suspect_fields: Vec::new(),
+            interval_max: BTreeMap::new(),
         };
         
         // Should serialize without errors
         let serialized = serde_json::to_string(&frame);
         assert!(serialized.is_ok());
     }
+    
+    #[test]
+    fn test_stream_config_device_periods() {
+        let config = StreamConfig {
+            period_ms: 100,
+            device_periods_ms: HashMap::from([(1, 1_000), (2, 10)]),
+            derived: false,
+            idle: None,
+            metrics: MetricSet::all(),
+            burst: Arc::default(),
+        };
+        assert_eq!(config.period_for(0), 100);
+        assert_eq!(config.period_for(1), 1_000);
+        assert_eq!(config.period_for(2), MIN_STREAM_PERIOD_MS);
+        assert_eq!(config.longest_period_ms(), 1_000);
+        
+        let idle = IdlePolicy { idle_after_seconds: 60, utilization_threshold: 0, idle_period_ms: 5_000, suspend_emission: false };
+        assert_eq!(StreamConfig { idle: Some(idle), ..config }.longest_period_ms(), 5_000);
+    }
+    
+    #[tokio::test]
+    async fn test_blocking_returns_result_and_reports_panics() {
+        assert_eq!(blocking(|| Ok(7)).await.unwrap(), 7);
+        let err = blocking::<(), _>(|| panic!("NVML hang")).await.unwrap_err();
+        assert!(err.to_string().contains("NVML task panicked"));
+    }
+    
+    #[test]
+    fn test_frame_deltas_between() {
+        let previous = TelemetryFrame { timestamp: 1_000, memory_used_mb: 4096, power_w: 200.0, temperature_c: 60, ..Default::default() };
+        let current = TelemetryFrame { timestamp: 1_500, memory_used_mb: 3072, power_w: 250.0, temperature_c: 61, ..Default::default() };
+
+        let deltas = FrameDeltas::between(&previous, &current).unwrap();
+        assert_eq!(deltas.interval_ms, 500);
+        assert_eq!(deltas.memory_used_delta_mb, -1024);
+        assert_eq!(deltas.power_slope_w_per_s, 100.0);
+        assert_eq!(deltas.temperature_rate_c_per_min, 120.0);
+        assert!(FrameDeltas::between(&current, &current).is_none());
+        assert!(FrameDeltas::between(&current, &previous).is_none());
+    }
+
+    #[test]
+    fn test_monotonic_time_survives_wall_clock_jumps() {
+        // The wall clock was stepped back 5 s between the samples
+        let previous = TelemetryFrame { timestamp: 10_000, monotonic_ms: 2_000.0, ..Default::default() };
+        let current = TelemetryFrame { timestamp: 5_100, monotonic_ms: 2_100.0, ..Default::default() };
+        assert_eq!(current.ms_since(&previous), 100.0);
+        assert_eq!(FrameDeltas::between(&previous, &current).unwrap().interval_ms, 100);
+
+        let mut recording = RecordingFile {
+            schema_version: schema::CURRENT_SCHEMA_VERSION,
+            device: StaticDeviceInfo::default(),
+            devices: Vec::new(),
+            samples: Vec::new(),
+            markers: Vec::new(),
+            timing: None,
+            process: None,
+            violations: Vec::new(),
+            clock: Some(ClockMapping { wall_clock_ms: 9_000, monotonic_ms: 1_000.0 }),
+        };
+        assert_eq!(recording.steady_timestamp(&current), 10_100);
+        recording.clock = None;
+        assert_eq!(recording.steady_timestamp(&current), 5_100);
+        assert!(monotonic_ms() <= monotonic_ms());
+    }
+    
+    fn kernel(name: &str, duration_ms: f64) -> KernelAnalysis {
+        KernelAnalysis {
+            name: name.to_string(),
+            duration_ms,
+            grid_size: (1, 1, 1),
+            block_size: (128, 1, 1),
+            registers_per_thread: 32,
+            shared_memory_bytes: 0,
+            occupancy_percent: 50.0,
+            sm_efficiency: 80.0,
+            memory_efficiency: 70.0,
+            source_hotspots: Vec::new(),
+            memory_workload: None,
+        }
+    }
+    
+    fn report(kernels: Vec<KernelAnalysis>) -> NSightAnalysis {
+        NSightAnalysis {
+            report_type: "NSight Compute".to_string(),
+            gpu_name: "Test GPU".to_string(),
+            kernels,
+            bottlenecks: vec![],
+            recommendations: vec![],
+            performance_summary: PerformanceSummary {
+                total_gpu_time_ms: 0.0,
+                average_sm_utilization: 0.0,
+                memory_throughput_gbps: 0.0,
+                compute_throughput_percent: 0.0,
+                bottleneck_analysis: String::new(),
+            },
+            cpu_gpu_timeline: None,
+        }
+    }
+    
+    #[test]
+    fn test_aggregate_kernels_flags_unstable() {
+        let reports = vec![
+            report(vec![kernel("gemm", 2.0), kernel("reduce", 1.0)]),
+            report(vec![kernel("gemm", 2.0), kernel("reduce", 2.0)]),
+            report(vec![kernel("gemm", 2.0)]),
+        ];
+        
+        let aggregate = aggregate_kernels(&reports);
+        assert_eq!(aggregate.report_count, 3);
+        assert_eq!(aggregate.kernels[0].name, "gemm");
+        assert_eq!(aggregate.kernels[0].samples, 3);
+        assert_eq!(aggregate.kernels[0].duration_ms.stddev, 0.0);
+        assert_eq!(aggregate.kernels[1].duration_ms.mean, 1.5);
+        assert_eq!(aggregate.unstable_kernels, vec!["reduce".to_string()]);
+    }
+    
+    #[test]
+    fn test_aggregate_kernels_of_parsed_reports() {
+        let run = |gemm_us: &str| ncu::parse_kernels_csv(&format!(
+            "\"ID\",\"Kernel Name\",\"gpu__time_duration.sum\",\"sm__warps_active.avg.pct_of_peak_sustained_active\"\n\
+             \"\",\"\",\"usecond\",\"%\"\n\
+             \"0\",\"gemm\",\"{}\",\"40\"\n\
+             \"1\",\"gemm\",\"{}\",\"60\"\n",
+            gemm_us, gemm_us,
+        ));
+        let reports: Vec<NSightAnalysis> = ["1,000", "3,000"].into_iter()
+            .map(|gemm_us| NSightAnalysis { kernels: run(gemm_us).kernels, ..Default::default() })
+            .collect();
+        
+        let aggregate = aggregate_kernels(&reports);
+        assert_eq!((aggregate.report_count, aggregate.kernels.len()), (2, 1));
+        let gemm = &aggregate.kernels[0];
+        assert_eq!(gemm.samples, 4);
+        assert!((gemm.duration_ms.mean - 2.0).abs() < 1e-9);
+        assert_eq!(gemm.occupancy_percent.mean, 50.0);
+        assert_eq!(aggregate.unstable_kernels, vec!["gemm".to_string()]);
+    }
+    
+    #[test]
+    fn test_metric_stats() {
+        let stats = metric_stats(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(stats.mean, 5.0);
+        assert!((stats.stddev - 2.138).abs() < 1e-3);
+        assert_eq!((stats.min, stats.max), (2.0, 9.0));
+        assert_eq!(metric_stats(&[3.0]).stddev, 0.0);
+    }
+    
+    #[test]
+    fn test_sampling_timing() {
+        // 3 Hz does not divide 1000 ms evenly; the fourth tick was skipped
+        let timing = sampling_timing(3, &[0.0, 333.3, 666.7, 1000.0, 1666.7]);
+        assert_eq!(timing.requested_rate_hz, 3);
+        assert!((timing.achieved_rate_hz - 2.4).abs() < 1e-3);
+        assert_eq!(timing.missed_ticks, 1);
+        let intervals = timing.interval_ms.unwrap();
+        assert!((intervals.max - 666.7).abs() < 1e-3);
+        assert!(intervals.stddev > 0.0);
+        
+        let single = sampling_timing(10, &[0.0]);
+        assert_eq!((single.achieved_rate_hz, single.interval_ms, single.missed_ticks), (0.0, None, 0));
+    }
+    
+    #[test]
+    fn test_recording_progress_remaining_time() {
+        let progress = recording_progress("rec_1", 25, std::time::Duration::from_millis(2_500), std::time::Duration::from_secs(10), 4_096);
+        assert_eq!((progress.elapsed_seconds, progress.remaining_seconds), (2.5, 7.5));
+        assert_eq!((progress.samples_collected, progress.file_size_bytes), (25, 4_096));
+        
+        // A last sample taken after the planned end reports nothing remaining
+        let overdue = recording_progress("rec_1", 100, std::time::Duration::from_millis(10_020), std::time::Duration::from_secs(10), 0);
+        assert_eq!(overdue.remaining_seconds, 0.0);
+    }
 }
 
 /// Recording status information.
@@ -643,19 +1454,112 @@ pub struct RecordingStatus {
     pub elapsed_seconds: Option<u64>,
     pub sample_rate_hz: Option<u64>,
     pub metrics: Vec<String>,
+    /// Recorded devices, in sampling order
+    pub device_indices: Vec<u32>,
+    /// Frames collected across all devices
     pub samples_collected: u64,
+    /// Frames collected per device
+    pub samples_per_device: BTreeMap<u32, u64>,
     pub output_file: Option<String>,
 }
 
-/// NSight report analysis results.
+/// Payload of the periodic `recording-progress` event.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RecordingProgress {
+    pub session_id: String,
+    /// Frames collected across all devices
+    pub samples_collected: u64,
+    pub elapsed_seconds: f64,
+    pub remaining_seconds: f64,
+    /// Size of the recording journal so far, in bytes
+    pub file_size_bytes: u64,
+}
+
+/// Payload of the `recording-complete` event.
 #[derive(Serialize, Clone, Debug)]
+pub struct RecordingComplete {
+    pub session_id: String,
+    pub output_file: String,
+    pub sample_count: usize,
+    pub timing: Option<SamplingTiming>,
+    /// Summary of the primary device; `None` if nothing was sampled
+    pub summary: Option<analysis::RecordingAnalysis>,
+}
+
+/// On-disk layout of a recording session.
+/// 
+/// Older layouts are upgraded on load by `schema::load_recording`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordingFile {
+    pub schema_version: u32,
+    /// Primary (first) recorded device
+    pub device: StaticDeviceInfo,
+    /// Every recorded device, primary first
+    pub devices: Vec<StaticDeviceInfo>,
+    /// Frames of all devices interleaved in time order, told apart by `device_index`
+    pub samples: Vec<TelemetryFrame>,
+    /// NVTX ranges received while the recording ran
+    #[serde(default)]
+    pub markers: Vec<markers::MarkerRange>,
+    /// Sampling rate actually achieved; absent in recordings made before it was tracked
+    #[serde(default)]
+    pub timing: Option<SamplingTiming>,
+    /// Process a watch-mode recording was made for
+    #[serde(default)]
+    pub process: Option<watch::WatchedProcess>,
+    /// Time each device's clocks were held down during the recording
+    #[serde(default)]
+    pub violations: Vec<violations::SessionViolations>,
+    /// Clock readings at the start, relating `monotonic_ms` of the samples
+    /// to wall-clock time
+    #[serde(default)]
+    pub clock: Option<ClockMapping>,
+}
+
+/// Requested versus achieved sampling rate of a recording
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SamplingTiming {
+    pub requested_rate_hz: u64,
+    pub achieved_rate_hz: f64,
+    /// Time between consecutive samples; `stddev` is the sampling jitter
+    pub interval_ms: Option<MetricStats>,
+    /// Ticks skipped because a sample took longer than the interval
+    pub missed_ticks: u64,
+}
+
+impl RecordingFile {
+    /// Wall-clock time of a sample, measured on the monotonic clock
+    /// 
+    /// Stays in order when the system time jumped during the recording;
+    /// falls back to the sample's own timestamp in older recordings.
+    pub fn steady_timestamp(&self, frame: &TelemetryFrame) -> u128 {
+        match self.clock {
+            Some(clock) if frame.monotonic_ms > 0.0 => clock.wall_clock_at(frame.monotonic_ms),
+            _ => frame.timestamp,
+        }
+    }
+
+    /// Frames of one recorded device, in time order
+    pub fn samples_of(&self, device_index: u32) -> Vec<TelemetryFrame> {
+        self.samples.iter()
+            .filter(|frame| frame.device_index == device_index)
+            .cloned()
+            .collect()
+    }
+}
+
+/// NSight report analysis results.
+#[derive(Serialize, Clone, Debug, Default)]
 pub struct NSightAnalysis {
     pub report_type: String,
     pub gpu_name: String,
     pub kernels: Vec<KernelAnalysis>,
+    /// Bottleneck labels of the recommendations, in priority order
     pub bottlenecks: Vec<String>,
-    pub recommendations: Vec<String>,
+    pub recommendations: Vec<recommendations::Recommendation>,
     pub performance_summary: PerformanceSummary,
+    /// CPU threads and GPU activity with attributed GPU gaps, for NSight Systems reports
+    pub cpu_gpu_timeline: Option<nsys::CpuGpuTimeline>,
 }
 
 /// Individual kernel analysis from NSight report.
@@ -670,10 +1574,46 @@ pub struct KernelAnalysis {
     pub occupancy_percent: f64,
     pub sm_efficiency: f64,
     pub memory_efficiency: f64,
+    /// Hottest source lines by warp stalls; empty without source correlation data
+    pub source_hotspots: Vec<ncu::SourceLineStats>,
+    /// Memory Workload Analysis metrics, when the section was collected
+    pub memory_workload: Option<ncu::MemoryWorkload>,
 }
 
-/// Performance summary from NSight analysis.
+/// Kernel statistics merged across several NSight reports.
+#[derive(Serialize, Clone, Debug)]
+pub struct NSightAggregate {
+    pub report_count: usize,
+    /// Per-kernel statistics, slowest kernel first
+    pub kernels: Vec<KernelAggregate>,
+    /// Names of kernels whose duration varies more than `UNSTABLE_KERNEL_CV_PERCENT`
+    pub unstable_kernels: Vec<String>,
+}
+
+/// Statistics for one kernel across all launches in all reports.
 #[derive(Serialize, Clone, Debug)]
+pub struct KernelAggregate {
+    pub name: String,
+    pub samples: usize,
+    pub duration_ms: MetricStats,
+    pub occupancy_percent: MetricStats,
+    pub sm_efficiency: MetricStats,
+    pub memory_efficiency: MetricStats,
+    pub unstable: bool,
+}
+
+/// Summary statistics of one kernel metric.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MetricStats {
+    pub mean: f64,
+    /// Sample standard deviation (0 for a single sample)
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Performance summary from NSight analysis.
+#[derive(Serialize, Clone, Debug, Default)]
 pub struct PerformanceSummary {
     pub total_gpu_time_ms: f64,
     pub average_sm_utilization: f64,
@@ -682,14 +1622,37 @@ pub struct PerformanceSummary {
     pub bottleneck_analysis: String,
 }
 
+/// Duration coefficient of variation above which a kernel is flagged unstable
+pub const UNSTABLE_KERNEL_CV_PERCENT: f64 = 10.0;
+
+/// Directory recordings are saved to
+pub const RECORDING_DIR: &str = "recordings";
+
+/// How often `recording-progress` is emitted while recording
+pub const RECORDING_PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
 // Global recording state
 static RECORDING_STATE: std::sync::RwLock<Option<RecordingStatus>> = std::sync::RwLock::new(None);
 
+// Handle to the background recording task, used to wait for data to be flushed
+static RECORDING_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
 /// Start interval recording of GPU metrics.
+/// 
+/// Every device in `device_indices` (device 0 if empty) is sampled each
+/// interval. When `analyze` is set, an analysis summary is written next to
+/// the recording once it has been saved.
+/// 
+/// While recording, `recording-progress` is emitted every
+/// `RECORDING_PROGRESS_INTERVAL`; once the file is saved,
+/// `recording-complete` carries its path and summary.
 pub async fn start_interval_recording(
     duration_seconds: u64,
     sample_rate_hz: u64,
-    metrics: Vec<String>
+    metrics: Vec<String>,
+    device_indices: Vec<u32>,
+    analyze: bool,
+    window: Window,
 ) -> Result<String> {
     // Check if already recording
     {
@@ -701,8 +1664,24 @@ pub async fn start_interval_recording(
         }
     }
     
+    if sample_rate_hz == 0 {
+        return Err(AppError::InvalidArgument("Sample rate must be at least 1 Hz".to_string()).into());
+    }
+    
+    // Validate the target devices before committing to a session
+    let device_indices = if device_indices.is_empty() { vec![0] } else { device_indices };
+    if let Some(duplicate) = device_indices.iter().enumerate().find_map(|(i, index)| device_indices[..i].contains(index).then_some(index)) {
+        return Err(AppError::InvalidArgument(format!("GPU {} is listed more than once", duplicate)).into());
+    }
+    let validate = device_indices.clone();
+    blocking(move || {
+        let nvml = Nvml::init().context("Failed to initialize NVML")?;
+        validate.iter().try_for_each(|&index| device_at(&nvml, index).map(drop))
+    }).await?;
+    
     let session_id = format!("rec_{}", now_ms());
-    let output_file = format!("recordings/gpu_recording_{}.json", session_id);
+    let recording_id = session_id.clone();
+    let output_file = format!("{}/gpu_recording_{}.json", RECORDING_DIR, session_id);
     
     // Create recording status
     let recording_status = RecordingStatus {
@@ -712,7 +1691,9 @@ pub async fn start_interval_recording(
         elapsed_seconds: Some(0),
         sample_rate_hz: Some(sample_rate_hz),
         metrics: metrics.clone(),
+        device_indices: device_indices.clone(),
         samples_collected: 0,
+        samples_per_device: device_indices.iter().map(|&index| (index, 0)).collect(),
         output_file: Some(output_file.clone()),
     };
     
@@ -723,10 +1704,31 @@ pub async fn start_interval_recording(
     }
     
     // Start recording task
-    let _session_id_clone = session_id.clone();
-    tokio::spawn(async move {
-        if let Err(e) = run_interval_recording(duration_seconds, sample_rate_hz, metrics, output_file).await {
-            eprintln!("Recording error: {}", e);
+    let task = tokio::spawn(async move {
+        let recording_file = output_file.clone();
+        let session = session_id.clone();
+        match run_interval_recording(duration_seconds, sample_rate_hz, metrics, device_indices, output_file, session, &window).await {
+            Ok(recording) => {
+                let summary = if analyze {
+                    analysis::analyze_recording(std::path::Path::new(&recording_file))
+                } else {
+                    analysis::analyze(&recording, &recording_file)
+                };
+                if let Err(ref e) = summary {
+                    eprintln!("Recording analysis error: {:#}", e);
+                }
+                let complete = RecordingComplete {
+                    session_id,
+                    output_file: recording_file,
+                    sample_count: recording.samples.len(),
+                    timing: recording.timing,
+                    summary: summary.ok(),
+                };
+                if let Err(e) = window.emit("recording-complete", &complete) {
+                    eprintln!("Failed to emit recording complete event: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Recording error: {}", e),
         }
         
         // Clear recording state when done
@@ -735,8 +1737,9 @@ pub async fn start_interval_recording(
             *state = None;
         }
     });
+    *RECORDING_TASK.lock().unwrap() = Some(task);
     
-    Ok(session_id)
+    Ok(recording_id)
 }
 
 /// Stop interval recording.
@@ -748,16 +1751,28 @@ pub async fn stop_interval_recording() -> Result<String> {
                 status.is_recording = false;
                 status.output_file.clone().unwrap_or_default()
             } else {
-                return Err(anyhow::anyhow!("No active recording to stop"));
+                return Err(AppError::NotRecording("No active recording to stop".to_string()).into());
             }
         } else {
-            return Err(anyhow::anyhow!("No active recording to stop"));
+            return Err(AppError::NotRecording("No active recording to stop".to_string()).into());
         }
     };
     
     Ok(output_file)
 }
 
+/// Stop any active recording and wait until its data has been written.
+/// 
+/// Returns the output file of the finalized session, if one was running.
+pub async fn finalize_active_recording() -> Result<Option<String>> {
+    let output_file = stop_interval_recording().await.ok();
+    let task = RECORDING_TASK.lock().unwrap().take();
+    if let Some(task) = task {
+        task.await.context("Recording task ended abnormally")?;
+    }
+    Ok(output_file)
+}
+
 /// Get current recording status.
 pub async fn get_recording_status() -> Result<RecordingStatus> {
     let state = RECORDING_STATE.read().unwrap();
@@ -770,125 +1785,344 @@ pub async fn get_recording_status() -> Result<RecordingStatus> {
             elapsed_seconds: None,
             sample_rate_hz: None,
             metrics: vec![],
+            device_indices: vec![],
             samples_collected: 0,
+            samples_per_device: BTreeMap::new(),
             output_file: None,
         })
     }
 }
 
-/// Run the actual interval recording.
+/// Run the actual interval recording, returning what was saved.
 async fn run_interval_recording(
     duration_seconds: u64,
     sample_rate_hz: u64,
     _metrics: Vec<String>,
-    output_file: String
-) -> Result<()> {
+    device_indices: Vec<u32>,
+    output_file: String,
+    session_id: String,
+    window: &Window,
+) -> Result<RecordingFile> {
     // Create output directory if it doesn't exist
     if let Some(parent) = std::path::Path::new(&output_file).parent() {
         std::fs::create_dir_all(parent).context("Failed to create output directory")?;
     }
     
-    let interval_ms = 1000 / sample_rate_hz;
-    let total_samples = duration_seconds * sample_rate_hz;
+    // Resolve the devices once rather than re-initializing NVML per sample
+    let sampler = SamplingThread::start_devices(device_indices).await?;
+    let devices = sampler.devices().to_vec();
+    
+    // Throttled time is the growth of the violation counters over the session
+    let violation_devices: Vec<u32> = devices.iter().map(|device| device.index).collect();
+    let counted = violation_devices.clone();
+    let violations_at_start = blocking(move || violations::snapshot(&counted)).await.unwrap_or_default();
+    
+    // Journal frames as they arrive so a crash does not lose the session
+    let clock = ClockMapping::capture();
+    let mut journal = journal::RecordingJournal::create(&journal::JournalHeader {
+        session_id: session_id.clone(),
+        output_file: output_file.clone(),
+        sample_rate_hz,
+        devices: devices.clone(),
+        clock: Some(clock),
+    })?;
+    
+    // Ticks are scheduled from the start, so slow samples do not accumulate
+    // drift; ticks that cannot be met are skipped rather than bunched up
+    let period = std::time::Duration::from_secs_f64(1.0 / sample_rate_hz as f64);
+    let duration = std::time::Duration::from_secs(duration_seconds);
+    let mut ticker = tokio::time::interval(period);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
     let mut samples = Vec::new();
+    let mut sample_times_ms = Vec::new();
+    let mut last_progress: Option<tokio::time::Instant> = None;
     
     println!("Starting GPU recording: {}s at {}Hz -> {}", duration_seconds, sample_rate_hz, output_file);
     
-    for sample_idx in 0..total_samples {
-        let start_time = std::time::Instant::now();
+    let started = tokio::time::Instant::now();
+    loop {
+        let tick = ticker.tick().await;
+        if tick.duration_since(started) >= duration {
+            break;
+        }
+        sample_times_ms.push(started.elapsed().as_secs_f64() * 1000.0);
         
         // Collect telemetry sample
-        if let Ok(frame) = collect_telemetry_frame().await {
-            samples.push(frame);
+        let frames = sampler.sample().await.unwrap_or_default();
+        let sampled: Vec<u32> = frames.iter().map(|frame| frame.device_index).collect();
+        if let Err(e) = journal.append(&frames) {
+            eprintln!("Recording journal error: {:#}", e);
         }
+        samples.extend(frames);
         
         // Update recording status
-        {
+        let (samples_collected, stopped) = {
             let mut state = RECORDING_STATE.write().unwrap();
-            if let Some(ref mut status) = *state {
-                status.samples_collected = sample_idx + 1;
-                status.elapsed_seconds = Some(sample_idx / sample_rate_hz);
-                
-                // Check if recording was stopped externally
-                if !status.is_recording {
-                    break;
+            match *state {
+                Some(ref mut status) => {
+                    status.samples_collected += sampled.len() as u64;
+                    for device_index in sampled {
+                        *status.samples_per_device.entry(device_index).or_default() += 1;
+                    }
+                    status.elapsed_seconds = Some(started.elapsed().as_secs());
+                    (status.samples_collected, !status.is_recording)
                 }
+                None => (samples.len() as u64, false),
             }
+        };
+        
+        // Check if recording was stopped externally
+        if stopped {
+            break;
         }
         
-        // Wait for next sample
-        let elapsed = start_time.elapsed();
-        let target_duration = std::time::Duration::from_millis(interval_ms);
-        if elapsed < target_duration {
-            tokio::time::sleep(target_duration - elapsed).await;
+        if last_progress.is_none_or(|at| at.elapsed() >= RECORDING_PROGRESS_INTERVAL) {
+            last_progress = Some(tokio::time::Instant::now());
+            let progress = recording_progress(&session_id, samples_collected, started.elapsed(), duration, journal.bytes_written());
+            if let Err(e) = window.emit("recording-progress", &progress) {
+                eprintln!("Failed to emit recording progress: {}", e);
+            }
         }
     }
     
-    // Save recorded data
-    let json_data = serde_json::to_string_pretty(&samples)
+    // Save recorded data with the NVTX ranges that overlap it
+    let markers = match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) => markers::ranges_between(first.timestamp, last.timestamp),
+        _ => Vec::new(),
+    };
+    let violations_at_end = blocking(move || violations::snapshot(&violation_devices)).await.unwrap_or_default();
+    let recording = RecordingFile {
+        schema_version: schema::CURRENT_SCHEMA_VERSION,
+        device: devices[0].clone(),
+        devices,
+        samples,
+        markers,
+        timing: Some(sampling_timing(sample_rate_hz, &sample_times_ms)),
+        process: None,
+        violations: violations::session_violations(&violations_at_start, &violations_at_end),
+        clock: Some(clock),
+    };
+    let json_data = serde_json::to_string_pretty(&recording)
         .context("Failed to serialize recording data")?;
     std::fs::write(&output_file, json_data)
         .context("Failed to write recording file")?;
+    journal.finish()?;
     
-    println!("Recording completed: {} samples saved to {}", samples.len(), output_file);
-    Ok(())
+    println!("Recording completed: {} samples saved to {}", recording.samples.len(), output_file);
+    Ok(recording)
 }
 
-/// Collect a single telemetry frame
-async fn collect_telemetry_frame() -> Result<TelemetryFrame> {
-    let nvml = Nvml::init().context("Failed to initialize NVML")?;
-    let device_count = nvml.device_count().context("Failed to get device count")?;
-    
-    if device_count == 0 {
-        return Err(anyhow::anyhow!("No NVIDIA GPUs found"));
+// Progress of a recording `elapsed` into its planned `duration`
+fn recording_progress(
+    session_id: &str,
+    samples_collected: u64,
+    elapsed: std::time::Duration,
+    duration: std::time::Duration,
+    file_size_bytes: u64,
+) -> RecordingProgress {
+    RecordingProgress {
+        session_id: session_id.to_string(),
+        samples_collected,
+        elapsed_seconds: elapsed.as_secs_f64(),
+        remaining_seconds: duration.saturating_sub(elapsed).as_secs_f64(),
+        file_size_bytes,
     }
-    
-    let device = nvml.device_by_index(0).context("Failed to get GPU device")?;
-    create_simple_telemetry_frame(&device, 0)
 }
 
 /// Process NSight report file and extract performance insights.
+/// 
+/// NSight Compute reports yield one `KernelAnalysis` per kernel launch,
+/// with source hotspots and memory metrics when they were collected.
+/// NSight Systems reports yield a CPU/GPU timeline and no kernel metrics.
+/// 
+/// # Arguments
+/// * `file_path` - Path to a `.ncu-rep` or `.nsys-rep` file
+/// 
+/// # Returns
+/// * `Result<NSightAnalysis>` - Analysis or error if the report cannot be read
 pub async fn process_nsight_report(file_path: String) -> Result<NSightAnalysis> {
-    // Check if file exists
-    if !std::path::Path::new(&file_path).exists() {
-        return Err(anyhow::anyhow!("NSight report file not found: {}", file_path));
-    }
-    
-    // For now, return a mock analysis since actual NSight parsing is complex
-    // In a real implementation, this would parse the binary NSight format
-    let analysis = NSightAnalysis {
-        report_type: "NSight Compute".to_string(),
-        gpu_name: "RTX 4090".to_string(), // This would be parsed from the report
-        kernels: vec![
-            KernelAnalysis {
-                name: "example_kernel".to_string(),
-                duration_ms: 1.23,
-                grid_size: (256, 1, 1),
-                block_size: (256, 1, 1),
-                registers_per_thread: 32,
-                shared_memory_bytes: 4096,
-                occupancy_percent: 87.5,
-                sm_efficiency: 92.3,
-                memory_efficiency: 78.9,
+    let report_path = std::path::Path::new(&file_path);
+    if !report_path.exists() {
+        return Err(AppError::Io(format!("NSight report file not found: {}", file_path)).into());
+    }
+    
+    let mut analysis = match report_path.extension().and_then(|ext| ext.to_str()) {
+        Some("ncu-rep") => {
+            let report = ncu_report_kernels(report_path)?;
+            NSightAnalysis {
+                report_type: "NSight Compute".to_string(),
+                gpu_name: report.gpu_name.unwrap_or_default(),
+                kernels: with_launch_details(report_path, report.kernels),
+                ..Default::default()
             }
-        ],
-        bottlenecks: vec![
-            "Memory bandwidth limited".to_string(),
-            "Low occupancy in kernel_xyz".to_string(),
-        ],
-        recommendations: vec![
-            "Increase block size to improve occupancy".to_string(),
-            "Optimize memory access patterns".to_string(),
-            "Consider using shared memory for frequently accessed data".to_string(),
-        ],
-        performance_summary: PerformanceSummary {
-            total_gpu_time_ms: 15.67,
-            average_sm_utilization: 85.2,
-            memory_throughput_gbps: 450.3,
-            compute_throughput_percent: 78.9,
-            bottleneck_analysis: "Memory bandwidth is the primary bottleneck".to_string(),
+        }
+        // Host threads, OS runtime calls and scheduling explain GPU idle time
+        Some("nsys-rep") => NSightAnalysis {
+            report_type: "NSight Systems".to_string(),
+            cpu_gpu_timeline: nsys::extract_timeline(report_path, nsys::DEFAULT_MIN_GAP_NS),
+            ..Default::default()
         },
+        _ => return Err(AppError::InvalidArgument(format!("Not an NSight report (.ncu-rep or .nsys-rep): {}", file_path)).into()),
     };
     
+    analysis.recommendations = recommendations::evaluate(&analysis.kernels, recommendations::RULES);
+    analysis.bottlenecks = recommendations::bottlenecks(&analysis.recommendations);
+    analysis.performance_summary = summarize_kernels(&analysis.kernels, &analysis.bottlenecks);
+    
     Ok(analysis)
 }
+
+// Kernel launches of an NSight Compute report
+fn ncu_report_kernels(report: &std::path::Path) -> Result<ncu::ReportKernels> {
+    ncu::extract_kernels(report).ok_or_else(|| {
+        AppError::NotSupported(format!("No kernels could be read from {} (is ncu installed?)", report.display())).into()
+    })
+}
+
+// Attach source/SASS correlation and memory metrics to each launch, when
+// the report was collected with them
+fn with_launch_details(report: &std::path::Path, mut kernels: Vec<KernelAnalysis>) -> Vec<KernelAnalysis> {
+    let mut launches: HashMap<String, usize> = HashMap::new();
+    for kernel in &mut kernels {
+        let launch = launches.entry(kernel.name.clone()).or_default();
+        kernel.source_hotspots = ncu::extract_source_hotspots(report, &kernel.name, *launch);
+        kernel.memory_workload = ncu::extract_memory_workload(report, &kernel.name, *launch);
+        *launch += 1;
+    }
+    kernels
+}
+
+// Report-wide totals and averages over every kernel launch
+fn summarize_kernels(kernels: &[KernelAnalysis], bottlenecks: &[String]) -> PerformanceSummary {
+    let mean = |values: &[f64]| if values.is_empty() { 0.0 } else { values.iter().sum::<f64>() / values.len() as f64 };
+    let total_gpu_time_ms: f64 = kernels.iter().map(|kernel| kernel.duration_ms).sum();
+    let sm_efficiency: Vec<f64> = kernels.iter().map(|kernel| kernel.sm_efficiency).collect();
+    let dram_bandwidth: Vec<f64> = kernels.iter()
+        .filter_map(|kernel| kernel.memory_workload.as_ref()?.dram_bandwidth_gbps)
+        .collect();
+    PerformanceSummary {
+        total_gpu_time_ms,
+        average_sm_utilization: mean(&sm_efficiency),
+        memory_throughput_gbps: mean(&dram_bandwidth),
+        // Weighted by duration, i.e. over the report's GPU time
+        compute_throughput_percent: if total_gpu_time_ms > 0.0 {
+            kernels.iter().map(|kernel| kernel.sm_efficiency * kernel.duration_ms).sum::<f64>() / total_gpu_time_ms
+        } else {
+            0.0
+        },
+        bottleneck_analysis: match bottlenecks.first() {
+            Some(bottleneck) => format!("{} is the primary bottleneck", bottleneck),
+            None if kernels.is_empty() => "No kernel metrics in this report".to_string(),
+            None => "No bottleneck identified".to_string(),
+        },
+    }
+}
+
+/// Merge kernel analyses from several NSight reports.
+/// 
+/// Every launch of a kernel in every report counts as one sample, so
+/// repeated benchmark runs can be compared for run-to-run stability.
+/// Only the launch metrics are read; source and memory exports are skipped.
+/// 
+/// # Arguments
+/// * `paths` - NSight Compute reports (`.ncu-rep`) of repeated runs
+/// 
+/// # Returns
+/// * `Result<NSightAggregate>` - Per-kernel statistics or error if a report cannot be read
+pub async fn aggregate_nsight_reports(paths: Vec<String>) -> Result<NSightAggregate> {
+    if paths.is_empty() {
+        return Err(AppError::InvalidArgument("No NSight reports given".to_string()).into());
+    }
+    
+    let mut reports = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let report_path = std::path::Path::new(path);
+        if report_path.extension().is_none_or(|ext| ext != "ncu-rep") {
+            return Err(AppError::InvalidArgument(format!("Only NSight Compute reports (.ncu-rep) have kernel metrics: {}", path)).into());
+        }
+        if !report_path.exists() {
+            return Err(AppError::Io(format!("NSight report file not found: {}", path)).into());
+        }
+        let kernels = ncu_report_kernels(report_path)?.kernels;
+        reports.push(NSightAnalysis { kernels, ..Default::default() });
+    }
+    
+    Ok(aggregate_kernels(&reports))
+}
+
+// Group kernel launches by name and compute per-metric statistics
+fn aggregate_kernels(reports: &[NSightAnalysis]) -> NSightAggregate {
+    let mut by_name: std::collections::BTreeMap<&str, Vec<&KernelAnalysis>> = std::collections::BTreeMap::new();
+    for kernel in reports.iter().flat_map(|report| &report.kernels) {
+        by_name.entry(kernel.name.as_str()).or_default().push(kernel);
+    }
+    
+    let mut kernels: Vec<KernelAggregate> = by_name.into_iter()
+        .map(|(name, launches)| {
+            let stats = |metric: fn(&KernelAnalysis) -> f64| {
+                metric_stats(&launches.iter().map(|kernel| metric(kernel)).collect::<Vec<_>>())
+            };
+            let duration_ms = stats(|kernel| kernel.duration_ms);
+            let unstable = duration_ms.mean > 0.0
+                && duration_ms.stddev / duration_ms.mean * 100.0 > UNSTABLE_KERNEL_CV_PERCENT;
+            
+            KernelAggregate {
+                name: name.to_string(),
+                samples: launches.len(),
+                duration_ms,
+                occupancy_percent: stats(|kernel| kernel.occupancy_percent),
+                sm_efficiency: stats(|kernel| kernel.sm_efficiency),
+                memory_efficiency: stats(|kernel| kernel.memory_efficiency),
+                unstable,
+            }
+        })
+        .collect();
+    kernels.sort_by(|a, b| b.duration_ms.mean.total_cmp(&a.duration_ms.mean));
+    
+    let unstable_kernels = kernels.iter()
+        .filter(|kernel| kernel.unstable)
+        .map(|kernel| kernel.name.clone())
+        .collect();
+    
+    NSightAggregate {
+        report_count: reports.len(),
+        kernels,
+        unstable_kernels,
+    }
+}
+
+/// Achieved rate and interval statistics of a recording
+///
+/// # Arguments
+/// * `requested_rate_hz` - Rate the recording was asked to sample at
+/// * `sample_times_ms` - When each sample was taken, in milliseconds since the first
+pub fn sampling_timing(requested_rate_hz: u64, sample_times_ms: &[f64]) -> SamplingTiming {
+    let intervals: Vec<f64> = sample_times_ms.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let period_ms = 1000.0 / requested_rate_hz as f64;
+    let span_ms = intervals.iter().sum::<f64>();
+    SamplingTiming {
+        requested_rate_hz,
+        achieved_rate_hz: if span_ms > 0.0 { intervals.len() as f64 * 1000.0 / span_ms } else { 0.0 },
+        interval_ms: (!intervals.is_empty()).then(|| metric_stats(&intervals)),
+        // An interval spanning n periods skipped n - 1 ticks
+        missed_ticks: intervals.iter().map(|interval| ((interval / period_ms).round() as u64).saturating_sub(1)).sum(),
+    }
+}
+
+// Mean, sample standard deviation and range of a non-empty slice
+fn metric_stats(values: &[f64]) -> MetricStats {
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let variance = if values.len() > 1 {
+        values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (count - 1.0)
+    } else {
+        0.0
+    };
+    
+    MetricStats {
+        mean,
+        stddev: variance.sqrt(),
+        min: values.iter().copied().fold(f64::INFINITY, f64::min),
+        max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+    }
+}
@@ -0,0 +1,331 @@
+//! OpenTelemetry metrics export
+//!
+//! Periodically samples the selected devices and pushes the readings to an
+//! OpenTelemetry collector over OTLP/HTTP with the JSON encoding, so GPU
+//! metrics land next to the traces and metrics a service already exports.
+//! Each device is its own resource, identified by the host name and the
+//! GPU's UUID, index and name; readings are sent as gauges named after the
+//! `gpu.*` convention (utilization as a 0-1 ratio, memory in bytes).
+//!
+//! Requests go through the system `curl`, like alert webhooks.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::Nvml;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::alerts;
+use crate::columnar::ColumnValue;
+use crate::error::AppError;
+use crate::nvml::{self, StaticDeviceInfo, TelemetryFrame};
+use crate::sampler::SamplingThread;
+use crate::sessions;
+use crate::validation;
+
+/// Export interval used when none is requested
+pub const DEFAULT_INTERVAL_SECONDS: u64 = 10;
+/// Shortest export interval
+pub const MIN_INTERVAL_SECONDS: u64 = 1;
+/// Path of the metrics service below the collector endpoint
+const METRICS_PATH: &str = "/v1/metrics";
+/// `service.name` of every exported resource
+const SERVICE_NAME: &str = "nsightful";
+
+/// Exported gauges: name, unit, description, the frame field validation
+/// checks, and the value in the gauge's unit
+const GAUGES: [(&str, &str, &str, &str, ColumnValue); 8] = [
+    ("gpu.utilization", "1", "Fraction of time a kernel was running", "util_gpu", |frame| frame.util_gpu as f64 / 100.0),
+    ("gpu.memory.utilization", "1", "Fraction of time device memory was read or written", "util_memory", |frame| frame.util_memory as f64 / 100.0),
+    ("gpu.memory.used", "By", "Device memory in use", "memory_used_mb", |frame| frame.memory_used_mb as f64 * 1024.0 * 1024.0),
+    ("gpu.temperature", "Cel", "Die temperature", "temperature_c", |frame| frame.temperature_c as f64),
+    ("gpu.power.usage", "W", "Board power draw", "power_w", |frame| frame.power_w as f64),
+    ("gpu.clock.sm", "MHz", "Streaming multiprocessor clock", "sm_clock_mhz", |frame| frame.sm_clock_mhz as f64),
+    ("gpu.clock.memory", "MHz", "Memory clock", "memory_clock_mhz", |frame| frame.memory_clock_mhz as f64),
+    ("gpu.fan.speed", "1", "Fan speed as a fraction of its maximum", "fan_speed_percent", |frame| frame.fan_speed_percent as f64 / 100.0),
+];
+
+/// Where and how often to export
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OtlpConfig {
+    /// Collector OTLP/HTTP endpoint, e.g. `http://localhost:4318`
+    pub endpoint: String,
+    /// Extra request headers, e.g. an authorization token
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Extra resource attributes, e.g. `deployment.environment`
+    #[serde(default)]
+    pub resource_attributes: BTreeMap<String, String>,
+    /// Devices to export; every device if empty
+    #[serde(default)]
+    pub device_indices: Vec<u32>,
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+fn default_interval_seconds() -> u64 {
+    DEFAULT_INTERVAL_SECONDS
+}
+
+/// State of the exporter
+#[derive(Serialize, Clone, Debug)]
+pub struct OtlpStatus {
+    pub running: bool,
+    pub config: OtlpConfig,
+    pub exports: u64,
+    pub failed_exports: u64,
+    /// Time of the last successful export (Unix milliseconds)
+    pub last_export_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+// Current or most recent exporter; kept after it stops so its counters can be read
+static OTLP_STATE: std::sync::RwLock<Option<OtlpStatus>> = std::sync::RwLock::new(None);
+
+// Cancellation token of the running exporter
+static OTLP_CANCEL: std::sync::Mutex<Option<CancellationToken>> = std::sync::Mutex::new(None);
+
+// Task of the running exporter
+static OTLP_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+/// Start exporting device metrics to a collector
+///
+/// # Arguments
+/// * `config` - Endpoint, headers, attributes, devices and interval
+///
+/// # Returns
+/// * `Result<OtlpStatus>` - Status of the started exporter or error for an invalid config
+pub async fn start_export(config: OtlpConfig) -> Result<OtlpStatus> {
+    validate(&config)?;
+    let status = OtlpStatus {
+        running: true,
+        config: config.clone(),
+        exports: 0,
+        failed_exports: 0,
+        last_export_ms: None,
+        last_error: None,
+    };
+    let claim = sessions::claim(&OTLP_STATE, |status| status.running, status.clone(), "The OpenTelemetry export is already running")?;
+    let device_indices = if config.device_indices.is_empty() {
+        let count = nvml::blocking(|| {
+            let nvml = Nvml::init().context("Failed to initialize NVML")?;
+            nvml.device_count().context("Failed to get device count")
+        }).await?;
+        (0..count).collect()
+    } else {
+        config.device_indices.clone()
+    };
+    let sampler = SamplingThread::start_devices(device_indices).await?;
+
+    let status = claim.commit(status);
+    let cancel = CancellationToken::new();
+    *OTLP_CANCEL.lock().unwrap() = Some(cancel.clone());
+
+    let task = tokio::spawn(async move {
+        let url = format!("{}{}", config.endpoint.trim_end_matches('/'), METRICS_PATH);
+        let headers: Vec<String> = config.headers.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+        let host = alerts::host_name();
+        let mut interval = tokio::time::interval(Duration::from_secs(config.interval_seconds));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+
+            let result = match sampler.sample().await {
+                Ok(frames) => {
+                    let body = payload(&frames, sampler.devices(), &host, &config.resource_attributes).to_string();
+                    let (url, headers) = (url.clone(), headers.clone());
+                    tokio::task::spawn_blocking(move || alerts::post_json(&url, &headers, &body)).await
+                        .context("OTLP export request panicked")
+                        .and_then(|result| result)
+                }
+                Err(e) => Err(e),
+            };
+            if let Some(status) = OTLP_STATE.write().unwrap().as_mut() {
+                match result {
+                    Ok(()) => {
+                        status.exports += 1;
+                        status.last_export_ms = Some(nvml::now_ms() as u64);
+                    }
+                    Err(e) => {
+                        eprintln!("OpenTelemetry export failed: {:#}", e);
+                        status.failed_exports += 1;
+                        status.last_error = Some(format!("{:#}", e));
+                    }
+                }
+            }
+        }
+
+        if let Some(status) = OTLP_STATE.write().unwrap().as_mut() {
+            status.running = false;
+        }
+    });
+    *OTLP_TASK.lock().unwrap() = Some(task);
+
+    Ok(status)
+}
+
+/// Stop the exporter
+///
+/// # Returns
+/// * `Result<OtlpStatus>` - Final status or error if the exporter is not running
+pub async fn stop_export() -> Result<OtlpStatus> {
+    if !OTLP_STATE.read().unwrap().as_ref().is_some_and(|status| status.running) {
+        return Err(AppError::InvalidArgument("The OpenTelemetry export is not running".to_string()).into());
+    }
+    finish_active_export().await?;
+    get_export_status().context("OpenTelemetry export state missing after stop")
+}
+
+/// Stop any running exporter and wait for it to exit
+pub async fn finish_active_export() -> Result<()> {
+    if let Some(cancel) = OTLP_CANCEL.lock().unwrap().take() {
+        cancel.cancel();
+    }
+    let task = OTLP_TASK.lock().unwrap().take();
+    if let Some(task) = task {
+        task.await.context("OpenTelemetry export task ended abnormally")?;
+    }
+    Ok(())
+}
+
+/// Get the status of the current or most recent exporter
+///
+/// # Returns
+/// * `Option<OtlpStatus>` - Status, or `None` if the exporter has not been started
+pub fn get_export_status() -> Option<OtlpStatus> {
+    OTLP_STATE.read().unwrap().clone()
+}
+
+fn validate(config: &OtlpConfig) -> Result<()> {
+    alerts::validate_url(&config.endpoint)?;
+    if config.interval_seconds < MIN_INTERVAL_SECONDS {
+        return Err(AppError::InvalidArgument(format!(
+            "Export interval must be at least {} second", MIN_INTERVAL_SECONDS
+        )).into());
+    }
+    // A colon or line break in a name would split the header
+    if let Some(name) = config.headers.keys().find(|name| name.is_empty() || name.contains([':', '\r', '\n'])) {
+        return Err(AppError::InvalidArgument(format!("Invalid header name {:?}", name)).into());
+    }
+    if let Some(value) = config.headers.values().find(|value| value.contains(['\r', '\n'])) {
+        return Err(AppError::InvalidArgument(format!("Header value {:?} contains a line break", value)).into());
+    }
+    Ok(())
+}
+
+/// OTLP JSON request with one resource per device
+///
+/// # Arguments
+/// * `frames` - One frame per device
+/// * `devices` - Static info of the sampled devices
+/// * `host` - Name of this machine
+/// * `extra_attributes` - Resource attributes added to every device
+///
+/// # Returns
+/// * `Value` - `ExportMetricsServiceRequest` body; readings validation flagged are left out
+pub fn payload(frames: &[TelemetryFrame], devices: &[StaticDeviceInfo], host: &str, extra_attributes: &BTreeMap<String, String>) -> Value {
+    let resource_metrics: Vec<Value> = frames.iter()
+        .map(|frame| {
+            let device = devices.iter().find(|device| device.index == frame.device_index);
+            let mut attributes = vec![
+                attribute("service.name", json!({ "stringValue": SERVICE_NAME })),
+                attribute("host.name", json!({ "stringValue": host })),
+                // OTLP JSON carries 64-bit integers as strings
+                attribute("gpu.index", json!({ "intValue": frame.device_index.to_string() })),
+            ];
+            if let Some(device) = device {
+                attributes.push(attribute("gpu.uuid", json!({ "stringValue": device.uuid })));
+                attributes.push(attribute("gpu.name", json!({ "stringValue": device.name })));
+            }
+            attributes.extend(extra_attributes.iter().map(|(key, value)| attribute(key, json!({ "stringValue": value }))));
+
+            let time_unix_nano = (frame.timestamp * 1_000_000).to_string();
+            let metrics: Vec<Value> = GAUGES.iter()
+                .filter(|(_, _, _, field, _)| !validation::is_suspect(frame, field))
+                .map(|(name, unit, description, _, value)| json!({
+                    "name": name,
+                    "unit": unit,
+                    "description": description,
+                    "gauge": { "dataPoints": [{ "timeUnixNano": time_unix_nano, "asDouble": value(frame) }] },
+                }))
+                .collect();
+            json!({
+                "resource": { "attributes": attributes },
+                "scopeMetrics": [{
+                    "scope": { "name": SERVICE_NAME, "version": env!("CARGO_PKG_VERSION") },
+                    "metrics": metrics,
+                }],
+            })
+        })
+        .collect();
+    json!({ "resourceMetrics": resource_metrics })
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(endpoint: &str) -> OtlpConfig {
+        OtlpConfig {
+            endpoint: endpoint.to_string(),
+            headers: BTreeMap::new(),
+            resource_attributes: BTreeMap::new(),
+            device_indices: Vec::new(),
+            interval_seconds: DEFAULT_INTERVAL_SECONDS,
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_endpoint_and_headers() {
+        assert!(validate(&config("http://localhost:4318")).is_ok());
+        assert!(validate(&config("localhost:4318")).is_err());
+        assert!(validate(&OtlpConfig { interval_seconds: 0, ..config("http://localhost:4318") }).is_err());
+
+        let headers = BTreeMap::from([("Authorization: Bearer".to_string(), "token".to_string())]);
+        assert!(validate(&OtlpConfig { headers, ..config("http://localhost:4318") }).is_err());
+        let headers = BTreeMap::from([("Authorization".to_string(), "Bearer token\r\nX-Injected: 1".to_string())]);
+        assert!(validate(&OtlpConfig { headers, ..config("http://localhost:4318") }).is_err());
+    }
+
+    #[test]
+    fn test_payload_has_device_resources_and_gauges() {
+        let device = StaticDeviceInfo { index: 1, uuid: "GPU-1234".to_string(), name: "RTX 4090".to_string(), ..Default::default() };
+        let frame = TelemetryFrame {
+            timestamp: 1_700_000_000_000,
+            device_index: 1,
+            util_gpu: 75,
+            memory_used_mb: 2,
+            temperature_c: 300,
+            suspect_fields: vec!["temperature_c".to_string()],
+            ..Default::default()
+        };
+        let extra = BTreeMap::from([("deployment.environment".to_string(), "prod".to_string())]);
+        let body = payload(&[frame], &[device], "trainer-01", &extra);
+
+        let resource = &body["resourceMetrics"][0];
+        let attributes: BTreeMap<&str, &Value> = resource["resource"]["attributes"].as_array().unwrap().iter()
+            .map(|attribute| (attribute["key"].as_str().unwrap(), &attribute["value"]))
+            .collect();
+        assert_eq!(attributes["host.name"]["stringValue"], "trainer-01");
+        assert_eq!(attributes["gpu.uuid"]["stringValue"], "GPU-1234");
+        assert_eq!(attributes["gpu.index"]["intValue"], "1");
+        assert_eq!(attributes["deployment.environment"]["stringValue"], "prod");
+
+        let metrics = resource["scopeMetrics"][0]["metrics"].as_array().unwrap();
+        let gauge = |name: &str| metrics.iter().find(|metric| metric["name"] == name).map(|metric| &metric["gauge"]["dataPoints"][0]);
+        assert_eq!(gauge("gpu.utilization").unwrap()["asDouble"], 0.75);
+        assert_eq!(gauge("gpu.memory.used").unwrap()["asDouble"], 2_097_152.0);
+        assert_eq!(gauge("gpu.utilization").unwrap()["timeUnixNano"], "1700000000000000000");
+        // The implausible temperature is left out rather than exported
+        assert!(gauge("gpu.temperature").is_none());
+    }
+}
@@ -0,0 +1,197 @@
+//! Self-profiling of the visualizer
+//!
+//! Monitoring should not perturb the workload it watches. The time every
+//! NVML metric group takes to read and every frontend event takes to emit
+//! is accumulated here, alongside the CPU time of the whole process, so
+//! `get_overhead_stats` can show what watching a GPU actually costs. Each
+//! measurement is two clock reads and an uncontended lock.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::sampler::SampledMetric;
+
+/// Clock ticks per second of `/proc` CPU times (`USER_HZ`, 100 on every
+/// mainstream Linux architecture)
+const CLOCK_TICKS_PER_SECOND: f64 = 100.0;
+
+/// Accumulated cost of one kind of operation
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct OperationCost {
+    pub calls: u64,
+    pub total_ms: f64,
+    pub mean_us: f64,
+    pub max_us: f64,
+}
+
+/// CPU time used by this process
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CpuUsage {
+    /// User and system time since the process started
+    pub cpu_time_ms: f64,
+    /// Share of one core used since measuring started
+    pub average_percent: f64,
+    /// Share of one core used since the previous query
+    pub recent_percent: f64,
+}
+
+/// Overhead of the visualizer since measuring started or was last reset
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct OverheadStats {
+    pub measured_ms: u64,
+    /// `None` where process CPU time cannot be read (non-Linux)
+    pub cpu: Option<CpuUsage>,
+    /// Cost of reading each metric group, across all devices
+    pub nvml: BTreeMap<SampledMetric, OperationCost>,
+    /// Cost of emitting each frontend event, including serialization
+    pub events: BTreeMap<&'static str, OperationCost>,
+    /// Share of wall time spent in the NVML calls above
+    pub nvml_busy_percent: f64,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Timing {
+    calls: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Timing {
+    fn add(&mut self, elapsed: Duration) {
+        self.calls += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+
+    fn cost(&self) -> OperationCost {
+        OperationCost {
+            calls: self.calls,
+            total_ms: self.total.as_secs_f64() * 1000.0,
+            mean_us: if self.calls > 0 { self.total.as_secs_f64() * 1e6 / self.calls as f64 } else { 0.0 },
+            max_us: self.max.as_secs_f64() * 1e6,
+        }
+    }
+}
+
+struct Recorder {
+    started: Instant,
+    /// CPU time when measuring started
+    start_cpu_ms: Option<f64>,
+    /// Time and CPU time of the previous query
+    last_query: (Instant, Option<f64>),
+    nvml: BTreeMap<SampledMetric, Timing>,
+    events: BTreeMap<&'static str, Timing>,
+}
+
+impl Recorder {
+    fn new() -> Self {
+        let cpu_ms = process_cpu_ms();
+        Recorder {
+            started: Instant::now(),
+            start_cpu_ms: cpu_ms,
+            last_query: (Instant::now(), cpu_ms),
+            nvml: BTreeMap::new(),
+            events: BTreeMap::new(),
+        }
+    }
+}
+
+fn recorder() -> &'static Mutex<Recorder> {
+    static RECORDER: OnceLock<Mutex<Recorder>> = OnceLock::new();
+    RECORDER.get_or_init(|| Mutex::new(Recorder::new()))
+}
+
+/// Run an NVML read and record how long it took
+///
+/// # Arguments
+/// * `metric` - Metric group the read belongs to
+/// * `read` - The NVML calls
+pub fn time_nvml<T>(metric: SampledMetric, read: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let value = read();
+    recorder().lock().unwrap().nvml.entry(metric).or_default().add(started.elapsed());
+    value
+}
+
+/// Record how long emitting a frontend event took
+pub fn record_event(event: &'static str, elapsed: Duration) {
+    recorder().lock().unwrap().events.entry(event).or_default().add(elapsed);
+}
+
+/// Get the overhead measured so far
+///
+/// # Arguments
+/// * `reset` - Start measuring afresh after taking the snapshot
+///
+/// # Returns
+/// * `OverheadStats` - CPU usage and per-operation costs
+pub fn get_overhead_stats(reset: bool) -> OverheadStats {
+    let mut recorder = recorder().lock().unwrap();
+    let now = Instant::now();
+    let cpu_ms = process_cpu_ms();
+    let elapsed = now.duration_since(recorder.started);
+    let nvml_total: Duration = recorder.nvml.values().map(|timing| timing.total).sum();
+
+    let stats = OverheadStats {
+        measured_ms: elapsed.as_millis() as u64,
+        cpu: cpu_ms.zip(recorder.start_cpu_ms).map(|(cpu_ms, start_cpu_ms)| CpuUsage {
+            cpu_time_ms: cpu_ms,
+            average_percent: percent(cpu_ms - start_cpu_ms, elapsed),
+            recent_percent: recorder.last_query.1
+                .map_or(0.0, |last_cpu_ms| percent(cpu_ms - last_cpu_ms, now.duration_since(recorder.last_query.0))),
+        }),
+        nvml: recorder.nvml.iter().map(|(&metric, timing)| (metric, timing.cost())).collect(),
+        events: recorder.events.iter().map(|(&event, timing)| (event, timing.cost())).collect(),
+        nvml_busy_percent: percent(nvml_total.as_secs_f64() * 1000.0, elapsed),
+    };
+    if reset {
+        *recorder = Recorder::new();
+    } else {
+        recorder.last_query = (now, cpu_ms);
+    }
+    stats
+}
+
+// Share of a wall-clock span, in percent of one core
+fn percent(busy_ms: f64, span: Duration) -> f64 {
+    let span_ms = span.as_secs_f64() * 1000.0;
+    if span_ms > 0.0 { busy_ms * 100.0 / span_ms } else { 0.0 }
+}
+
+// User plus system CPU time of this process
+fn process_cpu_ms() -> Option<f64> {
+    parse_cpu_ms(&std::fs::read_to_string("/proc/self/stat").ok()?)
+}
+
+// `utime` and `stime` are fields 14 and 15 of `/proc/<pid>/stat`; the
+// command name before them may itself contain spaces and parentheses
+fn parse_cpu_ms(stat: &str) -> Option<f64> {
+    let fields: Vec<&str> = stat.get(stat.rfind(')')? + 1..)?.split_whitespace().collect();
+    let ticks: u64 = fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?;
+    Some(ticks as f64 * 1000.0 / CLOCK_TICKS_PER_SECOND)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_ms_reads_utime_and_stime() {
+        let stat = "4242 (gpuviz (data)) S 1 4242 4242 0 -1 4194560 2101 0 0 0 150 25 0 0 20 0 12 0 345 0";
+        assert_eq!(parse_cpu_ms(stat), Some(1_750.0));
+        assert_eq!(parse_cpu_ms("4242 (truncated) S 1"), None);
+    }
+
+    #[test]
+    fn test_timing_accumulates_calls() {
+        let mut timing = Timing::default();
+        timing.add(Duration::from_micros(100));
+        timing.add(Duration::from_micros(300));
+        let cost = timing.cost();
+        assert_eq!((cost.calls, cost.max_us), (2, 300.0));
+        assert!((cost.mean_us - 200.0).abs() < 1e-6);
+        assert!((cost.total_ms - 0.4).abs() < 1e-9);
+    }
+}
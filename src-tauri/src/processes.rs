@@ -0,0 +1,433 @@
+//! GPU process listing with process tree attribution
+//!
+//! NVML only reports the PIDs holding GPU contexts, which for ML workloads
+//! are usually anonymous `python` processes (trainer ranks, DataLoader
+//! workers). Each PID is resolved through `/proc` to its executable, command
+//! line and parent, attributed to the script or module it runs, and grouped
+//! with the other GPU processes of the same process tree. On Linux, the
+//! cgroup of each process also attributes it to its container. Outside Linux
+//! only the NVML process name is available.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::enums::device::UsedGpuMemory;
+use nvml_wrapper::struct_wrappers::device::ProcessInfo;
+use nvml_wrapper::Nvml;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::containers::{self, ContainerInfo};
+use crate::nvml;
+
+/// Interpreters whose command line names the program actually being run
+const INTERPRETERS: [&str; 8] = ["python", "node", "ruby", "perl", "java", "julia", "Rscript", "lua"];
+/// Ancestors that end a process tree: shells, terminal multiplexers, remote
+/// sessions, container shims and init systems (`containerd-shim*` is matched by prefix)
+const TREE_BOUNDARIES: [&str; 12] = [
+    "bash", "sh", "zsh", "fish", "dash", "tmux", "screen", "sshd", "login",
+    "systemd", "init", "conmon",
+];
+/// Longest process name requested from NVML when `/proc` is unavailable
+const NVML_PROCESS_NAME_LENGTH: usize = 256;
+
+/// A process holding a context on a GPU
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct GpuProcess {
+    pub pid: u32,
+    pub device_index: u32,
+    /// "compute" or "graphics"
+    pub kind: String,
+    /// `None` when the driver does not report it (e.g. under WDDM)
+    pub used_memory_mb: Option<u64>,
+    pub name: String,
+    pub exe: Option<String>,
+    pub command_line: Vec<String>,
+    pub parent_pid: Option<u32>,
+    /// What the process actually runs: the script or module for interpreters,
+    /// otherwise the executable name
+    pub attributed_to: String,
+    /// PID of the top of the process tree this process belongs to
+    pub tree_root_pid: u32,
+    /// Cgroup path (Linux only)
+    pub cgroup: Option<String>,
+    /// Container the process runs in, if any
+    pub container: Option<ContainerInfo>,
+}
+
+/// GPU processes sharing a process tree, e.g. a launcher and its ranks
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ProcessTree {
+    pub root_pid: u32,
+    pub root_name: String,
+    pub attributed_to: String,
+    /// GPU memory of all processes in the tree across devices
+    pub used_memory_mb: u64,
+    pub pids: Vec<u32>,
+}
+
+/// GPU memory owned by one container
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ContainerUsage {
+    pub container: ContainerInfo,
+    pub used_memory_mb: u64,
+    pub pids: Vec<u32>,
+}
+
+/// GPU processes and their grouping into process trees and containers
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct GpuProcessReport {
+    pub processes: Vec<GpuProcess>,
+    /// Trees by descending memory usage
+    pub trees: Vec<ProcessTree>,
+    /// Containers by descending memory usage; host processes are not listed
+    pub containers: Vec<ContainerUsage>,
+}
+
+/// What `/proc` knows about a process
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProcEntry {
+    pub pid: u32,
+    pub parent_pid: Option<u32>,
+    pub name: String,
+    pub exe: Option<String>,
+    pub command_line: Vec<String>,
+    pub cgroup: Option<String>,
+}
+
+/// List processes on one or all GPUs
+///
+/// # Arguments
+/// * `device_index` - Device to list, or `None` for every device
+///
+/// # Returns
+/// * `Result<GpuProcessReport>` - Processes and process trees, or error if NVML is unavailable
+pub async fn get_gpu_processes(device_index: Option<u32>) -> Result<GpuProcessReport> {
+    nvml::blocking(move || get_gpu_processes_blocking(device_index)).await
+}
+
+fn get_gpu_processes_blocking(device_index: Option<u32>) -> Result<GpuProcessReport> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let devices = match device_index {
+        Some(index) => vec![(index, nvml::device_at(&nvml, index)?)],
+        None => nvml::list_devices(&nvml)?
+            .into_iter()
+            .enumerate()
+            .map(|(index, device)| (index as u32, device))
+            .collect(),
+    };
+
+    let mut processes = Vec::new();
+    let mut roots = BTreeMap::new();
+    for (index, device) in devices {
+        let listed = [
+            ("compute", device.running_compute_processes()),
+            ("graphics", device.running_graphics_processes()),
+        ];
+        for (kind, infos) in listed {
+            let infos = infos.with_context(|| format!("Failed to list {} processes on GPU {}", kind, index))?;
+            for info in infos {
+                let fallback_name = nvml.sys_process_name(info.pid, NVML_PROCESS_NAME_LENGTH).unwrap_or_default();
+                let (process, root) = describe_process(&info, index, kind, &fallback_name, read_proc_entry);
+                roots.insert(root.pid, root);
+                processes.push(process);
+            }
+        }
+    }
+
+    let trees = group_trees(&processes, &roots);
+    let containers = group_containers(&processes);
+    Ok(GpuProcessReport { processes, trees, containers })
+}
+
+// Combine NVML's view of a process with what `lookup` knows about it,
+// returning the process and the root of its tree
+fn describe_process(
+    info: &ProcessInfo,
+    device_index: u32,
+    kind: &str,
+    fallback_name: &str,
+    lookup: impl Fn(u32) -> Option<ProcEntry>,
+) -> (GpuProcess, ProcEntry) {
+    let entry = lookup(info.pid).unwrap_or_else(|| ProcEntry {
+        pid: info.pid,
+        name: fallback_name.to_string(),
+        ..Default::default()
+    });
+    let root = tree_root(&entry, &lookup);
+    let process = GpuProcess {
+        pid: info.pid,
+        device_index,
+        kind: kind.to_string(),
+        used_memory_mb: match info.used_gpu_memory {
+            UsedGpuMemory::Used(bytes) => Some(bytes / (1024 * 1024)),
+            UsedGpuMemory::Unavailable => None,
+        },
+        attributed_to: attribute(&entry),
+        tree_root_pid: root.pid,
+        container: entry.cgroup.as_deref().and_then(containers::parse_container),
+        cgroup: entry.cgroup,
+        name: entry.name,
+        exe: entry.exe,
+        command_line: entry.command_line,
+        parent_pid: entry.parent_pid,
+    };
+    (process, root)
+}
+
+/// Whether a process goes by a name: its process name, executable file name
+/// or attributed program, compared case-insensitively
+pub fn is_named(process: &GpuProcess, name: &str) -> bool {
+    let name = name.trim();
+    let exe_name = process.exe.as_deref()
+        .and_then(|exe| std::path::Path::new(exe).file_name())
+        .map(|exe_name| exe_name.to_string_lossy());
+    let named = [Some(process.name.as_str()), exe_name.as_deref(), Some(process.attributed_to.as_str())]
+        .into_iter()
+        .flatten()
+        .any(|candidate| candidate.eq_ignore_ascii_case(name));
+    named
+}
+
+/// Name what a process runs, looking through interpreters to their program
+///
+/// `python -m torch.distributed.run train.py` is attributed to
+/// `torch.distributed.run`, `python3 /work/train.py --lr 1e-3` to `train.py`.
+pub fn attribute(entry: &ProcEntry) -> String {
+    let program = entry.command_line.first()
+        .map(|arg| base_name(arg))
+        .filter(|program| !program.is_empty())
+        .unwrap_or(&entry.name);
+    if !is_interpreter(program) {
+        return program.to_string();
+    }
+
+    let mut args = entry.command_line.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" | "-jar" => {
+                return args.next().map_or_else(|| program.to_string(), |module| base_name(module).to_string());
+            }
+            "-c" | "-e" => return format!("{} (inline code)", program),
+            _ if arg.starts_with('-') => continue,
+            _ => return base_name(arg).to_string(),
+        }
+    }
+    program.to_string()
+}
+
+// Walk up the parents to the last process below a tree boundary
+fn tree_root(entry: &ProcEntry, lookup: &impl Fn(u32) -> Option<ProcEntry>) -> ProcEntry {
+    let mut root = entry.clone();
+    // Bounded in case of a cycle from PID reuse between reads
+    for _ in 0..64 {
+        let Some(parent) = root.parent_pid.filter(|&pid| pid > 1).and_then(lookup) else {
+            break;
+        };
+        if is_tree_boundary(&parent.name) {
+            break;
+        }
+        root = parent;
+    }
+    root
+}
+
+// Group processes by tree root, largest memory users first
+fn group_trees(processes: &[GpuProcess], roots: &BTreeMap<u32, ProcEntry>) -> Vec<ProcessTree> {
+    let mut trees: BTreeMap<u32, ProcessTree> = BTreeMap::new();
+    for process in processes {
+        let tree = trees.entry(process.tree_root_pid).or_insert_with(|| {
+            let root = roots.get(&process.tree_root_pid);
+            ProcessTree {
+                root_pid: process.tree_root_pid,
+                root_name: root.map_or_else(|| process.name.clone(), |root| root.name.clone()),
+                attributed_to: root.map_or_else(|| process.attributed_to.clone(), attribute),
+                used_memory_mb: 0,
+                pids: Vec::new(),
+            }
+        });
+        tree.used_memory_mb += process.used_memory_mb.unwrap_or(0);
+        if !tree.pids.contains(&process.pid) {
+            tree.pids.push(process.pid);
+        }
+    }
+
+    let mut trees: Vec<ProcessTree> = trees.into_values().collect();
+    trees.sort_by_key(|tree| Reverse(tree.used_memory_mb));
+    trees
+}
+
+// Sum memory per container, largest first
+fn group_containers(processes: &[GpuProcess]) -> Vec<ContainerUsage> {
+    let mut usage: BTreeMap<&str, ContainerUsage> = BTreeMap::new();
+    for process in processes {
+        let Some(container) = &process.container else {
+            continue;
+        };
+        let entry = usage.entry(container.id.as_str()).or_insert_with(|| ContainerUsage {
+            container: container.clone(),
+            used_memory_mb: 0,
+            pids: Vec::new(),
+        });
+        entry.used_memory_mb += process.used_memory_mb.unwrap_or(0);
+        if !entry.pids.contains(&process.pid) {
+            entry.pids.push(process.pid);
+        }
+    }
+
+    let mut usage: Vec<ContainerUsage> = usage.into_values().collect();
+    usage.sort_by_key(|container| Reverse(container.used_memory_mb));
+    usage
+}
+
+fn base_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+// Matches versioned names such as `python3.11` and `java17`
+fn is_interpreter(program: &str) -> bool {
+    let program = program.trim_end_matches(".exe");
+    INTERPRETERS.iter().any(|interpreter| {
+        program.strip_prefix(interpreter)
+            .is_some_and(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+    })
+}
+
+fn is_tree_boundary(name: &str) -> bool {
+    TREE_BOUNDARIES.contains(&name) || name.starts_with("containerd-shim")
+}
+
+/// Read a process from `/proc`
+///
+/// # Returns
+/// * `Option<ProcEntry>` - Process details, or `None` if it has exited or `/proc` is unavailable
+pub fn read_proc_entry(pid: u32) -> Option<ProcEntry> {
+    let dir = format!("/proc/{}", pid);
+    let stat = fs::read_to_string(format!("{}/stat", dir)).ok()?;
+    let (name, parent_pid) = parse_stat(&stat)?;
+    let command_line = fs::read(format!("{}/cmdline", dir))
+        .map(|raw| {
+            raw.split(|&byte| byte == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    // Reading another user's exe link needs privileges; the name still works
+    let exe = fs::read_link(format!("{}/exe", dir)).ok().map(|path| path.display().to_string());
+
+    Some(ProcEntry {
+        pid,
+        parent_pid: Some(parent_pid),
+        name,
+        exe,
+        command_line,
+        cgroup: containers::read_cgroup(pid),
+    })
+}
+
+// `pid (comm) state ppid ...`; comm may itself contain spaces and parentheses
+fn parse_stat(stat: &str) -> Option<(String, u32)> {
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let name = stat.get(open + 1..close)?.to_string();
+    let parent_pid = stat.get(close + 1..)?.split_whitespace().nth(1)?.parse().ok()?;
+    Some((name, parent_pid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn entry(pid: u32, parent_pid: u32, name: &str, command_line: &[&str]) -> ProcEntry {
+        ProcEntry {
+            pid,
+            parent_pid: Some(parent_pid),
+            name: name.to_string(),
+            exe: None,
+            command_line: command_line.iter().map(|arg| arg.to_string()).collect(),
+            cgroup: None,
+        }
+    }
+
+    fn info(pid: u32, used_mb: u64) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            used_gpu_memory: UsedGpuMemory::Used(used_mb * 1024 * 1024),
+            gpu_instance_id: None,
+            compute_instance_id: None,
+        }
+    }
+
+    #[test]
+    fn test_attribute_looks_through_interpreters() {
+        let attributed = |command_line: &[&str]| attribute(&entry(10, 1, "python3", command_line));
+        assert_eq!(attributed(&["/usr/bin/python3.11", "-u", "/work/train.py", "--lr", "1e-3"]), "train.py");
+        assert_eq!(attributed(&["python", "-m", "torch.distributed.run", "train.py"]), "torch.distributed.run");
+        assert_eq!(attributed(&["python", "-c", "import torch"]), "python (inline code)");
+        assert_eq!(attributed(&["/opt/llama/server", "--port", "8080"]), "server");
+        assert_eq!(attributed(&[]), "python3");
+    }
+
+    #[test]
+    fn test_trees_group_launcher_and_workers() {
+        let table: HashMap<u32, ProcEntry> = [
+            entry(100, 1, "sshd", &[]),
+            entry(200, 100, "bash", &["-bash"]),
+            entry(300, 200, "torchrun", &["python", "-m", "torch.distributed.run", "train.py"]),
+            entry(301, 300, "python", &["python", "-u", "train.py", "--local-rank=0"]),
+            entry(302, 300, "python", &["python", "-u", "train.py", "--local-rank=1"]),
+            entry(400, 200, "ollama", &["ollama", "serve"]),
+        ].into_iter().map(|entry| (entry.pid, entry)).collect();
+        let lookup = |pid: u32| table.get(&pid).cloned();
+
+        let (processes, roots): (Vec<GpuProcess>, BTreeMap<u32, ProcEntry>) = [(301, 0, 8_000), (302, 1, 7_000), (400, 0, 4_000)]
+            .into_iter()
+            .map(|(pid, device, mb)| {
+                let (process, root) = describe_process(&info(pid, mb), device, "compute", "", lookup);
+                (process, (root.pid, root))
+            })
+            .unzip();
+        assert_eq!(processes[0].attributed_to, "train.py");
+        assert_eq!(processes[0].tree_root_pid, 300);
+
+        let trees = group_trees(&processes, &roots);
+        assert_eq!(trees.len(), 2);
+        assert_eq!(trees[0].root_pid, 300);
+        assert_eq!(trees[0].used_memory_mb, 15_000);
+        assert_eq!(trees[0].pids, vec![301, 302]);
+        assert_eq!(trees[0].attributed_to, "torch.distributed.run");
+        assert_eq!(trees[1].root_pid, 400);
+        assert_eq!(trees[1].attributed_to, "ollama");
+    }
+
+    #[test]
+    fn test_container_usage_sums_memory() {
+        let id = "a".repeat(64);
+        let mut inside = entry(500, 1, "python", &["python", "serve.py"]);
+        inside.cgroup = Some(format!("/system.slice/docker-{}.scope", id));
+        let table: HashMap<u32, ProcEntry> = [inside, entry(600, 1, "python", &["python", "eval.py"])]
+            .into_iter().map(|entry| (entry.pid, entry)).collect();
+        let lookup = |pid: u32| table.get(&pid).cloned();
+        let processes: Vec<GpuProcess> = [(500, 2_000), (600, 1_000)].into_iter()
+            .map(|(pid, mb)| describe_process(&info(pid, mb), 0, "compute", "", lookup).0)
+            .collect();
+
+        assert_eq!(processes[1].container, None);
+        let containers = group_containers(&processes);
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].container.id, id);
+        assert_eq!(containers[0].used_memory_mb, 2_000);
+        assert_eq!(containers[0].pids, vec![500]);
+    }
+
+    #[test]
+    fn test_parse_stat_handles_spaces_in_name() {
+        assert_eq!(
+            parse_stat("1234 (tmux: server) S 1 1234 1234 0 -1"),
+            Some(("tmux: server".to_string(), 1))
+        );
+        assert_eq!(parse_stat("garbage"), None);
+    }
+}
@@ -0,0 +1,403 @@
+//! Profiling sessions under NSight Compute and NSight Systems
+//!
+//! Runs a target application under `ncu` or `nsys`, keeps the tail of the
+//! tool's output as progress, and when the tool exits analyzes the report
+//! it wrote with `process_nsight_report`. One session runs at a time.
+//!
+//! The profiled application is a child of the tool, so stopping a session
+//! must end more than the tool: the tool is started in its own process
+//! group (a job object on Windows), and the whole group is killed.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tauri::Window;
+
+use crate::error::AppError;
+use crate::nvml::{self, NSightAnalysis};
+use crate::sessions;
+
+/// Directory reports are written to, next to `recordings/`
+pub const PROFILE_DIR: &str = "profiles";
+/// Lines of tool output kept as progress
+pub const OUTPUT_TAIL_LINES: usize = 200;
+/// How often the running tool is checked for exit or a stop request
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Profiler to run the target under
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfilerTool {
+    /// NSight Compute, per-kernel metrics (`.ncu-rep`)
+    Ncu,
+    /// NSight Systems, timeline (`.nsys-rep`)
+    Nsys,
+}
+
+impl ProfilerTool {
+    fn program(self) -> &'static str {
+        match self {
+            ProfilerTool::Ncu => "ncu",
+            ProfilerTool::Nsys => "nsys",
+        }
+    }
+
+    fn report_extension(self) -> &'static str {
+        match self {
+            ProfilerTool::Ncu => "ncu-rep",
+            ProfilerTool::Nsys => "nsys-rep",
+        }
+    }
+}
+
+/// What the profiler runs against
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProfileTarget {
+    /// Start `program` with `args` under the profiler
+    Launch { program: String, args: Vec<String> },
+    /// Attach to an application already started with `ncu --mode launch`
+    Attach,
+}
+
+/// State of the current or most recent profiling session
+#[derive(Serialize, Clone, Debug)]
+pub struct ProfilingStatus {
+    pub running: bool,
+    pub session_id: String,
+    pub tool: ProfilerTool,
+    /// Full profiler command line
+    pub command_line: Vec<String>,
+    pub started_at: u128,
+    pub pid: u32,
+    /// Exit code of the profiler; `None` while running or if it was killed
+    pub exit_code: Option<i32>,
+    /// Last `OUTPUT_TAIL_LINES` lines of the profiler's stdout and stderr
+    pub output_tail: VecDeque<String>,
+    /// Report written by the profiler, once it exists
+    pub report_path: Option<String>,
+    pub analysis: Option<NSightAnalysis>,
+    /// Why the session produced no analysis
+    pub error: Option<String>,
+}
+
+// Current or most recent session; kept after it ends so its result can be read
+static PROFILING_STATE: std::sync::RwLock<Option<ProfilingStatus>> = std::sync::RwLock::new(None);
+
+// Set to kill the running profiler
+static PROFILING_STOP: AtomicBool = AtomicBool::new(false);
+
+// Handle to the background task, used to wait for the session to wind down
+static PROFILING_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+/// Start a profiling session
+///
+/// Emits `profiling-finished` with the final status once the profiler has
+/// exited and its report has been analyzed.
+///
+/// # Arguments
+/// * `tool` - Profiler to use
+/// * `target` - Application to launch, or attach to a waiting one (`ncu` only)
+/// * `tool_args` - Extra profiler options, placed before the target
+/// * `window` - Tauri window handle for the completion event
+///
+/// # Returns
+/// * `Result<ProfilingStatus>` - Status of the started session or error
+pub async fn start_profiling_session(
+    tool: ProfilerTool,
+    target: ProfileTarget,
+    tool_args: Vec<String>,
+    window: Window,
+) -> Result<ProfilingStatus> {
+    let session_id = format!("prof_{}", nvml::now_ms());
+    let output_base = format!("{}/{}", PROFILE_DIR, session_id);
+    let command_line = build_command(tool, &target, &tool_args, &output_base)?;
+    let mut status = ProfilingStatus {
+        running: true,
+        session_id,
+        tool,
+        command_line: command_line.clone(),
+        started_at: nvml::now_ms(),
+        pid: 0,
+        exit_code: None,
+        output_tail: VecDeque::new(),
+        report_path: None,
+        analysis: None,
+        error: None,
+    };
+    let claim = sessions::claim(&PROFILING_STATE, |status| status.running, status.clone(), "A profiling session is already running")?;
+    PROFILING_STOP.store(false, Ordering::SeqCst);
+    std::fs::create_dir_all(PROFILE_DIR).context("Failed to create profile directory")?;
+
+    let mut command = Command::new(&command_line[0]);
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command
+        .args(&command_line[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => AppError::NotSupported(format!("{} not found on PATH", tool.program())).into(),
+            _ => anyhow::Error::new(e).context(format!("Failed to start {}", tool.program())),
+        })?;
+
+    let group = ProcessGroup::of(&child);
+    status.pid = child.id();
+    let status = claim.commit(status);
+
+    let readers = [
+        child.stdout.take().map(spawn_output_reader),
+        child.stderr.take().map(spawn_output_reader),
+    ];
+    let report = format!("{}.{}", output_base, tool.report_extension());
+    let task = tokio::spawn(async move {
+        let waited = nvml::blocking(move || {
+            let exit_code = wait_for_exit(&mut child, &group)?;
+            for reader in readers.into_iter().flatten() {
+                reader.join().ok();
+            }
+            Ok(exit_code)
+        }).await;
+
+        let (exit_code, result) = match waited {
+            Ok(exit_code) if Path::new(&report).exists() => {
+                (exit_code, nvml::process_nsight_report(report.clone()).await.map_err(|e| format!("{:#}", e)))
+            }
+            Ok(exit_code) => (exit_code, Err(format!("{} exited without writing a report", tool.program()))),
+            Err(e) => (None, Err(format!("{:#}", e))),
+        };
+
+        let finished = {
+            let mut state = PROFILING_STATE.write().unwrap();
+            let status = state.as_mut().expect("profiling state is set while a session runs");
+            status.running = false;
+            status.exit_code = exit_code;
+            status.report_path = Path::new(&report).exists().then_some(report);
+            match result {
+                Ok(analysis) => status.analysis = Some(analysis),
+                Err(error) => status.error = Some(error),
+            }
+            status.clone()
+        };
+        if let Err(e) = window.emit("profiling-finished", &finished) {
+            eprintln!("Failed to emit profiling finished event: {}", e);
+        }
+    });
+    *PROFILING_TASK.lock().unwrap() = Some(task);
+
+    Ok(status)
+}
+
+/// Kill the running profiler
+///
+/// The report of a killed session is usually missing or incomplete.
+pub async fn stop_profiling_session() -> Result<ProfilingStatus> {
+    let state = PROFILING_STATE.read().unwrap();
+    match state.as_ref() {
+        Some(status) if status.running => {
+            PROFILING_STOP.store(true, Ordering::SeqCst);
+            Ok(status.clone())
+        }
+        _ => Err(AppError::InvalidArgument("No profiling session is running".to_string()).into()),
+    }
+}
+
+/// Kill any running profiler and wait until its session has ended
+pub async fn finish_active_profiling_session() -> Result<()> {
+    PROFILING_STOP.store(true, Ordering::SeqCst);
+    let task = PROFILING_TASK.lock().unwrap().take();
+    if let Some(task) = task {
+        task.await.context("Profiling task ended abnormally")?;
+    }
+    Ok(())
+}
+
+/// Get the status of the current or most recent profiling session
+///
+/// # Returns
+/// * `Option<ProfilingStatus>` - Status, or `None` if no session has been run
+pub fn get_profiling_status() -> Option<ProfilingStatus> {
+    PROFILING_STATE.read().unwrap().clone()
+}
+
+/// Profiler command line writing its report to `<output_base>.<extension>`
+fn build_command(tool: ProfilerTool, target: &ProfileTarget, tool_args: &[String], output_base: &str) -> Result<Vec<String>> {
+    let mut command = vec![tool.program().to_string()];
+    match (tool, target) {
+        (ProfilerTool::Ncu, _) => command.extend(["--export", output_base, "--force-overwrite"].map(String::from)),
+        (ProfilerTool::Nsys, ProfileTarget::Launch { .. }) => {
+            command.extend(["profile", "--output", output_base, "--force-overwrite", "true"].map(String::from))
+        }
+        (ProfilerTool::Nsys, ProfileTarget::Attach) => {
+            return Err(AppError::NotSupported("nsys cannot attach to a running application; launch it instead".to_string()).into());
+        }
+    }
+    if *target == ProfileTarget::Attach {
+        command.extend(["--mode", "attach", "--hostname", "127.0.0.1"].map(String::from));
+    }
+    command.extend(tool_args.iter().cloned());
+    if let ProfileTarget::Launch { program, args } = target {
+        if program.trim().is_empty() {
+            return Err(AppError::InvalidArgument("No program to profile".to_string()).into());
+        }
+        command.push(program.clone());
+        command.extend(args.iter().cloned());
+    }
+    Ok(command)
+}
+
+// Poll until the profiler exits, killing it and the application it
+// profiles if a stop is requested
+fn wait_for_exit(child: &mut Child, group: &ProcessGroup) -> Result<Option<i32>> {
+    loop {
+        if let Some(status) = child.try_wait().context("Failed to wait for profiler")? {
+            return Ok(status.code());
+        }
+        if PROFILING_STOP.load(Ordering::SeqCst) {
+            group.kill(child);
+            return Ok(child.wait().context("Failed to wait for profiler")?.code());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// The profiler and every process it starts: the profiler leads its own
+// process group on Unix and is placed in a job object on Windows
+struct ProcessGroup {
+    #[cfg(unix)]
+    id: libc::pid_t,
+    /// `None` if the job object could not be set up
+    #[cfg(windows)]
+    job: Option<std::os::windows::io::OwnedHandle>,
+}
+
+impl ProcessGroup {
+    fn of(child: &Child) -> Self {
+        #[cfg(unix)]
+        return ProcessGroup { id: child.id() as libc::pid_t };
+
+        #[cfg(windows)]
+        unsafe {
+            use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+            use windows_sys::Win32::System::JobObjects::{AssignProcessToJobObject, CreateJobObjectW};
+
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if job.is_null() {
+                eprintln!("Failed to create a job object for the profiler");
+                return ProcessGroup { job: None };
+            }
+            let job = OwnedHandle::from_raw_handle(job);
+            // Processes the profiler starts from now on join the job too
+            if AssignProcessToJobObject(job.as_raw_handle(), child.as_raw_handle()) == 0 {
+                eprintln!("Failed to place the profiler in a job object");
+                return ProcessGroup { job: None };
+            }
+            ProcessGroup { job: Some(job) }
+        }
+    }
+
+    // Kill every process in the group; the profiler may already have exited
+    fn kill(&self, child: &mut Child) {
+        #[cfg(unix)]
+        unsafe {
+            libc::kill(-self.id, libc::SIGKILL);
+        }
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            use std::os::windows::io::AsRawHandle;
+            unsafe { windows_sys::Win32::System::JobObjects::TerminateJobObject(job.as_raw_handle(), 1) };
+        }
+        child.kill().ok();
+    }
+}
+
+// Copy lines of profiler output into the session's output tail
+fn spawn_output_reader(output: impl Read + Send + 'static) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        for line in BufReader::new(output).lines().map_while(Result::ok) {
+            if let Some(status) = PROFILING_STATE.write().unwrap().as_mut() {
+                push_tail(&mut status.output_tail, line);
+            }
+        }
+    })
+}
+
+fn push_tail(tail: &mut VecDeque<String>, line: String) {
+    if tail.len() == OUTPUT_TAIL_LINES {
+        tail.pop_front();
+    }
+    tail.push_back(line);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kill_ends_processes_started_by_the_profiler() {
+        // Stands in for a profiler whose application outlives a kill of the profiler alone
+        let mut command = Command::new("sh");
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut child = command
+            .args(["-c", "sleep 30 & echo $!; wait"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+        let application: libc::pid_t = line.trim().parse().unwrap();
+
+        ProcessGroup::of(&child).kill(&mut child);
+        child.wait().unwrap();
+        // Gone entirely, or a zombie awaiting its reparented parent
+        std::thread::sleep(Duration::from_millis(100));
+        let status = std::fs::read_to_string(format!("/proc/{}/stat", application)).unwrap_or_default();
+        assert!(status.is_empty() || status.contains(") Z "), "application still running: {}", status);
+    }
+
+    fn launch(program: &str) -> ProfileTarget {
+        ProfileTarget::Launch { program: program.to_string(), args: vec!["--epochs".to_string(), "1".to_string()] }
+    }
+
+    #[test]
+    fn test_build_command() {
+        let options = vec!["--set".to_string(), "full".to_string()];
+        assert_eq!(
+            build_command(ProfilerTool::Ncu, &launch("./train"), &options, "profiles/p").unwrap().join(" "),
+            "ncu --export profiles/p --force-overwrite --set full ./train --epochs 1"
+        );
+        assert_eq!(
+            build_command(ProfilerTool::Nsys, &launch("python"), &[], "profiles/p").unwrap().join(" "),
+            "nsys profile --output profiles/p --force-overwrite true python --epochs 1"
+        );
+        assert_eq!(
+            build_command(ProfilerTool::Ncu, &ProfileTarget::Attach, &[], "profiles/p").unwrap().join(" "),
+            "ncu --export profiles/p --force-overwrite --mode attach --hostname 127.0.0.1"
+        );
+    }
+
+    #[test]
+    fn test_build_command_rejects_unsupported_targets() {
+        let err = build_command(ProfilerTool::Nsys, &ProfileTarget::Attach, &[], "p").unwrap_err();
+        assert_eq!(AppError::from(err).code(), "NOT_SUPPORTED");
+        let err = build_command(ProfilerTool::Ncu, &launch(" "), &[], "p").unwrap_err();
+        assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+    }
+
+    #[test]
+    fn test_output_tail_keeps_latest_lines() {
+        let mut tail = VecDeque::new();
+        for i in 0..OUTPUT_TAIL_LINES + 5 {
+            push_tail(&mut tail, i.to_string());
+        }
+        assert_eq!(tail.len(), OUTPUT_TAIL_LINES);
+        assert_eq!(tail.front().map(String::as_str), Some("5"));
+    }
+}
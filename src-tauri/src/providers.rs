@@ -0,0 +1,323 @@
+//! Extra metric sources merged into telemetry frames
+//!
+//! NVML only knows about the GPU. A `MetricProvider` contributes further
+//! values — motherboard sensors, PSU telemetry, a wall-power meter — which
+//! are sampled on the sampling thread right after the NVML queries and
+//! stored in each frame's `provider_metrics`, keyed `<provider>.<metric>`.
+//! Streams, recordings and triggers all sample through the same thread, so
+//! provider values reach every part of the pipeline that keeps frames.
+//!
+//! Providers are registered at compile time in `registry`; third-party
+//! providers are added there behind their own Cargo feature. Registered
+//! providers are off until enabled, and enabling one applies to samplers
+//! started afterwards (e.g. after restarting the stream).
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use crate::error::AppError;
+use crate::nvml::StaticDeviceInfo;
+
+/// One value a provider reports
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct MetricDescriptor {
+    /// Key within the provider, e.g. "k10temp.tctl_c"
+    pub name: String,
+    pub unit: String,
+}
+
+/// A source of extra metrics
+///
+/// Instances live on a sampling thread and are sampled once per frame of
+/// every device it samples; sources that are not per-GPU can ignore the
+/// device and report the same values for each.
+pub trait MetricProvider: Send {
+    /// Values this provider reports
+    fn metrics(&self) -> Vec<MetricDescriptor>;
+
+    /// Read the current values for a frame of `device`
+    fn sample(&mut self, device: &StaticDeviceInfo) -> Result<BTreeMap<String, f64>>;
+}
+
+/// A provider known to this build
+pub struct ProviderRegistration {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Create an instance; fails when the source is not present on this machine
+    pub create: fn() -> Result<Box<dyn MetricProvider>>,
+}
+
+/// A registered provider, as listed to the frontend
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ProviderInfo {
+    pub name: String,
+    pub description: String,
+    pub enabled: bool,
+    /// Why the provider cannot run here, if it cannot
+    pub unavailable_reason: Option<String>,
+    pub metrics: Vec<MetricDescriptor>,
+}
+
+// Names of the providers new samplers instantiate
+static ENABLED_PROVIDERS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Providers compiled into this build
+pub fn registry() -> Vec<ProviderRegistration> {
+    vec![
+        #[cfg(target_os = "linux")]
+        ProviderRegistration {
+            name: "hwmon",
+            description: "Motherboard, CPU and PSU sensors exposed through Linux hwmon",
+            create: || Ok(Box::new(hwmon::HwmonProvider::scan(std::path::Path::new(hwmon::SYSFS_HWMON))?)),
+        },
+    ]
+}
+
+/// List the registered providers with their availability and metrics
+pub fn list_providers() -> Vec<ProviderInfo> {
+    let enabled = ENABLED_PROVIDERS.read().unwrap();
+    registry().into_iter()
+        .map(|registration| {
+            let (unavailable_reason, metrics) = match (registration.create)() {
+                Ok(provider) => (None, provider.metrics()),
+                Err(e) => (Some(format!("{:#}", e)), Vec::new()),
+            };
+            ProviderInfo {
+                name: registration.name.to_string(),
+                description: registration.description.to_string(),
+                enabled: enabled.iter().any(|name| name == registration.name),
+                unavailable_reason,
+                metrics,
+            }
+        })
+        .collect()
+}
+
+/// Choose the providers samplers started from now on use
+///
+/// # Arguments
+/// * `names` - Registered provider names; empty disables all providers
+///
+/// # Returns
+/// * `Result<Vec<ProviderInfo>>` - Updated provider list or error for an unknown name
+pub fn set_enabled_providers(names: Vec<String>) -> Result<Vec<ProviderInfo>> {
+    let registry = registry();
+    if let Some(unknown) = names.iter().find(|name| !registry.iter().any(|registration| registration.name == name.as_str())) {
+        return Err(AppError::InvalidArgument(format!("Unknown metric provider: {}", unknown)).into());
+    }
+    *ENABLED_PROVIDERS.write().unwrap() = names;
+    Ok(list_providers())
+}
+
+/// Instances of the enabled providers, keyed by provider name
+///
+/// Providers that cannot be created are skipped with a message.
+pub fn create_enabled() -> Vec<(&'static str, Box<dyn MetricProvider>)> {
+    let enabled = ENABLED_PROVIDERS.read().unwrap().clone();
+    registry().into_iter()
+        .filter(|registration| enabled.iter().any(|name| name == registration.name))
+        .filter_map(|registration| match (registration.create)() {
+            Ok(provider) => Some((registration.name, provider)),
+            Err(e) => {
+                eprintln!("Metric provider {} unavailable: {:#}", registration.name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Sample every provider into one map keyed `<provider>.<metric>`
+///
+/// A provider that fails leaves its values out of this frame.
+pub fn sample_all(providers: &mut [(&'static str, Box<dyn MetricProvider>)], device: &StaticDeviceInfo) -> BTreeMap<String, f64> {
+    let mut values = BTreeMap::new();
+    for (name, provider) in providers.iter_mut() {
+        match provider.sample(device) {
+            Ok(sampled) => values.extend(sampled.into_iter().map(|(metric, value)| (format!("{}.{}", name, metric), value))),
+            Err(e) => eprintln!("Metric provider {} failed: {:#}", name, e),
+        }
+    }
+    values
+}
+
+#[cfg(target_os = "linux")]
+mod hwmon {
+    //! Sensors of the Linux hwmon subsystem
+    //!
+    //! Each chip under `/sys/class/hwmon` exposes `<type><n>_input` files
+    //! in fixed units: temperatures in m°C, fan speeds in RPM, voltages in
+    //! mV and power in µW.
+
+    use anyhow::{Context, Result};
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::{MetricDescriptor, MetricProvider};
+    use crate::error::AppError;
+    use crate::nvml::StaticDeviceInfo;
+
+    pub const SYSFS_HWMON: &str = "/sys/class/hwmon";
+
+    // Input file prefix, unit, and factor from the raw value to that unit
+    const SENSOR_KINDS: [(&str, &str, f64); 4] = [
+        ("temp", "c", 0.001),
+        ("fan", "rpm", 1.0),
+        ("in", "v", 0.001),
+        ("power", "w", 0.000_001),
+    ];
+
+    struct Sensor {
+        name: String,
+        unit: &'static str,
+        path: PathBuf,
+        scale: f64,
+    }
+
+    pub struct HwmonProvider {
+        sensors: Vec<Sensor>,
+    }
+
+    impl HwmonProvider {
+        /// Find the readable sensors of every chip under `root`
+        pub fn scan(root: &Path) -> Result<Self> {
+            let mut chips: Vec<PathBuf> = fs::read_dir(root)
+                .with_context(|| format!("Failed to read {}", root.display()))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .collect();
+            chips.sort();
+
+            let mut sensors = Vec::new();
+            for chip in chips {
+                let chip_name = fs::read_to_string(chip.join("name")).map(|name| sanitize(&name)).unwrap_or_default();
+                if chip_name.is_empty() {
+                    continue;
+                }
+                let mut files: Vec<String> = fs::read_dir(&chip).into_iter().flatten()
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .collect();
+                files.sort();
+                for file in files {
+                    let Some(sensor) = file.strip_suffix("_input") else {
+                        continue;
+                    };
+                    let Some(&(prefix, unit, scale)) = SENSOR_KINDS.iter().find(|(prefix, _, _)| sensor.starts_with(prefix)) else {
+                        continue;
+                    };
+                    // `in` also prefixes `intrusion0_*`; sensors are numbered
+                    if !sensor[prefix.len()..].chars().all(|c| c.is_ascii_digit()) {
+                        continue;
+                    }
+                    let path = chip.join(&file);
+                    if fs::read_to_string(&path).ok().and_then(|text| text.trim().parse::<f64>().ok()).is_none() {
+                        continue;
+                    }
+                    let label = fs::read_to_string(chip.join(format!("{}_label", sensor)))
+                        .map(|label| sanitize(&label))
+                        .ok()
+                        .filter(|label| !label.is_empty())
+                        .unwrap_or_else(|| sensor.to_string());
+                    sensors.push(Sensor { name: format!("{}.{}_{}", chip_name, label, unit), unit, path, scale });
+                }
+            }
+            if sensors.is_empty() {
+                return Err(AppError::NotSupported("No hwmon sensors found".to_string()).into());
+            }
+            Ok(HwmonProvider { sensors })
+        }
+    }
+
+    impl MetricProvider for HwmonProvider {
+        fn metrics(&self) -> Vec<MetricDescriptor> {
+            self.sensors.iter()
+                .map(|sensor| MetricDescriptor { name: sensor.name.clone(), unit: sensor.unit.to_string() })
+                .collect()
+        }
+
+        // A sensor that stops answering (e.g. a powered-down fan header) is left out
+        fn sample(&mut self, _device: &StaticDeviceInfo) -> Result<BTreeMap<String, f64>> {
+            Ok(self.sensors.iter()
+                .filter_map(|sensor| {
+                    let raw: f64 = fs::read_to_string(&sensor.path).ok()?.trim().parse().ok()?;
+                    Some((sensor.name.clone(), raw * sensor.scale))
+                })
+                .collect())
+        }
+    }
+
+    // Lowercase ASCII words joined by underscores, e.g. "CPU Fan" -> "cpu_fan"
+    fn sanitize(text: &str) -> String {
+        text.trim().to_ascii_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("_")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_scans_and_scales_sensors() {
+            let root = std::env::temp_dir().join(format!("nsightful_hwmon_{}", std::process::id()));
+            let chip = root.join("hwmon0");
+            fs::create_dir_all(&chip).unwrap();
+            for (file, content) in [
+                ("name", "nct6798\n"), ("temp1_input", "45500\n"), ("temp1_label", "CPU Socket\n"),
+                ("fan2_input", "1200\n"), ("power1_input", "65000000\n"), ("intrusion0_input", "0\n"),
+            ] {
+                fs::write(chip.join(file), content).unwrap();
+            }
+
+            let mut provider = HwmonProvider::scan(&root).unwrap();
+            let names: Vec<String> = provider.metrics().into_iter().map(|metric| metric.name).collect();
+            assert_eq!(names, vec!["nct6798.fan2_rpm", "nct6798.power1_w", "nct6798.cpu_socket_c"]);
+            let values = provider.sample(&StaticDeviceInfo::default()).unwrap();
+            assert_eq!(values["nct6798.cpu_socket_c"], 45.5);
+            assert_eq!(values["nct6798.power1_w"], 65.0);
+
+            assert!(HwmonProvider::scan(&root.join("missing")).is_err());
+            fs::remove_dir_all(&root).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(f64);
+
+    impl MetricProvider for Fixed {
+        fn metrics(&self) -> Vec<MetricDescriptor> {
+            vec![MetricDescriptor { name: "value".to_string(), unit: "w".to_string() }]
+        }
+
+        fn sample(&mut self, device: &StaticDeviceInfo) -> Result<BTreeMap<String, f64>> {
+            if device.index > 0 {
+                anyhow::bail!("only GPU 0");
+            }
+            Ok(BTreeMap::from([("value".to_string(), self.0)]))
+        }
+    }
+
+    #[test]
+    fn test_sample_all_prefixes_provider_names() {
+        let mut providers: Vec<(&'static str, Box<dyn MetricProvider>)> = vec![("psu", Box::new(Fixed(310.0))), ("meter", Box::new(Fixed(450.0)))];
+        let values = sample_all(&mut providers, &StaticDeviceInfo::default());
+        assert_eq!(values, BTreeMap::from([("meter.value".to_string(), 450.0), ("psu.value".to_string(), 310.0)]));
+
+        // A failing provider leaves its values out
+        let second = StaticDeviceInfo { index: 1, ..Default::default() };
+        assert!(sample_all(&mut providers, &second).is_empty());
+    }
+
+    #[test]
+    fn test_rejects_unknown_provider() {
+        let err = set_enabled_providers(vec!["bogus".to_string()]).unwrap_err();
+        assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+    }
+}
@@ -0,0 +1,183 @@
+//! Device rankings for multi-GPU summary widgets
+//!
+//! Dashboards with many GPUs show "hottest", "most power" or "most VRAM
+//! used" at a glance. The history keeps every streamed device's scalar
+//! metrics (the columns of a columnar recording) for a rolling window, and
+//! a ranking orders the devices by the mean of one metric over the most
+//! recent part of it. The same values back live metric histograms.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+use crate::columnar::METRIC_COLUMNS;
+use crate::error::AppError;
+use crate::nvml::TelemetryFrame;
+use crate::triggers::TriggerMetric;
+
+/// How far back streamed values are kept for rankings
+pub const RANKING_RETENTION_MS: u128 = 5 * 60 * 1000;
+/// Window used when a ranking query does not specify one
+pub const DEFAULT_WINDOW_SECONDS: u64 = 30;
+
+/// Position of one device in a ranking
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct DeviceRanking {
+    /// 1 for the device with the highest mean
+    pub rank: usize,
+    pub device_index: u32,
+    pub mean: f64,
+    pub max: f64,
+    /// Value in the device's most recent sample
+    pub latest: f64,
+    pub sample_count: usize,
+}
+
+// Every metric's value in one frame, indexed as `METRIC_COLUMNS`
+#[derive(Clone, Copy, Debug)]
+struct RankingSample {
+    /// Monotonic time of the frame (see `TelemetryFrame::clock_ms`)
+    clock_ms: f64,
+    values: [f64; METRIC_COLUMNS.len()],
+}
+
+/// Rolling per-device history of metric values
+#[derive(Default)]
+pub struct RankingHistory {
+    devices: HashMap<u32, VecDeque<RankingSample>>,
+}
+
+impl RankingHistory {
+    /// Add a streamed frame, dropping samples older than `RANKING_RETENTION_MS`
+    pub fn record(&mut self, frame: &TelemetryFrame) {
+        let samples = self.devices.entry(frame.device_index).or_default();
+        samples.push_back(RankingSample {
+            clock_ms: frame.clock_ms(),
+            values: METRIC_COLUMNS.map(|(_, value)| value(frame)),
+        });
+        let cutoff = frame.clock_ms() - RANKING_RETENTION_MS as f64;
+        while samples.front().is_some_and(|sample| sample.clock_ms < cutoff) {
+            samples.pop_front();
+        }
+    }
+
+    /// Devices ordered by the mean of a metric, highest first
+    ///
+    /// The window ends at the latest sample of any device, so a device
+    /// that stopped streaming drops out of the ranking.
+    ///
+    /// # Arguments
+    /// * `metric` - Metric to rank by
+    /// * `window_ms` - How far back from the latest sample to look
+    /// * `limit` - Keep only the top `limit` devices, if set
+    ///
+    /// # Returns
+    /// * `Vec<DeviceRanking>` - Ranked devices; empty if nothing has been streamed
+    pub fn rankings(&self, metric: TriggerMetric, window_ms: u64, limit: Option<usize>) -> Vec<DeviceRanking> {
+        let position = column_position(metric.column())
+            .expect("every trigger metric is a recording column");
+        let Some(latest) = self.devices.values().filter_map(|samples| samples.back()).map(|sample| sample.clock_ms).max_by(f64::total_cmp) else {
+            return Vec::new();
+        };
+        let cutoff = latest - window_ms as f64;
+
+        let mut rankings: Vec<DeviceRanking> = self.devices.iter()
+            .filter_map(|(&device_index, samples)| {
+                let values: Vec<f64> = samples.iter()
+                    .filter(|sample| sample.clock_ms >= cutoff)
+                    .map(|sample| sample.values[position])
+                    .collect();
+                Some(DeviceRanking {
+                    rank: 0,
+                    device_index,
+                    mean: values.iter().sum::<f64>() / values.len() as f64,
+                    max: values.iter().copied().fold(f64::MIN, f64::max),
+                    latest: *values.last()?,
+                    sample_count: values.len(),
+                })
+            })
+            .collect();
+        rankings.sort_by(|a, b| b.mean.total_cmp(&a.mean).then(a.device_index.cmp(&b.device_index)));
+        rankings.truncate(limit.unwrap_or(usize::MAX));
+        for (position, ranking) in rankings.iter_mut().enumerate() {
+            ranking.rank = position + 1;
+        }
+        rankings
+    }
+
+    /// Values of one metric over the most recent `window_ms` of a device's samples
+    ///
+    /// # Arguments
+    /// * `device_index` - Device to read
+    /// * `metric` - Recording column name, e.g. "temperature_c"
+    /// * `window_ms` - How far back from the device's latest sample to look
+    ///
+    /// # Returns
+    /// * `Result<Vec<f64>>` - Values, oldest first, or error for an unknown metric or unstreamed device
+    pub fn values(&self, device_index: u32, metric: &str, window_ms: u64) -> Result<Vec<f64>> {
+        let position = column_position(metric)
+            .ok_or_else(|| AppError::InvalidArgument(format!("Unknown metric: {}", metric)))?;
+        let samples = self.devices.get(&device_index)
+            .filter(|samples| !samples.is_empty())
+            .ok_or_else(|| AppError::InvalidArgument(format!("No streamed samples for GPU {}", device_index)))?;
+        let cutoff = samples.back().map_or(0.0, |sample| sample.clock_ms) - window_ms as f64;
+        Ok(samples.iter()
+            .filter(|sample| sample.clock_ms >= cutoff)
+            .map(|sample| sample.values[position])
+            .collect())
+    }
+}
+
+fn column_position(metric: &str) -> Option<usize> {
+    METRIC_COLUMNS.iter().position(|(name, _)| *name == metric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(device_index: u32, timestamp: u128, temperature_c: u32, power_w: f32) -> TelemetryFrame {
+        TelemetryFrame { device_index, timestamp, temperature_c, power_w, ..Default::default() }
+    }
+
+    #[test]
+    fn test_orders_devices_by_mean_over_window() {
+        let mut history = RankingHistory::default();
+        for (device, timestamp, temperature, power) in [(0, 0, 90, 100.0), (0, 1_000, 60, 100.0), (1, 1_000, 70, 300.0), (2, 1_000, 65, 200.0)] {
+            history.record(&frame(device, timestamp, temperature, power));
+        }
+
+        let hottest = history.rankings(TriggerMetric::TemperatureC, 10_000, None);
+        let order: Vec<(usize, u32)> = hottest.iter().map(|ranking| (ranking.rank, ranking.device_index)).collect();
+        assert_eq!(order, vec![(1, 0), (2, 1), (3, 2)]);
+        assert_eq!((hottest[0].mean, hottest[0].max, hottest[0].latest, hottest[0].sample_count), (75.0, 90.0, 60.0, 2));
+
+        // Only the latest second: device 0 is at 60 °C
+        let recent = history.rankings(TriggerMetric::TemperatureC, 500, None);
+        assert_eq!(recent.last().map(|ranking| ranking.device_index), Some(0));
+    }
+
+    #[test]
+    fn test_limit_and_stale_devices() {
+        let mut history = RankingHistory::default();
+        history.record(&frame(0, 0, 50, 500.0));
+        history.record(&frame(1, 60_000, 50, 100.0));
+        history.record(&frame(2, 60_000, 50, 200.0));
+
+        let top = history.rankings(TriggerMetric::PowerW, 30_000, Some(1));
+        assert_eq!(top.len(), 1);
+        assert_eq!((top[0].rank, top[0].device_index), (1, 2));
+        assert!(RankingHistory::default().rankings(TriggerMetric::PowerW, 30_000, None).is_empty());
+    }
+
+    #[test]
+    fn test_values_of_one_metric() {
+        let mut history = RankingHistory::default();
+        for timestamp in [0, 1_000, 2_000] {
+            history.record(&frame(0, timestamp, 40 + timestamp as u32 / 100, 0.0));
+        }
+        assert_eq!(history.values(0, "temperature_c", 1_000).unwrap(), vec![50.0, 60.0]);
+        assert!(history.values(0, "util_sm", 1_000).is_err());
+        assert!(history.values(1, "temperature_c", 1_000).is_err());
+    }
+}
@@ -0,0 +1,312 @@
+//! Rule-based kernel recommendations
+//!
+//! Each rule inspects the parsed metrics of one kernel and, when its
+//! condition holds, produces a recommendation carrying the metric values
+//! that triggered it. New checks are added by appending to `RULES`.
+//! Kernel metrics a report did not collect read as zero and do not fire
+//! rules.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::nvml::KernelAnalysis;
+
+/// Occupancy below which a kernel is flagged
+const LOW_OCCUPANCY_PERCENT: f64 = 50.0;
+/// Occupancy below which low occupancy is high severity
+const VERY_LOW_OCCUPANCY_PERCENT: f64 = 25.0;
+/// Registers per thread above which register pressure is flagged
+const HIGH_REGISTERS_PER_THREAD: u32 = 64;
+/// SM efficiency (share of time SMs have at least one active warp) below which the kernel is flagged
+const LOW_SM_EFFICIENCY_PERCENT: f64 = 60.0;
+/// DRAM throughput above which a kernel is considered DRAM-bound
+const DRAM_BOUND_PERCENT: f64 = 80.0;
+/// Sector efficiency below which global accesses are considered uncoalesced
+const LOW_SECTOR_EFFICIENCY_PERCENT: f64 = 50.0;
+/// L2 hit rate below which locality is flagged
+const LOW_L2_HIT_RATE_PERCENT: f64 = 50.0;
+/// L1 hit rate below which reuse through shared memory is suggested
+const LOW_L1_HIT_RATE_PERCENT: f64 = 20.0;
+
+/// How much a finding is expected to matter
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+/// Metric value that triggered a recommendation
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Evidence {
+    pub metric: String,
+    pub value: f64,
+    pub threshold: f64,
+}
+
+/// A finding for one kernel with the evidence behind it
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Recommendation {
+    /// Identifier of the rule that produced this recommendation
+    pub rule: String,
+    pub kernel: String,
+    pub severity: Severity,
+    /// Short bottleneck label, e.g. "Low occupancy"
+    pub bottleneck: String,
+    pub message: String,
+    pub evidence: Vec<Evidence>,
+}
+
+/// Outcome of a rule that fired
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    pub evidence: Vec<Evidence>,
+}
+
+/// A single check over a kernel's metrics
+pub struct Rule {
+    pub id: &'static str,
+    pub bottleneck: &'static str,
+    /// Returns a finding when the rule fires
+    pub check: fn(&KernelAnalysis) -> Option<Finding>,
+}
+
+/// Rules evaluated for every kernel
+pub const RULES: &[Rule] = &[
+    Rule { id: "low_occupancy", bottleneck: "Low occupancy", check: check_low_occupancy },
+    Rule { id: "register_pressure", bottleneck: "Register pressure", check: check_register_pressure },
+    Rule { id: "low_sm_efficiency", bottleneck: "Idle SMs", check: check_low_sm_efficiency },
+    Rule { id: "dram_bound", bottleneck: "DRAM bandwidth", check: check_dram_bound },
+    Rule { id: "uncoalesced_access", bottleneck: "Uncoalesced memory access", check: check_uncoalesced_access },
+    Rule { id: "low_l2_hit_rate", bottleneck: "Poor L2 locality", check: check_low_l2_hit_rate },
+    Rule { id: "low_l1_hit_rate", bottleneck: "Poor L1 reuse", check: check_low_l1_hit_rate },
+];
+
+/// Evaluate rules against every kernel launch
+///
+/// A rule firing on several launches of one kernel is reported once, from
+/// its most severe launch.
+///
+/// # Arguments
+/// * `kernels` - Parsed kernel analyses, one per launch
+/// * `rules` - Rules to evaluate (usually `RULES`)
+///
+/// # Returns
+/// * `Vec<Recommendation>` - Findings, most severe first and kernel with the most total time first within a severity
+pub fn evaluate(kernels: &[KernelAnalysis], rules: &[Rule]) -> Vec<Recommendation> {
+    let mut total_ms: HashMap<&str, f64> = HashMap::new();
+    for kernel in kernels {
+        *total_ms.entry(kernel.name.as_str()).or_default() += kernel.duration_ms;
+    }
+    let mut findings: Vec<(f64, Recommendation)> = kernels.iter()
+        .flat_map(|kernel| rules.iter().filter_map(|rule| {
+            (rule.check)(kernel).map(|finding| (total_ms[kernel.name.as_str()], Recommendation {
+                rule: rule.id.to_string(),
+                kernel: kernel.name.clone(),
+                severity: finding.severity,
+                bottleneck: rule.bottleneck.to_string(),
+                message: finding.message,
+                evidence: finding.evidence,
+            }))
+        }))
+        .collect();
+
+    findings.sort_by(|(a_ms, a), (b_ms, b)| b.severity.cmp(&a.severity).then(b_ms.total_cmp(a_ms)));
+    let mut reported = HashSet::new();
+    findings.into_iter()
+        .map(|(_, recommendation)| recommendation)
+        .filter(|recommendation| reported.insert((recommendation.rule.clone(), recommendation.kernel.clone())))
+        .collect()
+}
+
+/// Distinct bottleneck labels in priority order
+pub fn bottlenecks(recommendations: &[Recommendation]) -> Vec<String> {
+    let mut labels: Vec<String> = Vec::new();
+    for recommendation in recommendations {
+        if !labels.contains(&recommendation.bottleneck) {
+            labels.push(recommendation.bottleneck.clone());
+        }
+    }
+    labels
+}
+
+// Finding backed by a single metric
+fn finding(severity: Severity, message: String, metric: &str, value: f64, threshold: f64) -> Option<Finding> {
+    Some(Finding {
+        severity,
+        message,
+        evidence: vec![Evidence { metric: metric.to_string(), value, threshold }],
+    })
+}
+
+fn check_low_occupancy(kernel: &KernelAnalysis) -> Option<Finding> {
+    let occupancy = kernel.occupancy_percent;
+    if occupancy <= 0.0 || occupancy >= LOW_OCCUPANCY_PERCENT {
+        return None;
+    }
+    let severity = if occupancy < VERY_LOW_OCCUPANCY_PERCENT { Severity::High } else { Severity::Medium };
+    let message = format!(
+        "{}: achieved occupancy is {:.1}%; increase the block size ({} threads) or reduce registers and shared memory per block",
+        kernel.name, occupancy, kernel.block_size.0 * kernel.block_size.1 * kernel.block_size.2
+    );
+    finding(severity, message, "occupancy_percent", occupancy, LOW_OCCUPANCY_PERCENT)
+}
+
+fn check_register_pressure(kernel: &KernelAnalysis) -> Option<Finding> {
+    let registers = kernel.registers_per_thread;
+    if registers <= HIGH_REGISTERS_PER_THREAD {
+        return None;
+    }
+    // Register pressure only matters when it is actually limiting occupancy
+    let occupancy_limited = kernel.occupancy_percent > 0.0 && kernel.occupancy_percent < LOW_OCCUPANCY_PERCENT;
+    let severity = if occupancy_limited { Severity::High } else { Severity::Low };
+    let message = format!(
+        "{}: uses {} registers per thread; cap them with __launch_bounds__ or -maxrregcount to fit more warps per SM",
+        kernel.name, registers
+    );
+    finding(severity, message, "registers_per_thread", registers as f64, HIGH_REGISTERS_PER_THREAD as f64)
+}
+
+fn check_low_sm_efficiency(kernel: &KernelAnalysis) -> Option<Finding> {
+    let efficiency = kernel.sm_efficiency;
+    if efficiency <= 0.0 || efficiency >= LOW_SM_EFFICIENCY_PERCENT {
+        return None;
+    }
+    let message = format!(
+        "{}: SMs have active warps only {:.1}% of the time; launch more blocks (grid is {} blocks) or balance work across blocks",
+        kernel.name, efficiency, kernel.grid_size.0 * kernel.grid_size.1 * kernel.grid_size.2
+    );
+    finding(Severity::Medium, message, "sm_efficiency", efficiency, LOW_SM_EFFICIENCY_PERCENT)
+}
+
+fn check_dram_bound(kernel: &KernelAnalysis) -> Option<Finding> {
+    let throughput = kernel.memory_workload.as_ref()?.dram_throughput_percent?;
+    if throughput <= DRAM_BOUND_PERCENT {
+        return None;
+    }
+    let message = format!(
+        "{}: DRAM throughput is {:.1}% of peak; reduce traffic by reusing data in shared memory, using smaller data types or fusing kernels",
+        kernel.name, throughput
+    );
+    finding(Severity::High, message, "dram_throughput_percent", throughput, DRAM_BOUND_PERCENT)
+}
+
+fn check_uncoalesced_access(kernel: &KernelAnalysis) -> Option<Finding> {
+    let workload = kernel.memory_workload.as_ref()?;
+    let evidence: Vec<Evidence> = [
+        ("load_sector_efficiency_percent", workload.load_sector_efficiency_percent),
+        ("store_sector_efficiency_percent", workload.store_sector_efficiency_percent),
+    ]
+    .into_iter()
+    .filter_map(|(metric, value)| value.filter(|&value| value < LOW_SECTOR_EFFICIENCY_PERCENT).map(|value| (metric, value)))
+    .map(|(metric, value)| Evidence { metric: metric.to_string(), value, threshold: LOW_SECTOR_EFFICIENCY_PERCENT })
+    .collect();
+    if evidence.is_empty() {
+        return None;
+    }
+    let message = format!(
+        "{}: global accesses use less than half of each 32-byte sector; make adjacent threads access adjacent addresses to coalesce them",
+        kernel.name
+    );
+    Some(Finding { severity: Severity::High, message, evidence })
+}
+
+fn check_low_l2_hit_rate(kernel: &KernelAnalysis) -> Option<Finding> {
+    let hit_rate = kernel.memory_workload.as_ref()?.l2_hit_rate_percent?;
+    if hit_rate >= LOW_L2_HIT_RATE_PERCENT {
+        return None;
+    }
+    let message = format!(
+        "{}: L2 hit rate is {:.1}%; improve locality by tiling the working set or reordering accesses",
+        kernel.name, hit_rate
+    );
+    finding(Severity::Medium, message, "l2_hit_rate_percent", hit_rate, LOW_L2_HIT_RATE_PERCENT)
+}
+
+fn check_low_l1_hit_rate(kernel: &KernelAnalysis) -> Option<Finding> {
+    let hit_rate = kernel.memory_workload.as_ref()?.l1_hit_rate_percent?;
+    if hit_rate >= LOW_L1_HIT_RATE_PERCENT {
+        return None;
+    }
+    let message = format!(
+        "{}: L1 hit rate is {:.1}%; stage reused data in shared memory",
+        kernel.name, hit_rate
+    );
+    finding(Severity::Low, message, "l1_hit_rate_percent", hit_rate, LOW_L1_HIT_RATE_PERCENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ncu::MemoryWorkload;
+
+    fn kernel(name: &str, duration_ms: f64) -> KernelAnalysis {
+        KernelAnalysis {
+            name: name.to_string(),
+            duration_ms,
+            grid_size: (1024, 1, 1),
+            block_size: (256, 1, 1),
+            registers_per_thread: 32,
+            shared_memory_bytes: 0,
+            occupancy_percent: 80.0,
+            sm_efficiency: 95.0,
+            memory_efficiency: 80.0,
+            source_hotspots: Vec::new(),
+            memory_workload: None,
+        }
+    }
+
+    #[test]
+    fn test_healthy_kernel_has_no_findings() {
+        assert!(evaluate(&[kernel("gemm", 1.0)], RULES).is_empty());
+    }
+
+    #[test]
+    fn test_findings_are_prioritized_with_evidence() {
+        let mut short = kernel("short", 1.0);
+        short.occupancy_percent = 40.0;
+        let mut long = kernel("long", 10.0);
+        long.occupancy_percent = 40.0;
+        long.registers_per_thread = 128;
+        long.memory_workload = Some(MemoryWorkload {
+            dram_throughput_percent: Some(91.0),
+            ..Default::default()
+        });
+
+        let findings = evaluate(&[short, long], RULES);
+        let order: Vec<(&str, &str)> = findings.iter().map(|f| (f.kernel.as_str(), f.rule.as_str())).collect();
+        assert_eq!(order, vec![
+            ("long", "register_pressure"),
+            ("long", "dram_bound"),
+            ("long", "low_occupancy"),
+            ("short", "low_occupancy"),
+        ]);
+        assert_eq!(findings[1].evidence, vec![Evidence {
+            metric: "dram_throughput_percent".to_string(),
+            value: 91.0,
+            threshold: DRAM_BOUND_PERCENT,
+        }]);
+        assert_eq!(bottlenecks(&findings), vec!["Register pressure", "DRAM bandwidth", "Low occupancy"]);
+    }
+
+    #[test]
+    fn test_repeated_launches_are_reported_once() {
+        let mut launches: Vec<KernelAnalysis> = (0..3).map(|_| kernel("gemm", 1.0)).collect();
+        launches[1].occupancy_percent = 40.0;
+        launches[2].occupancy_percent = 20.0;
+        let mut other = kernel("reduce", 2.5);
+        other.occupancy_percent = 40.0;
+        launches.push(other);
+        // Metrics missing from the report read as zero
+        let mut uncollected = kernel("copy", 9.0);
+        (uncollected.occupancy_percent, uncollected.sm_efficiency) = (0.0, 0.0);
+        launches.push(uncollected);
+
+        let findings = evaluate(&launches, RULES);
+        let order: Vec<(&str, &str, Severity)> = findings.iter().map(|f| (f.kernel.as_str(), f.rule.as_str(), f.severity)).collect();
+        assert_eq!(order, vec![("gemm", "low_occupancy", Severity::High), ("reduce", "low_occupancy", Severity::Medium)]);
+        assert_eq!(findings[0].evidence[0].value, 20.0);
+    }
+}
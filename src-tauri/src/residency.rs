@@ -0,0 +1,232 @@
+//! P-state and clock residency
+//!
+//! Tracks how long each device spends in each performance state and SM
+//! clock bin. A GPU that never reaches P0 or sits in low clock bins under
+//! load points at power limits, thermals or a conservative driver policy.
+//!
+//! Each sample stands for the time until the next one, but no longer than
+//! the gap threshold of `gaps`: time the stream was paused or a device did
+//! not answer is reported separately instead of crediting the state before
+//! it.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::gaps;
+use crate::nvml::TelemetryFrame;
+
+/// Width of each SM clock bin
+pub const CLOCK_BIN_MHZ: u32 = 100;
+/// How far back streamed samples are kept for residency queries
+pub const HISTORY_RETENTION_MS: u128 = 15 * 60 * 1000;
+/// Window used when a residency query does not specify one
+pub const DEFAULT_WINDOW_SECONDS: u64 = 60;
+
+/// Time spent in one P-state or clock bin
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ResidencyBucket {
+    /// "P0".."P15", or an SM clock range such as "1800-1899 MHz"
+    pub label: String,
+    pub seconds: f64,
+    pub percent: f64,
+}
+
+/// Residency of one device over a window of samples
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Residency {
+    pub device_index: u32,
+    /// Time covered by the samples, which may be shorter than requested
+    pub window_seconds: f64,
+    /// Time in sampling gaps, beyond the capped interval credited before each
+    pub gap_seconds: f64,
+    pub sample_count: usize,
+    /// Time in each P-state, highest performance first; excludes samples without a P-state
+    pub pstates: Vec<ResidencyBucket>,
+    /// Time in each SM clock bin, lowest clock first
+    pub sm_clocks: Vec<ResidencyBucket>,
+}
+
+// The parts of a frame residency needs; the history keeps only these
+#[derive(Clone, Copy, Debug)]
+struct ResidencySample {
+    /// Monotonic time of the frame (see `TelemetryFrame::clock_ms`)
+    clock_ms: f64,
+    performance_state: Option<u32>,
+    sm_clock_mhz: u32,
+}
+
+impl From<&TelemetryFrame> for ResidencySample {
+    fn from(frame: &TelemetryFrame) -> Self {
+        ResidencySample {
+            clock_ms: frame.clock_ms(),
+            performance_state: frame.performance_state,
+            sm_clock_mhz: frame.sm_clock_mhz,
+        }
+    }
+}
+
+/// Rolling per-device history of streamed samples
+#[derive(Default)]
+pub struct ResidencyHistory {
+    devices: HashMap<u32, VecDeque<ResidencySample>>,
+}
+
+impl ResidencyHistory {
+    /// Add a streamed frame, dropping samples older than `HISTORY_RETENTION_MS`
+    pub fn record(&mut self, frame: &TelemetryFrame) {
+        let samples = self.devices.entry(frame.device_index).or_default();
+        samples.push_back(ResidencySample::from(frame));
+        let cutoff = frame.clock_ms() - HISTORY_RETENTION_MS as f64;
+        while samples.front().is_some_and(|sample| sample.clock_ms < cutoff) {
+            samples.pop_front();
+        }
+    }
+
+    /// Residency over the most recent `window_ms` of a device's samples
+    ///
+    /// # Arguments
+    /// * `device_index` - Device to report on
+    /// * `window_ms` - How far back from the latest sample to look
+    ///
+    /// # Returns
+    /// * `Option<Residency>` - Residency, or `None` if the device has no samples
+    pub fn residency(&self, device_index: u32, window_ms: u64) -> Option<Residency> {
+        let samples = self.devices.get(&device_index)?;
+        let cutoff = samples.back()?.clock_ms - window_ms as f64;
+        let window: Vec<ResidencySample> = samples.iter()
+            .filter(|sample| sample.clock_ms >= cutoff)
+            .copied()
+            .collect();
+        Some(compute(device_index, &window))
+    }
+}
+
+/// Residency over a complete sequence of frames, e.g. a recording
+pub fn from_frames(device_index: u32, frames: &[TelemetryFrame]) -> Residency {
+    let samples: Vec<ResidencySample> = frames.iter().map(ResidencySample::from).collect();
+    compute(device_index, &samples)
+}
+
+// Each sample stands for the time until the next one, capped at the gap
+// threshold; the last sample has nothing after it and only marks the end
+// of the window
+fn compute(device_index: u32, samples: &[ResidencySample]) -> Residency {
+    let mut pstate_ms: BTreeMap<u32, u64> = BTreeMap::new();
+    let mut clock_ms: BTreeMap<u32, u64> = BTreeMap::new();
+    let mut total_ms = 0u64;
+    let mut pstate_total_ms = 0u64;
+    let mut gap_ms = 0u64;
+
+    let intervals: Vec<u64> = samples.windows(2)
+        .map(|pair| (pair[1].clock_ms - pair[0].clock_ms).max(0.0).round() as u64)
+        .collect();
+    let cap = if intervals.is_empty() { 0 } else { gaps::default_threshold(&intervals) };
+
+    for (pair, &interval) in samples.windows(2).zip(&intervals) {
+        let duration = interval.min(cap);
+        gap_ms += interval - duration;
+        total_ms += duration;
+        *clock_ms.entry(pair[0].sm_clock_mhz / CLOCK_BIN_MHZ).or_default() += duration;
+        if let Some(pstate) = pair[0].performance_state {
+            *pstate_ms.entry(pstate).or_default() += duration;
+            pstate_total_ms += duration;
+        }
+    }
+
+    let bucket = |label: String, ms: u64, total: u64| ResidencyBucket {
+        label,
+        seconds: ms as f64 / 1000.0,
+        percent: if total == 0 { 0.0 } else { ms as f64 * 100.0 / total as f64 },
+    };
+    Residency {
+        device_index,
+        window_seconds: total_ms as f64 / 1000.0,
+        gap_seconds: gap_ms as f64 / 1000.0,
+        sample_count: samples.len(),
+        pstates: pstate_ms.into_iter()
+            .map(|(pstate, ms)| bucket(format!("P{}", pstate), ms, pstate_total_ms))
+            .collect(),
+        sm_clocks: clock_ms.into_iter()
+            .map(|(bin, ms)| {
+                let low = bin * CLOCK_BIN_MHZ;
+                bucket(format!("{}-{} MHz", low, low + CLOCK_BIN_MHZ - 1), ms, total_ms)
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp: u128, performance_state: Option<u32>, sm_clock_mhz: u32) -> TelemetryFrame {
+        TelemetryFrame { timestamp, performance_state, sm_clock_mhz, ..Default::default() }
+    }
+
+    #[test]
+    fn test_residency_is_time_weighted() {
+        let frames = [
+            frame(0, Some(8), 210),
+            frame(1_000, Some(0), 1_890),
+            frame(4_000, None, 1_950),
+            frame(5_000, Some(0), 1_950),
+        ];
+
+        let residency = from_frames(0, &frames);
+        assert_eq!(residency.window_seconds, 5.0);
+        assert_eq!(residency.gap_seconds, 0.0);
+        assert_eq!(residency.pstates, vec![
+            ResidencyBucket { label: "P0".to_string(), seconds: 3.0, percent: 75.0 },
+            ResidencyBucket { label: "P8".to_string(), seconds: 1.0, percent: 25.0 },
+        ]);
+        let labels: Vec<&str> = residency.sm_clocks.iter().map(|bucket| bucket.label.as_str()).collect();
+        assert_eq!(labels, vec!["200-299 MHz", "1800-1899 MHz", "1900-1999 MHz"]);
+        assert_eq!(residency.sm_clocks[1].percent, 60.0);
+    }
+
+    #[test]
+    fn test_gaps_are_not_credited_to_the_state_before_them() {
+        // Streaming paused for a minute while the device sat in P8
+        let frames = [
+            frame(0, Some(8), 300),
+            frame(1_000, Some(8), 300),
+            frame(2_000, Some(8), 300),
+            frame(62_000, Some(0), 1_900),
+            frame(63_000, Some(0), 1_900),
+            frame(64_000, Some(0), 1_900),
+        ];
+
+        let residency = from_frames(0, &frames);
+        assert_eq!(residency.window_seconds, 7.0);
+        assert_eq!(residency.gap_seconds, 57.0);
+        assert_eq!(residency.pstates, vec![
+            ResidencyBucket { label: "P0".to_string(), seconds: 2.0, percent: 2.0 * 100.0 / 7.0 },
+            ResidencyBucket { label: "P8".to_string(), seconds: 5.0, percent: 5.0 * 100.0 / 7.0 },
+        ]);
+    }
+
+    #[test]
+    fn test_history_window_and_retention() {
+        let mut history = ResidencyHistory::default();
+        for second in 0..=20u128 {
+            history.record(&frame(HISTORY_RETENTION_MS + second * 1_000, Some(2), 1_500));
+        }
+        history.record(&frame(2 * HISTORY_RETENTION_MS + 10_000, Some(0), 1_500));
+
+        let residency = history.residency(0, 60_000).unwrap();
+        assert_eq!(residency.sample_count, 1);
+        assert!(history.residency(1, 60_000).is_none());
+        // Samples more than the retention period older than the latest are dropped
+        assert_eq!(history.devices[&0].len(), 12);
+    }
+
+    #[test]
+    fn test_intervals_use_the_monotonic_clock() {
+        // The wall clock steps back between the samples; the monotonic clock does not
+        let frames = [
+            TelemetryFrame { timestamp: 10_000, monotonic_ms: 500.0, performance_state: Some(0), ..Default::default() },
+            TelemetryFrame { timestamp: 8_000, monotonic_ms: 1_500.0, performance_state: Some(0), ..Default::default() },
+        ];
+        assert_eq!(from_frames(0, &frames).window_seconds, 1.0);
+    }
+}
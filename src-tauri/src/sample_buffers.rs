@@ -0,0 +1,215 @@
+//! NVML sample buffers
+//!
+//! The driver keeps short internal histories of power, utilization and
+//! clock readings, taken more often than we poll. Reading them on each tick
+//! recovers what happened between polls: a frame carries the highest value
+//! of every buffered metric since the previous frame, so a power or
+//! utilization spike shorter than the poll interval still shows up in
+//! streams and recordings instead of being aliased away.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::device::Device;
+use nvml_wrapper::enum_wrappers::device::Sampling;
+use nvml_wrapper::enums::device::SampleValue;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::struct_wrappers::device::Sample;
+use nvml_wrapper::Nvml;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::nvml;
+
+/// Metric the driver buffers samples of
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BufferedMetric {
+    Power,
+    GpuUtilization,
+    MemoryUtilization,
+    EncoderUtilization,
+    DecoderUtilization,
+    SmClock,
+    MemoryClock,
+}
+
+impl BufferedMetric {
+    /// Every buffered metric
+    pub const ALL: [BufferedMetric; 7] = [
+        BufferedMetric::Power,
+        BufferedMetric::GpuUtilization,
+        BufferedMetric::MemoryUtilization,
+        BufferedMetric::EncoderUtilization,
+        BufferedMetric::DecoderUtilization,
+        BufferedMetric::SmClock,
+        BufferedMetric::MemoryClock,
+    ];
+
+    /// Name of the frame field the metric corresponds to
+    pub fn column(self) -> &'static str {
+        match self {
+            BufferedMetric::Power => "power_w",
+            BufferedMetric::GpuUtilization => "util_gpu",
+            BufferedMetric::MemoryUtilization => "util_memory",
+            BufferedMetric::EncoderUtilization => "engine_utilization.encoder",
+            BufferedMetric::DecoderUtilization => "engine_utilization.decoder",
+            BufferedMetric::SmClock => "sm_clock_mhz",
+            BufferedMetric::MemoryClock => "memory_clock_mhz",
+        }
+    }
+
+    fn sampling(self) -> Sampling {
+        match self {
+            BufferedMetric::Power => Sampling::Power,
+            BufferedMetric::GpuUtilization => Sampling::GpuUtilization,
+            BufferedMetric::MemoryUtilization => Sampling::MemoryUtilization,
+            BufferedMetric::EncoderUtilization => Sampling::EncoderUtilization,
+            BufferedMetric::DecoderUtilization => Sampling::DecoderUtilization,
+            BufferedMetric::SmClock => Sampling::ProcessorClock,
+            BufferedMetric::MemoryClock => Sampling::MemoryClock,
+        }
+    }
+
+    // Value in the unit of the frame field; power is buffered in milliwatts
+    fn value(self, value: &SampleValue) -> f64 {
+        let raw = match *value {
+            SampleValue::F64(value) => value,
+            SampleValue::U32(value) => value as f64,
+            SampleValue::U64(value) => value as f64,
+            SampleValue::I64(value) => value as f64,
+        };
+        match self {
+            BufferedMetric::Power => raw / 1000.0,
+            _ => raw,
+        }
+    }
+}
+
+/// One reading from a driver buffer
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct BufferedSample {
+    /// Wall-clock time in milliseconds since the Unix epoch
+    pub timestamp_ms: f64,
+    pub value: f64,
+}
+
+/// Buffered readings of one metric
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BufferedSeries {
+    pub device_index: u32,
+    pub metric: BufferedMetric,
+    pub samples: Vec<BufferedSample>,
+}
+
+/// Read a device's buffered samples of a metric
+///
+/// # Arguments
+/// * `device_index` - Device to query (defaults to 0)
+/// * `metric` - Metric to read
+/// * `since_ms` - Only samples taken after this time (Unix milliseconds); the whole buffer if unset
+///
+/// # Returns
+/// * `Result<BufferedSeries>` - Samples in time order or error if the device does not buffer the metric
+pub async fn get_sample_history(device_index: Option<u32>, metric: BufferedMetric, since_ms: Option<u64>) -> Result<BufferedSeries> {
+    let device_index = device_index.unwrap_or(0);
+    nvml::blocking(move || {
+        let nvml = Nvml::init().context("Failed to initialize NVML")?;
+        let device = nvml::device_at(&nvml, device_index)?;
+        let samples = match device.samples(metric.sampling(), since_ms.map(|ms| ms * 1000)) {
+            Err(NvmlError::NotFound) => Vec::new(),
+            samples => samples.with_context(|| format!("GPU {} does not buffer {:?} samples", device_index, metric))?,
+        };
+        Ok(BufferedSeries { device_index, metric, samples: convert(metric, &samples) })
+    }).await
+}
+
+/// Per-device read position in the driver buffers
+///
+/// Each read returns only the samples taken since the previous one.
+pub struct BufferCursor {
+    metrics: Vec<BufferedMetric>,
+    last_seen_us: BTreeMap<&'static str, u64>,
+}
+
+impl BufferCursor {
+    /// Probe which metrics a device buffers and start reading from now
+    pub fn new(device: &Device) -> Self {
+        let now_us = nvml::now_ms() as u64 * 1000;
+        let metrics: Vec<BufferedMetric> = BufferedMetric::ALL.into_iter()
+            // An empty buffer reports NotFound; unbuffered metrics report NotSupported
+            .filter(|metric| matches!(device.samples(metric.sampling(), now_us), Ok(_) | Err(NvmlError::NotFound)))
+            .collect();
+        let last_seen_us = metrics.iter().map(|metric| (metric.column(), now_us)).collect();
+        BufferCursor { metrics, last_seen_us }
+    }
+
+    /// Number of buffered metrics read per call to `read_peaks`
+    pub fn metric_count(&self) -> u32 {
+        self.metrics.len() as u32
+    }
+
+    /// Highest value of each buffered metric since the previous read
+    ///
+    /// # Returns
+    /// * `BTreeMap<String, f64>` - Peaks keyed by frame field; metrics with no new samples are left out
+    pub fn read_peaks(&mut self, device: &Device) -> BTreeMap<String, f64> {
+        let mut peaks = BTreeMap::new();
+        for &metric in &self.metrics {
+            let last_seen = self.last_seen_us.entry(metric.column()).or_default();
+            let Ok(samples) = device.samples(metric.sampling(), *last_seen) else {
+                continue;
+            };
+            if let Some((newest, peak)) = peak(metric, &samples) {
+                *last_seen = newest;
+                peaks.insert(metric.column().to_string(), peak);
+            }
+        }
+        peaks
+    }
+}
+
+// Newest timestamp and highest value of a batch of samples
+fn peak(metric: BufferedMetric, samples: &[Sample]) -> Option<(u64, f64)> {
+    let newest = samples.iter().map(|sample| sample.timestamp).max()?;
+    let highest = samples.iter()
+        .map(|sample| metric.value(&sample.value))
+        .fold(f64::MIN, f64::max);
+    Some((newest, highest))
+}
+
+fn convert(metric: BufferedMetric, samples: &[Sample]) -> Vec<BufferedSample> {
+    let mut converted: Vec<BufferedSample> = samples.iter()
+        .map(|sample| BufferedSample { timestamp_ms: sample.timestamp as f64 / 1000.0, value: metric.value(&sample.value) })
+        .collect();
+    converted.sort_by(|a, b| a.timestamp_ms.total_cmp(&b.timestamp_ms));
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp: u64, value: SampleValue) -> Sample {
+        Sample { timestamp, value }
+    }
+
+    #[test]
+    fn test_peak_finds_spike_between_polls() {
+        let samples = [
+            sample(1_000, SampleValue::U32(180_000)),
+            sample(1_050, SampleValue::U32(410_000)),
+            sample(1_100, SampleValue::U32(190_000)),
+        ];
+        assert_eq!(peak(BufferedMetric::Power, &samples), Some((1_100, 410.0)));
+        assert_eq!(peak(BufferedMetric::GpuUtilization, &[]), None);
+    }
+
+    #[test]
+    fn test_convert_orders_samples_and_scales_units() {
+        let samples = [sample(2_000_000, SampleValue::U64(1_950)), sample(1_000_000, SampleValue::F64(1_800.5))];
+        assert_eq!(convert(BufferedMetric::SmClock, &samples), vec![
+            BufferedSample { timestamp_ms: 1_000.0, value: 1_800.5 },
+            BufferedSample { timestamp_ms: 2_000.0, value: 1_950.0 },
+        ]);
+        assert_eq!(BufferedMetric::EncoderUtilization.column(), "engine_utilization.encoder");
+    }
+}
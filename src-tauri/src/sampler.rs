@@ -0,0 +1,665 @@
+//! Per-tick telemetry sampling with cached device state
+//!
+//! Device handles and static properties (name, SM count, and which optional
+//! queries the device supports) are resolved once when sampling starts, so
+//! each tick only issues the NVML queries for values that actually change.
+//! Streams and recordings sample on a dedicated thread (`SamplingThread`)
+//! so blocking NVML calls stay off the async runtime.
+//! Sensors are probed too, since guests under vGPU or WSL often reject
+//! temperature and clock queries; unsupported values are reported as zero.
+//! Enabled metric providers are sampled on the same thread after NVML.
+//! Frames are checked for implausible readings before they are returned.
+//! The driver's sample buffers are read on each tick for the peaks between
+//! polls. A sampler can be limited to a `MetricSet`, skipping the NVML
+//! calls for everything else to keep per-tick cost down.
+//! Target fan speeds and JPEG and OFA engine utilization are not wrapped by
+//! nvml-wrapper and are read through the raw NVML bindings.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::error::nvml_try;
+use serde::{Deserialize, Serialize};
+use nvml_wrapper::enum_wrappers::device::{Clock, PerformanceState, TemperatureSensor};
+use nvml_wrapper::{device::Device, Nvml};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::mpsc;
+use tokio::sync::oneshot;
+
+use crate::error::AppError;
+use crate::health;
+use crate::nvml::{self, EngineUtilization, StaticDeviceInfo, TelemetryFrame};
+use crate::overhead;
+use crate::providers;
+use crate::sample_buffers::BufferCursor;
+use crate::tuning;
+use crate::validation::{self, DeviceLimits};
+
+/// Group of frame fields read by one or a few NVML queries
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum SampledMetric {
+    /// GPU and memory utilization, and the SM, bandwidth and PCIe estimates derived from them
+    Utilization,
+    Memory,
+    Temperature,
+    Clocks,
+    Power,
+    /// Encoder, decoder, JPEG and OFA utilization
+    Engines,
+    ThrottleReasons,
+    PerformanceState,
+    Fans,
+    /// Peaks between polls from the driver's sample buffers
+    SampleBuffers,
+}
+
+impl SampledMetric {
+    /// Every metric, in frame order
+    pub const ALL: [SampledMetric; 10] = [
+        SampledMetric::Utilization,
+        SampledMetric::Memory,
+        SampledMetric::Temperature,
+        SampledMetric::Clocks,
+        SampledMetric::Power,
+        SampledMetric::Engines,
+        SampledMetric::ThrottleReasons,
+        SampledMetric::PerformanceState,
+        SampledMetric::Fans,
+        SampledMetric::SampleBuffers,
+    ];
+
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+/// Metrics a sampler reads; fields of the others stay zero or empty
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MetricSet(u16);
+
+impl MetricSet {
+    /// Every metric
+    pub fn all() -> Self {
+        Self::of(&SampledMetric::ALL)
+    }
+
+    /// Only the given metrics
+    pub fn of(metrics: &[SampledMetric]) -> Self {
+        MetricSet(metrics.iter().fold(0, |bits, metric| bits | metric.bit()))
+    }
+
+    /// This set plus one more metric
+    pub fn with(self, metric: SampledMetric) -> Self {
+        MetricSet(self.0 | metric.bit())
+    }
+
+    pub fn contains(self, metric: SampledMetric) -> bool {
+        self.0 & metric.bit() != 0
+    }
+}
+
+impl Default for MetricSet {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// Telemetry sampler bound to a single device
+///
+/// Holds the NVML device handle for the lifetime of a stream or recording
+/// instead of looking it up (or re-initializing NVML) on every tick.
+pub struct DeviceSampler<'nvml> {
+    device: Device<'nvml>,
+    info: StaticDeviceInfo,
+    temperature_supported: bool,
+    clocks_supported: bool,
+    power_supported: bool,
+    encoder_supported: bool,
+    decoder_supported: bool,
+    jpeg_supported: bool,
+    ofa_supported: bool,
+    throttle_supported: bool,
+    pstate_supported: bool,
+    fan_count: u32,
+    target_fan_supported: bool,
+    limits: DeviceLimits,
+    buffers: RefCell<BufferCursor>,
+    metrics: MetricSet,
+}
+
+impl<'nvml> DeviceSampler<'nvml> {
+    /// Read static device properties and probe optional queries once
+    ///
+    /// # Arguments
+    /// * `device` - NVML device handle
+    /// * `index` - Device index in the system
+    ///
+    /// # Returns
+    /// * `Result<DeviceSampler>` - Sampler or error if static info cannot be read
+    pub fn new(device: Device<'nvml>, index: u32) -> Result<Self> {
+        let info = read_static_info(&device, index)
+            .with_context(|| format!("Failed to read static info of GPU {}", index))?;
+        let temperature_supported = device.temperature(TemperatureSensor::Gpu).is_ok();
+        let clocks_supported = device.clock_info(Clock::Graphics).is_ok()
+            && device.clock_info(Clock::Memory).is_ok();
+        let power_supported = device.power_usage().is_ok();
+        let encoder_supported = device.encoder_utilization().is_ok();
+        let decoder_supported = device.decoder_utilization().is_ok();
+        let jpeg_supported = read_jpeg_utilization(&device).is_ok();
+        let ofa_supported = read_ofa_utilization(&device).is_ok();
+        let throttle_supported = device.current_throttle_reasons().is_ok();
+        let pstate_supported = device.performance_state().is_ok();
+        let fan_count = count_fans(&device);
+        let target_fan_supported = fan_count > 0 && read_target_fan_speed(&device, 0).is_ok();
+        let limits = DeviceLimits::read(&device, info.memory_total_mb, temperature_supported);
+        let buffers = RefCell::new(BufferCursor::new(&device));
+
+        Ok(DeviceSampler {
+            device,
+            info,
+            temperature_supported,
+            clocks_supported,
+            power_supported,
+            encoder_supported,
+            decoder_supported,
+            jpeg_supported,
+            ofa_supported,
+            throttle_supported,
+            pstate_supported,
+            fan_count,
+            target_fan_supported,
+            limits,
+            buffers,
+            metrics: MetricSet::all(),
+        })
+    }
+
+    /// Limit sampling to a set of metrics
+    ///
+    /// Skipped metrics cost no NVML calls; their frame fields stay zero or empty.
+    pub fn with_metrics(mut self, metrics: MetricSet) -> Self {
+        self.metrics = metrics;
+        // A skipped sensor reads 0 without being suspect
+        self.limits.temperature_supported &= metrics.contains(SampledMetric::Temperature);
+        self
+    }
+
+    /// Create samplers for every device NVML reports
+    pub fn for_all_devices(nvml: &'nvml Nvml) -> Result<Vec<Self>> {
+        nvml::list_devices(nvml)?
+            .into_iter()
+            .enumerate()
+            .map(|(index, device)| DeviceSampler::new(device, index as u32))
+            .collect()
+    }
+
+    /// Create a sampler for one device, validating the index
+    pub fn for_device(nvml: &'nvml Nvml, device_index: u32) -> Result<Self> {
+        DeviceSampler::new(nvml::device_at(nvml, device_index)?, device_index)
+    }
+
+    /// Device index this sampler reads from
+    pub fn index(&self) -> u32 {
+        self.info.index
+    }
+
+    /// Static properties read when the sampler was created
+    pub fn static_info(&self) -> &StaticDeviceInfo {
+        &self.info
+    }
+
+    /// Number of NVML queries issued by each call to `sample`
+    pub fn queries_per_sample(&self) -> u32 {
+        use SampledMetric::*;
+        let included = |metric: SampledMetric, queries: u32| if self.metrics.contains(metric) { queries } else { 0 };
+        included(Utilization, 1)
+            + included(Memory, 1)
+            + included(Temperature, self.temperature_supported as u32)
+            + included(Clocks, 2 * self.clocks_supported as u32)
+            + included(Power, self.power_supported as u32)
+            + included(Engines, self.encoder_supported as u32 + self.decoder_supported as u32
+                + self.jpeg_supported as u32 + self.ofa_supported as u32)
+            + included(ThrottleReasons, self.throttle_supported as u32)
+            + included(PerformanceState, self.pstate_supported as u32)
+            + included(Fans, self.fan_count * (1 + self.target_fan_supported as u32))
+            + included(SampleBuffers, self.buffers.borrow().metric_count())
+    }
+
+    // Whether a metric is both requested and supported
+    fn reads(&self, metric: SampledMetric, supported: bool) -> bool {
+        supported && self.metrics.contains(metric)
+    }
+
+    /// Collect a telemetry frame, skipping queries known to be unsupported
+    /// and metrics outside the sampler's set
+    ///
+    /// Each metric group's reads are timed for the overhead statistics.
+    pub fn sample(&self) -> Result<TelemetryFrame> {
+        use SampledMetric::*;
+        let (util_gpu, util_memory) = if self.metrics.contains(Utilization) {
+            let util = overhead::time_nvml(Utilization, || self.device.utilization_rates())?;
+            (util.gpu, util.memory)
+        } else {
+            (0, 0)
+        };
+        let memory_used_mb = if self.metrics.contains(Memory) {
+            overhead::time_nvml(Memory, || self.device.memory_info())?.used / (1024 * 1024)
+        } else {
+            0
+        };
+        let temp = if self.reads(Temperature, self.temperature_supported) {
+            overhead::time_nvml(Temperature, || self.device.temperature(TemperatureSensor::Gpu)).unwrap_or(0)
+        } else {
+            0
+        };
+        let (sm_clock, memory_clock) = if self.reads(Clocks, self.clocks_supported) {
+            overhead::time_nvml(Clocks, || (
+                self.device.clock_info(Clock::Graphics).unwrap_or(0),
+                self.device.clock_info(Clock::Memory).unwrap_or(0),
+            ))
+        } else {
+            (0, 0)
+        };
+        let power_w = if self.reads(Power, self.power_supported) {
+            overhead::time_nvml(Power, || self.device.power_usage()).unwrap_or(0) as f32 / 1000.0 // Convert mW to W
+        } else {
+            0.0
+        };
+        let any_engine = self.encoder_supported || self.decoder_supported || self.jpeg_supported || self.ofa_supported;
+        let engine_utilization = if self.reads(Engines, any_engine) {
+            overhead::time_nvml(Engines, || EngineUtilization {
+                graphics: util_gpu,
+                encoder: self.encoder_supported
+                    .then(|| self.device.encoder_utilization().ok().map(|info| info.utilization))
+                    .flatten(),
+                decoder: self.decoder_supported
+                    .then(|| self.device.decoder_utilization().ok().map(|info| info.utilization))
+                    .flatten(),
+                jpeg: self.jpeg_supported.then(|| read_jpeg_utilization(&self.device).ok()).flatten(),
+                ofa: self.ofa_supported.then(|| read_ofa_utilization(&self.device).ok()).flatten(),
+            })
+        } else {
+            EngineUtilization { graphics: util_gpu, ..Default::default() }
+        };
+        let throttle_reasons = if self.reads(ThrottleReasons, self.throttle_supported) {
+            overhead::time_nvml(ThrottleReasons, || self.device.current_throttle_reasons())
+                .map(|reasons| health::throttle_reason_names(reasons).into_iter().map(String::from).collect())
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        let performance_state = if self.reads(PerformanceState, self.pstate_supported) {
+            overhead::time_nvml(PerformanceState, || self.device.performance_state()).ok().and_then(pstate_number)
+        } else {
+            None
+        };
+        let (fan_speeds_percent, target_fan_speeds_percent) = if self.reads(Fans, self.fan_count > 0) {
+            overhead::time_nvml(Fans, || (
+                (0..self.fan_count).map(|fan| self.device.fan_speed(fan).unwrap_or(0)).collect(),
+                self.target_fan_supported.then(|| (0..self.fan_count)
+                    .map(|fan| read_target_fan_speed(&self.device, fan).unwrap_or(0))
+                    .collect()),
+            ))
+        } else {
+            (Vec::new(), None)
+        };
+        let (sm_utilizations, memory_bandwidth_gbps, pcie_utilization) = if self.metrics.contains(Utilization) {
+            (
+                nvml::generate_sm_utilizations(util_gpu, self.info.sm_count),
+                nvml::estimate_memory_bandwidth(&self.info.name, util_memory),
+                nvml::estimate_pcie_utilization(util_gpu, util_memory),
+            )
+        } else {
+            (Vec::new(), 0.0, 0)
+        };
+        let interval_max = if self.metrics.contains(SampleBuffers) {
+            overhead::time_nvml(SampleBuffers, || self.buffers.borrow_mut().read_peaks(&self.device))
+        } else {
+            BTreeMap::new()
+        };
+
+        let mut frame = TelemetryFrame {
+            timestamp: nvml::now_ms(),
+            monotonic_ms: nvml::monotonic_ms(),
+            device_index: self.info.index,
+            util_gpu,
+            util_memory,
+            memory_used_mb,
+            sm_clock_mhz: sm_clock,
+            memory_clock_mhz: memory_clock,
+            temperature_c: temp,
+            power_w,
+            fan_speed_percent: fan_speeds_percent.first().copied().unwrap_or(0),
+            fan_speeds_percent,
+            target_fan_speeds_percent,
+            engine_utilization,
+            throttle_reasons,
+            sm_utilizations,
+            sm_utilizations_measured: false,
+            memory_bandwidth_gbps,
+            pcie_utilization,
+            performance_state,
+            deltas: None,
+            provider_metrics: BTreeMap::new(),
+            suspect_fields: Vec::new(),
+            interval_max,
+        };
+        validation::validate(&mut frame, &self.limits);
+        Ok(frame)
+    }
+}
+
+/// Request for one frame per sampled device
+type SampleRequest = oneshot::Sender<Result<Vec<TelemetryFrame>>>;
+
+/// Device samplers owned by a dedicated OS thread
+///
+/// NVML calls block; at high sample rates running them inline would tie up
+/// async runtime workers that also serve commands and events. The thread
+/// owns the NVML handle and answers sample requests over a channel, and
+/// exits once this handle is dropped.
+pub struct SamplingThread {
+    requests: mpsc::Sender<SampleRequest>,
+    devices: Vec<StaticDeviceInfo>,
+    queries_per_sample: u32,
+}
+
+impl SamplingThread {
+    /// Start the thread and create samplers on it
+    ///
+    /// # Arguments
+    /// * `device_index` - Device to sample, or `None` for every device
+    ///
+    /// # Returns
+    /// * `Result<SamplingThread>` - Handle, or error if NVML or the device is unavailable
+    pub async fn start(device_index: Option<u32>) -> Result<Self> {
+        Self::spawn(device_index.map(|index| vec![index])).await
+    }
+
+    /// Start the thread for one device, reading only a set of metrics
+    ///
+    /// # Arguments
+    /// * `device_index` - Device to sample
+    /// * `metrics` - Metrics to read; the NVML calls for the rest are skipped
+    ///
+    /// # Returns
+    /// * `Result<SamplingThread>` - Handle, or error if NVML or the device is unavailable
+    pub async fn start_with_metrics(device_index: u32, metrics: MetricSet) -> Result<Self> {
+        Self::spawn_with_metrics(Some(vec![device_index]), metrics).await
+    }
+
+    /// Start the thread with samplers for a set of devices
+    ///
+    /// # Arguments
+    /// * `device_indices` - Devices to sample, in sampling order
+    ///
+    /// # Returns
+    /// * `Result<SamplingThread>` - Handle, or error if NVML or any device is unavailable
+    pub async fn start_devices(device_indices: Vec<u32>) -> Result<Self> {
+        Self::spawn(Some(device_indices)).await
+    }
+
+    async fn spawn(device_indices: Option<Vec<u32>>) -> Result<Self> {
+        Self::spawn_with_metrics(device_indices, MetricSet::all()).await
+    }
+
+    async fn spawn_with_metrics(device_indices: Option<Vec<u32>>, metrics: MetricSet) -> Result<Self> {
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let (requests, request_rx) = mpsc::channel::<SampleRequest>();
+        std::thread::Builder::new()
+            .name("nvml-sampler".to_string())
+            .spawn(move || run_sampling_thread(device_indices, metrics, ready_tx, request_rx))
+            .context("Failed to start NVML sampling thread")?;
+
+        let (devices, queries_per_sample) = ready_rx.await
+            .context("NVML sampling thread exited during startup")??;
+        Ok(SamplingThread { requests, devices, queries_per_sample })
+    }
+
+    /// Static properties of the sampled devices, in sampling order
+    pub fn devices(&self) -> &[StaticDeviceInfo] {
+        &self.devices
+    }
+
+    /// NVML queries issued per call to `sample`, across all devices
+    pub fn queries_per_sample(&self) -> u32 {
+        self.queries_per_sample
+    }
+
+    /// Collect one frame per device on the sampling thread
+    pub async fn sample(&self) -> Result<Vec<TelemetryFrame>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.requests.send(reply_tx)
+            .map_err(|_| anyhow::anyhow!("NVML sampling thread has exited"))?;
+        reply_rx.await.context("NVML sampling thread has exited")?
+    }
+}
+
+// Body of the sampling thread: NVML and the samplers borrowing it live here
+fn run_sampling_thread(
+    device_indices: Option<Vec<u32>>,
+    metrics: MetricSet,
+    ready: oneshot::Sender<Result<(Vec<StaticDeviceInfo>, u32)>>,
+    requests: mpsc::Receiver<SampleRequest>,
+) {
+    let nvml = match Nvml::init().context("Failed to initialize NVML") {
+        Ok(nvml) => nvml,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    let samplers = match device_indices {
+        Some(indices) => indices.into_iter().map(|index| DeviceSampler::for_device(&nvml, index)).collect(),
+        None => DeviceSampler::for_all_devices(&nvml),
+    };
+    let samplers = match samplers {
+        Ok(samplers) => samplers.into_iter().map(|sampler| sampler.with_metrics(metrics)).collect::<Vec<_>>(),
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    let devices = samplers.iter().map(|sampler| sampler.static_info().clone()).collect();
+    let queries = samplers.iter().map(DeviceSampler::queries_per_sample).sum();
+    if ready.send(Ok((devices, queries))).is_err() {
+        return;
+    }
+    let mut providers = providers::create_enabled();
+    // Ends when the handle, and with it the request sender, is dropped
+    for reply in requests {
+        let frames = samplers.iter()
+            .map(|sampler| {
+                let mut frame = sampler.sample().with_context(|| format!("Failed to sample GPU {}", sampler.index()))?;
+                frame.provider_metrics = providers::sample_all(&mut providers, sampler.static_info());
+                Ok(frame)
+            })
+            .collect();
+        let _ = reply.send(frames);
+    }
+}
+
+// P-state number, or `None` when the driver reports the state as unknown
+fn pstate_number(state: PerformanceState) -> Option<u32> {
+    use PerformanceState::*;
+    let number = match state {
+        Zero => 0,
+        One => 1,
+        Two => 2,
+        Three => 3,
+        Four => 4,
+        Five => 5,
+        Six => 6,
+        Seven => 7,
+        Eight => 8,
+        Nine => 9,
+        Ten => 10,
+        Eleven => 11,
+        Twelve => 12,
+        Thirteen => 13,
+        Fourteen => 14,
+        Fifteen => 15,
+        Unknown => return None,
+    };
+    Some(number)
+}
+
+// Number of fans whose speed can be read; passively cooled boards report none.
+// Older drivers may not implement the fan count but still answer for fan 0.
+fn count_fans(device: &Device) -> u32 {
+    match device.num_fans() {
+        Ok(count) => (0..count).take_while(|&fan| device.fan_speed(fan).is_ok()).count() as u32,
+        Err(_) => device.fan_speed(0).is_ok() as u32,
+    }
+}
+
+/// Read the speed the driver is steering a fan towards
+///
+/// # Arguments
+/// * `device` - NVML device handle
+/// * `fan` - Fan index on the device
+///
+/// # Returns
+/// * `Result<u32>` - Target speed in percent or error, e.g. `NOT_SUPPORTED`
+pub fn read_target_fan_speed(device: &Device, fan: u32) -> Result<u32> {
+    let get_target = tuning::raw_nvml()?.nvmlDeviceGetTargetFanSpeed.as_ref()
+        .map_err(|_| AppError::NotSupported("The driver does not report target fan speeds".to_string()))?;
+    let mut speed = 0;
+    nvml_try(unsafe { get_target(device.handle(), fan, &mut speed) })?;
+    Ok(speed)
+}
+
+/// Read the JPEG decode engine's utilization
+///
+/// # Arguments
+/// * `device` - NVML device handle
+///
+/// # Returns
+/// * `Result<u32>` - Utilization in percent or error, e.g. `NOT_SUPPORTED`
+pub fn read_jpeg_utilization(device: &Device) -> Result<u32> {
+    let get_utilization = tuning::raw_nvml()?.nvmlDeviceGetJpgUtilization.as_ref()
+        .map_err(|_| AppError::NotSupported("The driver does not report JPEG engine utilization".to_string()))?;
+    let (mut utilization, mut period_us) = (0, 0);
+    nvml_try(unsafe { get_utilization(device.handle(), &mut utilization, &mut period_us) })?;
+    Ok(utilization)
+}
+
+/// Read the optical flow accelerator's utilization
+///
+/// # Arguments
+/// * `device` - NVML device handle
+///
+/// # Returns
+/// * `Result<u32>` - Utilization in percent or error, e.g. `NOT_SUPPORTED`
+pub fn read_ofa_utilization(device: &Device) -> Result<u32> {
+    let get_utilization = tuning::raw_nvml()?.nvmlDeviceGetOfaUtilization.as_ref()
+        .map_err(|_| AppError::NotSupported("The driver does not report OFA utilization".to_string()))?;
+    let (mut utilization, mut period_us) = (0, 0);
+    nvml_try(unsafe { get_utilization(device.handle(), &mut utilization, &mut period_us) })?;
+    Ok(utilization)
+}
+
+// Read the properties that stay fixed for the lifetime of a device handle
+fn read_static_info(device: &Device, index: u32) -> Result<StaticDeviceInfo> {
+    let name = device.name()?;
+    let compute_capability = device.cuda_compute_capability()?;
+    let (sm_count, _) = nvml::estimate_gpu_specs(&name);
+
+    Ok(StaticDeviceInfo {
+        index,
+        uuid: device.uuid()?,
+        memory_total_mb: device.memory_info()?.total / (1024 * 1024),
+        compute_capability: format!("{}.{}", compute_capability.major, compute_capability.minor),
+        sm_count,
+        name,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Queries per frame before cached sampling: name, utilization, temperature,
+    /// two clocks, memory, power and fan were all read on every tick
+    const UNCACHED_QUERIES_PER_FRAME: u32 = 8;
+
+    /// Compares per-frame cost of re-initializing NVML per sample (the old
+    /// recording path) against a cached sampler. Needs an NVIDIA GPU:
+    /// `cargo test sampler -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_cached_sampler_vs_uncached() {
+        const FRAMES: u32 = 200;
+
+        let start = Instant::now();
+        for _ in 0..FRAMES {
+            let nvml = Nvml::init().unwrap();
+            let device = nvml::device_at(&nvml, 0).unwrap();
+            DeviceSampler::new(device, 0).unwrap().sample().unwrap();
+        }
+        let uncached = start.elapsed() / FRAMES;
+
+        let nvml = Nvml::init().unwrap();
+        let sampler = DeviceSampler::for_device(&nvml, 0).unwrap();
+        let start = Instant::now();
+        for _ in 0..FRAMES {
+            sampler.sample().unwrap();
+        }
+        let cached = start.elapsed() / FRAMES;
+
+        println!(
+            "uncached: {:?}/frame, cached: {:?}/frame ({} queries/frame vs {})",
+            uncached,
+            cached,
+            sampler.queries_per_sample(),
+            UNCACHED_QUERIES_PER_FRAME
+        );
+        assert!(cached < uncached);
+    }
+
+    /// Compares per-frame cost of the full metric set against utilization and
+    /// temperature only. Needs an NVIDIA GPU:
+    /// `cargo test sampler -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_low_overhead_metric_set() {
+        const FRAMES: u32 = 200;
+
+        let nvml = Nvml::init().unwrap();
+        let time_frames = |sampler: &DeviceSampler| {
+            let start = Instant::now();
+            for _ in 0..FRAMES {
+                sampler.sample().unwrap();
+            }
+            start.elapsed() / FRAMES
+        };
+        let full = DeviceSampler::for_device(&nvml, 0).unwrap();
+        let light = DeviceSampler::for_device(&nvml, 0).unwrap()
+            .with_metrics(MetricSet::of(&[SampledMetric::Utilization, SampledMetric::Temperature]));
+        let (full_cost, light_cost) = (time_frames(&full), time_frames(&light));
+
+        println!(
+            "full: {:?}/frame ({} queries), utilization+temperature: {:?}/frame ({} queries)",
+            full_cost,
+            full.queries_per_sample(),
+            light_cost,
+            light.queries_per_sample()
+        );
+        assert!(light.queries_per_sample() <= 2);
+        assert!(light_cost < full_cost);
+    }
+
+    #[test]
+    fn test_metric_set_membership() {
+        let set = MetricSet::of(&[SampledMetric::Utilization, SampledMetric::Temperature]);
+        assert!(set.contains(SampledMetric::Utilization) && set.contains(SampledMetric::Temperature));
+        assert!(!set.contains(SampledMetric::Memory) && !set.contains(SampledMetric::SampleBuffers));
+        assert!(SampledMetric::ALL.into_iter().all(|metric| MetricSet::default().contains(metric)));
+        assert!(MetricSet::of(&[SampledMetric::Temperature]).with(SampledMetric::Utilization).contains(SampledMetric::Utilization));
+
+        let parsed: Vec<SampledMetric> = serde_json::from_str(r#"["utilization", "throttle_reasons"]"#).unwrap();
+        assert_eq!(MetricSet::of(&parsed), MetricSet::of(&[SampledMetric::ThrottleReasons, SampledMetric::Utilization]));
+    }
+}
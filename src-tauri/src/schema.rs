@@ -0,0 +1,197 @@
+//! Recording file schema versioning and migration
+//!
+//! Every recording written by the app carries a `schema_version`. Loading a
+//! recording upgrades it step by step to the current version, so files made
+//! by older releases stay readable as the telemetry frame format evolves.
+//!
+//! Versions:
+//! * 1 - bare JSON array of frames, each repeating `name` and `memory_total_mb`
+//! * 2 - object with static `device` info and `samples`
+//! * 3 - adds `devices`, every recorded device; `samples` may interleave several
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::error::AppError;
+use crate::nvml::{self, RecordingFile};
+
+/// Schema version written by this build
+pub const CURRENT_SCHEMA_VERSION: u32 = 3;
+
+/// Load a recording file, migrating it to the current schema
+///
+/// # Arguments
+/// * `path` - Path to a recording JSON file
+///
+/// # Returns
+/// * `Result<RecordingFile>` - Recording in the current schema or error
+pub fn load_recording(path: &Path) -> Result<RecordingFile> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read recording {}", path.display()))?;
+    parse_recording(&json)
+}
+
+/// Parse recording JSON of any supported schema version
+///
+/// # Arguments
+/// * `json` - Recording file contents
+///
+/// # Returns
+/// * `Result<RecordingFile>` - Recording in the current schema or error if malformed or too new
+pub fn parse_recording(json: &str) -> Result<RecordingFile> {
+    let value: Value = serde_json::from_str(json)
+        .map_err(|e| AppError::InvalidArgument(format!("Recording is not valid JSON: {}", e)))?;
+    let value = migrate(value)?;
+    let recording = serde_json::from_value(value)
+        .map_err(|e| AppError::InvalidArgument(format!("Recording does not match schema: {}", e)))?;
+    Ok(recording)
+}
+
+// Upgrade a recording one version at a time until it is current
+fn migrate(mut value: Value) -> Result<Value> {
+    let mut version = schema_version_of(&value)?;
+    if !(1..=CURRENT_SCHEMA_VERSION).contains(&version) {
+        return Err(AppError::InvalidArgument(format!(
+            "Recording schema version {} is not supported (expected 1 to {})",
+            version, CURRENT_SCHEMA_VERSION
+        )).into());
+    }
+
+    while version < CURRENT_SCHEMA_VERSION {
+        value = match version {
+            1 => migrate_v1_to_v2(value),
+            _ => migrate_v2_to_v3(value),
+        };
+        version += 1;
+    }
+    value["schema_version"] = json!(CURRENT_SCHEMA_VERSION);
+    Ok(value)
+}
+
+// Version 1 files predate the field and are plain arrays; version 2 objects
+// written before the field existed are treated as version 2
+fn schema_version_of(value: &Value) -> Result<u32> {
+    let object = match value {
+        Value::Array(_) => return Ok(1),
+        Value::Object(object) => object,
+        _ => return Err(AppError::InvalidArgument("Recording is neither a frame array nor an object".to_string()).into()),
+    };
+    match object.get("schema_version") {
+        None => Ok(2),
+        Some(version) => version.as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| AppError::InvalidArgument(format!("Recording schema version {} is not supported", version)).into()),
+    }
+}
+
+// Hoist the per-frame device name and memory size into static device info
+fn migrate_v1_to_v2(value: Value) -> Value {
+    let samples = match value {
+        Value::Array(samples) => samples,
+        _ => Vec::new(),
+    };
+    let first = samples.first().cloned().unwrap_or_default();
+    let name = first.get("name").and_then(Value::as_str).unwrap_or_default().to_string();
+    let (sm_count, _) = nvml::estimate_gpu_specs(&name);
+
+    json!({
+        "device": {
+            "index": first.get("device_index").cloned().unwrap_or(json!(0)),
+            "uuid": "",
+            "name": name,
+            "memory_total_mb": first.get("memory_total_mb").cloned().unwrap_or(json!(0)),
+            "compute_capability": "",
+            "sm_count": sm_count,
+        },
+        "samples": samples,
+    })
+}
+
+// Single-device recordings list their one device
+fn migrate_v2_to_v3(mut value: Value) -> Value {
+    let device = value.get("device").cloned().unwrap_or(Value::Null);
+    value["devices"] = json!([device]);
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvml::{StaticDeviceInfo, TelemetryFrame};
+
+    #[test]
+    fn test_migrates_v1_frame_array() {
+        let legacy = r#"[
+            {"timestamp": 1, "device_index": 1, "name": "NVIDIA GeForce RTX 4090",
+             "util_gpu": 50, "util_memory": 20, "memory_used_mb": 1024, "memory_total_mb": 24576,
+             "sm_clock_mhz": 2500, "memory_clock_mhz": 10500, "temperature_c": 60, "power_w": 300.0,
+             "fan_speed_percent": 40, "sm_utilizations": [], "memory_bandwidth_gbps": 500.0,
+             "pcie_utilization": 10}
+        ]"#;
+
+        let recording = parse_recording(legacy).unwrap();
+        assert_eq!(recording.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(recording.device.index, 1);
+        assert_eq!(recording.device.name, "NVIDIA GeForce RTX 4090");
+        assert_eq!(recording.device.memory_total_mb, 24576);
+        assert_eq!(recording.devices.len(), 1);
+        assert_eq!(recording.devices[0].index, 1);
+        assert_eq!(recording.samples.len(), 1);
+        assert!(recording.samples[0].fan_speeds_percent.is_empty());
+        assert_eq!(recording.samples[0].target_fan_speeds_percent, None);
+    }
+
+    #[test]
+    fn test_round_trips_current_schema() {
+        let recording = RecordingFile {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            device: StaticDeviceInfo { name: "Test GPU".to_string(), ..Default::default() },
+            devices: vec![StaticDeviceInfo { name: "Test GPU".to_string(), ..Default::default() }],
+            samples: vec![TelemetryFrame { timestamp: 7, ..Default::default() }],
+            markers: Vec::new(),
+            timing: None,
+            process: None,
+            violations: Vec::new(),
+            clock: None,
+        };
+
+        let parsed = parse_recording(&serde_json::to_string(&recording).unwrap()).unwrap();
+        assert_eq!(parsed.device.name, "Test GPU");
+        assert_eq!(parsed.samples[0].timestamp, 7);
+    }
+
+    #[test]
+    fn test_migrates_v2_single_device() {
+        let v2 = r#"{"schema_version": 2, "device": {"index": 3, "uuid": "GPU-1", "name": "Test GPU",
+            "memory_total_mb": 8192, "compute_capability": "8.6", "sm_count": 28}, "samples": []}"#;
+
+        let recording = parse_recording(v2).unwrap();
+        assert_eq!(recording.devices.len(), 1);
+        assert_eq!(recording.devices[0].uuid, "GPU-1");
+        assert_eq!(recording.device.index, 3);
+    }
+
+    #[test]
+    fn test_rejects_newer_schema() {
+        let err = parse_recording(r#"{"schema_version": 99, "device": {}, "samples": []}"#).unwrap_err();
+        assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+    }
+
+    #[test]
+    fn test_rejects_malformed_schema_versions() {
+        for json in [
+            r#"{"schema_version": 0, "device": {}, "samples": []}"#,
+            r#"{"schema_version": 4294967297, "device": {}, "samples": []}"#,
+            r#"{"schema_version": -1, "device": {}, "samples": []}"#,
+            r#"{"schema_version": "2", "device": {}, "samples": []}"#,
+            "42",
+            r#""x""#,
+            "true",
+            "null",
+        ] {
+            let err = parse_recording(json).unwrap_err();
+            assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT", "{}", json);
+        }
+    }
+}
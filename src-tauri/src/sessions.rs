@@ -0,0 +1,87 @@
+//! Single-instance background sessions
+//!
+//! Stress tests, profiling sessions, triggers, measurements, watches and the
+//! alert, export and automation monitors each run one session at a time and
+//! keep its status in a static `RwLock<Option<Status>>`. Starting one checks
+//! that no session is active and then does slow setup (NVML, spawning a
+//! tool) before the session exists. The check and the claim happen under
+//! one write lock, before the first `.await`, so two concurrent starts
+//! cannot both pass it; if setup fails, dropping the claim puts the
+//! previous status back.
+
+use anyhow::Result;
+use std::sync::RwLock;
+
+use crate::error::AppError;
+
+/// Status slot of one kind of session
+pub type SessionState<T> = RwLock<Option<T>>;
+
+/// A claimed slot; released again unless `commit` is called
+pub struct SessionClaim<T: 'static> {
+    state: &'static SessionState<T>,
+    /// Status before the claim, restored on drop; `None` once committed
+    previous: Option<Option<T>>,
+}
+
+/// Claim the slot of a session kind for a session about to start
+///
+/// # Arguments
+/// * `state` - Status slot of the session kind
+/// * `active` - Whether a status belongs to a session that has not ended
+/// * `starting` - Status shown while the session starts; must count as active
+/// * `busy` - Error message if a session is already active
+///
+/// # Returns
+/// * `Result<SessionClaim<T>>` - The claim, or `InvalidArgument` if a session is active
+pub fn claim<T>(state: &'static SessionState<T>, active: impl Fn(&T) -> bool, starting: T, busy: &str) -> Result<SessionClaim<T>> {
+    let mut slot = state.write().unwrap();
+    if slot.as_ref().is_some_and(active) {
+        return Err(AppError::InvalidArgument(busy.to_string()).into());
+    }
+    let previous = slot.replace(starting);
+    Ok(SessionClaim { state, previous: Some(previous) })
+}
+
+impl<T: Clone> SessionClaim<T> {
+    /// Keep the slot for the started session, which now has `status`
+    pub fn commit(mut self, status: T) -> T {
+        self.previous = None;
+        *self.state.write().unwrap() = Some(status.clone());
+        status
+    }
+}
+
+impl<T> Drop for SessionClaim<T> {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            *self.state.write().unwrap() = previous;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static STATE: SessionState<(bool, u32)> = RwLock::new(None);
+
+    #[test]
+    fn test_claim_excludes_second_start_and_restores_on_failure() {
+        *STATE.write().unwrap() = Some((false, 1));
+        let active = |status: &(bool, u32)| status.0;
+
+        let claim = super::claim(&STATE, active, (true, 2), "busy").unwrap();
+        let err = super::claim(&STATE, active, (true, 3), "busy").err().unwrap();
+        assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+
+        // Setup failed: the finished session's status is visible again
+        drop(claim);
+        assert_eq!(*STATE.read().unwrap(), Some((false, 1)));
+
+        let claim = super::claim(&STATE, active, (true, 4), "busy").unwrap();
+        assert_eq!(claim.commit((true, 5)), (true, 5));
+        assert_eq!(*STATE.read().unwrap(), Some((true, 5)));
+        assert!(super::claim(&STATE, active, (true, 6), "busy").is_err());
+    }
+}
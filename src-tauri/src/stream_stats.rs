@@ -0,0 +1,143 @@
+//! Counters describing the health of the telemetry stream
+//!
+//! Shared between the stream tasks, the watchdog and the subscription
+//! commands, and reset whenever a stream starts, so `get_stream_status` can
+//! tell a frontend whether frames are flowing, at what rate, and whether
+//! anything went wrong along the way.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::nvml;
+
+/// Most recent failure of the stream
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct StreamError {
+    /// When it happened, in milliseconds since the Unix epoch
+    pub at_ms: u128,
+    pub message: String,
+}
+
+/// Snapshot of the counters
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct StreamCounters {
+    /// Interval each streamed device is currently sampled at, including idle slowdown
+    pub periods_ms: BTreeMap<u32, u64>,
+    /// Frames sent to the broadcast channel
+    pub frames_published: u64,
+    /// Frames also emitted to the frontend; idle devices may publish without emitting
+    pub frames_emitted: u64,
+    /// Frames subscribers (subscriptions, the watchdog, automation hooks)
+    /// missed because they fell behind the channel
+    pub dropped_frames: u64,
+    /// Times the watchdog restarted a stalled stream or device
+    pub restarts: u32,
+    pub last_error: Option<StreamError>,
+    /// Time since the stream started; `None` if it never has
+    pub uptime_ms: Option<u64>,
+}
+
+/// Live counters of the current (or last) stream
+#[derive(Default)]
+pub struct StreamStats {
+    started: Mutex<Option<Instant>>,
+    frames_published: AtomicU64,
+    frames_emitted: AtomicU64,
+    dropped_frames: AtomicU64,
+    restarts: AtomicU32,
+    last_error: Mutex<Option<StreamError>>,
+    periods_ms: Mutex<BTreeMap<u32, u64>>,
+}
+
+impl StreamStats {
+    /// Zero every counter and start timing a new stream
+    pub fn reset(&self) {
+        *self.started.lock().unwrap() = Some(Instant::now());
+        self.frames_published.store(0, Ordering::Relaxed);
+        self.frames_emitted.store(0, Ordering::Relaxed);
+        self.dropped_frames.store(0, Ordering::Relaxed);
+        self.restarts.store(0, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = None;
+        self.periods_ms.lock().unwrap().clear();
+    }
+
+    pub fn record_frame(&self, emitted: bool) {
+        self.frames_published.fetch_add(1, Ordering::Relaxed);
+        if emitted {
+            self.frames_emitted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_dropped(&self, frames: u64) {
+        self.dropped_frames.fetch_add(frames, Ordering::Relaxed);
+    }
+
+    pub fn record_restart(&self) {
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_error(&self, message: impl Into<String>) {
+        *self.last_error.lock().unwrap() = Some(StreamError { at_ms: nvml::now_ms(), message: message.into() });
+    }
+
+    /// Record the interval a device is now sampled at
+    pub fn set_period(&self, device_index: u32, period_ms: u64) {
+        self.periods_ms.lock().unwrap().insert(device_index, period_ms);
+    }
+
+    /// Interval each streamed device is currently sampled at
+    pub fn periods_ms(&self) -> BTreeMap<u32, u64> {
+        self.periods_ms.lock().unwrap().clone()
+    }
+
+    /// Forget a device that stopped streaming
+    pub fn remove_device(&self, device_index: u32) {
+        self.periods_ms.lock().unwrap().remove(&device_index);
+    }
+
+    pub fn snapshot(&self) -> StreamCounters {
+        StreamCounters {
+            periods_ms: self.periods_ms(),
+            frames_published: self.frames_published.load(Ordering::Relaxed),
+            frames_emitted: self.frames_emitted.load(Ordering::Relaxed),
+            dropped_frames: self.dropped_frames.load(Ordering::Relaxed),
+            restarts: self.restarts.load(Ordering::Relaxed),
+            last_error: self.last_error.lock().unwrap().clone(),
+            uptime_ms: self.started.lock().unwrap().map(|started| started.elapsed().as_millis() as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_accumulate_and_reset() {
+        let stats = StreamStats::default();
+        assert_eq!(stats.snapshot(), StreamCounters::default());
+
+        stats.reset();
+        stats.record_frame(true);
+        stats.record_frame(false);
+        stats.record_dropped(3);
+        stats.record_restart();
+        stats.record_error("no frames for 500 ms");
+        stats.set_period(0, 100);
+        stats.set_period(1, 1_000);
+        stats.remove_device(1);
+
+        let counters = stats.snapshot();
+        assert_eq!((counters.frames_published, counters.frames_emitted, counters.dropped_frames, counters.restarts), (2, 1, 3, 1));
+        assert_eq!(counters.periods_ms, BTreeMap::from([(0, 100)]));
+        assert_eq!(counters.last_error.unwrap().message, "no frames for 500 ms");
+        assert!(counters.uptime_ms.is_some());
+
+        stats.reset();
+        let counters = stats.snapshot();
+        assert_eq!((counters.frames_published, counters.last_error, counters.periods_ms.len()), (0, None, 0));
+    }
+}
@@ -0,0 +1,477 @@
+//! GPU stress test with safety limits
+//!
+//! Generates sustained compute and/or memory load so cooling and throttling
+//! behavior can be watched in the telemetry charts. The load runs in short
+//! steps; between steps the device temperature and power draw are checked
+//! and the test aborts as soon as a limit is crossed. Like the benchmarks,
+//! the load needs the CUDA driver and is only available with the `cuda`
+//! feature.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use nvml_wrapper::Nvml;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tauri::Window;
+
+use crate::error::AppError;
+use crate::nvml;
+use crate::sessions;
+
+/// Test length used when none is requested
+pub const DEFAULT_DURATION_SECONDS: u64 = 60;
+/// Longest test that can be started
+pub const MAX_DURATION_SECONDS: u64 = 30 * 60;
+/// Temperature limit used when none is requested
+pub const DEFAULT_MAX_TEMPERATURE_C: u32 = 85;
+/// Highest temperature limit that can be requested
+pub const MAX_TEMPERATURE_LIMIT_C: u32 = 95;
+/// How often temperature and power are checked while under load
+const LIMIT_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+/// Target length of one load step; limits are checked between steps
+#[cfg_attr(not(feature = "cuda"), allow(dead_code))]
+const STEP_TARGET: Duration = Duration::from_millis(50);
+
+/// Kind of load to generate
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StressLoad {
+    /// Floating-point FMA loops on every SM
+    Compute,
+    /// Device-to-device copies between large buffers
+    Memory,
+    /// Alternating compute and memory steps
+    Combined,
+}
+
+/// Thresholds that abort the test
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct StressLimits {
+    pub max_temperature_c: u32,
+    /// Power draw limit; `None` leaves power to the driver's enforced limit
+    pub max_power_w: Option<f64>,
+}
+
+/// How a stress test ended
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StressOutcome {
+    /// Ran for the full duration
+    Completed,
+    /// Stopped on request
+    Stopped,
+    /// A safety limit was crossed
+    Aborted,
+    /// The load could not be generated
+    Failed,
+}
+
+/// State of the current or most recent stress test
+#[derive(Serialize, Clone, Debug)]
+pub struct StressStatus {
+    pub running: bool,
+    pub device_index: u32,
+    pub load: StressLoad,
+    pub duration_seconds: u64,
+    pub elapsed_seconds: f64,
+    pub limits: StressLimits,
+    pub started_at: u128,
+    pub peak_temperature_c: Option<u32>,
+    pub peak_power_w: Option<f64>,
+    /// Set once the test has ended
+    pub outcome: Option<StressOutcome>,
+    /// Why the test was aborted or failed
+    pub reason: Option<String>,
+}
+
+// Current or most recent test; kept after the test ends so its outcome can be read
+static STRESS_STATE: std::sync::RwLock<Option<StressStatus>> = std::sync::RwLock::new(None);
+
+// Set to ask the running load to stop at its next step
+static STRESS_STOP: AtomicBool = AtomicBool::new(false);
+
+// Handle to the background task, used to wait for the load to wind down
+static STRESS_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+/// Start a stress test
+///
+/// Refuses to start when the device is already at or above the temperature
+/// limit. Emits `stress-test-finished` with the final status when the test ends.
+///
+/// # Arguments
+/// * `load` - Kind of load to generate
+/// * `duration_seconds` - Test length (defaults to 60 s, at most 30 minutes)
+/// * `device_index` - Device to load (defaults to 0)
+/// * `limits` - Temperature and power thresholds that abort the test
+/// * `window` - Tauri window handle for the completion event
+///
+/// # Returns
+/// * `Result<StressStatus>` - Status of the started test or error
+pub async fn start_stress_test(
+    load: StressLoad,
+    duration_seconds: Option<u64>,
+    device_index: Option<u32>,
+    limits: StressLimits,
+    window: Window,
+) -> Result<StressStatus> {
+    let duration_seconds = duration_seconds.unwrap_or(DEFAULT_DURATION_SECONDS);
+    validate(duration_seconds, &limits)?;
+    let device_index = device_index.unwrap_or(0);
+    let status = StressStatus {
+        running: true,
+        device_index,
+        load,
+        duration_seconds,
+        elapsed_seconds: 0.0,
+        limits,
+        started_at: nvml::now_ms(),
+        peak_temperature_c: None,
+        peak_power_w: None,
+        outcome: None,
+        reason: None,
+    };
+    let claim = sessions::claim(&STRESS_STATE, |status| status.running, status.clone(), "A stress test is already running")?;
+    STRESS_STOP.store(false, Ordering::SeqCst);
+
+    let bus_id = nvml::blocking(move || {
+        let nvml = Nvml::init().context("Failed to initialize NVML")?;
+        let device = nvml::device_at(&nvml, device_index)?;
+        let temperature = device.temperature(TemperatureSensor::Gpu).context("Failed to read GPU temperature")?;
+        if let Some(reason) = check_limits(&limits, temperature, None) {
+            return Err(AppError::InvalidArgument(format!("Not starting stress test: {}", reason)).into());
+        }
+        Ok(device.pci_info().context("Failed to read PCI bus ID")?.bus_id)
+    }).await?;
+    let status = claim.commit(status);
+
+    let task = tokio::spawn(async move {
+        let duration = Duration::from_secs(duration_seconds);
+        let result = nvml::blocking(move || run_stress_blocking(load, device_index, bus_id, duration, limits)).await;
+
+        let finished = {
+            let mut state = STRESS_STATE.write().unwrap();
+            let status = state.as_mut().expect("stress state is set while a test runs");
+            status.running = false;
+            match result {
+                Ok((outcome, reason)) => {
+                    status.outcome = Some(outcome);
+                    status.reason = reason;
+                }
+                Err(e) => {
+                    status.outcome = Some(StressOutcome::Failed);
+                    status.reason = Some(format!("{:#}", e));
+                }
+            }
+            status.clone()
+        };
+        if let Some(reason) = &finished.reason {
+            eprintln!("Stress test on GPU {} ended: {}", finished.device_index, reason);
+        }
+        if let Err(e) = window.emit("stress-test-finished", &finished) {
+            eprintln!("Failed to emit stress test finished event: {}", e);
+        }
+    });
+    *STRESS_TASK.lock().unwrap() = Some(task);
+
+    Ok(status)
+}
+
+/// Ask the running stress test to stop
+///
+/// The load winds down within one step; the returned status may still
+/// show it running.
+pub async fn stop_stress_test() -> Result<StressStatus> {
+    let state = STRESS_STATE.read().unwrap();
+    match state.as_ref() {
+        Some(status) if status.running => {
+            STRESS_STOP.store(true, Ordering::SeqCst);
+            Ok(status.clone())
+        }
+        _ => Err(AppError::InvalidArgument("No stress test is running".to_string()).into()),
+    }
+}
+
+/// Stop any running stress test and wait until the load has ended
+pub async fn finish_active_stress_test() -> Result<()> {
+    STRESS_STOP.store(true, Ordering::SeqCst);
+    let task = STRESS_TASK.lock().unwrap().take();
+    if let Some(task) = task {
+        task.await.context("Stress test task ended abnormally")?;
+    }
+    Ok(())
+}
+
+/// Get the status of the current or most recent stress test
+///
+/// # Returns
+/// * `Option<StressStatus>` - Status, or `None` if no test has been run
+pub fn get_stress_status() -> Option<StressStatus> {
+    STRESS_STATE.read().unwrap().clone()
+}
+
+fn validate(duration_seconds: u64, limits: &StressLimits) -> Result<()> {
+    if !(1..=MAX_DURATION_SECONDS).contains(&duration_seconds) {
+        return Err(AppError::InvalidArgument(format!(
+            "Duration must be between 1 and {} seconds, got {}",
+            MAX_DURATION_SECONDS, duration_seconds
+        )).into());
+    }
+    if limits.max_temperature_c > MAX_TEMPERATURE_LIMIT_C {
+        return Err(AppError::InvalidArgument(format!(
+            "Temperature limit must be at most {}°C, got {}°C",
+            MAX_TEMPERATURE_LIMIT_C, limits.max_temperature_c
+        )).into());
+    }
+    if limits.max_power_w.is_some_and(|watts| watts <= 0.0 || !watts.is_finite()) {
+        return Err(AppError::InvalidArgument("Power limit must be a positive number of watts".to_string()).into());
+    }
+    Ok(())
+}
+
+/// Why the test must stop, if a reading crosses a limit
+fn check_limits(limits: &StressLimits, temperature_c: u32, power_w: Option<f64>) -> Option<String> {
+    if temperature_c >= limits.max_temperature_c {
+        return Some(format!("temperature {}°C reached the {}°C limit", temperature_c, limits.max_temperature_c));
+    }
+    match (power_w, limits.max_power_w) {
+        (Some(power), Some(max)) if power >= max => Some(format!("power draw {:.0} W reached the {:.0} W limit", power, max)),
+        _ => None,
+    }
+}
+
+/// Scale the per-step work so a step takes about `STEP_TARGET`
+#[cfg_attr(not(feature = "cuda"), allow(dead_code))]
+fn next_iterations(iterations: u32, step: Duration) -> u32 {
+    if step < STEP_TARGET / 2 {
+        iterations.saturating_mul(2)
+    } else if step > STEP_TARGET * 2 {
+        (iterations / 2).max(1)
+    } else {
+        iterations
+    }
+}
+
+fn run_stress_blocking(
+    load: StressLoad,
+    device_index: u32,
+    bus_id: String,
+    duration: Duration,
+    limits: StressLimits,
+) -> Result<(StressOutcome, Option<String>)> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let device = nvml::device_at(&nvml, device_index)?;
+    let started = Instant::now();
+    let mut last_check: Option<Instant> = None;
+    let mut outcome = (StressOutcome::Completed, None);
+
+    // Called between load steps; returns false to end the test
+    let mut keep_going = || {
+        if STRESS_STOP.load(Ordering::SeqCst) {
+            outcome = (StressOutcome::Stopped, None);
+            return false;
+        }
+        if started.elapsed() >= duration {
+            return false;
+        }
+        if last_check.is_some_and(|checked| checked.elapsed() < LIMIT_CHECK_INTERVAL) {
+            return true;
+        }
+        last_check = Some(Instant::now());
+
+        // Without a temperature reading the test cannot be run safely
+        let temperature = match device.temperature(TemperatureSensor::Gpu) {
+            Ok(temperature) => temperature,
+            Err(e) => {
+                outcome = (StressOutcome::Aborted, Some(format!("temperature could not be read: {}", e)));
+                return false;
+            }
+        };
+        let power = device.power_usage().ok().map(|milliwatts| milliwatts as f64 / 1000.0);
+        if let Some(status) = STRESS_STATE.write().unwrap().as_mut() {
+            status.elapsed_seconds = started.elapsed().as_secs_f64();
+            status.peak_temperature_c = status.peak_temperature_c.max(Some(temperature));
+            status.peak_power_w = match (status.peak_power_w, power) {
+                (Some(peak), Some(power)) => Some(peak.max(power)),
+                (peak, power) => peak.or(power),
+            };
+        }
+        match check_limits(&limits, temperature, power) {
+            Some(reason) => {
+                outcome = (StressOutcome::Aborted, Some(reason));
+                false
+            }
+            None => true,
+        }
+    };
+
+    run_load(load, &bus_id, &mut keep_going)?;
+    if let Some(status) = STRESS_STATE.write().unwrap().as_mut() {
+        status.elapsed_seconds = started.elapsed().as_secs_f64();
+    }
+    Ok(outcome)
+}
+
+/// Generate load on a device until `keep_going` returns false
+///
+/// `keep_going` is called before the first and after every load step of
+/// about `STEP_TARGET`.
+///
+/// # Arguments
+/// * `load` - Kind of load to generate
+/// * `bus_id` - PCI bus ID of the device
+/// * `keep_going` - Called between steps; returns false to stop
+///
+/// # Returns
+/// * `Result<()>` - Error if CUDA fails, or `NotSupported` without the `cuda` feature
+pub fn run_load(load: StressLoad, bus_id: &str, keep_going: &mut dyn FnMut() -> bool) -> Result<()> {
+    driver::run_load(load, bus_id, keep_going)
+}
+
+#[cfg(not(feature = "cuda"))]
+mod driver {
+    use super::*;
+
+    pub fn run_load(_load: StressLoad, _bus_id: &str, _keep_going: &mut dyn FnMut() -> bool) -> Result<()> {
+        Err(AppError::NotSupported("This build does not include the stress test; rebuild with `--features cuda`".to_string()).into())
+    }
+}
+
+#[cfg(feature = "cuda")]
+mod driver {
+    use super::*;
+    use crate::cuda::Cuda;
+
+    /// Threads per block of the compute kernel
+    const THREADS_PER_BLOCK: u32 = 256;
+    /// Blocks per SM, enough to keep every SM busy
+    const BLOCKS_PER_SM: u32 = 8;
+    /// Loop iterations of the first compute step, before scaling to `STEP_TARGET`
+    const INITIAL_ITERATIONS: u32 = 1 << 14;
+    /// Size of each of the two buffers copied between by the memory load
+    const COPY_BUFFER_BYTES: usize = 512 * 1024 * 1024;
+
+    // Each thread runs a dependent FMA chain and stores the result so the
+    // loop is not optimized away
+    const BURN_PTX: &str = "\
+.version 6.0
+.target sm_50
+.address_size 64
+
+.visible .entry burn(.param .u64 out, .param .u32 iterations)
+{
+    .reg .pred %p<2>;
+    .reg .f32 %f<4>;
+    .reg .b32 %r<6>;
+    .reg .b64 %rd<4>;
+
+    ld.param.u64 %rd1, [out];
+    ld.param.u32 %r1, [iterations];
+    mov.u32 %r2, %ctaid.x;
+    mov.u32 %r3, %ntid.x;
+    mov.u32 %r4, %tid.x;
+    mad.lo.s32 %r2, %r2, %r3, %r4;
+    cvt.rn.f32.u32 %f1, %r2;
+    mov.f32 %f2, 0f3F7FFFFE;
+    mov.f32 %f3, 0f3F000000;
+    mov.u32 %r5, 0;
+$L_loop:
+    fma.rn.f32 %f1, %f1, %f2, %f3;
+    fma.rn.f32 %f3, %f3, %f2, %f1;
+    add.u32 %r5, %r5, 1;
+    setp.lt.u32 %p1, %r5, %r1;
+    @%p1 bra $L_loop;
+    add.f32 %f1, %f1, %f3;
+    cvta.to.global.u64 %rd2, %rd1;
+    mul.wide.u32 %rd3, %r2, 4;
+    add.s64 %rd2, %rd2, %rd3;
+    st.global.f32 [%rd2], %f1;
+    ret;
+}
+\0";
+
+    pub fn run_load(load: StressLoad, bus_id: &str, keep_going: &mut dyn FnMut() -> bool) -> Result<()> {
+        let cuda = Cuda::load()?;
+        let context = cuda.create_context(bus_id)?;
+        let compute = load != StressLoad::Memory;
+        let memory = load != StressLoad::Compute;
+
+        let module = if compute { Some(context.load_ptx(BURN_PTX)?) } else { None };
+        let blocks = context.multiprocessor_count()? * BLOCKS_PER_SM;
+        let output = context.alloc_device((blocks * THREADS_PER_BLOCK) as usize * std::mem::size_of::<f32>())?;
+        let output_ptr = output.device_ptr().context("Kernel output is not device memory")?;
+        let buffers = if memory {
+            Some((context.alloc_device(COPY_BUFFER_BYTES)?, context.alloc_device(COPY_BUFFER_BYTES)?))
+        } else {
+            None
+        };
+
+        let mut iterations = INITIAL_ITERATIONS;
+        let mut copies = 1;
+        let mut compute_step = compute;
+        while keep_going() {
+            let start = Instant::now();
+            match (&module, &buffers) {
+                (Some(module), _) if compute_step => {
+                    module.launch("burn", blocks, THREADS_PER_BLOCK, output_ptr, iterations)?;
+                    context.synchronize()?;
+                    iterations = next_iterations(iterations, start.elapsed());
+                }
+                (_, Some((dst, src))) => {
+                    for _ in 0..copies {
+                        context.copy(dst, src, COPY_BUFFER_BYTES)?;
+                    }
+                    context.synchronize()?;
+                    copies = next_iterations(copies, start.elapsed());
+                }
+                _ => {}
+            }
+            if compute && memory {
+                compute_step = !compute_step;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_power_w: Option<f64>) -> StressLimits {
+        StressLimits { max_temperature_c: DEFAULT_MAX_TEMPERATURE_C, max_power_w }
+    }
+
+    #[test]
+    fn test_check_limits() {
+        assert_eq!(check_limits(&limits(None), 70, Some(400.0)), None);
+        assert_eq!(
+            check_limits(&limits(None), 85, None),
+            Some("temperature 85°C reached the 85°C limit".to_string())
+        );
+        assert_eq!(
+            check_limits(&limits(Some(250.0)), 70, Some(251.4)),
+            Some("power draw 251 W reached the 250 W limit".to_string())
+        );
+        // A missing power reading does not abort; temperature still guards the test
+        assert_eq!(check_limits(&limits(Some(250.0)), 70, None), None);
+    }
+
+    #[test]
+    fn test_validate_rejects_unsafe_settings() {
+        assert!(validate(DEFAULT_DURATION_SECONDS, &limits(Some(300.0))).is_ok());
+        assert!(validate(0, &limits(None)).is_err());
+        assert!(validate(MAX_DURATION_SECONDS + 1, &limits(None)).is_err());
+        assert!(validate(60, &StressLimits { max_temperature_c: MAX_TEMPERATURE_LIMIT_C + 1, max_power_w: None }).is_err());
+        let err = validate(60, &limits(Some(-5.0))).unwrap_err();
+        assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+    }
+
+    #[test]
+    fn test_next_iterations_tracks_step_target() {
+        assert_eq!(next_iterations(100, Duration::from_millis(5)), 200);
+        assert_eq!(next_iterations(100, STEP_TARGET), 100);
+        assert_eq!(next_iterations(100, Duration::from_millis(500)), 50);
+        assert_eq!(next_iterations(1, Duration::from_secs(1)), 1);
+    }
+}
@@ -0,0 +1,247 @@
+//! Pull-based telemetry subscriptions
+//!
+//! Lets frontend components (and tests) consume the telemetry broadcast
+//! channel on demand instead of relying solely on global window events.
+//! Each subscription owns its own broadcast receiver; a consumer that falls
+//! behind loses the oldest frames and is told how many were dropped.
+//!
+//! Tauri 1 has no IPC channel type for streaming to a single webview
+//! (`ipc::Channel` only exists from Tauri 2), so consumers pull batches here,
+//! optionally long-polling, and the bounded broadcast receiver provides the
+//! backpressure a channel would. Subscriptions that are not polled within
+//! `IDLE_TIMEOUT` are dropped, as are all of them when the stream stops.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError, error::TryRecvError};
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+use crate::nvml::TelemetryFrame;
+
+/// Default number of frames returned by a single poll
+pub const DEFAULT_MAX_FRAMES: usize = 256;
+/// Subscriptions not polled for this long are removed
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Frames drained from a subscription in one poll
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct TelemetryBatch {
+    pub frames: Vec<TelemetryFrame>,
+    /// Frames skipped because the subscriber fell behind the channel
+    pub dropped: u64,
+    /// The stream ended; the subscription has been removed
+    pub closed: bool,
+}
+
+type SharedReceiver = Arc<Mutex<broadcast::Receiver<TelemetryFrame>>>;
+
+struct Subscription {
+    receiver: SharedReceiver,
+    last_polled: Instant,
+}
+
+/// Registry of active telemetry subscriptions keyed by subscription ID
+pub struct TelemetrySubscriptions {
+    next_id: AtomicU64,
+    idle_timeout: Duration,
+    subscriptions: Mutex<HashMap<u64, Subscription>>,
+}
+
+impl Default for TelemetrySubscriptions {
+    fn default() -> Self {
+        Self::new(IDLE_TIMEOUT)
+    }
+}
+
+impl TelemetrySubscriptions {
+    /// Create an empty registry
+    ///
+    /// # Arguments
+    /// * `idle_timeout` - How long a subscription may go unpolled before it is removed
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            idle_timeout,
+            subscriptions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register a new receiver on the given broadcast channel
+    ///
+    /// Only frames sent after subscribing are delivered.
+    pub async fn subscribe(&self, sender: &broadcast::Sender<TelemetryFrame>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let receiver = Arc::new(Mutex::new(sender.subscribe()));
+        let mut subscriptions = self.subscriptions.lock().await;
+        self.prune_idle(&mut subscriptions);
+        subscriptions.insert(id, Subscription { receiver, last_polled: Instant::now() });
+        id
+    }
+
+    /// Remove a subscription, returning whether it existed
+    pub async fn unsubscribe(&self, id: u64) -> bool {
+        self.subscriptions.lock().await.remove(&id).is_some()
+    }
+
+    /// Remove every subscription, e.g. when the stream they read from stops
+    pub async fn clear(&self) {
+        self.subscriptions.lock().await.clear();
+    }
+
+    // Drop subscriptions whose consumer has stopped polling
+    fn prune_idle(&self, subscriptions: &mut HashMap<u64, Subscription>) {
+        subscriptions.retain(|_, subscription| subscription.last_polled.elapsed() < self.idle_timeout);
+    }
+
+    /// Drain up to `max_frames` buffered frames from a subscription
+    ///
+    /// If `wait` is given and nothing is buffered, waits up to that long
+    /// for the first frame before returning.
+    ///
+    /// # Arguments
+    /// * `id` - Subscription ID returned by `subscribe`
+    /// * `max_frames` - Maximum frames to return (at least one)
+    /// * `wait` - Optional long-poll timeout
+    ///
+    /// # Returns
+    /// * `Result<TelemetryBatch>` - Drained frames or error for an unknown ID
+    pub async fn poll(&self, id: u64, max_frames: usize, wait: Option<Duration>) -> Result<TelemetryBatch> {
+        let receiver = {
+            let mut subscriptions = self.subscriptions.lock().await;
+            self.prune_idle(&mut subscriptions);
+            let subscription = subscriptions.get_mut(&id).ok_or_else(|| {
+                AppError::InvalidArgument(format!("Unknown telemetry subscription {}", id))
+            })?;
+            subscription.last_polled = Instant::now();
+            subscription.receiver.clone()
+        };
+        let mut receiver = receiver.lock().await;
+        let max_frames = max_frames.max(1);
+        let mut batch = TelemetryBatch::default();
+
+        while !batch.closed && batch.frames.len() < max_frames {
+            match receiver.try_recv() {
+                Ok(frame) => batch.frames.push(frame),
+                Err(TryRecvError::Lagged(skipped)) => batch.dropped += skipped,
+                Err(TryRecvError::Closed) => batch.closed = true,
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
+        if let (true, false, Some(wait)) = (batch.frames.is_empty(), batch.closed, wait) {
+            match tokio::time::timeout(wait, receiver.recv()).await {
+                Ok(Ok(frame)) => batch.frames.push(frame),
+                Ok(Err(RecvError::Lagged(skipped))) => batch.dropped += skipped,
+                Ok(Err(RecvError::Closed)) => batch.closed = true,
+                Err(_) => {} // Timed out with nothing to report
+            }
+        }
+        drop(receiver);
+
+        if batch.closed {
+            self.unsubscribe(id).await;
+        }
+        Ok(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp: u128) -> TelemetryFrame {
+        TelemetryFrame { timestamp, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn test_poll_drains_frames_in_order() {
+        let (tx, _rx) = broadcast::channel(16);
+        let subs = TelemetrySubscriptions::default();
+        let id = subs.subscribe(&tx).await;
+
+        for ts in 1..=5 {
+            tx.send(frame(ts)).unwrap();
+        }
+
+        let batch = subs.poll(id, 3, None).await.unwrap();
+        let stamps: Vec<u128> = batch.frames.iter().map(|f| f.timestamp).collect();
+        assert_eq!(stamps, vec![1, 2, 3]);
+        assert_eq!(batch.dropped, 0);
+
+        let batch = subs.poll(id, DEFAULT_MAX_FRAMES, None).await.unwrap();
+        assert_eq!(batch.frames.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_poll_reports_dropped_frames() {
+        let (tx, _rx) = broadcast::channel(2);
+        let subs = TelemetrySubscriptions::default();
+        let id = subs.subscribe(&tx).await;
+
+        for ts in 1..=5 {
+            tx.send(frame(ts)).unwrap();
+        }
+
+        let batch = subs.poll(id, DEFAULT_MAX_FRAMES, None).await.unwrap();
+        assert_eq!(batch.dropped, 3);
+        assert_eq!(batch.frames.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_poll_waits_for_first_frame() {
+        let (tx, _rx) = broadcast::channel(4);
+        let subs = TelemetrySubscriptions::default();
+        let id = subs.subscribe(&tx).await;
+
+        let sender = tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            sender.send(frame(42)).unwrap();
+        });
+
+        let batch = subs.poll(id, 1, Some(Duration::from_secs(2))).await.unwrap();
+        assert_eq!(batch.frames.len(), 1);
+        assert_eq!(batch.frames[0].timestamp, 42);
+    }
+
+    #[tokio::test]
+    async fn test_closed_stream_removes_subscription() {
+        let (tx, rx) = broadcast::channel(4);
+        let subs = TelemetrySubscriptions::default();
+        let id = subs.subscribe(&tx).await;
+        drop(rx);
+        drop(tx);
+
+        let batch = subs.poll(id, 1, None).await.unwrap();
+        assert!(batch.closed);
+        assert!(subs.poll(id, 1, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_subscription_is_invalid_argument() {
+        let subs = TelemetrySubscriptions::default();
+        let err = AppError::from(subs.poll(99, 1, None).await.unwrap_err());
+        assert_eq!(err.code(), "INVALID_ARGUMENT");
+        assert!(!subs.unsubscribe(99).await);
+    }
+
+    #[tokio::test]
+    async fn test_idle_and_cleared_subscriptions_are_removed() {
+        let (tx, _rx) = broadcast::channel(4);
+        let subs = TelemetrySubscriptions::new(Duration::from_millis(20));
+        let idle = subs.subscribe(&tx).await;
+        tokio::time::sleep(Duration::from_millis(40)).await;
+
+        let active = subs.subscribe(&tx).await;
+        assert!(subs.poll(idle, 1, None).await.is_err());
+        assert!(subs.poll(active, 1, None).await.is_ok());
+
+        subs.clear().await;
+        assert!(subs.poll(active, 1, None).await.is_err());
+    }
+}
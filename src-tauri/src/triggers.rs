@@ -0,0 +1,450 @@
+//! Trigger-based recording
+//!
+//! An armed trigger samples a device continuously into a ring buffer of the
+//! last `pre_trigger_seconds`. When its condition has held for the required
+//! time, the buffer becomes the start of a recording that continues for
+//! `post_trigger_seconds` and is then saved like an interval recording, so
+//! the capture shows what led up to the event as well as the event itself.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tauri::Window;
+
+use crate::error::AppError;
+use crate::markers;
+use crate::nvml::{self, RecordingFile, TelemetryFrame};
+use crate::sampler::SamplingThread;
+use crate::schema;
+use crate::sessions;
+use crate::validation;
+
+/// Pre-trigger buffer used when none is requested
+pub const DEFAULT_PRE_TRIGGER_SECONDS: u64 = 30;
+/// Post-trigger capture used when none is requested
+pub const DEFAULT_POST_TRIGGER_SECONDS: u64 = 60;
+/// Sample rate used when none is requested
+pub const DEFAULT_SAMPLE_RATE_HZ: u64 = 10;
+/// Longest pre-trigger buffer or post-trigger capture
+pub const MAX_WINDOW_SECONDS: u64 = 10 * 60;
+/// Highest sample rate a trigger can run at
+pub const MAX_SAMPLE_RATE_HZ: u64 = 100;
+
+/// Telemetry value a trigger watches
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerMetric {
+    GpuUtilization,
+    MemoryUtilization,
+    MemoryUsedMb,
+    TemperatureC,
+    PowerW,
+    SmClockMhz,
+}
+
+impl TriggerMetric {
+    fn value(self, frame: &TelemetryFrame) -> f64 {
+        match self {
+            TriggerMetric::GpuUtilization => frame.util_gpu as f64,
+            TriggerMetric::MemoryUtilization => frame.util_memory as f64,
+            TriggerMetric::MemoryUsedMb => frame.memory_used_mb as f64,
+            TriggerMetric::TemperatureC => frame.temperature_c as f64,
+            TriggerMetric::PowerW => frame.power_w as f64,
+            TriggerMetric::SmClockMhz => frame.sm_clock_mhz as f64,
+        }
+    }
+
+    /// Name of the metric's column in recordings
+    pub fn column(self) -> &'static str {
+        match self {
+            TriggerMetric::GpuUtilization => "util_gpu",
+            TriggerMetric::MemoryUtilization => "util_memory",
+            TriggerMetric::MemoryUsedMb => "memory_used_mb",
+            TriggerMetric::TemperatureC => "temperature_c",
+            TriggerMetric::PowerW => "power_w",
+            TriggerMetric::SmClockMhz => "sm_clock_mhz",
+        }
+    }
+}
+
+/// Direction of a threshold crossing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+/// Condition that fires a trigger, e.g. GPU utilization above 90% for 5 s
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TriggerCondition {
+    pub metric: TriggerMetric,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    /// How long the condition must hold; 0 fires on the first matching sample
+    #[serde(default)]
+    pub sustain_seconds: f64,
+}
+
+/// Settings of an armed trigger
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TriggerConfig {
+    pub device_index: u32,
+    pub condition: TriggerCondition,
+    /// Samples kept from before the trigger fired
+    pub pre_trigger_seconds: u64,
+    /// How long to keep recording after the trigger fired
+    pub post_trigger_seconds: u64,
+    pub sample_rate_hz: u64,
+}
+
+/// Phase of a trigger
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerPhase {
+    /// Waiting for the condition
+    Armed,
+    /// Condition fired; recording the post-trigger window
+    Capturing,
+    /// Capture written to `output_file`
+    Saved,
+    /// Disarmed before it fired
+    Disarmed,
+    /// Sampling or saving failed
+    Failed,
+}
+
+/// State of the current or most recent trigger
+#[derive(Serialize, Clone, Debug)]
+pub struct TriggerStatus {
+    pub phase: TriggerPhase,
+    pub config: TriggerConfig,
+    pub armed_at: u128,
+    pub triggered_at: Option<u128>,
+    /// Value of the watched metric when the trigger fired
+    pub trigger_value: Option<f64>,
+    pub output_file: Option<String>,
+    pub error: Option<String>,
+}
+
+// Current or most recent trigger; kept after it finishes so its result can be read
+static TRIGGER_STATE: std::sync::RwLock<Option<TriggerStatus>> = std::sync::RwLock::new(None);
+
+// Set to disarm the trigger, or end an in-progress capture early
+static TRIGGER_STOP: AtomicBool = AtomicBool::new(false);
+
+// Handle to the sampling task, used to wait for a capture to be saved
+static TRIGGER_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+/// Tracks how long a condition has held
+#[derive(Debug)]
+pub struct TriggerEvaluator {
+    condition: TriggerCondition,
+    /// Monotonic time the condition started holding (see `TelemetryFrame::clock_ms`)
+    holding_since: Option<f64>,
+}
+
+impl TriggerEvaluator {
+    pub fn new(condition: TriggerCondition) -> Self {
+        TriggerEvaluator { condition, holding_since: None }
+    }
+
+    /// Feed the next frame; returns true once the condition has held long enough
+    pub fn update(&mut self, frame: &TelemetryFrame) -> bool {
+        // An implausible reading neither starts nor breaks a sustained condition
+        if validation::is_suspect(frame, self.condition.metric.column()) {
+            return false;
+        }
+        let value = self.condition.metric.value(frame);
+        let matches = match self.condition.comparison {
+            Comparison::Above => value > self.condition.threshold,
+            Comparison::Below => value < self.condition.threshold,
+        };
+        if !matches {
+            self.holding_since = None;
+            return false;
+        }
+        let since = *self.holding_since.get_or_insert(frame.clock_ms());
+        frame.clock_ms() - since >= self.condition.sustain_seconds * 1000.0
+    }
+}
+
+/// Frames of the most recent time window
+struct RingBuffer {
+    window_ms: f64,
+    frames: VecDeque<TelemetryFrame>,
+}
+
+impl RingBuffer {
+    fn new(window_seconds: u64) -> Self {
+        RingBuffer { window_ms: window_seconds as f64 * 1000.0, frames: VecDeque::new() }
+    }
+
+    fn push(&mut self, frame: TelemetryFrame) {
+        let cutoff = frame.clock_ms() - self.window_ms;
+        self.frames.push_back(frame);
+        while self.frames.front().is_some_and(|oldest| oldest.clock_ms() < cutoff) {
+            self.frames.pop_front();
+        }
+    }
+}
+
+/// Arm a recording trigger
+///
+/// Only one trigger can be armed at a time. Emits
+/// `recording-triggered` when the condition fires and
+/// `trigger-recording-saved` once the capture is written.
+///
+/// # Arguments
+/// * `config` - Condition, device and capture windows
+/// * `window` - Tauri window handle for events
+///
+/// # Returns
+/// * `Result<TriggerStatus>` - Status of the armed trigger or error
+pub async fn arm_trigger(config: TriggerConfig, window: Window) -> Result<TriggerStatus> {
+    validate(&config)?;
+    let status = TriggerStatus {
+        phase: TriggerPhase::Armed,
+        config: config.clone(),
+        armed_at: nvml::now_ms(),
+        triggered_at: None,
+        trigger_value: None,
+        output_file: None,
+        error: None,
+    };
+    let claim = sessions::claim(&TRIGGER_STATE, |status| is_active(status.phase), status.clone(), "A recording trigger is already armed")?;
+    TRIGGER_STOP.store(false, Ordering::SeqCst);
+    let sampler = SamplingThread::start(Some(config.device_index)).await?;
+    let status = claim.commit(status);
+
+    let task = tokio::spawn(async move {
+        let result = run_trigger(sampler, &config, &window).await;
+        let finished = {
+            let mut state = TRIGGER_STATE.write().unwrap();
+            let status = state.as_mut().expect("trigger state is set while a trigger runs");
+            match result {
+                Ok(Some(output_file)) => {
+                    status.phase = TriggerPhase::Saved;
+                    status.output_file = Some(output_file);
+                }
+                Ok(None) => status.phase = TriggerPhase::Disarmed,
+                Err(e) => {
+                    status.phase = TriggerPhase::Failed;
+                    status.error = Some(format!("{:#}", e));
+                }
+            }
+            status.clone()
+        };
+        if finished.phase == TriggerPhase::Saved {
+            if let Err(e) = window.emit("trigger-recording-saved", &finished) {
+                eprintln!("Failed to emit trigger recording saved event: {}", e);
+            }
+        } else if let Some(error) = &finished.error {
+            eprintln!("Recording trigger error: {}", error);
+        }
+    });
+    *TRIGGER_TASK.lock().unwrap() = Some(task);
+
+    Ok(status)
+}
+
+/// Disarm the trigger
+///
+/// A capture already in progress is cut short and saved.
+pub async fn disarm_trigger() -> Result<TriggerStatus> {
+    let state = TRIGGER_STATE.read().unwrap();
+    match state.as_ref() {
+        Some(status) if is_active(status.phase) => {
+            TRIGGER_STOP.store(true, Ordering::SeqCst);
+            Ok(status.clone())
+        }
+        _ => Err(AppError::InvalidArgument("No recording trigger is armed".to_string()).into()),
+    }
+}
+
+/// Disarm any trigger and wait until an in-progress capture has been saved
+pub async fn finish_active_trigger() -> Result<()> {
+    TRIGGER_STOP.store(true, Ordering::SeqCst);
+    let task = TRIGGER_TASK.lock().unwrap().take();
+    if let Some(task) = task {
+        task.await.context("Trigger task ended abnormally")?;
+    }
+    Ok(())
+}
+
+/// Get the status of the current or most recent trigger
+pub fn get_trigger_status() -> Option<TriggerStatus> {
+    TRIGGER_STATE.read().unwrap().clone()
+}
+
+fn is_active(phase: TriggerPhase) -> bool {
+    matches!(phase, TriggerPhase::Armed | TriggerPhase::Capturing)
+}
+
+fn validate(config: &TriggerConfig) -> Result<()> {
+    if !(1..=MAX_SAMPLE_RATE_HZ).contains(&config.sample_rate_hz) {
+        return Err(AppError::InvalidArgument(format!(
+            "Sample rate must be between 1 and {} Hz, got {}",
+            MAX_SAMPLE_RATE_HZ, config.sample_rate_hz
+        )).into());
+    }
+    for (name, seconds) in [("Pre-trigger", config.pre_trigger_seconds), ("Post-trigger", config.post_trigger_seconds)] {
+        if seconds > MAX_WINDOW_SECONDS {
+            return Err(AppError::InvalidArgument(format!(
+                "{} window must be at most {} seconds, got {}",
+                name, MAX_WINDOW_SECONDS, seconds
+            )).into());
+        }
+    }
+    let sustain = config.condition.sustain_seconds;
+    if !sustain.is_finite() || !(0.0..=MAX_WINDOW_SECONDS as f64).contains(&sustain) {
+        return Err(AppError::InvalidArgument(format!("Sustain time must be between 0 and {} seconds", MAX_WINDOW_SECONDS)).into());
+    }
+    Ok(())
+}
+
+// Sample until the trigger fires and the capture completes; `None` if disarmed first
+async fn run_trigger(sampler: SamplingThread, config: &TriggerConfig, window: &Window) -> Result<Option<String>> {
+    let interval = Duration::from_secs_f64(1.0 / config.sample_rate_hz as f64);
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut evaluator = TriggerEvaluator::new(config.condition.clone());
+    let mut buffer = RingBuffer::new(config.pre_trigger_seconds);
+    // Monotonic time the post-trigger capture ends
+    let mut capture_until: Option<f64> = None;
+
+    loop {
+        ticker.tick().await;
+        if TRIGGER_STOP.load(Ordering::SeqCst) {
+            break;
+        }
+        // A failed sample only leaves a gap; the trigger stays armed
+        let Ok(mut frames) = sampler.sample().await else { continue };
+        let Some(frame) = frames.pop() else { continue };
+        let (timestamp, clock_ms) = (frame.timestamp, frame.clock_ms());
+
+        match capture_until {
+            None => {
+                let fired = evaluator.update(&frame);
+                let value = config.condition.metric.value(&frame);
+                buffer.push(frame);
+                if fired {
+                    capture_until = Some(clock_ms + config.post_trigger_seconds as f64 * 1000.0);
+                    let status = {
+                        let mut state = TRIGGER_STATE.write().unwrap();
+                        let status = state.as_mut().expect("trigger state is set while a trigger runs");
+                        status.phase = TriggerPhase::Capturing;
+                        status.triggered_at = Some(timestamp);
+                        status.trigger_value = Some(value);
+                        status.clone()
+                    };
+                    if let Err(e) = window.emit("recording-triggered", &status) {
+                        eprintln!("Failed to emit recording triggered event: {}", e);
+                    }
+                }
+            }
+            Some(until) => {
+                buffer.frames.push_back(frame);
+                if clock_ms >= until {
+                    break;
+                }
+            }
+        }
+    }
+
+    if capture_until.is_none() {
+        return Ok(None);
+    }
+    let samples: Vec<TelemetryFrame> = buffer.frames.into();
+    save_capture(&sampler, config.sample_rate_hz, samples).map(Some)
+}
+
+fn save_capture(sampler: &SamplingThread, sample_rate_hz: u64, samples: Vec<TelemetryFrame>) -> Result<String> {
+    let output_file = format!("recordings/gpu_trigger_{}.json", nvml::now_ms());
+    if let Some(parent) = std::path::Path::new(&output_file).parent() {
+        std::fs::create_dir_all(parent).context("Failed to create output directory")?;
+    }
+    let markers = match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) => markers::ranges_between(first.timestamp, last.timestamp),
+        _ => Vec::new(),
+    };
+    let sample_times_ms: Vec<f64> = samples.iter()
+        .map(|frame| frame.ms_since(&samples[0]))
+        .collect();
+    let recording = RecordingFile {
+        schema_version: schema::CURRENT_SCHEMA_VERSION,
+        device: sampler.devices()[0].clone(),
+        devices: sampler.devices().to_vec(),
+        timing: Some(nvml::sampling_timing(sample_rate_hz, &sample_times_ms)),
+        process: None,
+        violations: Vec::new(),
+        // Pre-trigger frames share the process's monotonic clock, so a mapping read now covers them
+        clock: Some(nvml::ClockMapping::capture()),
+        samples,
+        markers,
+    };
+    let json_data = serde_json::to_string_pretty(&recording)
+        .context("Failed to serialize recording data")?;
+    std::fs::write(&output_file, json_data)
+        .context("Failed to write recording file")?;
+    println!("Triggered recording saved: {} samples to {}", recording.samples.len(), output_file);
+    Ok(output_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp: u128, util_gpu: u32) -> TelemetryFrame {
+        TelemetryFrame { timestamp, util_gpu, ..Default::default() }
+    }
+
+    fn condition(sustain_seconds: f64) -> TriggerCondition {
+        TriggerCondition { metric: TriggerMetric::GpuUtilization, comparison: Comparison::Above, threshold: 90.0, sustain_seconds }
+    }
+
+    #[test]
+    fn test_trigger_fires_after_sustained_condition() {
+        let mut evaluator = TriggerEvaluator::new(condition(5.0));
+        assert!(!evaluator.update(&frame(0, 95)));
+        assert!(!evaluator.update(&frame(3_000, 99)));
+        // A dip resets the hold time
+        assert!(!evaluator.update(&frame(4_000, 50)));
+        assert!(!evaluator.update(&frame(5_000, 95)));
+        assert!(!evaluator.update(&frame(9_999, 95)));
+        assert!(evaluator.update(&frame(10_000, 95)));
+
+        let mut immediate = TriggerEvaluator::new(condition(0.0));
+        assert!(immediate.update(&frame(0, 91)));
+        // Implausible readings are ignored without resetting the hold time
+        let suspect = TelemetryFrame { suspect_fields: vec!["util_gpu".to_string()], ..frame(0, 100) };
+        assert!(!immediate.update(&suspect));
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_pre_trigger_window() {
+        let mut buffer = RingBuffer::new(2);
+        for second in 0..=5u128 {
+            buffer.push(frame(second * 1_000, 0));
+        }
+        let timestamps: Vec<u128> = buffer.frames.iter().map(|f| f.timestamp).collect();
+        assert_eq!(timestamps, vec![3_000, 4_000, 5_000]);
+    }
+
+    #[test]
+    fn test_validate_rejects_out_of_range_windows() {
+        let config = TriggerConfig {
+            device_index: 0,
+            condition: condition(5.0),
+            pre_trigger_seconds: 30,
+            post_trigger_seconds: 60,
+            sample_rate_hz: 10,
+        };
+        assert!(validate(&config).is_ok());
+        assert!(validate(&TriggerConfig { sample_rate_hz: 0, ..config.clone() }).is_err());
+        assert!(validate(&TriggerConfig { pre_trigger_seconds: MAX_WINDOW_SECONDS + 1, ..config.clone() }).is_err());
+        assert!(validate(&TriggerConfig { condition: condition(f64::NAN), ..config }).is_err());
+    }
+}
@@ -0,0 +1,604 @@
+//! Tuning profiles
+//!
+//! Named sets of power limit, clock offsets and fan curve, stored in
+//! `TUNING_CONFIG_FILE`. A profile is applied on demand, when the app
+//! starts, or when a matching process appears on a GPU. Power limits and
+//! clock offsets stay in effect until changed or until the driver unloads;
+//! a fan curve is followed by a background controller that hands the fans
+//! back to the driver when it stops. Clock offsets and manual fan speeds
+//! are not wrapped by nvml-wrapper and are set through the raw NVML
+//! bindings; drivers without them fail with `NOT_SUPPORTED`. Every change
+//! requires root or administrator rights.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::error::nvml_try;
+use nvml_wrapper::{device::Device, Nvml};
+use nvml_wrapper_sys::bindings::NvmlLib;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::AppError;
+use crate::nvml;
+use crate::processes;
+
+/// Where tuning profiles and auto-apply rules are stored
+pub const TUNING_CONFIG_FILE: &str = "tuning_profiles.json";
+/// How often the fan curve controller adjusts fan speeds
+pub const FAN_CURVE_INTERVAL_MS: u64 = 2_000;
+/// How often GPU processes are checked against the process rules
+pub const PROCESS_POLL_INTERVAL_MS: u64 = 5_000;
+
+#[cfg(target_os = "windows")]
+const NVML_LIBRARY: &str = "nvml.dll";
+#[cfg(not(target_os = "windows"))]
+const NVML_LIBRARY: &str = "libnvidia-ml.so.1";
+
+/// One point of a fan curve
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FanCurvePoint {
+    pub temperature_c: u32,
+    pub speed_percent: u32,
+}
+
+/// A named set of device settings
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TuningProfile {
+    pub name: String,
+    /// Devices the profile applies to; every device if empty
+    #[serde(default)]
+    pub device_indices: Vec<u32>,
+    #[serde(default)]
+    pub power_limit_w: Option<u32>,
+    /// Graphics clock offset along the voltage/frequency curve
+    #[serde(default)]
+    pub gpc_clock_offset_mhz: Option<i32>,
+    #[serde(default)]
+    pub memory_clock_offset_mhz: Option<i32>,
+    /// Fan speed by temperature; the driver controls the fans if empty
+    #[serde(default)]
+    pub fan_curve: Vec<FanCurvePoint>,
+}
+
+/// Apply a profile when a process with this name appears on a GPU
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProcessRule {
+    /// Process name, executable name or attributed script, case-insensitive
+    pub process_name: String,
+    pub profile: String,
+}
+
+/// Stored profiles and auto-apply settings
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TuningConfig {
+    #[serde(default)]
+    pub profiles: Vec<TuningProfile>,
+    /// Profile applied when the app starts
+    #[serde(default)]
+    pub launch_profile: Option<String>,
+    #[serde(default)]
+    pub process_rules: Vec<ProcessRule>,
+}
+
+/// What tuning is currently in effect
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct TuningStatus {
+    /// Most recently applied profile
+    pub active_profile: Option<String>,
+    /// Devices whose fans follow the active profile's curve
+    pub fan_curve_devices: Vec<u32>,
+    pub watching_processes: bool,
+    /// Failure of the most recent automatic application
+    pub last_error: Option<String>,
+}
+
+// Serializes read-modify-write of the config file
+static CONFIG_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+static TUNING_STATE: std::sync::RwLock<Option<TuningStatus>> = std::sync::RwLock::new(None);
+
+// Fan curve controller
+static FAN_CANCEL: std::sync::Mutex<Option<CancellationToken>> = std::sync::Mutex::new(None);
+static FAN_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+// Process rule watcher
+static WATCH_CANCEL: std::sync::Mutex<Option<CancellationToken>> = std::sync::Mutex::new(None);
+static WATCH_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+/// Read the stored tuning config
+///
+/// # Arguments
+/// * `path` - Config file; a missing file is an empty config
+///
+/// # Returns
+/// * `Result<TuningConfig>` - Config or error for an unreadable file
+pub fn load_config(path: &Path) -> Result<TuningConfig> {
+    if !path.exists() {
+        return Ok(TuningConfig::default());
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Write the tuning config
+pub fn save_config(path: &Path, config: &TuningConfig) -> Result<()> {
+    let text = serde_json::to_string_pretty(config).context("Failed to serialize tuning config")?;
+    std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Add a profile, or replace the profile of the same name
+///
+/// # Arguments
+/// * `config` - Config to change
+/// * `profile` - New profile
+///
+/// # Returns
+/// * `Result<()>` - Error for an unnamed profile or an invalid fan curve
+pub fn upsert_profile(config: &mut TuningConfig, profile: TuningProfile) -> Result<()> {
+    validate_profile(&profile)?;
+    match config.profiles.iter_mut().find(|existing| existing.name == profile.name) {
+        Some(existing) => *existing = profile,
+        None => config.profiles.push(profile),
+    }
+    Ok(())
+}
+
+/// Remove a profile together with the auto-apply settings that use it
+///
+/// # Returns
+/// * `Result<()>` - Error if no profile has that name
+pub fn remove_profile(config: &mut TuningConfig, name: &str) -> Result<()> {
+    let count = config.profiles.len();
+    config.profiles.retain(|profile| profile.name != name);
+    if config.profiles.len() == count {
+        return Err(unknown_profile(name));
+    }
+    if config.launch_profile.as_deref() == Some(name) {
+        config.launch_profile = None;
+    }
+    config.process_rules.retain(|rule| rule.profile != name);
+    Ok(())
+}
+
+/// Set the profile applied at launch and the process rules
+///
+/// # Returns
+/// * `Result<()>` - Error if a setting names an unknown profile or an empty process
+pub fn set_auto_apply(config: &mut TuningConfig, launch_profile: Option<String>, process_rules: Vec<ProcessRule>) -> Result<()> {
+    for name in launch_profile.iter().chain(process_rules.iter().map(|rule| &rule.profile)) {
+        if !config.profiles.iter().any(|profile| &profile.name == name) {
+            return Err(unknown_profile(name));
+        }
+    }
+    if process_rules.iter().any(|rule| rule.process_name.trim().is_empty()) {
+        return Err(AppError::InvalidArgument("Process rules need a process name".to_string()).into());
+    }
+    config.launch_profile = launch_profile;
+    config.process_rules = process_rules;
+    Ok(())
+}
+
+/// Fan speed for a temperature, interpolated linearly between curve points
+///
+/// Below the first point the first speed applies, above the last point the last.
+///
+/// # Arguments
+/// * `curve` - Points in increasing temperature order
+/// * `temperature_c` - Current GPU temperature
+///
+/// # Returns
+/// * `Option<u32>` - Fan speed in percent, or `None` for an empty curve
+pub fn fan_speed_for(curve: &[FanCurvePoint], temperature_c: u32) -> Option<u32> {
+    let first = curve.first()?;
+    let last = curve.last()?;
+    if temperature_c <= first.temperature_c {
+        return Some(first.speed_percent);
+    }
+    if temperature_c >= last.temperature_c {
+        return Some(last.speed_percent);
+    }
+    curve.windows(2)
+        .find(|pair| temperature_c < pair[1].temperature_c)
+        .map(|pair| {
+            let (low, high) = (pair[0], pair[1]);
+            let progress = (temperature_c - low.temperature_c) as f64 / (high.temperature_c - low.temperature_c) as f64;
+            (low.speed_percent as f64 + progress * (high.speed_percent as f64 - low.speed_percent as f64)).round() as u32
+        })
+}
+
+/// Apply a stored profile
+///
+/// # Arguments
+/// * `name` - Profile to apply
+///
+/// # Returns
+/// * `Result<TuningStatus>` - Tuning in effect or error for an unknown profile or a refused setting
+pub async fn apply_profile(name: &str) -> Result<TuningStatus> {
+    let profile = get_config().await?
+        .profiles.into_iter()
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| unknown_profile(name))?;
+    apply(profile).await
+}
+
+async fn apply(profile: TuningProfile) -> Result<TuningStatus> {
+    // A new profile takes the fans over from the previous curve
+    finish_fan_control().await?;
+    let settings = profile.clone();
+    let devices = nvml::blocking(move || apply_blocking(&settings)).await?;
+    let fan_curve_devices = if profile.fan_curve.is_empty() {
+        Vec::new()
+    } else {
+        start_fan_control(devices.clone(), profile.fan_curve.clone());
+        devices
+    };
+
+    let mut state = TUNING_STATE.write().unwrap();
+    let status = state.get_or_insert_with(TuningStatus::default);
+    status.active_profile = Some(profile.name);
+    status.fan_curve_devices = fan_curve_devices;
+    status.last_error = None;
+    Ok(status.clone())
+}
+
+fn apply_blocking(profile: &TuningProfile) -> Result<Vec<u32>> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let devices = if profile.device_indices.is_empty() {
+        (0..nvml.device_count().context("Failed to get device count")?).collect()
+    } else {
+        profile.device_indices.clone()
+    };
+    for &device_index in &devices {
+        let mut device = nvml::device_at(&nvml, device_index)?;
+        if let Some(watts) = profile.power_limit_w {
+            device.set_power_management_limit(watts * 1000)
+                .with_context(|| format!("Failed to set power limit on GPU {} (requires root)", device_index))?;
+        }
+        if let Some(offset) = profile.gpc_clock_offset_mhz {
+            set_clock_offset(&device, ClockOffset::Graphics, offset)
+                .with_context(|| format!("Failed to set graphics clock offset on GPU {} (requires root)", device_index))?;
+        }
+        if let Some(offset) = profile.memory_clock_offset_mhz {
+            set_clock_offset(&device, ClockOffset::Memory, offset)
+                .with_context(|| format!("Failed to set memory clock offset on GPU {} (requires root)", device_index))?;
+        }
+    }
+    Ok(devices)
+}
+
+/// Get the tuning currently in effect
+pub fn get_tuning_status() -> TuningStatus {
+    let mut status = TUNING_STATE.read().unwrap().clone().unwrap_or_default();
+    status.watching_processes = WATCH_TASK.lock().unwrap().as_ref().is_some_and(|task| !task.is_finished());
+    status
+}
+
+/// Read the stored tuning config
+pub async fn get_config() -> Result<TuningConfig> {
+    let _guard = CONFIG_LOCK.lock().await;
+    load_config(Path::new(TUNING_CONFIG_FILE))
+}
+
+/// Read, change and store the tuning config, restarting the process
+/// watcher for the new rules
+///
+/// # Arguments
+/// * `change` - Edit to make; nothing is stored if it fails
+///
+/// # Returns
+/// * `Result<TuningConfig>` - Stored config or error
+pub async fn update_config(change: impl FnOnce(&mut TuningConfig) -> Result<()>) -> Result<TuningConfig> {
+    let config = {
+        let _guard = CONFIG_LOCK.lock().await;
+        let path = Path::new(TUNING_CONFIG_FILE);
+        let mut config = load_config(path)?;
+        change(&mut config)?;
+        save_config(path, &config)?;
+        config
+    };
+    restart_process_watcher(&config).await?;
+    Ok(config)
+}
+
+/// Apply the launch profile and start watching for rule processes
+///
+/// Called once when the app starts; failures are recorded in the status
+/// rather than stopping the app.
+pub async fn apply_on_launch() {
+    let result = async {
+        let config = get_config().await?;
+        if let Some(name) = &config.launch_profile {
+            apply_profile(name).await?;
+        }
+        restart_process_watcher(&config).await
+    }.await;
+    if let Err(e) = result {
+        eprintln!("Failed to apply tuning profile on launch: {:#}", e);
+        record_error(&e);
+    }
+}
+
+/// Stop the process watcher and return the fans to driver control
+pub async fn finish_tuning() -> Result<()> {
+    stop_process_watcher().await?;
+    finish_fan_control().await
+}
+
+async fn restart_process_watcher(config: &TuningConfig) -> Result<()> {
+    stop_process_watcher().await?;
+    if config.process_rules.is_empty() {
+        return Ok(());
+    }
+    let rules = config.process_rules.clone();
+    let cancel = CancellationToken::new();
+    *WATCH_CANCEL.lock().unwrap() = Some(cancel.clone());
+    let task = tokio::spawn(async move {
+        // Processes already running when watching starts do not trigger a rule
+        let mut seen: Option<HashSet<u32>> = None;
+        let mut interval = tokio::time::interval(Duration::from_millis(PROCESS_POLL_INTERVAL_MS));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            let report = match processes::get_gpu_processes(None).await {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("Tuning process watcher could not list GPU processes: {:#}", e);
+                    continue;
+                }
+            };
+            let previous = seen.replace(report.processes.iter().map(|process| process.pid).collect());
+            let Some(previous) = previous else { continue };
+            let Some(profile) = report.processes.iter()
+                .filter(|process| !previous.contains(&process.pid))
+                .find_map(|process| matching_rule(&rules, process)) else { continue };
+            if let Err(e) = apply_profile(&profile).await {
+                eprintln!("Failed to apply tuning profile {}: {:#}", profile, e);
+                record_error(&e);
+            }
+        }
+    });
+    *WATCH_TASK.lock().unwrap() = Some(task);
+    Ok(())
+}
+
+async fn stop_process_watcher() -> Result<()> {
+    if let Some(cancel) = WATCH_CANCEL.lock().unwrap().take() {
+        cancel.cancel();
+    }
+    let task = WATCH_TASK.lock().unwrap().take();
+    if let Some(task) = task {
+        task.await.context("Tuning process watcher ended abnormally")?;
+    }
+    Ok(())
+}
+
+// Profile of the first rule matching a process
+fn matching_rule(rules: &[ProcessRule], process: &processes::GpuProcess) -> Option<String> {
+    rules.iter()
+        .find(|rule| processes::is_named(process, &rule.process_name))
+        .map(|rule| rule.profile.clone())
+}
+
+fn start_fan_control(devices: Vec<u32>, curve: Vec<FanCurvePoint>) {
+    let cancel = CancellationToken::new();
+    *FAN_CANCEL.lock().unwrap() = Some(cancel.clone());
+    let task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_millis(FAN_CURVE_INTERVAL_MS));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            let (devices, curve) = (devices.clone(), curve.clone());
+            if let Err(e) = nvml::blocking(move || follow_fan_curve(&devices, &curve)).await {
+                eprintln!("Fan curve controller failed: {:#}", e);
+            }
+        }
+        let devices = devices.clone();
+        if let Err(e) = nvml::blocking(move || restore_default_fans(&devices)).await {
+            eprintln!("Failed to return fans to driver control: {:#}", e);
+        }
+    });
+    *FAN_TASK.lock().unwrap() = Some(task);
+}
+
+async fn finish_fan_control() -> Result<()> {
+    if let Some(cancel) = FAN_CANCEL.lock().unwrap().take() {
+        cancel.cancel();
+    }
+    let task = FAN_TASK.lock().unwrap().take();
+    if let Some(task) = task {
+        task.await.context("Fan curve controller ended abnormally")?;
+    }
+    if let Some(status) = TUNING_STATE.write().unwrap().as_mut() {
+        status.fan_curve_devices.clear();
+    }
+    Ok(())
+}
+
+fn follow_fan_curve(devices: &[u32], curve: &[FanCurvePoint]) -> Result<()> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let lib = raw_nvml()?;
+    let set_speed = lib.nvmlDeviceSetFanSpeed_v2.as_ref()
+        .map_err(|_| AppError::NotSupported("The driver does not support manual fan control".to_string()))?;
+    for &device_index in devices {
+        let device = nvml::device_at(&nvml, device_index)?;
+        let temperature = device.temperature(nvml_wrapper::enum_wrappers::device::TemperatureSensor::Gpu)
+            .with_context(|| format!("Failed to read temperature of GPU {}", device_index))?;
+        let Some(speed) = fan_speed_for(curve, temperature) else { continue };
+        for fan in 0..device.num_fans().unwrap_or(0) {
+            nvml_try(unsafe { set_speed(device.handle(), fan, speed.min(100)) })
+                .with_context(|| format!("Failed to set fan {} of GPU {} (requires root)", fan, device_index))?;
+        }
+    }
+    Ok(())
+}
+
+fn restore_default_fans(devices: &[u32]) -> Result<()> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    let lib = raw_nvml()?;
+    let Ok(set_default) = lib.nvmlDeviceSetDefaultFanSpeed_v2.as_ref() else {
+        return Ok(());
+    };
+    for &device_index in devices {
+        let device = nvml::device_at(&nvml, device_index)?;
+        for fan in 0..device.num_fans().unwrap_or(0) {
+            nvml_try(unsafe { set_default(device.handle(), fan) })
+                .with_context(|| format!("Failed to restore fan {} of GPU {}", fan, device_index))?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum ClockOffset {
+    Graphics,
+    Memory,
+}
+
+fn set_clock_offset(device: &Device, clock: ClockOffset, offset_mhz: i32) -> Result<()> {
+    let lib = raw_nvml()?;
+    let set = match clock {
+        ClockOffset::Graphics => lib.nvmlDeviceSetGpcClkVfOffset.as_ref(),
+        ClockOffset::Memory => lib.nvmlDeviceSetMemClkVfOffset.as_ref(),
+    }.map_err(|_| AppError::NotSupported("The driver does not support clock offsets".to_string()))?;
+    nvml_try(unsafe { set(device.handle(), offset_mhz) })?;
+    Ok(())
+}
+
+/// Whether the driver exports the call to set fan speeds manually
+pub fn fan_control_supported() -> bool {
+    raw_nvml().is_ok_and(|lib| lib.nvmlDeviceSetFanSpeed_v2.is_ok())
+}
+
+/// Whether the driver exports the calls to offset clocks
+pub fn clock_offsets_supported() -> bool {
+    raw_nvml().is_ok_and(|lib| lib.nvmlDeviceSetGpcClkVfOffset.is_ok() && lib.nvmlDeviceSetMemClkVfOffset.is_ok())
+}
+
+// The raw bindings, loaded from the same library nvml-wrapper uses so device
+// handles are shared
+pub(crate) fn raw_nvml() -> Result<&'static NvmlLib> {
+    static RAW_NVML: OnceLock<Option<NvmlLib>> = OnceLock::new();
+    RAW_NVML.get_or_init(|| unsafe { NvmlLib::new(NVML_LIBRARY) }.ok())
+        .as_ref()
+        .ok_or_else(|| AppError::NvmlUnavailable(format!("Failed to load {}", NVML_LIBRARY)).into())
+}
+
+fn validate_profile(profile: &TuningProfile) -> Result<()> {
+    if profile.name.trim().is_empty() {
+        return Err(AppError::InvalidArgument("Tuning profiles need a name".to_string()).into());
+    }
+    if profile.power_limit_w == Some(0) {
+        return Err(AppError::InvalidArgument("Power limit must be above 0 W".to_string()).into());
+    }
+    if profile.fan_curve.iter().any(|point| point.speed_percent > 100) {
+        return Err(AppError::InvalidArgument("Fan speeds must be at most 100%".to_string()).into());
+    }
+    if profile.fan_curve.windows(2).any(|pair| pair[0].temperature_c >= pair[1].temperature_c) {
+        return Err(AppError::InvalidArgument("Fan curve temperatures must increase".to_string()).into());
+    }
+    Ok(())
+}
+
+fn unknown_profile(name: &str) -> anyhow::Error {
+    AppError::InvalidArgument(format!("Unknown tuning profile: {}", name)).into()
+}
+
+fn record_error(error: &anyhow::Error) {
+    TUNING_STATE.write().unwrap().get_or_insert_with(TuningStatus::default).last_error = Some(format!("{:#}", error));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str) -> TuningProfile {
+        TuningProfile {
+            name: name.to_string(),
+            device_indices: Vec::new(),
+            power_limit_w: Some(300),
+            gpc_clock_offset_mhz: Some(100),
+            memory_clock_offset_mhz: None,
+            fan_curve: vec![
+                FanCurvePoint { temperature_c: 40, speed_percent: 30 },
+                FanCurvePoint { temperature_c: 80, speed_percent: 90 },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_fan_curve_interpolates_and_clamps() {
+        let curve = profile("quiet").fan_curve;
+        assert_eq!(fan_speed_for(&curve, 20), Some(30));
+        assert_eq!(fan_speed_for(&curve, 60), Some(60));
+        assert_eq!(fan_speed_for(&curve, 70), Some(75));
+        assert_eq!(fan_speed_for(&curve, 95), Some(90));
+        assert_eq!(fan_speed_for(&[], 50), None);
+    }
+
+    #[test]
+    fn test_profiles_and_auto_apply_rules() {
+        let mut config = TuningConfig::default();
+        upsert_profile(&mut config, profile("quiet")).unwrap();
+        upsert_profile(&mut config, TuningProfile { power_limit_w: Some(250), ..profile("quiet") }).unwrap();
+        upsert_profile(&mut config, profile("boost")).unwrap();
+        assert_eq!(config.profiles.len(), 2);
+        assert_eq!(config.profiles[0].power_limit_w, Some(250));
+
+        let rule = ProcessRule { process_name: "blender".to_string(), profile: "boost".to_string() };
+        set_auto_apply(&mut config, Some("quiet".to_string()), vec![rule.clone()]).unwrap();
+        let err = set_auto_apply(&mut config, Some("missing".to_string()), Vec::new()).unwrap_err();
+        assert_eq!(AppError::from(err).code(), "INVALID_ARGUMENT");
+
+        remove_profile(&mut config, "boost").unwrap();
+        assert!(config.process_rules.is_empty());
+        assert_eq!(config.launch_profile.as_deref(), Some("quiet"));
+        assert!(remove_profile(&mut config, "boost").is_err());
+
+        let unsorted = TuningProfile { fan_curve: profile("x").fan_curve.into_iter().rev().collect(), ..profile("x") };
+        for invalid in [unsorted, profile(" "), TuningProfile { power_limit_w: Some(0), ..profile("x") }] {
+            assert!(upsert_profile(&mut config, invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_config_round_trip_and_rule_matching() {
+        let path = std::env::temp_dir().join(format!("nsightful_tuning_{}.json", std::process::id()));
+        assert_eq!(load_config(&path).unwrap(), TuningConfig::default());
+        let config = TuningConfig {
+            profiles: vec![profile("render")],
+            launch_profile: None,
+            process_rules: vec![ProcessRule { process_name: "Blender".to_string(), profile: "render".to_string() }],
+        };
+        save_config(&path, &config).unwrap();
+        assert_eq!(load_config(&path).unwrap(), config);
+        std::fs::remove_file(&path).ok();
+
+        let process = processes::GpuProcess {
+            pid: 42,
+            device_index: 0,
+            kind: "graphics".to_string(),
+            used_memory_mb: None,
+            name: "blender".to_string(),
+            exe: Some("/opt/blender/blender".to_string()),
+            command_line: Vec::new(),
+            parent_pid: None,
+            attributed_to: "blender".to_string(),
+            tree_root_pid: 42,
+            cgroup: None,
+            container: None,
+        };
+        assert_eq!(matching_rule(&config.process_rules, &process).as_deref(), Some("render"));
+        let other = processes::GpuProcess { name: "python".to_string(), exe: None, attributed_to: "train.py".to_string(), ..process };
+        assert_eq!(matching_rule(&config.process_rules, &other), None);
+    }
+}
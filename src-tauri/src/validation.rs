@@ -0,0 +1,213 @@
+//! Plausibility checks for sampled telemetry
+//!
+//! NVML occasionally returns readings no device can produce: a 0 °C die
+//! temperature from a sensor that answered a moment ago, utilization above
+//! 100%, or a power draw many times the board limit after a counter glitch.
+//! Every frame is checked against physical bounds and the device's own
+//! limits before it reaches streams, recordings and triggers. Values with a
+//! natural bound are clamped to it; the rest are kept as read. Either way
+//! the field is listed in the frame's `suspect_fields`, so charts and
+//! threshold checks can skip it.
+
+use nvml_wrapper::device::Device;
+use nvml_wrapper::enum_wrappers::device::Clock;
+
+use crate::nvml::TelemetryFrame;
+
+/// Hottest die temperature treated as a real reading
+pub const MAX_TEMPERATURE_C: u32 = 125;
+/// Multiple of the power limit above which a reading is suspect
+pub const POWER_LIMIT_FACTOR: f32 = 2.0;
+/// Highest power draw treated as real when the device reports no limit
+pub const MAX_POWER_W: f32 = 2_000.0;
+/// Multiple of the maximum clock above which a reading is suspect; leaves
+/// room for overclocking offsets the reported maximum does not include
+pub const CLOCK_LIMIT_FACTOR: f32 = 1.5;
+
+/// Per-device bounds, read once when sampling starts
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceLimits {
+    /// Whether the temperature sensor answers; unsupported sensors report 0 by convention
+    pub temperature_supported: bool,
+    pub power_limit_w: Option<f32>,
+    pub max_sm_clock_mhz: Option<u32>,
+    pub max_memory_clock_mhz: Option<u32>,
+    pub memory_total_mb: u64,
+}
+
+impl DeviceLimits {
+    /// Read a device's limits, leaving out the ones it does not report
+    ///
+    /// # Arguments
+    /// * `device` - NVML device handle
+    /// * `memory_total_mb` - Device memory size
+    /// * `temperature_supported` - Whether the temperature sensor was found to answer
+    pub fn read(device: &Device, memory_total_mb: u64, temperature_supported: bool) -> Self {
+        DeviceLimits {
+            temperature_supported,
+            power_limit_w: device.enforced_power_limit().ok().map(|limit| limit as f32 / 1000.0),
+            max_sm_clock_mhz: device.max_clock_info(Clock::Graphics).ok().filter(|&clock| clock > 0),
+            max_memory_clock_mhz: device.max_clock_info(Clock::Memory).ok().filter(|&clock| clock > 0),
+            memory_total_mb,
+        }
+    }
+}
+
+/// Check a frame, clamping bounded values and listing every implausible field
+///
+/// # Arguments
+/// * `frame` - Frame to check in place; `suspect_fields` is replaced
+/// * `limits` - Bounds of the device the frame was read from
+pub fn validate(frame: &mut TelemetryFrame, limits: &DeviceLimits) {
+    let mut suspect = Vec::new();
+
+    for (field, value) in [("util_gpu", &mut frame.util_gpu), ("util_memory", &mut frame.util_memory)] {
+        if *value > 100 {
+            *value = 100;
+            suspect.push(field);
+        }
+    }
+    // Mirrors util_gpu, which is flagged on its own
+    frame.engine_utilization.graphics = frame.engine_utilization.graphics.min(100);
+    for (field, value) in [
+        ("engine_utilization.encoder", &mut frame.engine_utilization.encoder),
+        ("engine_utilization.decoder", &mut frame.engine_utilization.decoder),
+        ("engine_utilization.jpeg", &mut frame.engine_utilization.jpeg),
+        ("engine_utilization.ofa", &mut frame.engine_utilization.ofa),
+    ] {
+        if let Some(value) = value.as_mut().filter(|value| **value > 100) {
+            *value = 100;
+            suspect.push(field);
+        }
+    }
+    if limits.memory_total_mb > 0 && frame.memory_used_mb > limits.memory_total_mb {
+        frame.memory_used_mb = limits.memory_total_mb;
+        suspect.push("memory_used_mb");
+    }
+
+    let temperature_invalid = (limits.temperature_supported && frame.temperature_c == 0)
+        || frame.temperature_c > MAX_TEMPERATURE_C;
+    if temperature_invalid {
+        suspect.push("temperature_c");
+    }
+    let max_power_w = limits.power_limit_w
+        .filter(|&limit| limit > 0.0)
+        .map_or(MAX_POWER_W, |limit| limit * POWER_LIMIT_FACTOR);
+    if !frame.power_w.is_finite() || frame.power_w > max_power_w {
+        suspect.push("power_w");
+    }
+    for (field, value, max) in [
+        ("sm_clock_mhz", frame.sm_clock_mhz, limits.max_sm_clock_mhz),
+        ("memory_clock_mhz", frame.memory_clock_mhz, limits.max_memory_clock_mhz),
+    ] {
+        if max.is_some_and(|max| value as f32 > max as f32 * CLOCK_LIMIT_FACTOR) {
+            suspect.push(field);
+        }
+    }
+
+    let mut suspect: Vec<String> = suspect.into_iter().map(String::from).collect();
+    // Buffered peaks pass through the same bounds; implausible ones are dropped
+    frame.interval_max.retain(|field, value| {
+        let plausible = match field.as_str() {
+            "power_w" => value.is_finite() && *value <= max_power_w as f64,
+            "util_gpu" | "util_memory" | "engine_utilization.encoder" | "engine_utilization.decoder" => *value <= 100.0,
+            _ => true,
+        };
+        if !plausible {
+            suspect.push(format!("interval_max.{}", field));
+        }
+        plausible
+    });
+    frame.suspect_fields = suspect;
+}
+
+/// Whether a field of a frame failed its plausibility check
+///
+/// # Arguments
+/// * `frame` - Checked frame
+/// * `field` - Field name as serialized, e.g. `temperature_c`
+pub fn is_suspect(frame: &TelemetryFrame, field: &str) -> bool {
+    frame.suspect_fields.iter().any(|suspect| suspect == field)
+}
+
+/// Whether a die temperature read directly from a working sensor is believable
+pub fn temperature_plausible(temperature_c: u32) -> bool {
+    (1..=MAX_TEMPERATURE_C).contains(&temperature_c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn limits() -> DeviceLimits {
+        DeviceLimits {
+            temperature_supported: true,
+            power_limit_w: Some(300.0),
+            max_sm_clock_mhz: Some(2_000),
+            max_memory_clock_mhz: Some(10_000),
+            memory_total_mb: 24_576,
+        }
+    }
+
+    fn frame() -> TelemetryFrame {
+        TelemetryFrame {
+            util_gpu: 80,
+            util_memory: 40,
+            memory_used_mb: 8_192,
+            temperature_c: 65,
+            power_w: 280.0,
+            sm_clock_mhz: 1_900,
+            memory_clock_mhz: 9_500,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plausible_frame_is_untouched() {
+        let mut checked = frame();
+        validate(&mut checked, &limits());
+        assert!(checked.suspect_fields.is_empty());
+        assert_eq!((checked.util_gpu, checked.memory_used_mb, checked.power_w), (80, 8_192, 280.0));
+
+        // A sensor that never answered reports 0 without being suspect
+        let mut unsupported = TelemetryFrame { temperature_c: 0, ..frame() };
+        validate(&mut unsupported, &DeviceLimits { temperature_supported: false, ..limits() });
+        assert!(unsupported.suspect_fields.is_empty());
+    }
+
+    #[test]
+    fn test_clamps_bounded_values_and_flags_the_rest() {
+        let mut checked = TelemetryFrame {
+            util_gpu: 255,
+            memory_used_mb: 30_000,
+            temperature_c: 0,
+            power_w: 3_000.0,
+            sm_clock_mhz: 6_000,
+            ..frame()
+        };
+        checked.engine_utilization.graphics = 255;
+        checked.engine_utilization.encoder = Some(140);
+        checked.engine_utilization.ofa = Some(101);
+        validate(&mut checked, &limits());
+        assert_eq!(checked.suspect_fields, vec![
+            "util_gpu", "engine_utilization.encoder", "engine_utilization.ofa", "memory_used_mb", "temperature_c", "power_w", "sm_clock_mhz",
+        ]);
+        assert_eq!((checked.util_gpu, checked.engine_utilization.graphics, checked.engine_utilization.encoder), (100, 100, Some(100)));
+        assert_eq!(checked.engine_utilization.ofa, Some(100));
+        assert_eq!(checked.memory_used_mb, 24_576);
+        // Unbounded values stay as read
+        assert_eq!((checked.temperature_c, checked.power_w), (0, 3_000.0));
+        assert!(is_suspect(&checked, "power_w") && !is_suspect(&checked, "util_memory"));
+
+        let mut spiky = frame();
+        spiky.interval_max = BTreeMap::from([("power_w".to_string(), 4_000.0), ("util_gpu".to_string(), 100.0)]);
+        validate(&mut spiky, &limits());
+        assert_eq!(spiky.suspect_fields, vec!["interval_max.power_w"]);
+        assert_eq!(spiky.interval_max, BTreeMap::from([("util_gpu".to_string(), 100.0)]));
+
+        let mut no_limit = TelemetryFrame { power_w: 1_500.0, ..frame() };
+        validate(&mut no_limit, &DeviceLimits { power_limit_w: None, ..limits() });
+        assert!(no_limit.suspect_fields.is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+//! Clock violation counters
+//!
+//! NVML keeps a cumulative count, per performance policy, of how long the
+//! driver held a device's clocks below what was requested: for power,
+//! thermal or sync-boost reasons, for board limits, and in total. Current
+//! throttle reasons only say why the clocks are limited right now; the
+//! counters say how much of a session was spent limited, so recordings
+//! store the difference between the counters at their start and end.
+
+use anyhow::{Context, Result};
+use nvml_wrapper::enum_wrappers::device::PerformancePolicy;
+use nvml_wrapper::{device::Device, Nvml};
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::{self, Capability};
+use crate::nvml;
+
+/// Reason a device's clocks were held down
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationPolicy {
+    Power,
+    Thermal,
+    SyncBoost,
+    BoardLimit,
+    LowUtilization,
+    Reliability,
+    /// Held below application clocks for any of the above reasons
+    TotalAppClocks,
+    /// Held below base clocks
+    TotalBaseClocks,
+}
+
+impl ViolationPolicy {
+    /// Every policy, in reporting order
+    pub const ALL: [ViolationPolicy; 8] = [
+        ViolationPolicy::Power,
+        ViolationPolicy::Thermal,
+        ViolationPolicy::SyncBoost,
+        ViolationPolicy::BoardLimit,
+        ViolationPolicy::LowUtilization,
+        ViolationPolicy::Reliability,
+        ViolationPolicy::TotalAppClocks,
+        ViolationPolicy::TotalBaseClocks,
+    ];
+}
+
+impl From<ViolationPolicy> for PerformancePolicy {
+    fn from(policy: ViolationPolicy) -> Self {
+        match policy {
+            ViolationPolicy::Power => PerformancePolicy::Power,
+            ViolationPolicy::Thermal => PerformancePolicy::Thermal,
+            ViolationPolicy::SyncBoost => PerformancePolicy::SyncBoost,
+            ViolationPolicy::BoardLimit => PerformancePolicy::BoardLimit,
+            ViolationPolicy::LowUtilization => PerformancePolicy::LowUtilization,
+            ViolationPolicy::Reliability => PerformancePolicy::Reliability,
+            ViolationPolicy::TotalAppClocks => PerformancePolicy::TotalAppClocks,
+            ViolationPolicy::TotalBaseClocks => PerformancePolicy::TotalBaseClocks,
+        }
+    }
+}
+
+/// Cumulative violation time of one policy
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ViolationCounter {
+    pub policy: ViolationPolicy,
+    /// Time spent in violation since the driver loaded
+    pub violation_ns: u64,
+    /// CPU timestamp of the reading, in microseconds
+    pub reference_time_us: u64,
+}
+
+/// Violation counters of one device
+///
+/// Policies the device does not report are left out.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ViolationStats {
+    pub device_index: u32,
+    pub counters: Vec<ViolationCounter>,
+}
+
+/// Time one policy held the clocks down during a session
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ViolationDuration {
+    pub policy: ViolationPolicy,
+    pub duration_ms: f64,
+    /// Share of the session
+    pub percent: f64,
+}
+
+/// Throttled time of one device during a session
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SessionViolations {
+    pub device_index: u32,
+    pub durations: Vec<ViolationDuration>,
+}
+
+/// Read the violation counters of a device
+///
+/// # Arguments
+/// * `device_index` - Device to query (defaults to 0)
+///
+/// # Returns
+/// * `Result<ViolationStats>` - Counters or error if the device is unreachable
+pub async fn get_violation_stats(device_index: Option<u32>) -> Result<ViolationStats> {
+    let device_index = device_index.unwrap_or(0);
+    nvml::blocking(move || {
+        let nvml = Nvml::init().context("Failed to initialize NVML")?;
+        let device = nvml::device_at(&nvml, device_index)?;
+        Ok(read_stats(&device, device_index))
+    }).await
+}
+
+/// Read the violation counters of several devices, skipping unreachable ones
+pub fn snapshot(device_indices: &[u32]) -> Result<Vec<ViolationStats>> {
+    let nvml = Nvml::init().context("Failed to initialize NVML")?;
+    Ok(device_indices.iter()
+        .filter_map(|&index| nvml::device_at(&nvml, index).ok().map(|device| read_stats(&device, index)))
+        .collect())
+}
+
+/// Read the counters of one device
+///
+/// Devices the capability probe found without counters are not queried.
+pub fn read_stats(device: &Device, device_index: u32) -> ViolationStats {
+    if capabilities::known_unavailable(device_index, Capability::ViolationCounters) {
+        return ViolationStats { device_index, counters: Vec::new() };
+    }
+    ViolationStats {
+        device_index,
+        counters: ViolationPolicy::ALL.into_iter()
+            .filter_map(|policy| {
+                let time = device.violation_status(policy.into()).ok()?;
+                Some(ViolationCounter { policy, violation_ns: time.violation_time, reference_time_us: time.reference_time })
+            })
+            .collect(),
+    }
+}
+
+/// Throttled time between two snapshots
+///
+/// # Arguments
+/// * `start` - Counters read when the session began
+/// * `end` - Counters read when it ended
+///
+/// # Returns
+/// * `Vec<SessionViolations>` - Per-device time in violation, for policies read both times
+pub fn session_violations(start: &[ViolationStats], end: &[ViolationStats]) -> Vec<SessionViolations> {
+    end.iter()
+        .filter_map(|last| {
+            let first = start.iter().find(|stats| stats.device_index == last.device_index)?;
+            let durations = last.counters.iter()
+                .filter_map(|counter| {
+                    let before = first.counters.iter().find(|before| before.policy == counter.policy)?;
+                    let violation_ns = counter.violation_ns.saturating_sub(before.violation_ns);
+                    let elapsed_ns = counter.reference_time_us.saturating_sub(before.reference_time_us) * 1000;
+                    Some(ViolationDuration {
+                        policy: counter.policy,
+                        duration_ms: violation_ns as f64 / 1e6,
+                        percent: if elapsed_ns > 0 { (violation_ns as f64 * 100.0 / elapsed_ns as f64).min(100.0) } else { 0.0 },
+                    })
+                })
+                .collect();
+            Some(SessionViolations { device_index: last.device_index, durations })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(device_index: u32, counters: &[(ViolationPolicy, u64, u64)]) -> ViolationStats {
+        ViolationStats {
+            device_index,
+            counters: counters.iter()
+                .map(|&(policy, violation_ns, reference_time_us)| ViolationCounter { policy, violation_ns, reference_time_us })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_session_violations_from_counter_deltas() {
+        let start = [stats(0, &[(ViolationPolicy::Power, 5_000_000_000, 1_000_000), (ViolationPolicy::Thermal, 0, 1_000_000)])];
+        let end = [stats(0, &[(ViolationPolicy::Power, 7_500_000_000, 11_000_000), (ViolationPolicy::Thermal, 0, 11_000_000)])];
+        let session = session_violations(&start, &end);
+        assert_eq!(session, vec![SessionViolations {
+            device_index: 0,
+            durations: vec![
+                ViolationDuration { policy: ViolationPolicy::Power, duration_ms: 2_500.0, percent: 25.0 },
+                ViolationDuration { policy: ViolationPolicy::Thermal, duration_ms: 0.0, percent: 0.0 },
+            ],
+        }]);
+    }
+
+    #[test]
+    fn test_skips_counters_missing_from_a_snapshot() {
+        let start = [stats(0, &[(ViolationPolicy::Power, 0, 0)])];
+        let end = [
+            stats(0, &[(ViolationPolicy::Power, 10, 0), (ViolationPolicy::SyncBoost, 10, 5)]),
+            stats(1, &[(ViolationPolicy::Power, 10, 5)]),
+        ];
+        let session = session_violations(&start, &end);
+        assert_eq!(session.len(), 1);
+        assert_eq!(session[0].durations, vec![ViolationDuration { policy: ViolationPolicy::Power, duration_ms: 1e-5, percent: 0.0 }]);
+        assert_eq!(serde_json::to_value(ViolationPolicy::SyncBoost).unwrap(), "sync_boost");
+    }
+}
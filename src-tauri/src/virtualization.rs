@@ -0,0 +1,194 @@
+//! Virtualization environment detection
+//!
+//! Works out whether the app runs on bare metal, in a VM with a passed-through
+//! GPU, on a vGPU slice, or under WSL. Many NVML queries (temperature, clocks,
+//! fans, power) are blocked in guests, so the UI uses this to explain missing
+//! data instead of reporting it as an error.
+//!
+//! The driver's own answer (`nvmlDeviceGetVirtualizationMode`) is used where
+//! it is supported; host heuristics (`/proc`, DMI, vGPU profile names) only
+//! fill in for drivers that do not support the query, and for WSL, which
+//! the driver does not report.
+
+use nvml_wrapper::device::Device;
+use nvml_wrapper::error::nvml_try;
+use nvml_wrapper_sys::bindings::{
+    nvmlGpuVirtualizationMode_NVML_GPU_VIRTUALIZATION_MODE_HOST_VGPU as MODE_HOST_VGPU,
+    nvmlGpuVirtualizationMode_NVML_GPU_VIRTUALIZATION_MODE_HOST_VSGA as MODE_HOST_VSGA,
+    nvmlGpuVirtualizationMode_NVML_GPU_VIRTUALIZATION_MODE_NONE as MODE_NONE,
+    nvmlGpuVirtualizationMode_NVML_GPU_VIRTUALIZATION_MODE_PASSTHROUGH as MODE_PASSTHROUGH,
+    nvmlGpuVirtualizationMode_NVML_GPU_VIRTUALIZATION_MODE_VGPU as MODE_VGPU,
+};
+use serde::Serialize;
+use std::fs;
+
+use crate::tuning;
+
+/// How the GPU is exposed to this system, from least to most virtualized
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum VirtualizationMode {
+    /// No hypervisor detected
+    BareMetal,
+    /// Whole GPU assigned to a virtual machine
+    Passthrough,
+    /// Time-sliced or MIG-backed virtual GPU
+    Vgpu,
+    /// Windows Subsystem for Linux (GPU paravirtualization)
+    Wsl,
+}
+
+/// Detected virtualization environment
+#[derive(Serialize, Clone, Debug)]
+pub struct VirtualizationInfo {
+    pub mode: VirtualizationMode,
+    /// Hypervisor or platform vendor reported by firmware, if any
+    pub hypervisor: Option<String>,
+    /// Explanation of expected data gaps, for display in the UI
+    pub note: Option<String>,
+}
+
+/// Virtualization mode the driver reports for a device
+///
+/// A host that runs vGPU guests counts as bare metal: it sees every sensor.
+///
+/// # Arguments
+/// * `device` - Device to query
+///
+/// # Returns
+/// * `Option<VirtualizationMode>` - Reported mode, or `None` if the driver does not support the query
+pub fn query_mode(device: &Device) -> Option<VirtualizationMode> {
+    let lib = tuning::raw_nvml().ok()?;
+    let get_mode = lib.nvmlDeviceGetVirtualizationMode.as_ref().ok()?;
+    let mut mode = MODE_NONE;
+    nvml_try(unsafe { get_mode(device.handle(), &mut mode) }).ok()?;
+    match mode {
+        MODE_NONE | MODE_HOST_VGPU | MODE_HOST_VSGA => Some(VirtualizationMode::BareMetal),
+        MODE_PASSTHROUGH => Some(VirtualizationMode::Passthrough),
+        MODE_VGPU => Some(VirtualizationMode::Vgpu),
+        _ => None,
+    }
+}
+
+/// Detect the virtualization environment
+///
+/// # Arguments
+/// * `gpu_names` - Device names reported by NVML (vGPU profiles are named like `GRID T4-4Q`)
+/// * `reported` - Mode reported by the driver for each device (see `query_mode`)
+///
+/// # Returns
+/// * `VirtualizationInfo` - Detected mode, never fails (unreadable sources count as bare metal)
+pub fn detect(gpu_names: &[String], reported: &[Option<VirtualizationMode>]) -> VirtualizationInfo {
+    let proc_version = fs::read_to_string("/proc/version").unwrap_or_default();
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let hypervisor_flag = cpuinfo.lines()
+        .filter(|line| line.starts_with("flags"))
+        .any(|line| line.split_whitespace().any(|flag| flag == "hypervisor"));
+
+    let mode = classify(&proc_version, hypervisor_flag, gpu_names, reported);
+    let hypervisor = match mode {
+        VirtualizationMode::BareMetal => None,
+        VirtualizationMode::Wsl => Some("Microsoft Hyper-V".to_string()),
+        _ => read_dmi_vendor(),
+    };
+
+    VirtualizationInfo {
+        mode,
+        hypervisor,
+        note: note_for(mode),
+    }
+}
+
+// WSL wins over everything, then the most virtualized mode any device
+// reports; without reports, a vGPU profile name wins over the generic
+// hypervisor flag
+fn classify(proc_version: &str, hypervisor_flag: bool, gpu_names: &[String], reported: &[Option<VirtualizationMode>]) -> VirtualizationMode {
+    let proc_version = proc_version.to_lowercase();
+    if proc_version.contains("microsoft") || proc_version.contains("wsl") {
+        VirtualizationMode::Wsl
+    } else if let Some(mode) = reported.iter().flatten().max() {
+        *mode
+    } else if gpu_names.iter().any(|name| is_vgpu_profile_name(name)) {
+        VirtualizationMode::Vgpu
+    } else if hypervisor_flag {
+        VirtualizationMode::Passthrough
+    } else {
+        VirtualizationMode::BareMetal
+    }
+}
+
+// vGPU profiles carry a `-<framebuffer GB><series letter>` suffix such as
+// `-4Q`, `-8C` or `-2B`, and older ones are branded GRID
+fn is_vgpu_profile_name(name: &str) -> bool {
+    if name.starts_with("GRID ") {
+        return true;
+    }
+    let Some((_, profile)) = name.rsplit_once('-') else {
+        return false;
+    };
+    let Some(series) = profile.chars().last() else {
+        return false;
+    };
+    let size = &profile[..profile.len() - series.len_utf8()];
+    matches!(series, 'A' | 'B' | 'C' | 'Q')
+        && !size.is_empty()
+        && size.chars().all(|c| c.is_ascii_digit())
+}
+
+fn read_dmi_vendor() -> Option<String> {
+    fs::read_to_string("/sys/class/dmi/id/sys_vendor")
+        .ok()
+        .map(|vendor| vendor.trim().to_string())
+        .filter(|vendor| !vendor.is_empty())
+}
+
+fn note_for(mode: VirtualizationMode) -> Option<String> {
+    let note = match mode {
+        VirtualizationMode::BareMetal => return None,
+        VirtualizationMode::Passthrough => "Running in a virtual machine; some sensors may be hidden by the hypervisor",
+        VirtualizationMode::Vgpu => "Running on a vGPU; temperature, clocks, fans and power are managed by the host and usually unavailable",
+        VirtualizationMode::Wsl => "Running under WSL; fan, power and clock controls are unavailable and some sensors report no data",
+    };
+    Some(note.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        let wsl = "Linux version 5.15.90.1-microsoft-standard-WSL2";
+        assert_eq!(classify(wsl, true, &[], &[]), VirtualizationMode::Wsl);
+        assert_eq!(classify("Linux version 6.1", true, &names(&["NVIDIA A40-8Q"]), &[]), VirtualizationMode::Vgpu);
+        assert_eq!(classify("Linux version 6.1", true, &names(&["NVIDIA A40"]), &[]), VirtualizationMode::Passthrough);
+        assert_eq!(classify("Linux version 6.1", false, &names(&["NVIDIA GeForce RTX 4090"]), &[]), VirtualizationMode::BareMetal);
+    }
+
+    #[test]
+    fn test_classify_prefers_driver_reports() {
+        // A Windows guest has no /proc to read; the driver still knows
+        assert_eq!(classify("", false, &names(&["NVIDIA A40"]), &[Some(VirtualizationMode::Passthrough)]), VirtualizationMode::Passthrough);
+        // A hypervisor flag alone does not override the driver
+        assert_eq!(classify("Linux version 6.1", true, &names(&["NVIDIA A40"]), &[Some(VirtualizationMode::BareMetal)]), VirtualizationMode::BareMetal);
+        assert_eq!(
+            classify("", false, &names(&["NVIDIA A40", "NVIDIA A40-8Q"]), &[None, Some(VirtualizationMode::Vgpu)]),
+            VirtualizationMode::Vgpu,
+        );
+        // Devices without support fall back to the heuristics
+        assert_eq!(classify("Linux version 6.1", true, &names(&["NVIDIA A40-8Q"]), &[None]), VirtualizationMode::Vgpu);
+    }
+
+    #[test]
+    fn test_is_vgpu_profile_name() {
+        assert!(is_vgpu_profile_name("GRID T4-4Q"));
+        assert!(is_vgpu_profile_name("NVIDIA A100-40C"));
+        assert!(!is_vgpu_profile_name("NVIDIA GeForce RTX 4090"));
+        assert!(!is_vgpu_profile_name("NVIDIA A100-SXM4-80GB"));
+        assert!(!is_vgpu_profile_name("NVIDIA H100-C"));
+    }
+}
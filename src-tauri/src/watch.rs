@@ -0,0 +1,380 @@
+//! Process watch mode
+//!
+//! Waits for a target process, given by name or PID, to appear on a GPU,
+//! records every device it uses for as long as it runs, and stops the
+//! recording when the process exits. The saved recording is tagged with the
+//! process, so a training job's telemetry is captured from its first to its
+//! last kernel without anyone pressing record.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::Duration;
+use tauri::Window;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::AppError;
+use crate::nvml;
+use crate::processes::{self, GpuProcess};
+use crate::schema;
+use crate::sessions;
+
+/// How often GPU processes are checked for the target
+pub const WATCH_POLL_INTERVAL_MS: u64 = 1_000;
+/// Sample rate used when none is requested
+pub const DEFAULT_SAMPLE_RATE_HZ: u64 = 10;
+/// Longest a watched recording runs if the process never exits
+pub const MAX_RECORDING_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Process to watch for
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchTarget {
+    Pid(u32),
+    /// Process name, executable name or attributed script, case-insensitive
+    Name(String),
+}
+
+impl WatchTarget {
+    /// Read a target from a PID or a process name
+    ///
+    /// # Returns
+    /// * `Result<WatchTarget>` - Target or error for an empty string
+    pub fn parse(name_or_pid: &str) -> Result<Self> {
+        let name_or_pid = name_or_pid.trim();
+        if name_or_pid.is_empty() {
+            return Err(AppError::InvalidArgument("Give a process name or PID to watch".to_string()).into());
+        }
+        Ok(match name_or_pid.parse() {
+            Ok(pid) => WatchTarget::Pid(pid),
+            Err(_) => WatchTarget::Name(name_or_pid.to_string()),
+        })
+    }
+
+    fn matches(&self, process: &GpuProcess) -> bool {
+        match self {
+            WatchTarget::Pid(pid) => process.pid == *pid,
+            WatchTarget::Name(name) => processes::is_named(process, name),
+        }
+    }
+}
+
+/// The process a watched recording was made for
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WatchedProcess {
+    /// First matching process seen
+    pub pid: u32,
+    pub name: String,
+    pub exe: Option<String>,
+    pub command_line: Vec<String>,
+    pub attributed_to: String,
+    /// Every matching process, e.g. all ranks of a training job
+    pub pids: Vec<u32>,
+    /// Recorded devices: every device a matching process used at the start
+    pub device_indices: Vec<u32>,
+    /// When the process was first seen on a GPU (Unix milliseconds)
+    pub started_at_ms: u64,
+    /// When the last matching process left the GPUs; `None` if the watch was stopped first
+    pub exited_at_ms: Option<u64>,
+}
+
+impl WatchedProcess {
+    /// Describe the matching processes, or `None` if none match
+    pub fn find(target: &WatchTarget, processes: &[GpuProcess], now_ms: u64) -> Option<Self> {
+        let matching: Vec<&GpuProcess> = processes.iter().filter(|process| target.matches(process)).collect();
+        let first = matching.iter().min_by_key(|process| process.pid)?;
+        let pids: BTreeSet<u32> = matching.iter().map(|process| process.pid).collect();
+        let devices: BTreeSet<u32> = matching.iter().map(|process| process.device_index).collect();
+        Some(WatchedProcess {
+            pid: first.pid,
+            name: first.name.clone(),
+            exe: first.exe.clone(),
+            command_line: first.command_line.clone(),
+            attributed_to: first.attributed_to.clone(),
+            pids: pids.into_iter().collect(),
+            device_indices: devices.into_iter().collect(),
+            started_at_ms: now_ms,
+            exited_at_ms: None,
+        })
+    }
+
+    /// Whether any of the watched processes is still on a GPU
+    pub fn is_running(&self, processes: &[GpuProcess]) -> bool {
+        processes.iter().any(|process| self.pids.contains(&process.pid))
+    }
+}
+
+/// Phase of a process watch
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchPhase {
+    /// Waiting for the process to appear
+    Waiting,
+    /// Recording while the process runs
+    Recording,
+    /// Process exited; recording written to `output_file`
+    Saved,
+    /// Stopped before the process exited
+    Stopped,
+    /// Recording could not be started or saved
+    Failed,
+}
+
+/// State of the current or most recent process watch
+#[derive(Serialize, Clone, Debug)]
+pub struct WatchStatus {
+    pub phase: WatchPhase,
+    pub target: WatchTarget,
+    pub sample_rate_hz: u64,
+    pub process: Option<WatchedProcess>,
+    /// Recording session started for the process
+    pub session_id: Option<String>,
+    pub output_file: Option<String>,
+    pub error: Option<String>,
+}
+
+// Current or most recent watch; kept after it ends so its result can be read
+static WATCH_STATE: std::sync::RwLock<Option<WatchStatus>> = std::sync::RwLock::new(None);
+
+// Cancelled to stop the watch, saving a recording in progress
+static WATCH_CANCEL: std::sync::Mutex<Option<CancellationToken>> = std::sync::Mutex::new(None);
+
+// Handle to the watch task, used to wait for a recording to be saved
+static WATCH_TASK: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+/// Record while a process runs
+///
+/// Emits `process-watch-started` when the process appears and recording
+/// begins, and `process-watch-finished` once the recording is saved.
+///
+/// # Arguments
+/// * `name_or_pid` - PID, or process name, executable name or script name
+/// * `sample_rate_hz` - Sampling frequency of the recording
+/// * `window` - Tauri window handle for recording and watch events
+///
+/// # Returns
+/// * `Result<WatchStatus>` - Status of the waiting watch or error
+pub async fn watch_process(name_or_pid: &str, sample_rate_hz: u64, window: Window) -> Result<WatchStatus> {
+    let target = WatchTarget::parse(name_or_pid)?;
+    if sample_rate_hz == 0 {
+        return Err(AppError::InvalidArgument("Sample rate must be at least 1 Hz".to_string()).into());
+    }
+    let status = WatchStatus {
+        phase: WatchPhase::Waiting,
+        target: target.clone(),
+        sample_rate_hz,
+        process: None,
+        session_id: None,
+        output_file: None,
+        error: None,
+    };
+    let status = sessions::claim(&WATCH_STATE, |status| is_active(status.phase), status.clone(), "A process is already being watched")?
+        .commit(status);
+    let cancel = CancellationToken::new();
+    *WATCH_CANCEL.lock().unwrap() = Some(cancel.clone());
+
+    let task = tokio::spawn(async move {
+        let result = run_watch(&target, sample_rate_hz, &cancel, &window).await;
+        let finished = update_status(|status| match result {
+            Ok(Some(output_file)) => {
+                status.phase = if cancel.is_cancelled() { WatchPhase::Stopped } else { WatchPhase::Saved };
+                status.output_file = Some(output_file);
+            }
+            Ok(None) => status.phase = WatchPhase::Stopped,
+            Err(e) => {
+                status.phase = WatchPhase::Failed;
+                status.error = Some(format!("{:#}", e));
+            }
+        });
+        if let Some(error) = &finished.error {
+            eprintln!("Process watch error: {}", error);
+        }
+        if let Err(e) = window.emit("process-watch-finished", &finished) {
+            eprintln!("Failed to emit process watch finished event: {}", e);
+        }
+    });
+    *WATCH_TASK.lock().unwrap() = Some(task);
+
+    Ok(status)
+}
+
+/// Stop watching; a recording in progress is saved
+///
+/// # Returns
+/// * `Result<WatchStatus>` - Final status or error if no watch is active
+pub async fn stop_watch() -> Result<WatchStatus> {
+    if !WATCH_STATE.read().unwrap().as_ref().is_some_and(|status| is_active(status.phase)) {
+        return Err(AppError::InvalidArgument("No process is being watched".to_string()).into());
+    }
+    finish_active_watch().await?;
+    get_watch_status().context("Process watch state missing after stop")
+}
+
+/// Stop any watch and wait until its recording has been saved
+pub async fn finish_active_watch() -> Result<()> {
+    if let Some(cancel) = WATCH_CANCEL.lock().unwrap().take() {
+        cancel.cancel();
+    }
+    let task = WATCH_TASK.lock().unwrap().take();
+    if let Some(task) = task {
+        task.await.context("Process watch task ended abnormally")?;
+    }
+    Ok(())
+}
+
+/// Get the status of the current or most recent process watch
+///
+/// # Returns
+/// * `Option<WatchStatus>` - Status, or `None` if no process has been watched
+pub fn get_watch_status() -> Option<WatchStatus> {
+    WATCH_STATE.read().unwrap().clone()
+}
+
+fn is_active(phase: WatchPhase) -> bool {
+    matches!(phase, WatchPhase::Waiting | WatchPhase::Recording)
+}
+
+fn update_status(change: impl FnOnce(&mut WatchStatus)) -> WatchStatus {
+    let mut state = WATCH_STATE.write().unwrap();
+    let status = state.as_mut().expect("watch state is set while a watch runs");
+    change(status);
+    status.clone()
+}
+
+// Wait for the process, record until it exits; `None` if stopped before it appeared
+async fn run_watch(target: &WatchTarget, sample_rate_hz: u64, cancel: &CancellationToken, window: &Window) -> Result<Option<String>> {
+    let mut interval = tokio::time::interval(Duration::from_millis(WATCH_POLL_INTERVAL_MS));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let mut process = loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return Ok(None),
+            _ = interval.tick() => {}
+        }
+        // NVML can fail briefly, e.g. while a process creates its context
+        let Ok(report) = processes::get_gpu_processes(None).await else { continue };
+        if let Some(process) = WatchedProcess::find(target, &report.processes, nvml::now_ms() as u64) {
+            break process;
+        }
+    };
+
+    let session_id = nvml::start_interval_recording(
+        MAX_RECORDING_SECONDS, sample_rate_hz, Vec::new(), process.device_indices.clone(), false, window.clone(),
+    ).await?;
+    let output_file = nvml::get_recording_status().await?.output_file
+        .context("Recording started without an output file")?;
+    let status = update_status(|status| {
+        status.phase = WatchPhase::Recording;
+        status.process = Some(process.clone());
+        status.session_id = Some(session_id.clone());
+    });
+    if let Err(e) = window.emit("process-watch-started", &status) {
+        eprintln!("Failed to emit process watch started event: {}", e);
+    }
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = interval.tick() => {}
+        }
+        let recording = nvml::get_recording_status().await?;
+        if recording.session_id.as_deref() != Some(session_id.as_str()) {
+            // Stopped from elsewhere; the recording has already been saved
+            break;
+        }
+        let Ok(report) = processes::get_gpu_processes(None).await else { continue };
+        if !process.is_running(&report.processes) {
+            process.exited_at_ms = Some(nvml::now_ms() as u64);
+            break;
+        }
+    }
+
+    nvml::finalize_active_recording().await?;
+    update_status(|status| status.process = Some(process.clone()));
+    let path = output_file.clone();
+    nvml::blocking(move || tag_recording(Path::new(&path), process)).await?;
+    Ok(Some(output_file))
+}
+
+/// Store the watched process in a saved recording
+///
+/// # Arguments
+/// * `path` - JSON recording
+/// * `process` - Process the recording was made for
+///
+/// # Returns
+/// * `Result<()>` - Error if the recording cannot be read or written
+pub fn tag_recording(path: &Path, process: WatchedProcess) -> Result<()> {
+    let mut recording = schema::load_recording(path)?;
+    recording.process = Some(process);
+    let json_data = serde_json::to_string_pretty(&recording)
+        .context("Failed to serialize recording data")?;
+    std::fs::write(path, json_data).context("Failed to write recording file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvml::{RecordingFile, StaticDeviceInfo};
+
+    fn process(pid: u32, device_index: u32, name: &str) -> GpuProcess {
+        GpuProcess {
+            pid,
+            device_index,
+            kind: "compute".to_string(),
+            used_memory_mb: Some(1_024),
+            name: name.to_string(),
+            exe: Some(format!("/usr/bin/{}", name)),
+            command_line: vec![name.to_string()],
+            parent_pid: None,
+            attributed_to: if name == "python3" { "train.py".to_string() } else { name.to_string() },
+            tree_root_pid: pid,
+            cgroup: None,
+            container: None,
+        }
+    }
+
+    #[test]
+    fn test_parses_pid_or_name() {
+        assert_eq!(WatchTarget::parse(" 4242 ").unwrap(), WatchTarget::Pid(4242));
+        assert_eq!(WatchTarget::parse("train.py").unwrap(), WatchTarget::Name("train.py".to_string()));
+        assert_eq!(AppError::from(WatchTarget::parse("  ").unwrap_err()).code(), "INVALID_ARGUMENT");
+    }
+
+    #[test]
+    fn test_finds_every_rank_of_a_job() {
+        let running = vec![process(11, 1, "python3"), process(10, 0, "python3"), process(20, 0, "Xorg")];
+        let job = WatchedProcess::find(&WatchTarget::Name("TRAIN.PY".to_string()), &running, 5).unwrap();
+        assert_eq!((job.pid, job.pids.clone(), job.device_indices.clone()), (10, vec![10, 11], vec![0, 1]));
+        assert_eq!(job.started_at_ms, 5);
+
+        assert!(job.is_running(&running[..1]));
+        assert!(!job.is_running(&running[2..]));
+        assert_eq!(WatchedProcess::find(&WatchTarget::Pid(20), &running, 0).map(|found| found.name), Some("Xorg".to_string()));
+        assert!(WatchedProcess::find(&WatchTarget::Pid(30), &running, 0).is_none());
+    }
+
+    #[test]
+    fn test_tags_saved_recording() {
+        let path = std::env::temp_dir().join(format!("nsightful_watch_{}.json", std::process::id()));
+        let device = StaticDeviceInfo::default();
+        let recording = RecordingFile {
+            schema_version: schema::CURRENT_SCHEMA_VERSION,
+            device: device.clone(),
+            devices: vec![device],
+            samples: Vec::new(),
+            markers: Vec::new(),
+            timing: None,
+            process: None,
+            violations: Vec::new(),
+            clock: None,
+        };
+        std::fs::write(&path, serde_json::to_string(&recording).unwrap()).unwrap();
+
+        let job = WatchedProcess::find(&WatchTarget::Pid(7), &[process(7, 0, "blender")], 1_000).unwrap();
+        tag_recording(&path, job.clone()).unwrap();
+        assert_eq!(schema::load_recording(&path).unwrap().process, Some(job));
+        std::fs::remove_file(&path).ok();
+    }
+}
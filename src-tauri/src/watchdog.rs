@@ -0,0 +1,330 @@
+//! Supervision of the streaming task
+//!
+//! A hung NVML call or a panicked task stops frames from arriving, which the
+//! frontend cannot tell apart from an idle GPU. The supervisor watches the
+//! broadcast channel and tracks the last frame of each device against that
+//! device's own period. A device that goes silent is reported and only its
+//! task is restarted, while the others keep streaming; when no device sends
+//! frames or the stream task ends on its own, the whole stream is restarted.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::nvml::{self, TelemetryFrame};
+
+/// Missed periods after which the stream is considered stalled
+pub const STALL_PERIODS: u64 = 10;
+/// Lower bound on the stall timeout; NVML initialization alone can take seconds
+pub const MIN_STALL_TIMEOUT: Duration = Duration::from_secs(5);
+/// Consecutive restarts attempted before the supervisor gives up
+pub const MAX_RESTARTS: u32 = 5;
+/// Pause before restarting a stalled stream
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// A detected stall, emitted as the `stream-stalled` event
+#[derive(Serialize, Clone, Debug)]
+pub struct StallIncident {
+    pub detected_at: u128,
+    /// Device that stopped sending frames; `None` if the whole stream stalled
+    pub device_index: Option<u32>,
+    pub reason: String,
+    /// Restart attempt about to be made (1-based)
+    pub restart_attempt: u32,
+    /// Whether the supervisor will restart the stream or device
+    pub restarting: bool,
+}
+
+/// How long the stream may go without producing a frame
+pub fn stall_timeout(period_ms: u64) -> Duration {
+    Duration::from_millis(period_ms.saturating_mul(STALL_PERIODS)).max(MIN_STALL_TIMEOUT)
+}
+
+// How often silent devices are looked for
+fn check_interval(timeout: Duration) -> Duration {
+    timeout.min(MIN_STALL_TIMEOUT) / 10
+}
+
+// Last frame and restarts of one streamed device
+struct WatchedDevice {
+    last_frame: Instant,
+    restarts: u32,
+    /// Restarts were exhausted; not reported again until the device sends a frame
+    given_up: bool,
+}
+
+// Per-device stall detection within one run of the stream task
+#[derive(Default)]
+struct DeviceWatch {
+    devices: HashMap<u32, WatchedDevice>,
+}
+
+impl DeviceWatch {
+    fn seen(&mut self, device_index: u32, at: Instant) {
+        self.devices.insert(device_index, WatchedDevice { last_frame: at, restarts: 0, given_up: false });
+    }
+
+    // Report devices silent for longer than their timeout; a device starts
+    // being watched when it is first listed, so one that hangs before its
+    // first frame is caught too
+    fn check(&mut self, streamed: &[(u32, Duration)], now: Instant) -> Vec<StallIncident> {
+        self.devices.retain(|device_index, _| streamed.iter().any(|(streamed, _)| streamed == device_index));
+        let mut incidents = Vec::new();
+        for &(device_index, timeout) in streamed {
+            let watched = self.devices.entry(device_index)
+                .or_insert(WatchedDevice { last_frame: now, restarts: 0, given_up: false });
+            if watched.given_up || now.duration_since(watched.last_frame) < timeout {
+                continue;
+            }
+            watched.restarts += 1;
+            watched.given_up = watched.restarts > MAX_RESTARTS;
+            // The restarted device gets a full timeout to produce a frame
+            watched.last_frame = now;
+            incidents.push(StallIncident {
+                detected_at: nvml::now_ms(),
+                device_index: Some(device_index),
+                reason: format!("no frames from GPU {} for {} ms", device_index, timeout.as_millis()),
+                restart_attempt: watched.restarts,
+                restarting: !watched.given_up,
+            });
+        }
+        incidents
+    }
+}
+
+/// Run a stream task and restart it, or one of its devices, whenever it stalls
+///
+/// Returns once `cancel` is triggered, the channel closes, or `MAX_RESTARTS`
+/// consecutive restarts of the whole stream have failed to produce a frame.
+///
+/// # Arguments
+/// * `spawn` - Starts the stream task; the token passed in stops it, and the
+///   receiver yields devices whose task should be restarted
+/// * `frames` - Receiver on the channel the stream task sends frames to
+/// * `timeout` - Time without any frame after which the whole stream counts as stalled
+/// * `device_timeouts` - Devices currently streamed, each with its own stall timeout
+/// * `cancel` - Token used to stop the supervisor and its stream task
+/// * `on_stall` - Called with each incident before restarting
+/// * `on_lagged` - Called with the number of frames the supervisor missed by falling behind
+pub async fn supervise<S, D, N, L>(
+    spawn: S,
+    mut frames: broadcast::Receiver<TelemetryFrame>,
+    timeout: Duration,
+    device_timeouts: D,
+    cancel: CancellationToken,
+    on_stall: N,
+    on_lagged: L,
+) where
+    S: Fn(CancellationToken, mpsc::UnboundedReceiver<u32>) -> JoinHandle<()>,
+    D: Fn() -> Vec<(u32, Duration)>,
+    N: Fn(&StallIncident),
+    L: Fn(u64),
+{
+    let check_every = check_interval(timeout);
+    let mut restarts = 0;
+    loop {
+        let stream_cancel = cancel.child_token();
+        let (restart_device, restart_requests) = mpsc::unbounded_channel();
+        let mut task = spawn(stream_cancel.clone(), restart_requests);
+        let mut watch = DeviceWatch::default();
+        let mut last_frame = Instant::now();
+
+        let reason = loop {
+            // Cancellation also ends the stream task; check it first so a
+            // requested stop is not reported as a stall
+            tokio::select! {
+                biased;
+                _ = cancel.cancelled() => {
+                    if tokio::time::timeout(timeout, &mut task).await.is_err() {
+                        task.abort();
+                    }
+                    return;
+                }
+                result = &mut task => break match result {
+                    Err(e) if e.is_panic() => "stream task panicked".to_string(),
+                    _ => "stream task exited".to_string(),
+                },
+                received = tokio::time::timeout(check_every, frames.recv()) => match received {
+                    Ok(Ok(frame)) => {
+                        restarts = 0;
+                        last_frame = Instant::now();
+                        watch.seen(frame.device_index, last_frame);
+                    }
+                    // Frames are flowing, but which devices sent them is unknown
+                    Ok(Err(RecvError::Lagged(missed))) => {
+                        on_lagged(missed);
+                        restarts = 0;
+                        last_frame = Instant::now();
+                    }
+                    Ok(Err(RecvError::Closed)) => return,
+                    Err(_) => {}
+                },
+            }
+            if last_frame.elapsed() >= timeout {
+                break format!("no frames for {} ms", timeout.as_millis());
+            }
+
+            for incident in watch.check(&device_timeouts(), Instant::now()) {
+                report(&incident, &on_stall);
+                if let (true, Some(device_index)) = (incident.restarting, incident.device_index) {
+                    // The stream task may have ended; its exit is handled above
+                    let _ = restart_device.send(device_index);
+                }
+            }
+        };
+
+        // A task blocked inside NVML ignores cancellation; abort detaches it
+        stream_cancel.cancel();
+        task.abort();
+
+        restarts += 1;
+        let incident = StallIncident {
+            detected_at: nvml::now_ms(),
+            device_index: None,
+            reason,
+            restart_attempt: restarts,
+            restarting: restarts <= MAX_RESTARTS,
+        };
+        report(&incident, &on_stall);
+        if !incident.restarting {
+            eprintln!("NVML stream watchdog giving up after {} restarts", MAX_RESTARTS);
+            return;
+        }
+
+        tokio::select! {
+            _ = cancel.cancelled() => return,
+            _ = tokio::time::sleep(RESTART_DELAY) => {}
+        }
+        // Frames buffered from the stalled task are stale
+        frames = frames.resubscribe();
+    }
+}
+
+fn report<N: Fn(&StallIncident)>(incident: &StallIncident, on_stall: &N) {
+    eprintln!(
+        "NVML stream stalled: {} (restart attempt {}/{})",
+        incident.reason, incident.restart_attempt, MAX_RESTARTS
+    );
+    on_stall(incident);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_stall_timeout_scales_with_period() {
+        assert_eq!(stall_timeout(100), MIN_STALL_TIMEOUT);
+        assert_eq!(stall_timeout(1_000), Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_restarts_stream_that_stops_producing_frames() {
+        let (tx, rx) = broadcast::channel(16);
+        let spawned = Arc::new(AtomicU32::new(0));
+        let incidents = Arc::new(Mutex::new(Vec::new()));
+        let cancel = CancellationToken::new();
+
+        let spawn = {
+            let spawned = spawned.clone();
+            let tx = tx.clone();
+            move |stream_cancel: CancellationToken, _restarts: mpsc::UnboundedReceiver<u32>| {
+                // First run sends one frame then hangs; the restart keeps producing
+                let run = spawned.fetch_add(1, Ordering::SeqCst);
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    loop {
+                        let _ = tx.send(TelemetryFrame::default());
+                        if run == 0 {
+                            stream_cancel.cancelled().await;
+                            return;
+                        }
+                        tokio::select! {
+                            _ = stream_cancel.cancelled() => return,
+                            _ = tokio::time::sleep(Duration::from_millis(5)) => {}
+                        }
+                    }
+                })
+            }
+        };
+        let on_stall = {
+            let incidents = incidents.clone();
+            move |incident: &StallIncident| incidents.lock().unwrap().push(incident.clone())
+        };
+        let supervisor = tokio::spawn(supervise(spawn, rx, Duration::from_millis(50), Vec::new, cancel.clone(), on_stall, |_| {}));
+
+        tokio::time::sleep(RESTART_DELAY + Duration::from_millis(300)).await;
+        cancel.cancel();
+        supervisor.await.unwrap();
+
+        assert_eq!(spawned.load(Ordering::SeqCst), 2);
+        let incidents = incidents.lock().unwrap();
+        assert_eq!(incidents.len(), 1);
+        assert_eq!(incidents[0].reason, "no frames for 50 ms");
+        assert_eq!(incidents[0].device_index, None);
+        assert!(incidents[0].restarting);
+    }
+
+    #[tokio::test]
+    async fn test_restarts_only_the_device_that_stops() {
+        let (tx, rx) = broadcast::channel(64);
+        let spawned = Arc::new(AtomicU32::new(0));
+        let restarted = Arc::new(Mutex::new(Vec::new()));
+        let incidents = Arc::new(Mutex::new(Vec::new()));
+        let cancel = CancellationToken::new();
+
+        let spawn = {
+            let (spawned, restarted, tx) = (spawned.clone(), restarted.clone(), tx.clone());
+            move |stream_cancel: CancellationToken, mut restarts: mpsc::UnboundedReceiver<u32>| {
+                spawned.fetch_add(1, Ordering::SeqCst);
+                let (restarted, tx) = (restarted.clone(), tx.clone());
+                tokio::spawn(async move {
+                    // GPU 0 keeps streaming; GPU 1 sends one frame, then hangs until restarted
+                    let mut device_1_running = true;
+                    let _ = tx.send(TelemetryFrame { device_index: 1, ..Default::default() });
+                    loop {
+                        let _ = tx.send(TelemetryFrame { device_index: 0, ..Default::default() });
+                        tokio::select! {
+                            _ = stream_cancel.cancelled() => return,
+                            Some(device_index) = restarts.recv() => {
+                                restarted.lock().unwrap().push(device_index);
+                                device_1_running = true;
+                            }
+                            _ = tokio::time::sleep(Duration::from_millis(5)) => {}
+                        }
+                        if device_1_running && restarted.lock().unwrap().is_empty() {
+                            device_1_running = false;
+                        } else if device_1_running {
+                            let _ = tx.send(TelemetryFrame { device_index: 1, ..Default::default() });
+                        }
+                    }
+                })
+            }
+        };
+        let device_timeouts = || vec![(0, Duration::from_millis(500)), (1, Duration::from_millis(100))];
+        let on_stall = {
+            let incidents = incidents.clone();
+            move |incident: &StallIncident| incidents.lock().unwrap().push(incident.clone())
+        };
+        let supervisor = tokio::spawn(supervise(spawn, rx, Duration::from_millis(500), device_timeouts, cancel.clone(), on_stall, |_| {}));
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        cancel.cancel();
+        supervisor.await.unwrap();
+
+        // Only GPU 1 was restarted, once, and the stream itself kept running
+        assert_eq!(spawned.load(Ordering::SeqCst), 1);
+        assert_eq!(*restarted.lock().unwrap(), vec![1]);
+        let incidents = incidents.lock().unwrap();
+        assert_eq!(incidents.len(), 1);
+        assert_eq!(incidents[0].device_index, Some(1));
+        assert_eq!(incidents[0].reason, "no frames from GPU 1 for 100 ms");
+        assert!(incidents[0].restarting);
+    }
+}